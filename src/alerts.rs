@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{journal::JournalEntry, webhook::Webhook};
+
+// Where and when to send alerts
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AlertSettings {
+    // Receives `{"text": "..."}`, which Slack incoming webhooks accept
+    pub webhook: String,
+    #[serde(default = "default_unmatched_threshold")]
+    pub unmatched_threshold: usize,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    #[serde(default = "default_upstream_failures")]
+    pub upstream_failures: u32,
+    // Least time between two unmatched alerts
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+fn default_unmatched_threshold() -> usize {
+    20
+}
+
+fn default_window_secs() -> u64 {
+    60
+}
+
+fn default_upstream_failures() -> u32 {
+    5
+}
+
+fn default_cooldown_secs() -> u64 {
+    600
+}
+
+#[derive(Default)]
+struct AlertState {
+    unmatched: VecDeque<Instant>,
+    last_unmatched_alert: Option<Instant>,
+    // Failures in a row per backend, and whether an alert went out for them
+    upstream: HashMap<String, (u32, bool)>,
+}
+
+pub struct Alerts {
+    settings: AlertSettings,
+    webhook: Webhook,
+    state: Mutex<AlertState>,
+}
+
+impl Alerts {
+    pub fn new(settings: AlertSettings) -> std::io::Result<Self> {
+        Ok(Alerts {
+            webhook: Webhook::new(&settings.webhook)?,
+            settings,
+            state: Mutex::new(AlertState::default()),
+        })
+    }
+
+    // Counts requests no mock matched, whether or not a backend answered
+    // them, alerting once too many arrive within the window
+    pub fn request(&self, workspace: &str, entry: &JournalEntry) {
+        if entry.matched.is_some() {
+            return;
+        }
+        let now = Instant::now();
+        let window = Duration::from_secs(self.settings.window_secs);
+        let count = {
+            let mut state = self.state.lock().unwrap();
+            state.unmatched.push_back(now);
+            while state
+                .unmatched
+                .front()
+                .is_some_and(|seen| now.duration_since(*seen) > window)
+            {
+                state.unmatched.pop_front();
+            }
+            let cooldown = Duration::from_secs(self.settings.cooldown_secs);
+            let cooled = state
+                .last_unmatched_alert
+                .is_none_or(|sent| now.duration_since(sent) >= cooldown);
+            if state.unmatched.len() < self.settings.unmatched_threshold || !cooled {
+                return;
+            }
+            state.last_unmatched_alert = Some(now);
+            state.unmatched.len()
+        };
+        self.send(format!(
+            "{} requests matched no mock in the last {}s, latest {} {} in workspace {}",
+            count, self.settings.window_secs, entry.method, entry.path, workspace
+        ));
+    }
+
+    // Alerts when a backend fails `upstream_failures` requests in a row, and
+    // again once it answers. Requests keep going to it meanwhile; there's no
+    // circuit breaker.
+    pub fn upstream(&self, backend: &str, result: Result<(), &hyper::Error>) {
+        let message = {
+            let mut state = self.state.lock().unwrap();
+            let (failures, alerted) = state.upstream.entry(backend.to_string()).or_default();
+            match result {
+                Ok(()) => {
+                    let recovered = *alerted;
+                    (*failures, *alerted) = (0, false);
+                    if !recovered {
+                        return;
+                    }
+                    format!("Backend {} is answering again", backend)
+                }
+                Err(e) => {
+                    *failures += 1;
+                    if *alerted || *failures < self.settings.upstream_failures {
+                        return;
+                    }
+                    *alerted = true;
+                    format!(
+                        "Backend {} failed {} requests in a row: {}",
+                        backend, failures, e
+                    )
+                }
+            }
+        };
+        self.send(message);
+    }
+
+    fn send(&self, text: String) {
+        tracing::warn!("Alert: {}", text);
+        let text = format!("Mock server: {}", text);
+        self.webhook.post(&serde_json::json!({ "text": text }));
+    }
+}
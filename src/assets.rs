@@ -0,0 +1,50 @@
+use axum::{
+    body::Body,
+    http::{header, Request, Response, StatusCode},
+};
+use std::io;
+
+// Files from ./static compiled into the binary, so the admin page works
+// without the static directory or internet access: (path, content type, bytes)
+const EMBEDDED: &[(&str, &str, &[u8])] = &[
+    (
+        "/admin.js",
+        "application/javascript",
+        include_bytes!("../static/admin.js"),
+    ),
+    (
+        "/admin.css",
+        "text/css",
+        include_bytes!("../static/admin.css"),
+    ),
+    (
+        "/favicon.svg",
+        "image/svg+xml",
+        include_bytes!("../static/favicon.svg"),
+    ),
+    (
+        "/favicon.ico",
+        "image/x-icon",
+        include_bytes!("../static/favicon.ico"),
+    ),
+];
+
+// Fallback for `/static` when a file isn't on disk. Paths are relative to
+// the `/static` mount.
+pub async fn serve_embedded(req: Request<Body>) -> io::Result<Response<Body>> {
+    let response = match EMBEDDED
+        .iter()
+        .find(|(path, _, _)| *path == req.uri().path())
+    {
+        Some((_, content_type, bytes)) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, *content_type)
+            .body(Body::from(*bytes))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("Not found"))
+            .unwrap(),
+    };
+    Ok(response)
+}
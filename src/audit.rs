@@ -0,0 +1,264 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    net::IpAddr,
+    sync::Mutex,
+};
+use utoipa::ToSchema;
+
+use crate::{workspace::WorkspaceSettings, Settings};
+
+// One admin change: who made it and which endpoints it touched
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    #[schema(value_type = String)]
+    pub client_ip: IpAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    pub action: String,
+    pub changes: Vec<WorkspaceChanges>,
+}
+
+// Endpoints are named "METHOD /path", prefixed with the host for virtual
+// host endpoints
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WorkspaceChanges {
+    pub workspace: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ProfileSwitch>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modified: Vec<String>,
+    // What changed in each modified endpoint, e.g. "status: 200 → 500"
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ProfileSwitch {
+    pub from: String,
+    pub to: String,
+}
+
+struct AuditState {
+    next_id: u64,
+    entries: VecDeque<AuditEntry>,
+    // The settings as of the last recorded change, to diff the next one against
+    previous: Settings,
+}
+
+// Bounded log of admin changes; the oldest entries are dropped once
+// `capacity` is reached.
+pub struct AuditLog {
+    capacity: usize,
+    state: Mutex<AuditState>,
+}
+
+impl AuditLog {
+    pub fn new(capacity: usize, settings: &Settings) -> Self {
+        AuditLog {
+            capacity,
+            state: Mutex::new(AuditState {
+                next_id: 1,
+                entries: VecDeque::new(),
+                previous: settings.clone(),
+            }),
+        }
+    }
+
+    // Records a change that resulted in `settings`
+    pub fn record(
+        &self,
+        settings: &Settings,
+        action: &str,
+        client_ip: IpAddr,
+        user: Option<&str>,
+    ) -> AuditEntry {
+        let mut state = self.state.lock().unwrap();
+        let changes = diff_settings(&state.previous, settings);
+        state.previous = settings.clone();
+        let entry = AuditEntry {
+            id: state.next_id,
+            timestamp: Utc::now(),
+            client_ip,
+            user: user.map(str::to_string),
+            action: action.to_string(),
+            changes,
+        };
+        state.next_id += 1;
+        tracing::info!(
+            "Audit: {} by {} from {}",
+            entry.action,
+            entry.user.as_deref().unwrap_or("anonymous"),
+            entry.client_ip
+        );
+        if self.capacity == 0 {
+            return entry;
+        }
+        while state.entries.len() >= self.capacity {
+            state.entries.pop_front();
+        }
+        state.entries.push_back(entry.clone());
+        entry
+    }
+
+    // Diffs the next change against `settings` without logging an entry, for
+    // changes that didn't come through this instance's admin API
+    pub fn rebase(&self, settings: &Settings) {
+        self.state.lock().unwrap().previous = settings.clone();
+    }
+
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.state.lock().unwrap().entries.iter().cloned().collect()
+    }
+}
+
+fn all_workspaces(settings: &Settings) -> impl Iterator<Item = &WorkspaceSettings> {
+    std::iter::once(&settings.workspace).chain(&settings.workspaces)
+}
+
+fn diff_settings(before: &Settings, after: &Settings) -> Vec<WorkspaceChanges> {
+    let mut changes = Vec::new();
+    for workspace in all_workspaces(after) {
+        let previous = before.workspace(workspace.name());
+        let old = previous.map(endpoint_map).unwrap_or_default();
+        let new = endpoint_map(workspace);
+
+        let mut change = WorkspaceChanges {
+            workspace: workspace.name().to_string(),
+            profile: None,
+            added: Vec::new(),
+            removed: Vec::new(),
+            modified: Vec::new(),
+            fields: BTreeMap::new(),
+        };
+        if let Some(previous) = previous {
+            if previous.active_profile() != workspace.active_profile() {
+                change.profile = Some(ProfileSwitch {
+                    from: previous.active_profile().to_string(),
+                    to: workspace.active_profile().to_string(),
+                });
+            }
+        }
+        for (key, endpoint) in &new {
+            match old.get(key) {
+                None => change.added.push(key.clone()),
+                Some(old_endpoint) if old_endpoint != endpoint => {
+                    change.modified.push(key.clone());
+                    change
+                        .fields
+                        .insert(key.clone(), changed_fields(old_endpoint, endpoint));
+                }
+                Some(_) => {}
+            }
+        }
+        change.removed = old
+            .keys()
+            .filter(|key| !new.contains_key(*key))
+            .cloned()
+            .collect();
+
+        let unchanged = change.profile.is_none()
+            && change.added.is_empty()
+            && change.removed.is_empty()
+            && change.modified.is_empty();
+        if !unchanged {
+            changes.push(change);
+        }
+    }
+    changes
+}
+
+// What differs between two versions of an endpoint. Objects and arrays, such
+// as the payload, are only named.
+fn changed_fields(old: &serde_json::Value, new: &serde_json::Value) -> Vec<String> {
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return Vec::new();
+    };
+    let scalar = |value: &serde_json::Value| !value.is_object() && !value.is_array();
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter_map(|key| {
+            let change = match (old.get(key), new.get(key)) {
+                (before, after) if before == after => return None,
+                (Some(before), Some(after)) if scalar(before) && scalar(after) => {
+                    format!("{}: {} → {}", key, before, after)
+                }
+                (None, Some(after)) if scalar(after) => format!("{}: set to {}", key, after),
+                (Some(_), None) => format!("{}: removed", key),
+                _ => key.clone(),
+            };
+            Some(change)
+        })
+        .collect()
+}
+
+impl AuditEntry {
+    // One line per change, e.g. "alice: Updated default profile" followed by
+    // "changed GET /orders/{id} (status: 200 → 500)"
+    pub fn summary(&self) -> String {
+        let who = match &self.user {
+            Some(user) => user.clone(),
+            None => format!("anonymous ({})", self.client_ip),
+        };
+        let mut lines = vec![format!("{}: {}", who, self.action)];
+        for change in &self.changes {
+            let workspace = &change.workspace;
+            if let Some(profile) = &change.profile {
+                lines.push(format!(
+                    "{}: switched profile {} → {}",
+                    workspace, profile.from, profile.to
+                ));
+            }
+            for endpoint in &change.added {
+                lines.push(format!("{}: added {}", workspace, endpoint));
+            }
+            for endpoint in &change.removed {
+                lines.push(format!("{}: removed {}", workspace, endpoint));
+            }
+            for endpoint in &change.modified {
+                match change
+                    .fields
+                    .get(endpoint)
+                    .filter(|fields| !fields.is_empty())
+                {
+                    Some(fields) => lines.push(format!(
+                        "{}: changed {} ({})",
+                        workspace,
+                        endpoint,
+                        fields.join(", ")
+                    )),
+                    None => lines.push(format!("{}: changed {}", workspace, endpoint)),
+                }
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+// The endpoints a workspace serves, keyed by name, as JSON for comparison
+fn endpoint_map(workspace: &WorkspaceSettings) -> BTreeMap<String, serde_json::Value> {
+    let mut endpoints = BTreeMap::new();
+    for endpoint in workspace.active_endpoints() {
+        endpoints.insert(
+            format!("{} {}", endpoint.method, endpoint.path),
+            serde_json::to_value(endpoint).unwrap(),
+        );
+    }
+    for vhost in &workspace.virtual_hosts {
+        let host = vhost.hosts.first().map(String::as_str).unwrap_or("");
+        for endpoint in &vhost.endpoints {
+            endpoints.insert(
+                format!("{} {}{}", endpoint.method, host, endpoint.path),
+                serde_json::to_value(endpoint).unwrap(),
+            );
+        }
+    }
+    endpoints
+}
@@ -0,0 +1,345 @@
+use hyper::{body::Bytes, header, Body, Method, Request, Uri};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::task::JoinSet;
+
+use crate::{
+    include,
+    upstream::{self, HttpsClient, UpstreamSettings},
+    Settings, DEFAULT_PORT,
+};
+
+const USAGE: &str = "usage: mock-api bench [--target <base url>] [--duration <seconds>] \
+                     [--requests <n>] [--concurrency <n>] [--path <path>]... \
+                     [--upload <megabytes>] [--insecure]";
+
+// Upload bodies are sent in pieces of this size
+static UPLOAD_CHUNK: [u8; 64 * 1024] = [0; 64 * 1024];
+
+// One request sent over and over
+struct Target {
+    method: Method,
+    path: String,
+}
+
+// What one request took and the status it got
+struct Sample {
+    target: usize,
+    latency: Duration,
+    outcome: Result<u16, String>,
+}
+
+// Sends synthetic traffic at the endpoints in settings.json (and any
+// `--path`, e.g. to measure proxied requests) and reports throughput and
+// latency percentiles. With `--upload`, POSTs bodies of that size to the
+// `--path`s instead, to measure uploads passed through to the backend.
+// Returns the process exit code.
+pub async fn run(args: &[String]) -> i32 {
+    let mut target = None;
+    let mut duration = Duration::from_secs(10);
+    let mut max_requests = None;
+    let mut concurrency = 16;
+    let mut paths = Vec::new();
+    let mut insecure = false;
+    let mut upload = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target" => match args.next() {
+                Some(url) => target = Some(url.trim_end_matches('/').to_string()),
+                None => return usage(),
+            },
+            "--duration" => match args.next().and_then(|v| v.parse::<f64>().ok()) {
+                Some(secs) if secs > 0.0 => duration = Duration::from_secs_f64(secs),
+                _ => return usage(),
+            },
+            "--requests" => match args.next().and_then(|v| v.parse().ok()) {
+                Some(n) => max_requests = Some(n),
+                None => return usage(),
+            },
+            "--concurrency" => match args.next().and_then(|v| v.parse::<usize>().ok()) {
+                Some(n) => concurrency = n.max(1),
+                None => return usage(),
+            },
+            "--path" => match args.next() {
+                Some(path) if path.starts_with('/') => paths.push(path.clone()),
+                _ => return usage(),
+            },
+            "--upload" => match args.next().and_then(|v| v.parse::<f64>().ok()) {
+                Some(mb) if mb > 0.0 => upload = Some((mb * 1024.0 * 1024.0) as u64),
+                _ => return usage(),
+            },
+            "--insecure" => insecure = true,
+            _ => return usage(),
+        }
+    }
+
+    let settings: Settings = match include::load(Path::new("settings.json")) {
+        Ok((settings, _)) => settings,
+        Err(e) => {
+            eprintln!("Failed to read settings.json: {}", e);
+            return 2;
+        }
+    };
+    let target = target.unwrap_or_else(|| default_target(&settings));
+    let targets = match upload {
+        Some(_) if paths.is_empty() => {
+            eprintln!("--upload needs a --path to upload to, e.g. a proxied one");
+            return 2;
+        }
+        Some(_) => paths
+            .iter()
+            .map(|path| Target {
+                method: Method::POST,
+                path: path.clone(),
+            })
+            .collect(),
+        None => targets(&settings, &paths),
+    };
+    if targets.is_empty() {
+        eprintln!("Nothing to benchmark: settings.json has no endpoints and no --path was given");
+        return 2;
+    }
+
+    let client = match upstream::build_client(&UpstreamSettings {
+        allow_http: true,
+        insecure_skip_verify: insecure,
+        ..UpstreamSettings::default()
+    }) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to set up the HTTP client: {}", e);
+            return 2;
+        }
+    };
+
+    match max_requests {
+        Some(n) => println!(
+            "Sending {} requests to {} paths at {} over {} connections",
+            n,
+            targets.len(),
+            target,
+            concurrency
+        ),
+        None => println!(
+            "Benchmarking {} paths at {} for {:.0?} over {} connections",
+            targets.len(),
+            target,
+            duration,
+            concurrency
+        ),
+    }
+
+    // Workers take turns through the targets until time or requests run out
+    let targets = Arc::new(targets);
+    let next = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+    let deadline = started + duration;
+    let mut workers = JoinSet::new();
+    for _ in 0..concurrency {
+        let (client, base, targets, next) = (
+            client.clone(),
+            target.clone(),
+            targets.clone(),
+            next.clone(),
+        );
+        workers.spawn(async move {
+            let mut samples = Vec::new();
+            loop {
+                let n = next.fetch_add(1, Ordering::Relaxed);
+                let done = match max_requests {
+                    Some(max) => n >= max,
+                    None => Instant::now() >= deadline,
+                };
+                if done {
+                    return samples;
+                }
+                let index = n % targets.len();
+                let sent = Instant::now();
+                let outcome = send(&client, &base, &targets[index], upload).await;
+                samples.push(Sample {
+                    target: index,
+                    latency: sent.elapsed(),
+                    outcome,
+                });
+            }
+        });
+    }
+    let mut samples = Vec::new();
+    while let Some(joined) = workers.join_next().await {
+        samples.extend(joined.unwrap());
+    }
+    let elapsed = started.elapsed();
+
+    for (index, target) in targets.iter().enumerate() {
+        let name = format!("{} {}", target.method, target.path);
+        report(&name, samples.iter().filter(|s| s.target == index));
+    }
+    report("total", samples.iter());
+    println!(
+        "{} requests in {:.2?} ({:.1}/s)",
+        samples.len(),
+        elapsed,
+        samples.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+
+    let mut statuses = BTreeMap::new();
+    for status in samples.iter().filter_map(|s| s.outcome.as_ref().ok()) {
+        *statuses.entry(status).or_insert(0) += 1;
+    }
+    let statuses: Vec<String> = statuses
+        .iter()
+        .map(|(status, count)| format!("{} x{}", status, count))
+        .collect();
+    println!("Statuses: {}", statuses.join(", "));
+    if let Some(size) = upload {
+        let uploaded = size * samples.iter().filter(|s| s.outcome.is_ok()).count() as u64;
+        let mb = uploaded as f64 / (1024.0 * 1024.0);
+        println!(
+            "Uploaded {:.1} MB ({:.1} MB/s)",
+            mb,
+            mb / elapsed.as_secs_f64().max(f64::EPSILON)
+        );
+    }
+
+    // Only a few distinct errors, so a dead target doesn't flood the output
+    let mut errors: BTreeSet<&str> = BTreeSet::new();
+    for error in samples.iter().filter_map(|s| s.outcome.as_ref().err()) {
+        if errors.len() < 5 && errors.insert(error) {
+            println!("FAILED {}", error);
+        }
+    }
+    if samples.iter().all(|s| s.outcome.is_err()) {
+        1
+    } else {
+        0
+    }
+}
+
+fn usage() -> i32 {
+    eprintln!("{}", USAGE);
+    2
+}
+
+// The server these settings start, on the default workspace's port
+fn default_target(settings: &Settings) -> String {
+    let port = settings.workspace.port.unwrap_or(DEFAULT_PORT);
+    format!("http://127.0.0.1:{}", port)
+}
+
+// Every endpoint of the default workspace, with path variables filled in.
+// Long polls and NDJSON streams are left out as they hold the connection.
+fn targets(settings: &Settings, paths: &[String]) -> Vec<Target> {
+    let prefix: String = [
+        settings.base_path.as_deref(),
+        settings.workspace.path_prefix.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|part| part.trim_matches('/'))
+    .filter(|part| !part.is_empty())
+    .map(|part| format!("/{}", part))
+    .collect();
+    let endpoints = settings
+        .workspace
+        .endpoints
+        .iter()
+        .filter(|ep| ep.long_poll.is_none() && ep.ndjson.is_none())
+        .filter_map(|ep| {
+            Some(Target {
+                method: Method::from_bytes(ep.method.to_ascii_uppercase().as_bytes()).ok()?,
+                path: format!("{}{}", prefix, sample_path(&ep.path)),
+            })
+        });
+    let extra = paths.iter().map(|path| Target {
+        method: Method::GET,
+        path: path.clone(),
+    });
+    endpoints.chain(extra).collect()
+}
+
+// "/users/{id}/files/{*rest}" becomes "/users/1/files/1"
+fn sample_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if segment.starts_with('{') && segment.ends_with('}') {
+                "1"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// `len` zero bytes, generated as they're sent so any size can be uploaded
+fn upload_body(len: u64) -> Body {
+    let chunk = UPLOAD_CHUNK.len() as u64;
+    let chunks = (0..len.div_ceil(chunk)).map(move |i| {
+        let size = (len - i * chunk).min(chunk) as usize;
+        Ok::<_, std::io::Error>(Bytes::from_static(&UPLOAD_CHUNK[..size]))
+    });
+    Body::wrap_stream(futures_util::stream::iter(chunks))
+}
+
+async fn send(
+    client: &HttpsClient,
+    base: &str,
+    target: &Target,
+    upload: Option<u64>,
+) -> Result<u16, String> {
+    let uri: Uri = format!("{}{}", base, target.path)
+        .parse()
+        .map_err(|e| format!("invalid URL: {}", e))?;
+    let builder = Request::builder().method(target.method.clone()).uri(uri);
+    let req = match upload {
+        Some(len) => builder
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(header::CONTENT_LENGTH, len)
+            .body(upload_body(len)),
+        None => builder.body(Body::empty()),
+    }
+    .map_err(|e| e.to_string())?;
+    let response = client.request(req).await.map_err(|e| e.to_string())?;
+    let status = response.status().as_u16();
+    // Read the body so the connection can be reused
+    hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(status)
+}
+
+fn report<'a>(name: &str, samples: impl Iterator<Item = &'a Sample>) {
+    let mut latencies = Vec::new();
+    let mut failed = 0;
+    for sample in samples {
+        match sample.outcome {
+            Ok(_) => latencies.push(sample.latency),
+            Err(_) => failed += 1,
+        }
+    }
+    latencies.sort();
+    let at = |p: f64| -> Duration {
+        match latencies.len() {
+            0 => Duration::ZERO,
+            n => latencies[((p * n as f64).ceil() as usize).clamp(1, n) - 1],
+        }
+    };
+    println!(
+        "{:<40} {:>8} ok {:>6} failed  p50 {:>9.2?}  p95 {:>9.2?}  p99 {:>9.2?}  max {:>9.2?}",
+        name,
+        latencies.len(),
+        failed,
+        at(0.50),
+        at(0.95),
+        at(0.99),
+        at(1.0)
+    );
+}
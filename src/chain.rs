@@ -0,0 +1,82 @@
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, Response, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::ProxiedTo;
+
+// How many chained mock servers a request went through before this one
+const HOPS: &str = "x-mock-hops";
+// Their names, in order
+const CHAIN: &str = "x-mock-chain";
+// Which server produced the response
+const SERVED_BY: &str = "x-mock-served-by";
+
+// Marks requests this server proxies, so that when default_endpoint is
+// another mock server, e.g. a team-shared one behind personal overrides, the
+// layers can tell who answered and loops are cut short
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MockChain {
+    #[serde(default = "default_name")]
+    pub name: String,
+    #[serde(default = "default_max_hops")]
+    pub max_hops: u32,
+}
+
+fn default_name() -> String {
+    "mock-api".to_string()
+}
+
+fn default_max_hops() -> u32 {
+    5
+}
+
+impl MockChain {
+    // Counts this hop before the request is proxied, or answers 508 if the
+    // request has been through too many mock servers already
+    pub fn forward(&self, headers: &mut HeaderMap) -> Option<Response<Body>> {
+        let hops: u32 = headers
+            .get(HOPS)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.trim().parse().ok())
+            .unwrap_or(0);
+        let chain = match headers.get(CHAIN).and_then(|h| h.to_str().ok()) {
+            Some(chain) => format!("{}, {}", chain, self.name),
+            None => self.name.clone(),
+        };
+        if hops >= self.max_hops {
+            tracing::warn!("Not proxying after {} mock servers: {}", hops, chain);
+            return Some(
+                Response::builder()
+                    .status(StatusCode::LOOP_DETECTED)
+                    .header("Content-Type", "application/json")
+                    .header(SERVED_BY, &self.name)
+                    .body(Body::from(
+                        serde_json::json!({
+                            "error": "Too many chained mock servers",
+                            "chain": chain,
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            );
+        }
+        headers.insert(HOPS, HeaderValue::from(hops + 1));
+        if let Ok(chain) = HeaderValue::from_str(&chain) {
+            headers.insert(CHAIN, chain);
+        }
+        None
+    }
+
+    // Names this server on responses it produced itself. Proxied responses
+    // keep the header of the mock server that answered, if any.
+    pub fn mark(&self, response: &mut Response<Body>) {
+        if response.extensions().get::<ProxiedTo>().is_some() {
+            return;
+        }
+        if let Ok(name) = HeaderValue::from_str(&self.name) {
+            response.headers_mut().insert(SERVED_BY, name);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use axum::http::{header, Response, StatusCode};
+use hyper::Body;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::random::Random;
+
+// Trouble injected into traffic proxied to one backend, e.g. a payments
+// service that is slow and sometimes fails, while mocks and other backends
+// behave. Unlike an endpoint's `fault` it applies to real responses.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ChaosPolicy {
+    // Added before the request is forwarded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    // Up to this much more, picked at random each time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jitter_ms: Option<u64>,
+    // Share of requests, from 0 to 1, answered with `error_status` instead
+    // of being forwarded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_rate: Option<f64>,
+    // 502 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_status: Option<u16>,
+    // Only requests whose path starts with one of these; all when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+}
+
+// Backend URL, as in default_endpoint => policy
+pub struct BackendChaos(BTreeMap<String, ChaosPolicy>);
+
+impl BackendChaos {
+    pub fn new(policies: &BTreeMap<String, ChaosPolicy>) -> Self {
+        let policies = policies
+            .iter()
+            .filter_map(|(backend, policy)| {
+                if let Some(rate) = policy.error_rate {
+                    if !(0.0..=1.0).contains(&rate) {
+                        tracing::error!(
+                            "Ignoring backend_chaos for {}: error_rate {} isn't between 0 and 1",
+                            backend,
+                            rate
+                        );
+                        return None;
+                    }
+                }
+                if let Some(status) = policy.error_status {
+                    if !(100..=599).contains(&status) {
+                        tracing::error!(
+                            "Ignoring backend_chaos for {}: invalid error_status {}",
+                            backend,
+                            status
+                        );
+                        return None;
+                    }
+                }
+                Some((backend.trim_end_matches('/').to_string(), policy.clone()))
+            })
+            .collect();
+        BackendChaos(policies)
+    }
+
+    // Waits out the backend's injected latency, then returns the injected
+    // error if this request drew one; None means forward it as usual
+    pub async fn apply(
+        &self,
+        backend: &str,
+        path: &str,
+        random: &Random,
+    ) -> Option<Response<Body>> {
+        let policy = self.0.get(backend.trim_end_matches('/'))?;
+        if !policy.paths.is_empty() && !policy.paths.iter().any(|p| path.starts_with(p.as_str())) {
+            return None;
+        }
+        let (jitter, fails) = random.with(|rng| {
+            let jitter = policy.jitter_ms.map_or(0, |ms| rng.gen_range(0..=ms));
+            let fails = policy.error_rate.is_some_and(|rate| rng.gen_bool(rate));
+            (jitter, fails)
+        });
+        let delay = policy.latency_ms.unwrap_or(0) + jitter;
+        if delay > 0 {
+            tracing::info!("Delaying {} by {}ms for backend_chaos", path, delay);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+        if !fails {
+            return None;
+        }
+        let status = policy
+            .error_status
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .unwrap_or(StatusCode::BAD_GATEWAY);
+        tracing::info!("Failing {} with {} for backend_chaos", path, status);
+        Some(
+            Response::builder()
+                .status(status)
+                .header(header::CONTENT_TYPE, "text/plain")
+                .body(Body::from(format!(
+                    "{} (injected by backend_chaos for {})",
+                    status, backend
+                )))
+                .unwrap(),
+        )
+    }
+}
@@ -0,0 +1,327 @@
+use serde_json::Value;
+use std::path::Path;
+
+use crate::{
+    har::{self, Har},
+    include,
+    recording::RecordingSettings,
+    startup::{self, StartupError},
+    workspace::WorkspaceSettings,
+    EndpointConfig, Settings, DEFAULT_PORT,
+};
+
+pub const USAGE: &str = "\
+usage: mock-api [serve] [--read-only] [--tui] [--config-map=<dir>] [--check-upstream] [--wait-for-upstream[=<seconds>]]
+       mock-api validate [<settings.json>]
+       mock-api record [--target <url>] [--out <file>] [--include <prefix>]... [--exclude <prefix>]... [--mask <jsonpath>]... [serve options]
+       mock-api replay <capture.json> --target <base url> [--concurrency <n>] [--rate <requests per second>] [--insecure]
+       mock-api export [--workspace <name>] [--har] [--out <file>] [--settings <file>]
+       mock-api import <stubs.json | capture.har> [--workspace <name>] [--settings <file>]
+       mock-api bench [--target <base url>] [--duration <seconds>] [--requests <n>] [--concurrency <n>] [--path <path>]... [--upload <megabytes>] [--insecure]";
+
+const SETTINGS_FILE: &str = "settings.json";
+
+fn usage() -> i32 {
+    eprintln!("{}", USAGE);
+    2
+}
+
+// Loads and checks the settings the way the server does at startup, without
+// binding ports or contacting backends. Failures are reported like a failed
+// start, as one JSON line on stderr.
+pub fn validate(args: &[String]) -> i32 {
+    let path = match args {
+        [] => SETTINGS_FILE,
+        [path] if !path.starts_with("--") => path.as_str(),
+        _ => return usage(),
+    };
+    let settings = match include::load(Path::new(path)) {
+        Ok((settings, _)) => settings,
+        Err(e) => StartupError::config(e).exit(),
+    };
+    if let Err(e) = startup::validate(&settings) {
+        e.exit();
+    }
+    let workspaces: Vec<&WorkspaceSettings> = std::iter::once(&settings.workspace)
+        .chain(&settings.workspaces)
+        .collect();
+    let endpoints: usize = workspaces.iter().map(|w| w.active_endpoints().len()).sum();
+    println!(
+        "{} is valid: {} endpoints in {} workspaces",
+        path,
+        endpoints,
+        workspaces.len()
+    );
+    0
+}
+
+// `record`'s own options: the server runs as usual, read-only, and proxied
+// responses are saved as stubs
+pub struct RecordSession {
+    target: Option<String>,
+    recording: RecordingSettings,
+}
+
+impl RecordSession {
+    // Splits off the options for the server, which may follow
+    pub fn from_args(args: &[String]) -> Result<(RecordSession, Vec<String>), StartupError> {
+        let mut target = None;
+        let mut recording = serde_json::json!({ "file": "recorded.json" });
+        let mut serve = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let key = match arg.as_str() {
+                "--target" | "--out" | "--include" | "--exclude" | "--mask" => arg.as_str(),
+                _ => {
+                    serve.push(arg.clone());
+                    continue;
+                }
+            };
+            let Some(value) = args.next() else {
+                return Err(StartupError::usage(format!("{} takes a value", key)));
+            };
+            match key {
+                "--target" => target = Some(value.trim_end_matches('/').to_string()),
+                "--out" => recording["file"] = Value::from(value.as_str()),
+                list => {
+                    let field = &mut recording[list.trim_start_matches('-')];
+                    match field.as_array_mut() {
+                        Some(values) => values.push(Value::from(value.as_str())),
+                        None => *field = serde_json::json!([value]),
+                    }
+                }
+            }
+        }
+        let recording =
+            serde_json::from_value(recording).map_err(|e| StartupError::usage(e.to_string()))?;
+        Ok((RecordSession { target, recording }, serve))
+    }
+
+    // Proxies the default workspace to the target, if one was given, and
+    // records. A recording session stays local, so Redis is left out.
+    pub fn apply(&self, settings: &mut Settings) -> Result<(), StartupError> {
+        if let Some(target) = &self.target {
+            settings.workspace.default_endpoint = Some(target.clone());
+            if target.starts_with("http://") {
+                settings.upstream.allow_http = true;
+            }
+        }
+        if settings.workspace.default_endpoint.is_none() {
+            return Err(StartupError::usage(
+                "record needs --target, or a default_endpoint in settings.json",
+            ));
+        }
+        settings.recording = Some(self.recording.clone());
+        settings.redis = None;
+        tracing::info!(
+            "Recording responses proxied to {} in {}",
+            settings.workspace.default_endpoint.as_deref().unwrap(),
+            self.recording.file
+        );
+        Ok(())
+    }
+}
+
+// Options shared by export and import
+struct Target {
+    settings: String,
+    workspace: Option<String>,
+}
+
+impl Target {
+    fn workspace<'a>(&self, settings: &'a mut Settings) -> Option<&'a mut WorkspaceSettings> {
+        let Some(name) = &self.workspace else {
+            return Some(&mut settings.workspace);
+        };
+        std::iter::once(&mut settings.workspace)
+            .chain(&mut settings.workspaces)
+            .find(|w| w.name() == name)
+    }
+}
+
+// Writes the served endpoints of a workspace as stubs, the format of
+// `endpoints` and of recordings, or with --har as a HAR file
+pub fn export(args: &[String]) -> i32 {
+    let mut target = Target {
+        settings: SETTINGS_FILE.to_string(),
+        workspace: None,
+    };
+    let mut har = false;
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--har" => har = true,
+            "--out" => match args.next() {
+                Some(file) => out = Some(file.clone()),
+                None => return usage(),
+            },
+            "--workspace" => match args.next() {
+                Some(name) => target.workspace = Some(name.clone()),
+                None => return usage(),
+            },
+            "--settings" => match args.next() {
+                Some(file) => target.settings = file.clone(),
+                None => return usage(),
+            },
+            _ => return usage(),
+        }
+    }
+
+    let mut settings = match include::load(Path::new(&target.settings)) {
+        Ok((settings, _)) => settings,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", target.settings, e);
+            return 2;
+        }
+    };
+    let base_path = settings.base_path.clone().unwrap_or_default();
+    let Some(workspace) = target.workspace(&mut settings) else {
+        eprintln!(
+            "Unknown workspace: {}",
+            target.workspace.unwrap_or_default()
+        );
+        return 2;
+    };
+    let endpoints = workspace.active_endpoints();
+    let contents = if har {
+        let base_url = format!(
+            "http://localhost:{}{}{}",
+            workspace.port.unwrap_or(DEFAULT_PORT),
+            prefix(&base_path),
+            prefix(workspace.path_prefix.as_deref().unwrap_or_default())
+        );
+        serde_json::to_string_pretty(&har::from_endpoints(endpoints, &base_url))
+    } else {
+        serde_json::to_string_pretty(endpoints)
+    };
+    let contents = contents.unwrap();
+    match out {
+        Some(file) => {
+            if let Err(e) = std::fs::write(&file, contents) {
+                eprintln!("Failed to write {}: {}", file, e);
+                return 2;
+            }
+            eprintln!(
+                "Exported {} endpoints of workspace {} to {}",
+                endpoints.len(),
+                workspace.name(),
+                file
+            );
+        }
+        None => println!("{}", contents),
+    }
+    0
+}
+
+// "api/" => "/api"; "" stays empty
+fn prefix(path: &str) -> String {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+// Adds the endpoints of a stubs file (a recording or an export) or a HAR
+// file to a workspace in settings.json, replacing any with the same method
+// and path. A running server picks them up once restarted.
+pub fn import(args: &[String]) -> i32 {
+    let mut target = Target {
+        settings: SETTINGS_FILE.to_string(),
+        workspace: None,
+    };
+    let mut input = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--workspace" => match args.next() {
+                Some(name) => target.workspace = Some(name.clone()),
+                None => return usage(),
+            },
+            "--settings" => match args.next() {
+                Some(file) => target.settings = file.clone(),
+                None => return usage(),
+            },
+            path if input.is_none() && !path.starts_with("--") => input = Some(path.to_string()),
+            _ => return usage(),
+        }
+    }
+    let Some(input) = input else {
+        return usage();
+    };
+
+    let imported = match load_endpoints(&input) {
+        Ok(endpoints) => endpoints,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", input, e);
+            return 2;
+        }
+    };
+    let (mut settings, source) = match include::load(Path::new(&target.settings)) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", target.settings, e);
+            return 2;
+        }
+    };
+    let Some(workspace) = target.workspace(&mut settings) else {
+        eprintln!(
+            "Unknown workspace: {}",
+            target.workspace.unwrap_or_default()
+        );
+        return 2;
+    };
+    let name = workspace.name().to_string();
+    let endpoints = workspace.active_endpoints_mut();
+    let before = endpoints.len();
+    endpoints.retain(|old| !imported.iter().any(|new| same_route(old, new)));
+    let replaced = before - endpoints.len();
+    let count = imported.len();
+    endpoints.extend(imported);
+
+    if let Err(e) = startup::validate(&settings) {
+        e.exit();
+    }
+    let contents = source
+        .file_contents(&settings)
+        .and_then(|value| serde_json::to_string_pretty(&value));
+    if let Err(e) = contents.map(|contents| std::fs::write(&target.settings, contents)) {
+        eprintln!("Failed to write {}: {}", target.settings, e);
+        return 2;
+    }
+    println!(
+        "Imported {} endpoints into workspace {} of {} ({} replaced)",
+        count, name, target.settings, replaced
+    );
+    0
+}
+
+fn same_route(a: &EndpointConfig, b: &EndpointConfig) -> bool {
+    a.method.eq_ignore_ascii_case(&b.method) && a.path == b.path
+}
+
+// Stubs, or the HAR entries that got a response; a later endpoint for the
+// same method and path replaces an earlier one
+fn load_endpoints(path: &str) -> Result<Vec<EndpointConfig>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let loaded = match serde_json::from_str::<Vec<EndpointConfig>>(&text) {
+        Ok(stubs) => stubs,
+        Err(_) => match serde_json::from_str::<Har>(&text) {
+            Ok(har) => har
+                .log
+                .entries
+                .iter()
+                .filter_map(|entry| entry.endpoint().transpose())
+                .collect::<Result<_, _>>()?,
+            Err(e) => return Err(format!("neither stubs nor a HAR file: {}", e)),
+        },
+    };
+    let mut endpoints: Vec<EndpointConfig> = Vec::new();
+    for endpoint in loaded {
+        endpoints.retain(|earlier| !same_route(earlier, &endpoint));
+        endpoints.push(endpoint);
+    }
+    Ok(endpoints)
+}
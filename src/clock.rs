@@ -0,0 +1,224 @@
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::AppState;
+
+// The fastest a clock may run: a day passes in under a tenth of a second
+const MAX_RATE: f64 = 1_000_000.0;
+
+// The time mocks see: `{{now}}` templates, endpoint and condition schedules
+// and mocked login expiry. It is the real time until a test freezes, moves
+// or speeds it up, so "expires in an hour" can be checked without waiting
+// an hour.
+#[derive(Default)]
+pub struct Clock {
+    setting: Mutex<Option<Setting>>, // None runs on real time
+}
+
+#[derive(Clone, Copy)]
+struct Setting {
+    start: DateTime<Utc>, // Virtual time when it was set
+    since: DateTime<Utc>, // Real time when it was set
+    rate: f64,            // Virtual seconds per real second; 0 is frozen
+}
+
+impl Setting {
+    // Stops at the end of the representable range rather than overflowing
+    fn at(&self, real: DateTime<Utc>) -> DateTime<Utc> {
+        let elapsed = (real - self.since).num_microseconds().unwrap_or(i64::MAX);
+        let moved = Duration::microseconds((elapsed as f64 * self.rate) as i64);
+        self.start
+            .checked_add_signed(moved)
+            .unwrap_or(if moved < Duration::zero() {
+                DateTime::<Utc>::MIN_UTC
+            } else {
+                DateTime::<Utc>::MAX_UTC
+            })
+    }
+}
+
+impl Clock {
+    pub fn now(&self) -> DateTime<Utc> {
+        let real = Utc::now();
+        let setting = *self.setting.lock().unwrap();
+        match setting {
+            Some(setting) => setting.at(real),
+            None => real,
+        }
+    }
+
+    fn set(&self, update: &ClockUpdate) -> Result<(), String> {
+        let advance = match &update.advance {
+            Some(advance) => parse_duration(advance)?,
+            None => Duration::zero(),
+        };
+        if let Some(rate) = update.rate {
+            if !(0.0..=MAX_RATE).contains(&rate) {
+                return Err(format!(
+                    "rate must be between 0 and {}, not {}",
+                    MAX_RATE, rate
+                ));
+            }
+        }
+        let real = Utc::now();
+        let setting = *self.setting.lock().unwrap();
+        let current = setting.map(|s| (s.at(real), s.rate));
+        let (now, rate) = current.unwrap_or((real, 1.0));
+        let rate = match (update.frozen, update.rate) {
+            (Some(true), _) => 0.0,
+            (_, Some(rate)) => rate,
+            (Some(false), None) if rate == 0.0 => 1.0,
+            _ => rate,
+        };
+        let start = update
+            .time
+            .unwrap_or(now)
+            .checked_add_signed(advance)
+            .ok_or("the time is out of range")?;
+        *self.setting.lock().unwrap() = Some(Setting {
+            start,
+            since: real,
+            rate,
+        });
+        Ok(())
+    }
+
+    fn reset(&self) {
+        *self.setting.lock().unwrap() = None;
+    }
+
+    fn status(&self) -> ClockStatus {
+        let real = Utc::now();
+        let setting = *self.setting.lock().unwrap();
+        let now = setting.map_or(real, |s| s.at(real));
+        ClockStatus {
+            now,
+            real_time: setting.is_none(),
+            rate: setting.map_or(1.0, |s| s.rate),
+            offset_ms: (now - real).num_milliseconds(),
+        }
+    }
+}
+
+// A duration such as "90s", "1h30m" or "-2d". Units are ms, s, m, h, d
+// and w.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "invalid duration {:?} (e.g. \"90s\", \"1h30m\", \"-2d\")",
+            text
+        )
+    };
+    let trimmed = text.trim();
+    let (negative, mut rest) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    let mut total = Duration::zero();
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let amount: i64 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+        let units = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let part = match &rest[..units] {
+            "ms" => Duration::try_milliseconds(amount),
+            "s" => Duration::try_seconds(amount),
+            "m" => Duration::try_minutes(amount),
+            "h" => Duration::try_hours(amount),
+            "d" => Duration::try_days(amount),
+            "w" => Duration::try_weeks(amount),
+            _ => None,
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .ok_or_else(invalid)?;
+        rest = &rest[units..];
+    }
+    Ok(if negative { -total } else { total })
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ClockStatus {
+    now: DateTime<Utc>,
+    // False once the clock has been set, until it is reset
+    real_time: bool,
+    rate: f64,
+    // How far the clock is ahead of the real time (behind when negative)
+    offset_ms: i64,
+}
+
+// Changes are made from the clock's current time unless `time` is given
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ClockUpdate {
+    // Jump to this time
+    #[serde(default)]
+    time: Option<DateTime<Utc>>,
+    // Then move by this much, e.g. "1h" or "-30m"
+    #[serde(default)]
+    advance: Option<String>,
+    // Stop the clock, or start it again at the real speed
+    #[serde(default)]
+    frozen: Option<bool>,
+    // Run this many times faster than the real time, up to a million
+    #[serde(default)]
+    rate: Option<f64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/clock",
+    tag = "clock",
+    responses((status = 200, description = "The time mocks see", body = ClockStatus))
+)]
+pub async fn get_clock(State(state): State<AppState>) -> Json<ClockStatus> {
+    Json(state.clock.status())
+}
+
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/clock",
+    tag = "clock",
+    request_body = ClockUpdate,
+    responses(
+        (status = 200, description = "The clock was set", body = ClockStatus),
+        (status = 400, description = "Invalid duration or rate")
+    )
+)]
+pub async fn set_clock(
+    State(state): State<AppState>,
+    Json(update): Json<ClockUpdate>,
+) -> Result<Json<ClockStatus>, (StatusCode, String)> {
+    state
+        .clock
+        .set(&update)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let status = state.clock.status();
+    tracing::info!(
+        "Mock clock set to {} (rate {})",
+        status.now.to_rfc3339_opts(SecondsFormat::Millis, true),
+        status.rate
+    );
+    Ok(Json(status))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/mockserver/admin/clock",
+    tag = "clock",
+    responses((status = 200, description = "The clock follows the real time again", body = ClockStatus))
+)]
+pub async fn reset_clock(State(state): State<AppState>) -> Json<ClockStatus> {
+    state.clock.reset();
+    tracing::info!("Mock clock reset to the real time");
+    Json(state.clock.status())
+}
@@ -0,0 +1,183 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Json, State},
+    http::{header, HeaderMap, Request, Response, StatusCode},
+};
+use hyper::client::HttpConnector;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+use crate::{
+    apply_settings, invalid_settings_response, route_conflicts_response, save_settings,
+    secrets_refused, settings_route_conflicts,
+    startup::{self, StartupError},
+    AppState, Settings,
+};
+
+const SECRET_HEADER: &str = "X-Cluster-Secret";
+pub const SYNC_PATH: &str = "/mockserver/admin/cluster/sync";
+
+// Instances that receive a copy of every admin change made on this one
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ClusterSettings {
+    // Base URLs such as "http://10.0.0.5:8000"
+    pub peers: Vec<String>,
+    // Shared secret peers must present when pushing settings here; without
+    // one, this instance doesn't accept pushed settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+pub struct Cluster {
+    settings: ClusterSettings,
+    client: hyper::Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+impl Cluster {
+    pub fn new(settings: &ClusterSettings) -> Self {
+        let connector = HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Cluster {
+            settings: settings.clone(),
+            client: hyper::Client::builder().build(connector),
+        }
+    }
+
+    // Pushes the saved settings to every peer in the background. Peers apply
+    // them without forwarding, so updates don't bounce around the cluster.
+    pub fn broadcast(&self, contents: &str) {
+        for peer in &self.settings.peers {
+            let url = format!("{}{}", peer.trim_end_matches('/'), SYNC_PATH);
+            let mut request = Request::post(&url).header(header::CONTENT_TYPE, "application/json");
+            if let Some(secret) = &self.settings.secret {
+                request = request.header(SECRET_HEADER, secret);
+            }
+            let request = match request.body(Body::from(contents.to_string())) {
+                Ok(request) => request,
+                Err(e) => {
+                    tracing::error!("Invalid cluster peer {}: {}", peer, e);
+                    continue;
+                }
+            };
+            let client = self.client.clone();
+            let peer = peer.clone();
+            tokio::spawn(async move {
+                match client.request(request).await {
+                    Ok(response) if response.status().is_success() => {
+                        tracing::info!("Synced settings to {}", peer)
+                    }
+                    Ok(response) => {
+                        tracing::warn!("Peer {} rejected settings: {}", peer, response.status())
+                    }
+                    Err(e) => tracing::error!("Failed to sync settings to {}: {}", peer, e),
+                }
+            });
+        }
+    }
+}
+
+// The configured secret, if there's one to check
+fn secret(settings: &Settings) -> Option<&str> {
+    settings
+        .cluster
+        .as_ref()
+        .and_then(|c| c.secret.as_deref())
+        .filter(|secret| !secret.is_empty())
+}
+
+// Whether a request carries this instance's cluster secret. Nothing does when
+// no secret is configured.
+pub fn verified(settings: &Settings, headers: &HeaderMap) -> bool {
+    let Some(secret) = secret(settings) else {
+        return false;
+    };
+    headers
+        .get(SECRET_HEADER)
+        .is_some_and(|presented| same_secret(presented.as_bytes(), secret.as_bytes()))
+}
+
+// Looks at every byte, so the time taken doesn't tell how much of a guess
+// was right
+fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Receives settings pushed by a peer, only when a cluster secret is
+// configured and presented. The local admin tokens, secrets, cluster, Redis
+// and git configuration are kept, since those describe this instance, and
+// settings naming secrets are refused.
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/cluster/sync",
+    tag = "cluster",
+    request_body(content = Object, description = "The peer's full settings"),
+    responses(
+        (status = 200, description = "Settings applied"),
+        (status = 400, description = "Settings that wouldn't start"),
+        (status = 401, description = "Missing or wrong X-Cluster-Secret"),
+        (status = 404, description = "No cluster secret is configured"),
+        (status = 422, description = "Conflicting routes")
+    ),
+    security(())
+)]
+pub async fn receive_sync(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(incoming): Json<serde_json::Value>,
+) -> Response<Body> {
+    let mut settings = state.settings.write().await;
+    if secret(&settings).is_none() {
+        tracing::warn!("Rejected cluster sync from {}: no cluster secret", addr);
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("Not found"))
+            .unwrap();
+    }
+    if !verified(&settings, &headers) {
+        tracing::warn!("Rejected cluster sync from {}: bad secret", addr);
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::from("Invalid cluster secret"))
+            .unwrap();
+    }
+
+    if let Some(response) = secrets_refused(&incoming) {
+        return response;
+    }
+    let incoming = match state.source.expand(incoming) {
+        Ok(incoming) => incoming,
+        Err(e) => return invalid_settings_response(StartupError::config(e)),
+    };
+    let conflicts = settings_route_conflicts(&incoming, state.path_matching);
+    if !conflicts.is_empty() {
+        return route_conflicts_response(conflicts);
+    }
+    let incoming = incoming.with_local(&settings);
+    if let Err(e) = startup::validate(&incoming) {
+        return invalid_settings_response(e);
+    }
+
+    let description = format!("Synced from peer {}", addr.ip());
+    *settings = incoming;
+    apply_settings(&state, &settings, &description).await;
+
+    if let Err(e) = save_settings(&state, &settings, &description, None).await {
+        tracing::error!("Failed to write settings to file: {}", e);
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from("Failed to write settings to file"))
+            .unwrap();
+    }
+
+    state.audit.record(&settings, &description, addr.ip(), None);
+    tracing::info!("{}", description);
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from("Settings synced"))
+        .unwrap()
+}
@@ -0,0 +1,102 @@
+use serde_json::{Map, Value};
+
+// Other encodings of JSON documents: binary ones for IoT-style clients, and
+// HTML form posts
+#[derive(Debug, Clone, Copy)]
+pub enum Codec {
+    MessagePack,
+    Cbor,
+    Form,
+}
+
+impl Codec {
+    pub fn for_content_type(content_type: &str) -> Option<Codec> {
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match essence.as_str() {
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Codec::MessagePack)
+            }
+            "application/cbor" => Some(Codec::Cbor),
+            "application/x-www-form-urlencoded" => Some(Codec::Form),
+            _ => None,
+        }
+    }
+
+    pub fn encode(self, json: &str) -> Result<Vec<u8>, String> {
+        let value: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        match self {
+            // Named, so objects stay maps rather than arrays
+            Codec::MessagePack => rmp_serde::to_vec_named(&value).map_err(|e| e.to_string()),
+            Codec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(&value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(bytes)
+            }
+            Codec::Form => encode_form(value),
+        }
+    }
+
+    // The body as JSON, so conditions and templates can read it
+    pub fn decode(self, body: &[u8]) -> Result<Vec<u8>, String> {
+        let value: Value = match self {
+            Codec::MessagePack => rmp_serde::from_slice(body).map_err(|e| e.to_string())?,
+            Codec::Cbor => ciborium::from_reader(body).map_err(|e| e.to_string())?,
+            Codec::Form => decode_form(body),
+        };
+        Ok(serde_json::to_vec(&value).unwrap())
+    }
+}
+
+// An object's fields as form fields, a list giving the field once per item.
+// A string payload is taken as already encoded.
+fn encode_form(value: Value) -> Result<Vec<u8>, String> {
+    let fields = match value {
+        Value::Object(fields) => fields,
+        Value::String(encoded) => return Ok(encoded.into_bytes()),
+        _ => return Err("a form payload must be an object".to_string()),
+    };
+    let mut form = form_urlencoded::Serializer::new(String::new());
+    for (name, value) in &fields {
+        let values = match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::String(text) => form.append_pair(name, text),
+                Value::Null => form.append_pair(name, ""),
+                other => form.append_pair(name, &other.to_string()),
+            };
+        }
+    }
+    Ok(form.finish().into_bytes())
+}
+
+// Fields as strings; a repeated field, or one named like `tags[]`, becomes a
+// list
+fn decode_form(body: &[u8]) -> Value {
+    let mut fields = Map::new();
+    for (name, value) in form_urlencoded::parse(body) {
+        let (name, list) = match name.strip_suffix("[]") {
+            Some(name) => (name.to_string(), true),
+            None => (name.into_owned(), false),
+        };
+        let value = Value::String(value.into_owned());
+        match fields.get_mut(&name) {
+            Some(Value::Array(items)) => items.push(value),
+            Some(first) => *first = Value::Array(vec![first.take(), value]),
+            None if list => {
+                fields.insert(name, Value::Array(vec![value]));
+            }
+            None => {
+                fields.insert(name, value);
+            }
+        }
+    }
+    Value::Object(fields)
+}
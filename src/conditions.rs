@@ -0,0 +1,262 @@
+use axum::http::request::Parts;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::schedule::{self, Schedule};
+
+// An alternative response for requests that pass a test. Set one of `body`,
+// `param`, `query` or `header` to pick the value tested, and any of
+// `equals`, `regex`, `gt` and `lt`, all of which must hold. With `active`
+// the condition only applies during those schedules, and with `state` only
+// while the endpoint's scenario is in that state.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, ToSchema)]
+pub struct Condition {
+    // A JSONPath into the request body, e.g. `$.order.items[0].sku`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    // A path variable, e.g. `id` for `/users/{id}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub param: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub equals: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub active: Vec<Schedule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+
+    // The response when the test holds; unset fields come from the endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub payload: Option<serde_json::Value>,
+}
+
+// The parts of a request conditions can test
+pub struct RequestValues<'a> {
+    pub parts: &'a Parts,
+    pub params: &'a [(&'a str, &'a str)],
+    pub body: &'a [u8],
+    pub now: DateTime<Utc>,
+    // The state of the endpoint's scenario, if it has one
+    pub state: Option<&'a str>,
+}
+
+// One test a condition made, for /mockserver/admin/explain
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Check {
+    pub test: String, // e.g. "header X-Tier" or "equals \"gold\""
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Condition {
+    pub fn matches(&self, request: &RequestValues) -> bool {
+        self.evaluate(request, None)
+    }
+
+    // The tests behind `matches`, up to the first that failed
+    pub fn checks(&self, request: &RequestValues) -> Vec<Check> {
+        let mut checks = Vec::new();
+        self.evaluate(request, Some(&mut checks));
+        checks
+    }
+
+    // Descriptions of the tests are only built when they are reported
+    fn evaluate(&self, request: &RequestValues, mut report: Option<&mut Vec<Check>>) -> bool {
+        let mut record = |passed: bool, describe: &dyn Fn() -> (String, Option<String>)| {
+            if let Some(report) = report.as_deref_mut() {
+                let (test, detail) = describe();
+                report.push(Check {
+                    test,
+                    passed,
+                    detail,
+                });
+            }
+            passed
+        };
+        if !self.active.is_empty() {
+            let active = schedule::any_active(&self.active, request.now);
+            if !record(active, &|| ("active".to_string(), None)) {
+                return false;
+            }
+        }
+        if let Some(expected) = &self.state {
+            let passed = request.state == Some(expected.as_str());
+            if !record(passed, &|| {
+                let detail = request.state.unwrap_or("no scenario");
+                (format!("state {}", expected), Some(detail.to_string()))
+            }) {
+                return false;
+            }
+        }
+        let tests_value = self.body.is_some()
+            || self.param.is_some()
+            || self.query.is_some()
+            || self.header.is_some();
+        if !tests_value {
+            // Only a schedule or state, e.g. a maintenance window
+            let compares = self.equals.is_some()
+                || self.regex.is_some()
+                || self.gt.is_some()
+                || self.lt.is_some();
+            return !compares
+                || record(false, &|| {
+                    let detail = "equals, regex, gt and lt need body, param, query or header";
+                    ("value".to_string(), Some(detail.to_string()))
+                });
+        }
+        let Some(value) = self.value(request) else {
+            return record(false, &|| {
+                (self.tested(), Some("not in the request".to_string()))
+            });
+        };
+        record(true, &|| (self.tested(), Some(value.to_string())));
+        if let Some(expected) = &self.equals {
+            if !record(equals(&value, expected), &|| {
+                (format!("equals {}", expected), None)
+            }) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.regex {
+            let describe = || (format!("regex {}", pattern), None);
+            match Regex::new(pattern) {
+                Ok(regex) if regex.is_match(&as_text(&value)) => {
+                    record(true, &describe);
+                }
+                Ok(_) => return record(false, &describe),
+                Err(e) => {
+                    tracing::warn!("Invalid condition regex {}: {}", pattern, e);
+                    return record(false, &|| {
+                        (format!("regex {}", pattern), Some(e.to_string()))
+                    });
+                }
+            }
+        }
+        if self.gt.is_some() || self.lt.is_some() {
+            let Some(number) = as_number(&value) else {
+                return record(false, &|| {
+                    ("gt/lt".to_string(), Some("not a number".to_string()))
+                });
+            };
+            if let Some(gt) = self.gt {
+                if !record(number > gt, &|| (format!("gt {}", gt), None)) {
+                    return false;
+                }
+            }
+            if let Some(lt) = self.lt {
+                if !record(number < lt, &|| (format!("lt {}", lt), None)) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // What the condition tests, e.g. "body $.tier"
+    fn tested(&self) -> String {
+        let (kind, name) = match (&self.body, &self.param, &self.query, &self.header) {
+            (Some(path), ..) => ("body", path),
+            (_, Some(name), ..) => ("param", name),
+            (_, _, Some(name), _) => ("query", name),
+            (.., Some(name)) => ("header", name),
+            _ => return "value".to_string(),
+        };
+        format!("{} {}", kind, name)
+    }
+
+    fn value(&self, request: &RequestValues) -> Option<serde_json::Value> {
+        let text = |s: &str| Some(serde_json::Value::String(s.to_string()));
+        if let Some(path) = &self.body {
+            let body: serde_json::Value = serde_json::from_slice(request.body).ok()?;
+            return json_path(&body, path).cloned();
+        }
+        if let Some(name) = &self.param {
+            let (_, value) = request.params.iter().find(|(key, _)| key == name)?;
+            return text(value);
+        }
+        if let Some(name) = &self.query {
+            let query = request.parts.uri.query()?;
+            let (_, value) = query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| key == name)?;
+            return text(value);
+        }
+        if let Some(name) = &self.header {
+            return text(request.parts.headers.get(name.as_str())?.to_str().ok()?);
+        }
+        None
+    }
+}
+
+// Path variables, query values and headers are strings, so `"equals": 5`
+// also matches "5"
+fn equals(value: &serde_json::Value, expected: &serde_json::Value) -> bool {
+    match (value, expected) {
+        (serde_json::Value::String(s), serde_json::Value::String(_)) => {
+            Some(s.as_str()) == expected.as_str()
+        }
+        (serde_json::Value::String(s), _) => {
+            serde_json::from_str::<serde_json::Value>(s).ok().as_ref() == Some(expected)
+        }
+        _ => value == expected,
+    }
+}
+
+fn as_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn as_number(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+// A small JSONPath subset: `$`, `.name`, `['name']` and `[index]`
+fn json_path<'a>(root: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    let mut rest = path.trim().strip_prefix('$')?;
+    let mut current = root;
+    while !rest.is_empty() {
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+            current = current.get(&after_dot[..end])?;
+            rest = &after_dot[end..];
+        } else if let Some(after_bracket) = rest.strip_prefix('[') {
+            let end = after_bracket.find(']')?;
+            let key = &after_bracket[..end];
+            current = match key.trim_matches(|c| c == '\'' || c == '"') {
+                quoted if quoted.len() != key.len() => current.get(quoted)?,
+                index => current.get(index.parse::<usize>().ok()?)?,
+            };
+            rest = &after_bracket[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(current)
+}
@@ -0,0 +1,102 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{apply_settings, include, startup, startup::StartupError, AppState};
+
+// How often the mounted directory is checked for a new version
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// The directory named by `--config-map=<dir>`, e.g. a mounted Kubernetes
+// ConfigMap holding settings.json and any files it includes
+pub fn dir_from_args(args: &[String]) -> Result<Option<PathBuf>, StartupError> {
+    for arg in args {
+        if arg == "--config-map" || arg == "--config-map=" {
+            return Err(StartupError::usage(
+                "--config-map takes a directory, e.g. --config-map=/etc/mock-api",
+            ));
+        }
+        if let Some(dir) = arg.strip_prefix("--config-map=") {
+            return Ok(Some(PathBuf::from(dir)));
+        }
+    }
+    Ok(None)
+}
+
+// Reloads the settings whenever a new version of the directory appears.
+// Settings that fail to load or validate are logged and the current ones
+// kept.
+pub async fn watch(state: AppState, dir: PathBuf) {
+    let path = dir.join("settings.json");
+    let mut current = version(&dir).ok();
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let next = match version(&dir) {
+            Ok(next) => next,
+            Err(e) => {
+                tracing::warn!("Failed to check {} for changes: {}", dir.display(), e);
+                continue;
+            }
+        };
+        if current.as_ref() == Some(&next) {
+            continue;
+        }
+        current = Some(next);
+        reload(&state, &path).await;
+    }
+}
+
+async fn reload(state: &AppState, path: &Path) {
+    let settings = match include::load(path) {
+        Ok((settings, _)) => settings,
+        Err(e) => {
+            tracing::error!("Keeping the current settings: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = startup::validate(&settings) {
+        tracing::error!("Keeping the current settings: {}", e.message);
+        return;
+    }
+    let (_, pending_restart) = apply_settings(state, &settings, "Reloaded from ConfigMap").await;
+    if !pending_restart.is_empty() {
+        tracing::warn!(
+            "Workspaces need a restart to be fully served: {}",
+            pending_restart.join(", ")
+        );
+    }
+    state.audit.rebase(&settings);
+    *state.settings.write().await = settings;
+    tracing::info!("Reloaded settings from {}", path.display());
+}
+
+// What identifies the mounted version. The kubelet writes an update to a new
+// timestamped directory and then swaps the `..data` symlink over to it, so
+// the link's target changes exactly once per update and files are never seen
+// half written. Other directories fall back to each file's size and
+// modification time.
+fn version(dir: &Path) -> io::Result<String> {
+    if let Ok(target) = std::fs::read_link(dir.join("..data")) {
+        return Ok(target.display().to_string());
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        // Follows symlinks, so a retargeted link counts as a change
+        let metadata = std::fs::metadata(entry.path())?;
+        files.push(format!(
+            "{} {} {:?}",
+            name,
+            metadata.len(),
+            metadata.modified()?
+        ));
+    }
+    files.sort();
+    Ok(files.join("\n"))
+}
@@ -0,0 +1,366 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::{body::HttpBody, header, Body, Method, Request, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use crate::{
+    upstream::{self, HttpsClient, UpstreamSettings},
+    workspace::{Workspaces, DEFAULT_WORKSPACE},
+    EndpointConfig,
+};
+
+// How long to wait before trying again after the store couldn't be read
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+// How long a Consul blocking query waits for a change
+const CONSUL_WAIT: &str = "60s";
+const READ_TIMEOUT: Duration = Duration::from_secs(90);
+
+// A key-value store holding centrally managed endpoints, e.g.
+// `{ "consul": { "url": "http://consul:8500", "prefix": "mocks/" } }`. Each
+// key under the prefix holds an endpoint or a list of them; keys one level
+// further down (`mocks/payments/refunds`) belong to the workspace named by
+// that level.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigSource {
+    Consul(ConsulSource),
+    // Read through etcd's v3 JSON gateway
+    Etcd(EtcdSource),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ConsulSource {
+    pub url: String,
+    pub prefix: String,
+    // ACL token, sent as X-Consul-Token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EtcdSource {
+    pub url: String,
+    pub prefix: String,
+}
+
+impl ConfigSource {
+    pub fn url(&self) -> &str {
+        match self {
+            ConfigSource::Consul(consul) => &consul.url,
+            ConfigSource::Etcd(etcd) => &etcd.url,
+        }
+    }
+
+    fn prefix(&self) -> &str {
+        match self {
+            ConfigSource::Consul(consul) => &consul.prefix,
+            ConfigSource::Etcd(etcd) => &etcd.prefix,
+        }
+    }
+}
+
+// Reads the endpoints under the prefix and serves them under each
+// workspace's own until the server stops, following every change. While the
+// store can't be reached the last endpoints read stay in place.
+pub async fn watch(source: ConfigSource, workspaces: Arc<Workspaces>) {
+    let client = match upstream::build_client(&UpstreamSettings {
+        allow_http: true,
+        ..UpstreamSettings::default()
+    }) {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Not reading endpoints from {}: {}", source.url(), e);
+            return;
+        }
+    };
+    let mut shared = Shared {
+        source: &source,
+        workspaces: &workspaces,
+        last: None,
+    };
+    match &source {
+        ConfigSource::Consul(consul) => watch_consul(consul, &client, &mut shared).await,
+        ConfigSource::Etcd(etcd) => watch_etcd(etcd, &client, &mut shared).await,
+    }
+}
+
+// Follows the prefix with blocking queries, which return as soon as
+// something under it changes
+async fn watch_consul(consul: &ConsulSource, client: &HttpsClient, shared: &mut Shared<'_>) {
+    let url = format!(
+        "{}/v1/kv/{}?recurse=true",
+        consul.url.trim_end_matches('/'),
+        consul.prefix
+    );
+    let mut index: Option<u64> = None;
+    loop {
+        let uri = match index {
+            Some(index) => format!("{}&index={}&wait={}", url, index, CONSUL_WAIT),
+            None => url.clone(),
+        };
+        match read_consul(client, &uri, consul.token.as_deref()).await {
+            Ok((next, values)) => {
+                shared.update(values);
+                // An index that goes backwards means Consul's state was reset
+                index = match index {
+                    Some(index) if next < index => None,
+                    _ => Some(next),
+                };
+            }
+            Err(e) => {
+                tracing::warn!("Failed to read endpoints from Consul at {}: {}", url, e);
+                index = None;
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulEntry {
+    key: String,
+    value: Option<String>, // Base64; null for folders
+}
+
+// The values under the prefix and the index to wait on for the next change
+async fn read_consul(
+    client: &HttpsClient,
+    uri: &str,
+    token: Option<&str>,
+) -> Result<(u64, BTreeMap<String, String>), String> {
+    let mut req = Request::get(uri);
+    if let Some(token) = token {
+        req = req.header("X-Consul-Token", token);
+    }
+    let req = req.body(Body::empty()).map_err(|e| e.to_string())?;
+    let response = tokio::time::timeout(READ_TIMEOUT, client.request(req))
+        .await
+        .map_err(|_| "timed out".to_string())?
+        .map_err(|e| e.to_string())?;
+    let index = response
+        .headers()
+        .get("X-Consul-Index")
+        .and_then(|index| index.to_str().ok()?.parse().ok())
+        .unwrap_or(0);
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| e.to_string())?;
+    // Nothing has been stored under the prefix yet
+    if status == StatusCode::NOT_FOUND {
+        return Ok((index, BTreeMap::new()));
+    }
+    if !status.is_success() {
+        return Err(format!(
+            "answered {}: {}",
+            status,
+            String::from_utf8_lossy(&body).trim()
+        ));
+    }
+    let entries: Vec<ConsulEntry> = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+    let values = entries
+        .into_iter()
+        .filter_map(|entry| Some((entry.key, decode(entry.value.as_deref()?)?)))
+        .collect();
+    Ok((index, values))
+}
+
+// Reads the prefix, then watches it from the revision read, reading it
+// again after each change
+async fn watch_etcd(etcd: &EtcdSource, client: &HttpsClient, shared: &mut Shared<'_>) {
+    let url = etcd.url.trim_end_matches('/');
+    let range = json!({
+        "key": STANDARD.encode(&etcd.prefix),
+        "range_end": STANDARD.encode(range_end(etcd.prefix.as_bytes())),
+    });
+    loop {
+        let revision = match read_etcd(client, url, &range).await {
+            Ok((revision, values)) => {
+                shared.update(values);
+                revision
+            }
+            Err(e) => {
+                tracing::warn!("Failed to read endpoints from etcd at {}: {}", url, e);
+                tokio::time::sleep(RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let mut watch = range.clone();
+        watch["start_revision"] = json!((revision + 1).to_string());
+        if let Err(e) = wait_etcd(client, url, json!({ "create_request": watch })).await {
+            tracing::warn!("Stopped watching etcd at {}: {}", url, e);
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+}
+
+// The values under the prefix and the store's revision
+async fn read_etcd(
+    client: &HttpsClient,
+    url: &str,
+    range: &Value,
+) -> Result<(u64, BTreeMap<String, String>), String> {
+    let response = tokio::time::timeout(
+        READ_TIMEOUT,
+        client.request(post(&format!("{}/v3/kv/range", url), range)?),
+    )
+    .await
+    .map_err(|_| "timed out".to_string())?
+    .map_err(|e| e.to_string())?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!(
+            "answered {}: {}",
+            status,
+            String::from_utf8_lossy(&body).trim()
+        ));
+    }
+    let body: Value = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+    // The gateway writes 64-bit integers as strings
+    let revision = body["header"]["revision"]
+        .as_str()
+        .and_then(|revision| revision.parse().ok())
+        .ok_or("no revision in the response")?;
+    let values = body["kvs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|kv| {
+            let key = String::from_utf8(STANDARD.decode(kv["key"].as_str()?).ok()?).ok()?;
+            Some((key, decode(kv["value"].as_str()?)?))
+        })
+        .collect();
+    Ok((revision, values))
+}
+
+// Returns once the watch reports a change, or that the revision it started
+// from has been compacted away
+async fn wait_etcd(client: &HttpsClient, url: &str, watch: Value) -> Result<(), String> {
+    let response = client
+        .request(post(&format!("{}/v3/watch", url), &watch)?)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("answered {}", response.status()));
+    }
+    let mut body = response.into_body();
+    let mut buffer = Vec::new();
+    while let Some(chunk) = body.data().await {
+        buffer.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+        // The stream is a sequence of JSON messages
+        let mut messages = serde_json::Deserializer::from_slice(&buffer).into_iter::<Value>();
+        for message in messages.by_ref() {
+            let Ok(message) = message else {
+                break;
+            };
+            if let Some(error) = message.get("error") {
+                return Err(error.to_string());
+            }
+            let result = &message["result"];
+            let changed = result["events"]
+                .as_array()
+                .is_some_and(|events| !events.is_empty());
+            if changed || result["canceled"] == json!(true) {
+                return Ok(());
+            }
+        }
+        let consumed = messages.byte_offset();
+        buffer.drain(..consumed);
+    }
+    Err("the watch ended".to_string())
+}
+
+fn post(uri: &str, body: &Value) -> Result<Request<Body>, String> {
+    Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .map_err(|e| e.to_string())
+}
+
+// The first key after every key starting with `prefix`
+fn range_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    // Everything, for an empty prefix
+    vec![0]
+}
+
+fn decode(value: &str) -> Option<String> {
+    String::from_utf8(STANDARD.decode(value).ok()?).ok()
+}
+
+// Hands what was read to the workspaces, skipping reads that changed nothing
+struct Shared<'a> {
+    source: &'a ConfigSource,
+    workspaces: &'a Workspaces,
+    last: Option<BTreeMap<String, String>>,
+}
+
+impl Shared<'_> {
+    fn update(&mut self, values: BTreeMap<String, String>) {
+        if self.last.as_ref() == Some(&values) {
+            return;
+        }
+        let mut endpoints: BTreeMap<&str, Vec<EndpointConfig>> = BTreeMap::new();
+        let mut count = 0;
+        for (key, value) in &values {
+            let Some(name) = key.strip_prefix(self.source.prefix()) else {
+                continue;
+            };
+            let workspace = match name.split_once('/') {
+                Some((workspace, rest)) if !rest.is_empty() => workspace,
+                // Folder markers
+                Some(_) => continue,
+                None => DEFAULT_WORKSPACE,
+            };
+            if value.trim().is_empty() {
+                continue;
+            }
+            match parse(value) {
+                Ok(parsed) => {
+                    count += parsed.len();
+                    endpoints.entry(workspace).or_default().extend(parsed);
+                }
+                Err(e) => tracing::warn!("Ignoring endpoints in {}: {}", key, e),
+            }
+        }
+        for name in endpoints.keys() {
+            if self.workspaces.get(name).is_none() {
+                tracing::warn!("Ignoring endpoints for unknown workspace {}", name);
+            }
+        }
+        for workspace in self.workspaces.iter() {
+            let shared = endpoints
+                .remove(workspace.name.as_str())
+                .unwrap_or_default();
+            workspace.set_shared_endpoints(shared);
+        }
+        tracing::info!(
+            "Serving {} endpoints from {} under {}",
+            count,
+            self.source.url(),
+            self.source.prefix()
+        );
+        self.last = Some(values);
+    }
+}
+
+// A key holds one endpoint or a list of them
+fn parse(value: &str) -> serde_json::Result<Vec<EndpointConfig>> {
+    match serde_json::from_str(value)? {
+        Value::Array(items) => serde_json::from_value(Value::Array(items)),
+        endpoint => Ok(vec![serde_json::from_value(endpoint)?]),
+    }
+}
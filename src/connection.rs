@@ -0,0 +1,269 @@
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, HeaderValue, Request, Response},
+    Router,
+};
+use futures_util::stream;
+use hyper::{
+    server::{accept, conn::Http},
+    service::make_service_fn,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::Infallible,
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+};
+use tower::{
+    util::{Oneshot, ServiceFn},
+    ServiceExt,
+};
+use utoipa::ToSchema;
+
+use crate::ip_filter::IpFilter;
+
+// Lets a handler send bytes hyper would refuse to produce, such as a broken
+// status line. Handlers find it in the request extensions; whatever response
+// they return is discarded in favour of the raw bytes, and the connection is
+// closed after them.
+#[derive(Clone, Default)]
+pub struct RawResponse(Arc<Mutex<Option<Vec<u8>>>>);
+
+impl RawResponse {
+    pub fn send(&self, bytes: Vec<u8>) {
+        *self.0.lock().unwrap() = Some(bytes);
+    }
+
+    fn take(&self) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+// How many requests a connection has carried and when it was opened, found
+// in each request's extensions
+#[derive(Clone)]
+pub struct ConnectionStats(Arc<Stats>);
+
+struct Stats {
+    requests: AtomicU64,
+    opened: Instant,
+}
+
+impl ConnectionStats {
+    fn new() -> Self {
+        ConnectionStats(Arc::new(Stats {
+            requests: AtomicU64::new(0),
+            opened: Instant::now(),
+        }))
+    }
+
+    // Requests so far, this one included
+    pub fn requests(&self) -> u64 {
+        self.0.requests.load(Ordering::Relaxed)
+    }
+
+    pub fn age(&self) -> Duration {
+        self.0.opened.elapsed()
+    }
+}
+
+// Makes an endpoint end keep-alive connections, like servers that recycle
+// them aggressively. Each limit closes the connection after the response
+// that reaches it.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct KeepAlive {
+    // Close after every response
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub close: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_requests: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+}
+
+impl KeepAlive {
+    pub fn apply(&self, stats: Option<&ConnectionStats>, response: &mut Response<Body>) {
+        let close = self.close
+            || stats.is_some_and(|stats| {
+                self.max_requests.is_some_and(|max| stats.requests() >= max)
+                    || self
+                        .max_age_secs
+                        .is_some_and(|max| stats.age() >= Duration::from_secs(max))
+            });
+        if close {
+            response
+                .headers_mut()
+                .insert(header::CONNECTION, HeaderValue::from_static("close"));
+        }
+    }
+}
+
+// An accepted connection whose writes can be replaced by a `RawResponse`
+pub struct Connection {
+    stream: TcpStream,
+    remote: SocketAddr,
+    raw: RawResponse,
+    stats: ConnectionStats,
+    pending: Option<(Vec<u8>, usize)>, // Raw bytes being written, and how many are done
+    closed: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, remote: SocketAddr) -> Self {
+        Connection {
+            stream,
+            remote,
+            raw: RawResponse::default(),
+            stats: ConnectionStats::new(),
+            pending: None,
+            closed: false,
+        }
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.closed {
+            // End of stream, so hyper drops the connection
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.closed {
+            // Swallow hyper's own response
+            return Poll::Ready(Ok(buf.len()));
+        }
+        if this.pending.is_none() {
+            this.pending = this.raw.take().map(|bytes| (bytes, 0));
+        }
+        let Some((bytes, written)) = &mut this.pending else {
+            return Pin::new(&mut this.stream).poll_write(cx, buf);
+        };
+        while *written < bytes.len() {
+            match Pin::new(&mut this.stream).poll_write(cx, &bytes[*written..]) {
+                Poll::Ready(Ok(n)) => *written += n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        this.pending = None;
+        this.closed = true;
+        let _ = Pin::new(&mut this.stream).poll_shutdown(cx);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.closed {
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.closed {
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.stream).poll_shutdown(cx)
+    }
+}
+
+// Serves `app` on a bound listener, giving each request the client's address
+// as `ConnectInfo<SocketAddr>` and its connection's `RawResponse` and
+// `ConnectionStats`. Connections from clients `ip_filter` blocks are closed
+// before anything is read from them. Once `shutdown` completes the listener
+// is closed and in-flight requests finish before this returns.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    ip_filter: Arc<IpFilter>,
+    shutdown: impl Future<Output = ()>,
+) -> hyper::Result<()> {
+    let incoming = stream::unfold(listener, move |listener| {
+        let ip_filter = ip_filter.clone();
+        async move {
+            loop {
+                match listener.accept().await {
+                    Ok((_, remote)) if !ip_filter.permits(remote.ip()) => {
+                        tracing::warn!("Closed connection from {}: blocked by ip_filter", remote);
+                    }
+                    Ok((stream, remote)) => {
+                        return Some((
+                            Ok::<_, io::Error>(Connection::new(stream, remote)),
+                            listener,
+                        ))
+                    }
+                    Err(e) => {
+                        // E.g. out of file descriptors; back off rather than spin
+                        tracing::error!("Failed to accept connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                }
+            }
+        }
+    });
+
+    let make_service = make_service_fn(move |conn: &Connection| {
+        let service = service(app.clone(), conn);
+        async move { Ok::<_, Infallible>(service) }
+    });
+    hyper::Server::builder(accept::from_stream(incoming))
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+// Serves `app` on one already accepted connection, e.g. one tunneled
+// through SOCKS, WebSocket upgrades included
+pub async fn serve_stream(stream: TcpStream, remote: SocketAddr, app: Router) -> hyper::Result<()> {
+    let conn = Connection::new(stream, remote);
+    let service = service(app, &conn);
+    Http::new()
+        .serve_connection(conn, service)
+        .with_upgrades()
+        .await
+}
+
+fn service(
+    app: Router,
+    conn: &Connection,
+) -> ServiceFn<impl FnMut(Request<Body>) -> Oneshot<Router, Request<Body>> + Clone> {
+    let remote = conn.remote;
+    let raw = conn.raw.clone();
+    let stats = conn.stats.clone();
+    tower::service_fn(move |mut req: Request<Body>| {
+        stats.0.requests.fetch_add(1, Ordering::Relaxed);
+        req.extensions_mut().insert(ConnectInfo(remote));
+        req.extensions_mut().insert(raw.clone());
+        req.extensions_mut().insert(stats.clone());
+        app.clone().oneshot(req)
+    })
+}
@@ -0,0 +1,44 @@
+use axum::http::{HeaderMap, StatusCode};
+use std::time::Duration;
+
+// Reserved request headers that change how a single mocked request is
+// answered, so a test can force an edge case without editing the mocks
+const STATUS_HEADER: &str = "x-mock-status";
+const DELAY_HEADER: &str = "x-mock-delay"; // Milliseconds
+
+#[derive(Debug, Default)]
+pub struct ControlHeaders {
+    pub status: Option<StatusCode>,
+    pub delay: Option<Duration>,
+}
+
+impl ControlHeaders {
+    // Unusable values are logged and ignored rather than failing the request
+    pub fn read(headers: &HeaderMap) -> Self {
+        let value = |name: &str| {
+            let value = headers.get(name)?.to_str().ok()?.trim().to_string();
+            Some(value)
+        };
+        let status = value(STATUS_HEADER).and_then(|status| {
+            match status
+                .parse::<u16>()
+                .ok()
+                .and_then(|s| StatusCode::from_u16(s).ok())
+            {
+                Some(status) => Some(status),
+                None => {
+                    tracing::warn!("Ignoring {}: {}", STATUS_HEADER, status);
+                    None
+                }
+            }
+        });
+        let delay = value(DELAY_HEADER).and_then(|delay| match delay.parse::<u64>() {
+            Ok(ms) => Some(Duration::from_millis(ms)),
+            Err(_) => {
+                tracing::warn!("Ignoring {}: {}", DELAY_HEADER, delay);
+                None
+            }
+        });
+        ControlHeaders { status, delay }
+    }
+}
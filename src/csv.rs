@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::transform;
+
+// Rows sent as CSV instead of the payload, for export and download endpoints
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct CsvPayload {
+    // JSON objects, or arrays of cells
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<Object>)]
+    pub rows: Vec<serde_json::Value>,
+    // A JSON file holding an array of rows, read on every request and sent
+    // after `rows`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows_file: Option<String>,
+    // Which object fields to write and in what order. Every field if left
+    // out, sorted by name within each row.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<String>,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub omit_header: bool,
+    // Sent as an attachment with this name; may use path variables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+impl CsvPayload {
+    pub fn content_disposition(&self, params: &[(&str, &str)]) -> Option<String> {
+        let mut filename = self.filename.clone()?;
+        for (key, value) in params {
+            filename = filename.replace(&format!("{{{{{}}}}}", key), value);
+        }
+        Some(format!(
+            "attachment; filename=\"{}\"",
+            filename.replace(['"', '\\'], "_")
+        ))
+    }
+
+    // Cells may use path variables as {{name}}
+    pub fn render(&self, params: &[(&str, &str)]) -> Result<String, String> {
+        let mut rows = self.rows.clone();
+        if let Some(file) = &self.rows_file {
+            let text = std::fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+            match serde_json::from_str(&text) {
+                Ok(serde_json::Value::Array(more)) => rows.extend(more),
+                Ok(_) => return Err(format!("{} doesn't hold an array of rows", file)),
+                Err(e) => return Err(format!("{}: {}", file, e)),
+            }
+        }
+
+        let mut columns = self.columns.clone();
+        if columns.is_empty() {
+            for row in &rows {
+                if let serde_json::Value::Object(map) = row {
+                    for key in map.keys() {
+                        if !columns.contains(key) {
+                            columns.push(key.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        let lookup = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| serde_json::Value::String(value.to_string()))
+        };
+        let mut out = String::new();
+        if !self.omit_header && !columns.is_empty() {
+            self.write_row(&mut out, columns.iter().map(String::as_str));
+        }
+        for row in &rows {
+            let cells: Vec<String> = match transform::fill(row, &lookup) {
+                serde_json::Value::Object(map) => columns
+                    .iter()
+                    .map(|column| map.get(column).map(cell).unwrap_or_default())
+                    .collect(),
+                serde_json::Value::Array(items) => items.iter().map(cell).collect(),
+                other => vec![cell(&other)],
+            };
+            self.write_row(&mut out, cells.iter().map(String::as_str));
+        }
+        Ok(out)
+    }
+
+    fn write_row<'a>(&self, out: &mut String, cells: impl Iterator<Item = &'a str>) {
+        for (i, value) in cells.enumerate() {
+            if i > 0 {
+                out.push(self.delimiter);
+            }
+            // Quoted as in RFC 4180 when the cell would otherwise be misread
+            if value.contains([self.delimiter, '"', '\r', '\n']) {
+                out.push('"');
+                out.push_str(&value.replace('"', "\"\""));
+                out.push('"');
+            } else {
+                out.push_str(value);
+            }
+        }
+        out.push_str("\r\n");
+    }
+}
+
+fn cell(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,574 @@
+use axum::{
+    body::Body,
+    extract::{Json, Path, Query, State},
+    http::{header, HeaderMap, Request, Response, StatusCode, Uri},
+    Extension,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    admin_workspace, current_endpoints, unknown_virtual_host, unknown_workspace, vhost,
+    workspace::Workspace, AdminQuery, AppState, EndpointConfig, ListenerPort,
+};
+
+// Headers that curl sets itself or that wouldn't survive a replay
+const SKIPPED_HEADERS: &[&str] = &["host", "content-length", "connection", "accept-encoding"];
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CurlQuery {
+    // "mock" (the default) or "backend" for the default_endpoint of the
+    // workspace or virtual host
+    target: Option<String>,
+}
+
+impl CurlQuery {
+    fn to_backend(&self) -> bool {
+        self.target.as_deref() == Some("backend")
+    }
+}
+
+// Builds a shell-safe curl command line
+fn curl_command(method: &str, url: &str, headers: &[(String, String)], body: &str) -> String {
+    let mut command = String::from("curl");
+    if method != "GET" {
+        command.push_str(&format!(" -X {}", method));
+    }
+    command.push_str(&format!(" {}", shell_quote(url)));
+    for (name, value) in headers {
+        command.push_str(&format!(
+            " -H {}",
+            shell_quote(&format!("{}: {}", name, value))
+        ));
+    }
+    if !body.is_empty() {
+        command.push_str(&format!(" --data-raw {}", shell_quote(body)));
+    }
+    command
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+// Where a snippet sends its request: the mock as reached by the admin
+// request (plus the workspace's path prefix), or the real backend of the
+// workspace or virtual host
+fn base_url(
+    workspace: &Workspace,
+    host: Option<&str>,
+    query: &CurlQuery,
+    headers: &HeaderMap,
+) -> Result<String, String> {
+    match query.target.as_deref() {
+        None | Some("mock") => {
+            let host = headers
+                .get(header::HOST)
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or("localhost");
+            Ok(format!(
+                "http://{}{}",
+                host,
+                workspace.path_prefix().unwrap_or("")
+            ))
+        }
+        Some("backend") => {
+            match workspace.backend_for(&vhost::normalize_host(host.unwrap_or(""))) {
+                Some(backend) => Ok(backend.trim_end_matches('/').to_string()),
+                None => Err(format!(
+                    "Workspace {} has no default_endpoint",
+                    workspace.name
+                )),
+            }
+        }
+        Some(other) => Err(format!("Unknown curl target: {}", other)),
+    }
+}
+
+// Route parameters like `{id}` or `{*rest}` become placeholders to fill in
+fn fill_placeholders(path: &str) -> String {
+    let mut filled = String::new();
+    let mut rest = path;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        filled.push_str(&rest[..start]);
+        let name = rest[start + 1..start + end].trim_start_matches('*');
+        filled.push_str(&name.to_uppercase());
+        rest = &rest[start + end + 1..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+fn snippet(command: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(command))
+        .unwrap()
+}
+
+fn bad_request(message: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+fn not_found(message: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from(message.to_string()))
+        .unwrap()
+}
+
+// A curl command that calls the endpoint at `index` in the admin endpoint list
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/endpoints/{index}/curl",
+    tag = "endpoints",
+    params(("index" = usize, Path, description = "Position in the endpoint list"), AdminQuery, CurlQuery),
+    responses(
+        (status = 200, description = "A curl command", content_type = "text/plain", body = String),
+        (status = 404, description = "No such endpoint, workspace or virtual host")
+    )
+)]
+pub async fn endpoint_curl(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Path(index): Path<usize>,
+    Query(query): Query<AdminQuery>,
+    Query(curl): Query<CurlQuery>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name),
+    };
+    let endpoints = match current_endpoints(&workspace, query.host.as_deref()).await {
+        Ok(endpoints) => endpoints,
+        Err(host) => return unknown_virtual_host(&host),
+    };
+    let Some(endpoint) = endpoints.get(index) else {
+        return not_found("No such endpoint");
+    };
+    let base = match base_url(&workspace, query.host.as_deref(), &curl, &headers) {
+        Ok(base) => base,
+        Err(message) => return bad_request(message),
+    };
+
+    let url = format!("{}{}", base, fill_placeholders(&endpoint.path));
+    let mut request_headers = Vec::new();
+    if let Some(host) = &query.host {
+        request_headers.push(("Host".to_string(), host.clone()));
+    }
+    snippet(curl_command(
+        &endpoint.method.to_uppercase(),
+        &url,
+        &request_headers,
+        "",
+    ))
+}
+
+// A curl command that repeats a journaled request
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/journal/{id}/curl",
+    tag = "journal",
+    params(("id" = u64, Path, description = "Journal entry id"), AdminQuery, CurlQuery),
+    responses(
+        (status = 200, description = "A curl command", content_type = "text/plain", body = String),
+        (status = 404, description = "No such journal entry or workspace")
+    )
+)]
+pub async fn journal_curl(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Path(id): Path<u64>,
+    Query(query): Query<AdminQuery>,
+    Query(curl): Query<CurlQuery>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name),
+    };
+    let Some(entry) = workspace.journal.get(id) else {
+        return not_found("No such journal entry");
+    };
+    let base = match base_url(&workspace, query.host.as_deref(), &curl, &headers) {
+        Ok(base) => base,
+        Err(message) => return bad_request(message),
+    };
+
+    let mut url = format!("{}{}", base, entry.path);
+    if let Some(query) = &entry.query {
+        url.push('?');
+        url.push_str(query);
+    }
+    // Keep the original Host so virtual hosts still match on the mock
+    let request_headers: Vec<(String, String)> = entry
+        .headers
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.as_str();
+            !SKIPPED_HEADERS.contains(&name) || (name == "host" && !curl.to_backend())
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    snippet(curl_command(
+        &entry.method,
+        &url,
+        &request_headers,
+        &entry.body,
+    ))
+}
+
+// A request described by a pasted curl command
+#[derive(Debug, Default)]
+struct ParsedCurl {
+    method: Option<String>,
+    url: Option<String>,
+    headers: Vec<(String, String)>,
+    data: Vec<String>,
+    get: bool,
+    head: bool,
+}
+
+impl ParsedCurl {
+    fn method(&self) -> String {
+        match &self.method {
+            Some(method) => method.to_uppercase(),
+            None if self.head => "HEAD".to_string(),
+            None if !self.data.is_empty() && !self.get => "POST".to_string(),
+            None => "GET".to_string(),
+        }
+    }
+
+    fn body(&self) -> String {
+        if self.get {
+            String::new()
+        } else {
+            self.data.join("&")
+        }
+    }
+}
+
+// Splits a command line the way a POSIX shell would: quotes, backslash
+// escapes, line continuations and bash's $'...' strings
+fn shell_words(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated ' quote".to_string()),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => word.push('\n'),
+                            Some('t') => word.push('\t'),
+                            Some('r') => word.push('\r'),
+                            Some(c) => word.push(c),
+                            None => return Err("Unterminated $' quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated $' quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("Unterminated \" quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated \" quote".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') | Some('\r') => {}
+                Some(c) => {
+                    in_word = true;
+                    word.push(c);
+                }
+                None => {}
+            },
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+fn parse_curl(command: &str) -> Result<ParsedCurl, String> {
+    let words = shell_words(command)?;
+    let mut args = words.into_iter();
+    match args.next() {
+        Some(program) if program == "curl" || program.ends_with("/curl") => {}
+        _ => return Err("Expected a command starting with curl".to_string()),
+    }
+
+    let mut parsed = ParsedCurl::default();
+    while let Some(arg) = args.next() {
+        // Short options may carry their value directly, e.g. -XPOST
+        let (flag, inline) = match arg.strip_prefix('-') {
+            Some(rest) if !rest.starts_with('-') && rest.len() > 1 => {
+                (format!("-{}", &rest[..1]), Some(rest[1..].to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("Missing value for {}", name))
+        };
+        match flag.as_str() {
+            "-X" | "--request" => parsed.method = Some(value(&flag)?),
+            "-H" | "--header" => {
+                let header = value(&flag)?;
+                if let Some((name, val)) = header.split_once(':') {
+                    parsed
+                        .headers
+                        .push((name.trim().to_string(), val.trim().to_string()));
+                }
+            }
+            "-d" | "--data" | "--data-raw" | "--data-binary" | "--data-ascii"
+            | "--data-urlencode" => parsed.data.push(value(&flag)?),
+            "--json" => {
+                parsed.data.push(value(&flag)?);
+                parsed
+                    .headers
+                    .push(("Content-Type".to_string(), "application/json".to_string()));
+                parsed
+                    .headers
+                    .push(("Accept".to_string(), "application/json".to_string()));
+            }
+            "-A" | "--user-agent" => parsed
+                .headers
+                .push(("User-Agent".to_string(), value(&flag)?)),
+            "-b" | "--cookie" => parsed.headers.push(("Cookie".to_string(), value(&flag)?)),
+            "-e" | "--referer" => parsed.headers.push(("Referer".to_string(), value(&flag)?)),
+            "--url" => parsed.url = Some(value(&flag)?),
+            "-G" | "--get" => parsed.get = true,
+            "-I" | "--head" => parsed.head = true,
+            // Options whose value doesn't affect the request we scaffold
+            "-u" | "--user" | "-o" | "--output" | "-m" | "--max-time" | "--connect-timeout"
+            | "-x" | "--proxy" | "-w" | "--write-out" | "--cacert" | "--cert" | "--key" | "-F"
+            | "--form" => {
+                value(&flag)?;
+            }
+            flag if flag.starts_with('-') => {}
+            _ => {
+                if parsed.url.is_none() {
+                    parsed.url = Some(arg);
+                }
+            }
+        }
+    }
+    if parsed.url.is_none() {
+        return Err("No URL found in curl command".to_string());
+    }
+    Ok(parsed)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct FromCurlRequest {
+    curl: String,
+    // Send the request to the default_endpoint of the workspace, or of the
+    // virtual host for the `host` query or the command's URL, and use its
+    // response for the status, content type and payload
+    #[serde(default)]
+    capture: bool,
+}
+
+// Turns a pasted curl command into an endpoint for the admin page to review
+// and save. Nothing is saved here.
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/endpoints/from-curl",
+    tag = "endpoints",
+    params(AdminQuery),
+    request_body = FromCurlRequest,
+    responses(
+        (status = 200, description = "A new, unsaved endpoint", body = EndpointConfig),
+        (status = 400, description = "The command couldn't be parsed"),
+        (status = 502, description = "Capturing from the backend failed")
+    )
+)]
+pub async fn endpoint_from_curl(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+    Json(request): Json<FromCurlRequest>,
+) -> Response<Body> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name),
+    };
+    let parsed = match parse_curl(&request.curl) {
+        Ok(parsed) => parsed,
+        Err(message) => return bad_request(message),
+    };
+    let url = parsed.url.as_deref().unwrap_or("/");
+    // Scheme-less URLs are fine for curl, but not for Uri's path parsing
+    let uri = if url.contains("://") || url.starts_with('/') {
+        url.parse::<Uri>()
+    } else {
+        format!("http://{}", url).parse::<Uri>()
+    };
+    let uri = match uri {
+        Ok(uri) => uri,
+        Err(e) => return bad_request(format!("Invalid URL {}: {}", url, e)),
+    };
+
+    let mut endpoint = EndpointConfig {
+        id: None,
+        method: parsed.method(),
+        path: uri.path().to_string(),
+        status: 200,
+        content_type: "application/json".to_string(),
+        payload: serde_json::json!({}),
+        languages: BTreeMap::new(),
+        locale: None,
+        ip_filter: None,
+        max_body_bytes: None,
+        max_concurrency: None,
+        request_schema: None,
+        tags: Vec::new(),
+        transform: None,
+        conditions: Vec::new(),
+        active: Vec::new(),
+        fault: None,
+        redirect: None,
+        session: None,
+        idempotency: None,
+        long_poll: None,
+        delay_headers_ms: None,
+        delay_body_ms: None,
+        trailers: BTreeMap::new(),
+        expect_continue: None,
+        keep_alive: None,
+        check_xml: false,
+        csv: None,
+        protobuf: None,
+        ndjson: None,
+        websocket: None,
+        scenario: None,
+    };
+
+    if request.capture {
+        let host = query
+            .host
+            .as_deref()
+            .or(uri.host())
+            .map(vhost::normalize_host)
+            .unwrap_or_default();
+        let (Some(backend), Some(client)) = (workspace.backend_for(&host), &state.client) else {
+            return bad_request(format!(
+                "Workspace {} has no default_endpoint to capture from",
+                workspace.name
+            ));
+        };
+        let mut path_and_query = uri.path().to_string();
+        if let Some(q) = uri.query() {
+            path_and_query.push('?');
+            path_and_query.push_str(q);
+        }
+        let backend_url = format!("{}{}", backend.trim_end_matches('/'), path_and_query);
+        let mut builder = Request::builder()
+            .method(endpoint.method.as_str())
+            .uri(&backend_url);
+        for (name, value) in &parsed.headers {
+            if !name.eq_ignore_ascii_case("host") {
+                builder = builder.header(name, value);
+            }
+        }
+        let backend_request = match builder.body(Body::from(parsed.body())) {
+            Ok(backend_request) => backend_request,
+            Err(e) => return bad_request(format!("Can't build request: {}", e)),
+        };
+        tracing::info!("Capturing response for new endpoint from {}", backend_url);
+        let response = match client.request(backend_request).await {
+            Ok(response) => response,
+            Err(e) => {
+                return Response::builder()
+                    .status(StatusCode::BAD_GATEWAY)
+                    .body(Body::from(format!(
+                        "Request to {} failed: {}",
+                        backend_url, e
+                    )))
+                    .unwrap()
+            }
+        };
+        let (parts, body) = response.into_parts();
+        let bytes = match hyper::body::to_bytes(body).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return Response::builder()
+                    .status(StatusCode::BAD_GATEWAY)
+                    .body(Body::from(format!("Failed to read response: {}", e)))
+                    .unwrap()
+            }
+        };
+        endpoint.status = parts.status.as_u16();
+        if let Some(content_type) = parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+        {
+            endpoint.content_type = content_type.to_string();
+        }
+        // JSON bodies are kept as JSON so they're easy to edit
+        endpoint.payload = serde_json::from_slice(&bytes).unwrap_or_else(|_| {
+            serde_json::Value::String(String::from_utf8_lossy(&bytes).into_owned())
+        });
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&endpoint).unwrap()))
+        .unwrap()
+}
@@ -0,0 +1,142 @@
+use chrono::{DateTime, NaiveDate, SecondsFormat, TimeZone, Utc};
+use serde_json::Value;
+
+use crate::clock::parse_duration;
+
+// Values of the time templates:
+//
+//   {{now}}, {{now.epoch}}, {{now.epoch_ms}}
+//   {{date_add <date> <duration> [format]}}   e.g. {{date_add now '2d'}}
+//   {{date_format <date> <format>}}
+//   {{from_epoch <seconds> [format]}}
+//   {{to_epoch <date>}}, {{to_epoch_ms <date>}}
+//
+// Text arguments are quoted with " or '. A date is `now`, a quoted RFC 3339
+// time or YYYY-MM-DD date, Unix seconds, or any other template name, e.g.
+// `session.issued_at`, which `values` resolves. None leaves the placeholder
+// as written; bad arguments are also logged.
+pub fn template(
+    name: &str,
+    now: DateTime<Utc>,
+    values: &dyn Fn(&str) -> Option<Value>,
+) -> Option<Value> {
+    match name {
+        "now" => return format(now, "RFC3339").ok(),
+        "now.epoch" => return Some(now.timestamp().into()),
+        "now.epoch_ms" => return Some(now.timestamp_millis().into()),
+        _ => {}
+    }
+    let (helper, rest) = name.split_once(char::is_whitespace)?;
+    let result = split_args(rest).and_then(|args| {
+        let date = |at: usize| match args.get(at) {
+            Some(arg) => parse_date(arg, now, values),
+            None => Err(format!("{} needs a date", helper)),
+        };
+        let text = |at: usize| match args.get(at) {
+            Some(Arg::Quoted(text)) => Ok(Some(*text)),
+            Some(Arg::Bare(word)) => Err(format!("{} must be quoted", word)),
+            None => Ok(None),
+        };
+        let (date, format_at) = match helper {
+            "date_add" => {
+                let duration = text(1)?.ok_or("date_add needs a duration")?;
+                let date = date(0)?
+                    .checked_add_signed(parse_duration(duration)?)
+                    .ok_or("the date is out of range")?;
+                (date, 2)
+            }
+            "date_format" => (date(0)?, 1),
+            "from_epoch" => (date(0)?, 1),
+            "to_epoch" => return Ok(Some(date(0)?.timestamp().into())),
+            "to_epoch_ms" => return Ok(Some(date(0)?.timestamp_millis().into())),
+            _ => return Ok(None),
+        };
+        format(date, text(format_at)?.unwrap_or("RFC3339")).map(Some)
+    });
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            tracing::warn!("Leaving {{{{{}}}}} as written: {}", name, e);
+            None
+        }
+    }
+}
+
+// "RFC3339", "RFC3339_MS", "RFC2822", "HTTP" (as in Expires headers),
+// "date" (YYYY-MM-DD), "epoch" and "epoch_ms" (numbers), or a strftime
+// pattern such as "%d/%m/%Y"
+fn format(date: DateTime<Utc>, format: &str) -> Result<Value, String> {
+    Ok(match format {
+        "RFC3339" => date.to_rfc3339_opts(SecondsFormat::Secs, true).into(),
+        "RFC3339_MS" => date.to_rfc3339_opts(SecondsFormat::Millis, true).into(),
+        "RFC2822" => date.to_rfc2822().into(),
+        "HTTP" => date.format("%a, %d %b %Y %H:%M:%S GMT").to_string().into(),
+        "date" => date.format("%Y-%m-%d").to_string().into(),
+        "epoch" => date.timestamp().into(),
+        "epoch_ms" => date.timestamp_millis().into(),
+        pattern if pattern.contains('%') => {
+            let mut text = String::new();
+            // Writing an invalid pattern fails rather than panicking
+            std::fmt::Write::write_fmt(&mut text, format_args!("{}", date.format(pattern)))
+                .map_err(|_| format!("invalid date format {:?}", pattern))?;
+            text.into()
+        }
+        other => return Err(format!("unknown date format {:?}", other)),
+    })
+}
+
+fn parse_date(
+    arg: &Arg,
+    now: DateTime<Utc>,
+    values: &dyn Fn(&str) -> Option<Value>,
+) -> Result<DateTime<Utc>, String> {
+    let value = match arg {
+        Arg::Bare("now") => return Ok(now),
+        Arg::Bare(word) => match word.parse::<f64>() {
+            Ok(number) => Value::from(number),
+            Err(_) => values(word).ok_or_else(|| format!("no value for {}", word))?,
+        },
+        Arg::Quoted(text) => Value::String(text.to_string()),
+    };
+    let parsed = match &value {
+        Value::Number(seconds) => seconds.as_f64().and_then(|seconds| {
+            let millis = (seconds * 1000.0).round() as i64;
+            Utc.timestamp_millis_opt(millis).single()
+        }),
+        Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .map(|date| date.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                let day = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+                Some(day.and_hms_opt(0, 0, 0)?.and_utc())
+            })
+            .or_else(|| Utc.timestamp_opt(text.parse().ok()?, 0).single()),
+        _ => None,
+    };
+    parsed.ok_or_else(|| format!("{} isn't a date", value))
+}
+
+enum Arg<'a> {
+    Bare(&'a str),
+    Quoted(&'a str),
+}
+
+// Words and "quoted" or 'quoted' text
+fn split_args(text: &str) -> Result<Vec<Arg<'_>>, String> {
+    let mut args = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(first) = rest.chars().next() {
+        if first == '"' || first == '\'' {
+            let quoted = &rest[1..];
+            let end = quoted.find(first).ok_or("unclosed quote")?;
+            args.push(Arg::Quoted(&quoted[..end]));
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            args.push(Arg::Bare(&rest[..end]));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(args)
+}
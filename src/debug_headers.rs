@@ -0,0 +1,62 @@
+use axum::http::{HeaderName, HeaderValue, Response};
+use hyper::Body;
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use crate::ProxiedTo;
+
+const MATCHED: HeaderName = HeaderName::from_static("x-mock-matched");
+const PROXIED: HeaderName = HeaderName::from_static("x-mock-proxied");
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+// Request extension, present with debug_headers on, that matching fills in
+// once it has decided how to answer
+#[derive(Clone, Default)]
+pub struct MatchOutcome(Arc<OnceLock<Decision>>);
+
+struct Decision {
+    endpoint: Option<String>, // Its id, or method and path
+    took: Duration,
+}
+
+impl MatchOutcome {
+    pub fn matched(&self, endpoint: String, took: Duration) {
+        let _ = self.0.set(Decision {
+            endpoint: Some(endpoint),
+            took,
+        });
+    }
+
+    pub fn unmatched(&self, took: Duration) {
+        let _ = self.0.set(Decision {
+            endpoint: None,
+            took,
+        });
+    }
+}
+
+// Tells developers whether a response came from a mock or a backend:
+// X-Mock-Matched names the endpoint, X-Mock-Proxied the backend, and
+// `Server-Timing: match;dur=MS` how long matching took. Requests streamed
+// straight to the backend aren't matched and get no timing.
+pub fn apply(response: &mut Response<Body>, outcome: &MatchOutcome) {
+    let proxied = response
+        .extensions()
+        .get::<ProxiedTo>()
+        .map(|backend| backend.0.clone());
+    let headers = response.headers_mut();
+    if let Some(decision) = outcome.0.get() {
+        if let Some(endpoint) = &decision.endpoint {
+            if let Ok(value) = HeaderValue::from_str(endpoint) {
+                headers.insert(MATCHED, value);
+            }
+        }
+        let timing = format!("match;dur={:.3}", decision.took.as_secs_f64() * 1000.0);
+        headers.append(SERVER_TIMING, HeaderValue::from_str(&timing).unwrap());
+    }
+    if let Some(value) = proxied.and_then(|backend| HeaderValue::from_str(&backend).ok()) {
+        headers.insert(PROXIED, value);
+    }
+}
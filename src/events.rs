@@ -0,0 +1,131 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{header, Response, StatusCode},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+use crate::AppState;
+
+// Holds a request until the named event is triggered through the admin API,
+// or answers with the timeout response. Mocks job-status and long-poll APIs.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct LongPoll {
+    // May use path variables, e.g. "job-{{id}}"
+    pub event: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_timeout_status")]
+    pub timeout_status: u16,
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    #[schema(value_type = Object)]
+    pub timeout_payload: serde_json::Value,
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_timeout_status() -> u16 {
+    204
+}
+
+impl LongPoll {
+    pub fn event_name(&self, params: &[(&str, &str)]) -> String {
+        let mut name = self.event.clone();
+        for (key, value) in params {
+            name = name.replace(&format!("{{{{{}}}}}", key), value);
+        }
+        name
+    }
+
+    // The data the event was triggered with, or None on timeout
+    pub async fn wait(&self, events: &Events, name: &str) -> Option<serde_json::Value> {
+        let mut receiver = events.subscribe(name);
+        let timeout = Duration::from_secs(self.timeout_secs);
+        match tokio::time::timeout(timeout, receiver.changed()).await {
+            Ok(Ok(())) => Some(receiver.borrow().clone()),
+            _ => None,
+        }
+    }
+
+    pub fn timeout_response(&self) -> Response<Body> {
+        let builder = Response::builder()
+            .status(StatusCode::from_u16(self.timeout_status).unwrap_or(StatusCode::NO_CONTENT));
+        match &self.timeout_payload {
+            serde_json::Value::Null => builder.body(Body::empty()),
+            serde_json::Value::String(s) => builder
+                .header(header::CONTENT_TYPE, "text/plain")
+                .body(Body::from(s.clone())),
+            payload => builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(payload.to_string())),
+        }
+        .unwrap()
+    }
+}
+
+// Named events long-polling requests wait for
+#[derive(Default)]
+pub struct Events {
+    channels: Mutex<HashMap<String, watch::Sender<serde_json::Value>>>,
+}
+
+impl Events {
+    fn subscribe(&self, name: &str) -> watch::Receiver<serde_json::Value> {
+        let mut channels = self.channels.lock().unwrap();
+        channels.retain(|_, sender| sender.receiver_count() > 0);
+        channels
+            .entry(name.to_string())
+            .or_insert_with(|| watch::channel(serde_json::Value::Null).0)
+            .subscribe()
+    }
+
+    // Wakes everything waiting for `name` and returns how many there were
+    fn trigger(&self, name: &str, data: serde_json::Value) -> usize {
+        let channels = self.channels.lock().unwrap();
+        match channels.get(name) {
+            Some(sender) => {
+                sender.send_replace(data);
+                sender.receiver_count()
+            }
+            None => 0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TriggerResult {
+    woken: usize,
+}
+
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/events/{name}",
+    tag = "events",
+    params(("name" = String, Path, description = "Event to trigger")),
+    request_body(content = Object, description = "Optional data for {{event.FIELD}} templates"),
+    responses(
+        (status = 200, description = "Waiting requests were woken", body = TriggerResult),
+        (status = 400, description = "The body isn't JSON")
+    )
+)]
+pub async fn trigger(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<Json<TriggerResult>, (StatusCode, String)> {
+    let data = if body.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid JSON: {}", e)))?
+    };
+    let woken = state.events.trigger(&name, data);
+    tracing::info!("Triggered event {}, waking {} request(s)", name, woken);
+    Ok(Json(TriggerResult { woken }))
+}
@@ -0,0 +1,57 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Response, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+// How an endpoint answers `Expect: 100-continue`. hyper sends the interim
+// 100 response when the body is first read, so rejecting means answering
+// before reading it and delaying means waiting before reading it.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ExpectContinue {
+    // Send 100 Continue straight away, as without this option
+    Continue,
+    // Refuse the body with `status`, 417 Expectation Failed by default
+    Reject {
+        #[serde(default = "default_reject_status")]
+        status: u16,
+    },
+    // Send 100 Continue only after `ms` milliseconds
+    Delay {
+        ms: u64,
+    },
+}
+
+fn default_reject_status() -> u16 {
+    417
+}
+
+pub fn expects_continue(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::EXPECT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("100-continue"))
+}
+
+impl ExpectContinue {
+    // Runs before the body is read. A response means the body is refused.
+    pub async fn before_body(&self) -> Option<Response<Body>> {
+        match self {
+            ExpectContinue::Continue => None,
+            ExpectContinue::Reject { status } => Some(
+                Response::builder()
+                    .status(StatusCode::from_u16(*status).unwrap_or(StatusCode::EXPECTATION_FAILED))
+                    .header(header::CONNECTION, "close")
+                    .body(Body::empty())
+                    .unwrap(),
+            ),
+            ExpectContinue::Delay { ms } => {
+                tokio::time::sleep(Duration::from_millis(*ms)).await;
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Query, State},
+    http::{header, Method, Request, Response, StatusCode},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, net::IpAddr};
+use utoipa::ToSchema;
+
+use crate::{
+    codec::Codec,
+    conditions::{Check, RequestValues},
+    control, language,
+    login::SessionAction,
+    matching::{self, HeadRequests, MethodMismatch},
+    schedule, vhost, workspace, AdminQuery, AppState, EndpointConfig, ListenerPort,
+};
+
+// A request to explain, as a client would send it
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ExplainRequest {
+    #[serde(default = "default_method")]
+    method: String,
+    // Path and query, e.g. "/users/7?expand=orders"
+    path: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    // JSON, or a string sent as is
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    body: Option<Value>,
+    // For ip_filter; left unchecked without one
+    #[serde(default)]
+    client_ip: Option<IpAddr>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Explanation {
+    workspace: String,
+    // The path endpoints are matched on, after base_path and the
+    // workspace's path prefix are removed
+    path: String,
+    method: String,
+    // The endpoints on the matching route, in settings order
+    candidates: Vec<Candidate>,
+    decision: Decision,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Candidate {
+    endpoint: String, // Its id, or method and path
+    checks: Vec<Check>,
+    selected: bool,
+    // Tested in order for the selected endpoint until one matches
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    conditions: Vec<ConditionReport>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConditionReport {
+    index: usize,
+    matched: bool,
+    checks: Vec<Check>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Decision {
+    // The endpoint answers; `condition` is the index of the matching one
+    Mock {
+        endpoint: String,
+        status: u16,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        condition: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+    },
+    // The endpoint proxies the request and transforms the response
+    Transform {
+        endpoint: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        backend: Option<String>,
+    },
+    // The endpoint turns the request away before answering it
+    Reject {
+        endpoint: String,
+        status: u16,
+        reason: String,
+    },
+    MethodNotAllowed {
+        allow: Vec<String>,
+    },
+    Proxy {
+        backend: String,
+    },
+    // No endpoint answers and the server responds itself
+    Unmatched {
+        status: u16,
+    },
+}
+
+// Walks a sample request through the same steps as mock traffic, without
+// sending it anywhere or changing any state: no login is started, no
+// idempotency key stored and no long poll waited on
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/explain",
+    tag = "endpoints",
+    params(AdminQuery),
+    request_body = ExplainRequest,
+    responses(
+        (status = 200, description = "How the request would be answered", body = Explanation),
+        (status = 400, description = "Invalid method, path or header"),
+        (status = 404, description = "Unknown workspace")
+    )
+)]
+pub async fn explain(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    Json(sample): Json<ExplainRequest>,
+) -> Result<Json<Explanation>, Response<Body>> {
+    let mut request = Request::builder()
+        .method(sample.method.to_ascii_uppercase().as_str())
+        .uri(&sample.path);
+    for (name, value) in &sample.headers {
+        request = request.header(name, value);
+    }
+    let (mut parts, ()) = request
+        .body(())
+        .map_err(|e| bad_request(e.to_string()))?
+        .into_parts();
+    let bytes = match &sample.body {
+        None => Bytes::new(),
+        Some(Value::String(text)) => Bytes::from(text.clone()),
+        Some(json) => Bytes::from(json.to_string()),
+    };
+    let json_body = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .and_then(Codec::for_content_type)
+        .and_then(|codec| codec.decode(&bytes).ok())
+        .map_or_else(|| bytes.clone(), Bytes::from);
+
+    let host = vhost::request_host(&parts).unwrap_or_default();
+    let mut path = parts.uri.path().to_string();
+    if let Some(base_path) = &state.base_path {
+        match workspace::strip_path_prefix(base_path, &path) {
+            Some(stripped) => path = stripped,
+            // Everything outside the base path is unknown
+            None => {
+                return Ok(Json(Explanation {
+                    workspace: String::new(),
+                    path,
+                    method: parts.method.to_string(),
+                    candidates: Vec::new(),
+                    decision: Decision::Unmatched { status: 404 },
+                }))
+            }
+        }
+    }
+    let (workspace, stripped) = match &query.workspace {
+        Some(name) => {
+            let workspace = state.workspaces.get(name).cloned().ok_or_else(|| {
+                Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from(format!("Unknown workspace: {}", name)))
+                    .unwrap()
+            })?;
+            let stripped = workspace
+                .path_prefix()
+                .and_then(|prefix| workspace::strip_path_prefix(prefix, &path));
+            (workspace, stripped)
+        }
+        None => state.workspaces.select(port, &host, &path),
+    };
+    if let Some(stripped) = stripped {
+        path = stripped;
+    }
+    parts.uri = match parts.uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.clone(),
+    }
+    .parse()
+    .map_err(|_| bad_request(format!("invalid path {}", path)))?;
+
+    let method = state
+        .method_override
+        .then(|| matching::method_override(&parts.method, &parts.headers, &json_body))
+        .flatten()
+        .unwrap_or_else(|| parts.method.clone());
+    let mut explanation = Explanation {
+        workspace: workspace.name.clone(),
+        path: path.clone(),
+        method: method.to_string(),
+        candidates: Vec::new(),
+        decision: Decision::Unmatched { status: 404 },
+    };
+
+    let routes = workspace.routes_for(&host);
+    let lookup = state.path_matching.request_path(&path);
+    let now = state.clock.now();
+    if let Ok(matched) = routes.router.at(&lookup) {
+        let endpoints: Vec<&EndpointConfig> = matched
+            .value
+            .iter()
+            .map(|&idx| &routes.endpoints[idx])
+            .collect();
+        let active = |ep: &EndpointConfig| schedule::any_active(&ep.active, now);
+        let with_method = |method: &str| {
+            endpoints
+                .iter()
+                .position(|ep| active(ep) && ep.method.eq_ignore_ascii_case(method))
+        };
+        let head_mocks_get = state.head_requests == HeadRequests::Mock;
+        let selected = with_method(method.as_str()).or_else(|| {
+            (method == Method::HEAD && head_mocks_get)
+                .then(|| with_method("GET"))
+                .flatten()
+        });
+        for (at, endpoint) in endpoints.iter().enumerate() {
+            let mut checks = vec![Check {
+                test: format!("method {}", endpoint.method.to_ascii_uppercase()),
+                passed: endpoint.method.eq_ignore_ascii_case(method.as_str())
+                    || Some(at) == selected,
+                detail: None,
+            }];
+            if !endpoint.active.is_empty() {
+                checks.push(Check {
+                    test: "active".to_string(),
+                    passed: active(endpoint),
+                    detail: None,
+                });
+            }
+            explanation.candidates.push(Candidate {
+                endpoint: name(endpoint),
+                checks,
+                selected: Some(at) == selected,
+                conditions: Vec::new(),
+            });
+        }
+
+        let Some(at) = selected else {
+            let candidates: Vec<&&EndpointConfig> =
+                endpoints.iter().filter(|ep| active(ep)).collect();
+            if !candidates.is_empty() && state.method_mismatch == MethodMismatch::Reject {
+                let mut allow: Vec<String> = Vec::new();
+                let head = (head_mocks_get && with_method("GET").is_some()).then_some("HEAD");
+                let methods = candidates.iter().map(|ep| ep.method.as_str()).chain(head);
+                for method in methods.map(str::to_ascii_uppercase) {
+                    if !allow.contains(&method) {
+                        allow.push(method);
+                    }
+                }
+                explanation.decision = Decision::MethodNotAllowed { allow };
+                return Ok(Json(explanation));
+            }
+            explanation.decision = unmatched_decision(&state, &workspace, &host, &path, &method);
+            return Ok(Json(explanation));
+        };
+
+        let endpoint = endpoints[at];
+        let params: Vec<(&str, &str)> = matched
+            .params
+            .iter()
+            .map(|(key, value)| (key, matching::original_param(&path, &lookup, value)))
+            .collect();
+        let checks = &mut explanation.candidates[at].checks;
+        let reject = |status: u16, reason: &str| Decision::Reject {
+            endpoint: name(endpoint),
+            status,
+            reason: reason.to_string(),
+        };
+        let mut rejected = None;
+        if let (Some(filter), Some(ip)) = (&endpoint.ip_filter, sample.client_ip) {
+            let passed = filter.permits(ip);
+            checks.push(check("ip_filter", passed));
+            if !passed {
+                rejected = Some(reject(403, "ip_filter"));
+            }
+        }
+        if let Some(limit) = endpoint.max_body_bytes.filter(|_| rejected.is_none()) {
+            let passed = bytes.len() <= limit;
+            checks.push(check("max_body_bytes", passed));
+            if !passed {
+                rejected = Some(reject(413, "max_body_bytes"));
+            }
+        }
+        let requires_login = matches!(endpoint.session, Some(SessionAction::Require));
+        if requires_login && rejected.is_none() {
+            let passed = state.logins.get(&parts.headers, now).is_some();
+            checks.push(check("session", passed));
+            if !passed {
+                rejected = Some(reject(401, "session"));
+            }
+        }
+        if let Some(schema) = endpoint
+            .request_schema
+            .as_ref()
+            .filter(|_| rejected.is_none())
+        {
+            let mut schema_check = check("request_schema", true);
+            if let Some(response) = schema.check(&json_body) {
+                rejected = Some(reject(response.status().as_u16(), "request_schema"));
+                // The response lists the violations
+                let body = hyper::body::to_bytes(response.into_body()).await;
+                schema_check.passed = false;
+                schema_check.detail = body
+                    .ok()
+                    .map(|body| String::from_utf8_lossy(&body).into_owned());
+            }
+            checks.push(schema_check);
+        }
+        if let Some(config) = endpoint.idempotency.as_ref().filter(|_| rejected.is_none()) {
+            if config.required {
+                let passed = config.key(&parts.headers).is_some();
+                checks.push(check("idempotency key", passed));
+                if !passed {
+                    let status = config.missing_key().status().as_u16();
+                    rejected = Some(reject(status, "idempotency key"));
+                }
+            }
+        }
+        if let Some(rejected) = rejected {
+            explanation.decision = rejected;
+            return Ok(Json(explanation));
+        }
+        if endpoint.transform.is_some() {
+            explanation.decision = Decision::Transform {
+                endpoint: name(endpoint),
+                backend: workspace.backend_for(&host),
+            };
+            return Ok(Json(explanation));
+        }
+
+        let client = crate::client_key(&state, &parts.headers);
+        let scenario_state = endpoint
+            .scenario
+            .as_ref()
+            .map(|step| workspace.scenarios.state(client.as_deref(), &step.name));
+        let values = RequestValues {
+            parts: &parts,
+            params: &params,
+            body: &json_body,
+            now,
+            state: scenario_state.as_deref(),
+        };
+        let mut condition = None;
+        for (index, candidate) in endpoint.conditions.iter().enumerate() {
+            let checks = candidate.checks(&values);
+            let matched = checks.iter().all(|check| check.passed);
+            explanation.candidates[at].conditions.push(ConditionReport {
+                index,
+                matched,
+                checks,
+            });
+            if matched {
+                condition = Some((index, candidate));
+                break;
+            }
+        }
+        let mut status = condition
+            .and_then(|(_, c)| c.status)
+            .unwrap_or(endpoint.status);
+        let language = match condition.and_then(|(_, c)| c.payload.as_ref()) {
+            Some(_) => None,
+            None => {
+                let tags: Vec<&str> = endpoint.languages.keys().map(String::as_str).collect();
+                language::negotiate(&parts.headers, &tags).map(str::to_string)
+            }
+        };
+        if state.control_headers {
+            let control = control::ControlHeaders::read(&parts.headers);
+            status = control.status.map_or(status, |status| status.as_u16());
+        }
+        explanation.decision = Decision::Mock {
+            endpoint: name(endpoint),
+            status,
+            condition: condition.map(|(index, _)| index),
+            language,
+        };
+        return Ok(Json(explanation));
+    }
+
+    explanation.decision = unmatched_decision(&state, &workspace, &host, &path, &method);
+    Ok(Json(explanation))
+}
+
+fn unmatched_decision(
+    state: &AppState,
+    workspace: &workspace::Workspace,
+    host: &str,
+    path: &str,
+    method: &Method,
+) -> Decision {
+    if let Some(response) = state.unmatched.action(path).respond(method, path) {
+        return Decision::Unmatched {
+            status: response.status().as_u16(),
+        };
+    }
+    match (workspace.backend_for(host), &state.client) {
+        (Some(backend), Some(_)) => Decision::Proxy { backend },
+        _ => Decision::Unmatched { status: 404 },
+    }
+}
+
+fn name(endpoint: &EndpointConfig) -> String {
+    match &endpoint.id {
+        Some(id) => id.clone(),
+        None => format!("{} {}", endpoint.method.to_ascii_uppercase(), endpoint.path),
+    }
+}
+
+fn check(test: &str, passed: bool) -> Check {
+    Check {
+        test: test.to_string(),
+        passed,
+        detail: None,
+    }
+}
+
+fn bad_request(message: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from(message))
+        .unwrap()
+}
@@ -0,0 +1,341 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use serde_json::Value;
+
+use crate::random::Random;
+
+// Locales fake data comes in; the first is the fallback
+pub const LOCALES: [&str; 4] = ["en", "de", "fr", "es"];
+
+// Values of the {{fake.KIND}} templates, drawn from the seeded generator so
+// a fixed seed gives the same data every run:
+//
+//   first_name, last_name, name, email, phone, street, city, postcode,
+//   country, company, uuid
+//
+// Names, addresses and phone numbers follow the locale's conventions.
+pub fn template(name: &str, locale: &str, random: &Random) -> Option<Value> {
+    let kind = name.strip_prefix("fake.")?;
+    let data = data(locale);
+    random.with(|rng| {
+        let pick = |rng: &mut StdRng, items: &[&str]| items.choose(rng).unwrap().to_string();
+        let text = match kind {
+            "first_name" => pick(rng, data.first_names),
+            "last_name" => pick(rng, data.last_names),
+            "name" => format!(
+                "{} {}",
+                pick(rng, data.first_names),
+                pick(rng, data.last_names)
+            ),
+            "email" => format!(
+                "{}.{}{}@{}",
+                ascii(&pick(rng, data.first_names)),
+                ascii(&pick(rng, data.last_names)),
+                rng.gen_range(1..100),
+                pick(rng, data.email_domains)
+            ),
+            "phone" => digits(rng, data.phone),
+            "street" => data
+                .street
+                .replace("{name}", &pick(rng, data.street_names))
+                .replace("{number}", &rng.gen_range(1..200).to_string()),
+            "city" => pick(rng, data.cities),
+            "postcode" => digits(rng, data.postcode),
+            "country" => data.country.to_string(),
+            "company" => format!(
+                "{} {}",
+                pick(rng, data.last_names),
+                pick(rng, data.company_suffixes)
+            ),
+            "uuid" => {
+                let mut bytes: [u8; 16] = rng.gen();
+                bytes[6] = (bytes[6] & 0x0f) | 0x40;
+                bytes[8] = (bytes[8] & 0x3f) | 0x80;
+                let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                format!(
+                    "{}-{}-{}-{}-{}",
+                    &hex[..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..]
+                )
+            }
+            _ => return None,
+        };
+        Some(Value::String(text))
+    })
+}
+
+// Fills each # in `pattern` with a random digit
+fn digits(rng: &mut StdRng, pattern: &str) -> String {
+    pattern
+        .chars()
+        .map(|c| match c {
+            '#' => char::from(b'0' + rng.gen_range(0..10)),
+            c => c,
+        })
+        .collect()
+}
+
+// Lowercase ASCII for email addresses
+fn ascii(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .flat_map(|c| match c {
+            'ä' => "ae".chars().collect(),
+            'ö' => "oe".chars().collect(),
+            'ü' => "ue".chars().collect(),
+            'ß' => "ss".chars().collect(),
+            'à' | 'á' | 'â' => vec!['a'],
+            'ç' => vec!['c'],
+            'è' | 'é' | 'ê' | 'ë' => vec!['e'],
+            'í' | 'î' | 'ï' => vec!['i'],
+            'ñ' => vec!['n'],
+            'ó' | 'ô' => vec!['o'],
+            'ú' | 'û' => vec!['u'],
+            c if c.is_ascii_alphanumeric() => vec![c],
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+struct LocaleData {
+    first_names: &'static [&'static str],
+    last_names: &'static [&'static str],
+    street_names: &'static [&'static str],
+    street: &'static str, // {name} and {number}
+    cities: &'static [&'static str],
+    postcode: &'static str,
+    phone: &'static str,
+    country: &'static str,
+    company_suffixes: &'static [&'static str],
+    email_domains: &'static [&'static str],
+}
+
+// Unknown locales get English data
+fn data(locale: &str) -> &'static LocaleData {
+    match locale {
+        "de" => &DE,
+        "fr" => &FR,
+        "es" => &ES,
+        _ => &EN,
+    }
+}
+
+static EN: LocaleData = LocaleData {
+    first_names: &[
+        "James",
+        "Mary",
+        "John",
+        "Patricia",
+        "Robert",
+        "Jennifer",
+        "Michael",
+        "Linda",
+        "David",
+        "Elizabeth",
+        "William",
+        "Susan",
+        "Richard",
+        "Jessica",
+        "Joseph",
+        "Sarah",
+    ],
+    last_names: &[
+        "Smith", "Johnson", "Williams", "Brown", "Jones", "Miller", "Davis", "Wilson", "Anderson",
+        "Taylor", "Thomas", "Moore", "Jackson", "Martin", "Lee", "Harris",
+    ],
+    street_names: &[
+        "Main Street",
+        "Oak Avenue",
+        "Maple Drive",
+        "Cedar Lane",
+        "Park Road",
+        "Elm Street",
+        "Washington Avenue",
+        "Lake View Drive",
+    ],
+    street: "{number} {name}",
+    cities: &[
+        "Springfield",
+        "Riverside",
+        "Franklin",
+        "Greenville",
+        "Madison",
+        "Georgetown",
+        "Salem",
+        "Fairview",
+    ],
+    postcode: "#####",
+    phone: "+1 (###) ###-####",
+    country: "United States",
+    company_suffixes: &["Inc.", "LLC", "Group", "Holdings"],
+    email_domains: &["example.com", "example.org", "example.net"],
+};
+
+static DE: LocaleData = LocaleData {
+    first_names: &[
+        "Lukas",
+        "Anna",
+        "Jonas",
+        "Lea",
+        "Leon",
+        "Hannah",
+        "Finn",
+        "Sophie",
+        "Paul",
+        "Marie",
+        "Felix",
+        "Laura",
+        "Maximilian",
+        "Julia",
+        "Jürgen",
+        "Jörg",
+    ],
+    last_names: &[
+        "Müller",
+        "Schmidt",
+        "Schneider",
+        "Fischer",
+        "Weber",
+        "Meyer",
+        "Wagner",
+        "Becker",
+        "Schulz",
+        "Hoffmann",
+        "Schäfer",
+        "Koch",
+        "Bauer",
+        "Richter",
+        "Klein",
+        "Wolf",
+    ],
+    street_names: &[
+        "Hauptstraße",
+        "Schulstraße",
+        "Gartenstraße",
+        "Bahnhofstraße",
+        "Dorfstraße",
+        "Bergstraße",
+        "Lindenstraße",
+        "Kirchweg",
+    ],
+    street: "{name} {number}",
+    cities: &[
+        "Berlin",
+        "Hamburg",
+        "München",
+        "Köln",
+        "Frankfurt am Main",
+        "Stuttgart",
+        "Düsseldorf",
+        "Leipzig",
+    ],
+    postcode: "#####",
+    phone: "+49 ### #######",
+    country: "Deutschland",
+    company_suffixes: &["GmbH", "AG", "KG", "GmbH & Co. KG"],
+    email_domains: &["example.de", "example.com", "example.org"],
+};
+
+static FR: LocaleData = LocaleData {
+    first_names: &[
+        "Gabriel", "Louise", "Raphaël", "Emma", "Léo", "Jade", "Louis", "Alice", "Jules", "Chloé",
+        "Hugo", "Léa", "Arthur", "Manon", "Théo", "Inès",
+    ],
+    last_names: &[
+        "Martin", "Bernard", "Dubois", "Thomas", "Robert", "Richard", "Petit", "Durand", "Leroy",
+        "Moreau", "Simon", "Laurent", "Lefèvre", "Michel", "Garcia", "Rousseau",
+    ],
+    street_names: &[
+        "rue de la Paix",
+        "avenue Victor Hugo",
+        "rue de l'Église",
+        "boulevard Saint-Michel",
+        "rue des Écoles",
+        "place de la République",
+        "rue du Moulin",
+        "chemin des Vignes",
+    ],
+    street: "{number} {name}",
+    cities: &[
+        "Paris",
+        "Marseille",
+        "Lyon",
+        "Toulouse",
+        "Nice",
+        "Nantes",
+        "Strasbourg",
+        "Bordeaux",
+    ],
+    postcode: "#####",
+    phone: "+33 # ## ## ## ##",
+    country: "France",
+    company_suffixes: &["SA", "SARL", "SAS", "et Fils"],
+    email_domains: &["example.fr", "example.com", "example.org"],
+};
+
+static ES: LocaleData = LocaleData {
+    first_names: &[
+        "Hugo",
+        "Lucía",
+        "Martín",
+        "Sofía",
+        "Pablo",
+        "María",
+        "Alejandro",
+        "Martina",
+        "Álvaro",
+        "Paula",
+        "Daniel",
+        "Julia",
+        "Adrián",
+        "Carmen",
+        "Javier",
+        "Elena",
+    ],
+    last_names: &[
+        "García",
+        "Rodríguez",
+        "González",
+        "Fernández",
+        "López",
+        "Martínez",
+        "Sánchez",
+        "Pérez",
+        "Gómez",
+        "Martín",
+        "Jiménez",
+        "Ruiz",
+        "Hernández",
+        "Díaz",
+        "Moreno",
+        "Muñoz",
+    ],
+    street_names: &[
+        "Calle Mayor",
+        "Calle Real",
+        "Avenida de la Constitución",
+        "Calle del Sol",
+        "Plaza de España",
+        "Calle de Alcalá",
+        "Paseo del Prado",
+        "Calle Nueva",
+    ],
+    street: "{name}, {number}",
+    cities: &[
+        "Madrid",
+        "Barcelona",
+        "Valencia",
+        "Sevilla",
+        "Zaragoza",
+        "Málaga",
+        "Bilbao",
+        "Alicante",
+    ],
+    postcode: "#####",
+    phone: "+34 ### ### ###",
+    country: "España",
+    company_suffixes: &["S.A.", "S.L.", "y Asociados", "Grupo"],
+    email_domains: &["example.es", "example.com", "example.org"],
+};
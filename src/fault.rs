@@ -0,0 +1,97 @@
+use axum::http::StatusCode;
+use hyper::body::Bytes;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+// A deliberate failure an endpoint produces instead of behaving, for testing
+// how clients cope
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Fault {
+    // Hold the connection open without responding, or respond only after
+    // `after_secs`, to exercise client timeouts and cancellation
+    Timeout {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        after_secs: Option<u64>,
+    },
+    // A body with a stray closing brace, so JSON parsers fail
+    InvalidJson,
+    // A Content-Length `by` bytes off from the body actually sent; more
+    // than sent makes clients wait for the rest until the connection closes
+    WrongContentLength {
+        #[serde(default = "default_length_error")]
+        by: i64,
+    },
+    // Close the connection after `bytes` of the body, half of it by default
+    Truncated {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bytes: Option<usize>,
+    },
+    // Replace the status line, "HTTP/1.1 abc Broken" by default
+    InvalidStatusLine {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        line: Option<String>,
+    },
+}
+
+fn default_length_error() -> i64 {
+    10
+}
+
+impl Fault {
+    // Waits before the mocked response is sent; a timeout may never return
+    pub async fn delay(&self) {
+        match self {
+            Fault::Timeout {
+                after_secs: Some(secs),
+            } => tokio::time::sleep(Duration::from_secs(*secs)).await,
+            Fault::Timeout { after_secs: None } => std::future::pending().await,
+            _ => {}
+        }
+    }
+
+    pub fn body(&self, body: Bytes) -> Bytes {
+        if let Fault::InvalidJson = self {
+            let mut body = body.to_vec();
+            body.push(b'}');
+            return Bytes::from(body);
+        }
+        body
+    }
+
+    // The bytes to put on the wire for faults hyper can't produce, sent in
+    // place of the response through `connection::RawResponse`
+    pub fn raw_response(&self, status: u16, content_type: &str, body: &[u8]) -> Option<Vec<u8>> {
+        let reason = StatusCode::from_u16(status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("");
+        let status_line = format!("HTTP/1.1 {} {}", status, reason);
+        match self {
+            Fault::WrongContentLength { by } => {
+                let length = (body.len() as i64 + by).max(0) as usize;
+                Some(raw(&status_line, content_type, length, body))
+            }
+            Fault::Truncated { bytes } => {
+                let sent = bytes.unwrap_or(body.len() / 2).min(body.len());
+                Some(raw(&status_line, content_type, body.len(), &body[..sent]))
+            }
+            Fault::InvalidStatusLine { line } => {
+                let line = line.as_deref().unwrap_or("HTTP/1.1 abc Broken");
+                Some(raw(line, content_type, body.len(), body))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn raw(status_line: &str, content_type: &str, length: usize, body: &[u8]) -> Vec<u8> {
+    let mut bytes = format!(
+        "{}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status_line, content_type, length
+    )
+    .into_bytes();
+    bytes.extend_from_slice(body);
+    bytes
+}
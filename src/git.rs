@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::process::Command;
+
+// Commits every settings change into a local git repository
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GitSettings {
+    #[serde(default = "default_repo")]
+    pub repo: String,
+    #[serde(default = "default_author_name")]
+    pub author_name: String,
+    #[serde(default = "default_author_email")]
+    pub author_email: String,
+}
+
+fn default_repo() -> String {
+    ".".to_string()
+}
+
+fn default_author_name() -> String {
+    "mock-server".to_string()
+}
+
+fn default_author_email() -> String {
+    "mock-server@localhost".to_string()
+}
+
+pub struct ConfigRepo {
+    dir: PathBuf,
+    settings: GitSettings,
+}
+
+impl ConfigRepo {
+    // Uses the repository at `settings.repo`, initialising it if needed
+    pub async fn open(settings: &GitSettings) -> io::Result<Self> {
+        let dir = PathBuf::from(&settings.repo);
+        std::fs::create_dir_all(&dir)?;
+        if !dir.join(".git").exists() {
+            run_git(&dir, &["init", "--quiet"], None).await?;
+            tracing::info!("Initialised settings repository in {}", dir.display());
+        }
+        Ok(ConfigRepo {
+            dir,
+            settings: settings.clone(),
+        })
+    }
+
+    // Commits `contents` as settings.json. `author` is "Name <email>" or just
+    // a name; the configured author is used when it's missing.
+    pub async fn commit(
+        &self,
+        contents: &str,
+        message: &str,
+        author: Option<&str>,
+    ) -> io::Result<()> {
+        tokio::fs::write(self.dir.join("settings.json"), contents).await?;
+        run_git(&self.dir, &["add", "settings.json"], None).await?;
+
+        // Nothing staged means the file didn't actually change
+        let unchanged = Command::new("git")
+            .args(["diff", "--cached", "--quiet"])
+            .current_dir(&self.dir)
+            .status()
+            .await?
+            .success();
+        if unchanged {
+            return Ok(());
+        }
+
+        let author = match author {
+            Some(author) if author.contains('<') => author.to_string(),
+            Some(name) => format!("{} <{}>", name, self.settings.author_email),
+            None => format!(
+                "{} <{}>",
+                self.settings.author_name, self.settings.author_email
+            ),
+        };
+        run_git(
+            &self.dir,
+            &["commit", "--quiet", "-m", message, "--author", &author],
+            Some(&self.settings),
+        )
+        .await
+    }
+}
+
+async fn run_git(dir: &Path, args: &[&str], committer: Option<&GitSettings>) -> io::Result<()> {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(dir);
+    if let Some(committer) = committer {
+        // Don't depend on a user.name/user.email being configured on the host
+        command
+            .env("GIT_COMMITTER_NAME", &committer.author_name)
+            .env("GIT_COMMITTER_EMAIL", &committer.author_email);
+    }
+    let output = command.output().await?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
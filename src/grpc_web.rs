@@ -0,0 +1,213 @@
+use axum::http::{header, response::Builder, HeaderMap, HeaderValue, Response, StatusCode};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::Body;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// Browser gRPC clients speak gRPC-Web: each message is framed with a flag
+// byte and a big-endian u32 length, and the status comes last as a trailer
+// frame in the body instead of HTTP/2 trailers. The text variant base64s
+// the whole thing for clients that can't read binary responses.
+#[derive(Debug, Clone, Copy)]
+pub struct GrpcWeb {
+    text: bool,
+}
+
+const TRAILER_FLAG: u8 = 0x80;
+
+impl GrpcWeb {
+    // application/grpc-web, application/grpc-web+proto and their -text forms
+    pub fn from_headers(headers: &HeaderMap) -> Option<GrpcWeb> {
+        let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        let essence = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "application/grpc-web" | "application/grpc-web+proto" => Some(GrpcWeb { text: false }),
+            "application/grpc-web-text" | "application/grpc-web-text+proto" => {
+                Some(GrpcWeb { text: true })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        if self.text {
+            "application/grpc-web-text+proto"
+        } else {
+            "application/grpc-web+proto"
+        }
+    }
+
+    // The message in a request body. Calls carry exactly one; a trailing
+    // frame, if a client sends one, is ignored.
+    pub fn request_message(&self, body: &[u8]) -> Result<Vec<u8>, String> {
+        let decoded;
+        let body = if self.text {
+            let text: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            decoded = STANDARD
+                .decode(text)
+                .map_err(|e| format!("body isn't base64: {}", e))?;
+            decoded.as_slice()
+        } else {
+            body
+        };
+        if body.is_empty() {
+            return Ok(Vec::new());
+        }
+        if body.len() < 5 {
+            return Err("the frame header is cut short".to_string());
+        }
+        if body[0] & 0x01 != 0 {
+            return Err("compressed messages aren't supported".to_string());
+        }
+        let length = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+        body.get(5..5 + length)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| format!("the frame says {} bytes but is shorter", length))
+    }
+
+    // A message frame and a trailer frame with grpc-status 0. `trailers`
+    // go in the trailer frame too and can set a different grpc-status.
+    pub fn body(&self, message: &[u8], trailers: &BTreeMap<String, String>) -> Vec<u8> {
+        let mut fields = trailers.clone();
+        fields
+            .entry("grpc-status".to_string())
+            .or_insert_with(|| "0".to_string());
+        let mut bytes = frame(0, message);
+        bytes.extend(frame(TRAILER_FLAG, &trailer_block(&fields)));
+        self.encode(bytes)
+    }
+
+    // A response with no message, only the status the endpoint's HTTP
+    // status maps to and a grpc-message from the payload, either a string
+    // or an object's "message"
+    pub fn error(&self, status: u16, payload: &Value, headers: &HeaderMap) -> Response<Body> {
+        let mut fields = BTreeMap::new();
+        fields.insert("grpc-status".to_string(), code(status).to_string());
+        let message = match payload {
+            Value::String(text) => Some(text.as_str()),
+            other => other.get("message").and_then(Value::as_str),
+        };
+        if let Some(message) = message.filter(|m| !m.is_empty()) {
+            fields.insert("grpc-message".to_string(), percent_encode(message));
+        }
+        let body = self.encode(frame(TRAILER_FLAG, &trailer_block(&fields)));
+        let mut builder = Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, self.content_type());
+        // Clients also look for the status in the headers of such responses
+        for (name, value) in &fields {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        cors(builder, headers).body(Body::from(body)).unwrap()
+    }
+
+    fn encode(&self, bytes: Vec<u8>) -> Vec<u8> {
+        if self.text {
+            STANDARD.encode(bytes).into_bytes()
+        } else {
+            bytes
+        }
+    }
+}
+
+// Lets the page's script read the status, which gRPC-Web puts in headers
+// when there is no message
+pub fn cors(builder: Builder, headers: &HeaderMap) -> Builder {
+    let origin = headers
+        .get(header::ORIGIN)
+        .cloned()
+        .unwrap_or(HeaderValue::from_static("*"));
+    builder
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin)
+        .header(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            "grpc-status, grpc-message",
+        )
+        .header(header::VARY, "Origin")
+}
+
+// Answers the CORS preflight browsers send before a gRPC-Web call, which
+// asks to send the x-grpc-web header; None for other OPTIONS requests
+pub fn preflight(headers: &HeaderMap) -> Option<Response<Body>> {
+    let requested = headers
+        .get(header::ACCESS_CONTROL_REQUEST_HEADERS)?
+        .to_str()
+        .ok()?;
+    if !requested
+        .split(',')
+        .any(|name| name.trim().eq_ignore_ascii_case("x-grpc-web"))
+    {
+        return None;
+    }
+    let builder = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(header::ACCESS_CONTROL_ALLOW_METHODS, "POST, OPTIONS")
+        .header(header::ACCESS_CONTROL_ALLOW_HEADERS, requested)
+        .header(header::ACCESS_CONTROL_MAX_AGE, "600");
+    Some(cors(builder, headers).body(Body::empty()).unwrap())
+}
+
+// Adds the CORS headers to a proxied gRPC-Web response the backend left
+// them off
+pub fn expose(response: &mut Response<Body>, headers: &HeaderMap) {
+    if response
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+    {
+        return;
+    }
+    if let Some(cors) = cors(Response::builder(), headers).headers_ref() {
+        response.headers_mut().extend(cors.clone());
+    }
+}
+
+fn frame(flag: u8, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(5 + payload.len());
+    bytes.push(flag);
+    bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+fn trailer_block(fields: &BTreeMap<String, String>) -> Vec<u8> {
+    fields
+        .iter()
+        .map(|(name, value)| format!("{}:{}\r\n", name.to_ascii_lowercase(), value))
+        .collect::<String>()
+        .into_bytes()
+}
+
+// The gRPC status code closest to an HTTP status
+fn code(status: u16) -> u16 {
+    match status {
+        200..=299 => 0, // OK
+        400 => 3,       // INVALID_ARGUMENT
+        401 => 16,      // UNAUTHENTICATED
+        403 => 7,       // PERMISSION_DENIED
+        404 => 5,       // NOT_FOUND
+        409 => 6,       // ALREADY_EXISTS
+        412 => 9,       // FAILED_PRECONDITION
+        429 => 8,       // RESOURCE_EXHAUSTED
+        499 => 1,       // CANCELLED
+        500 => 13,      // INTERNAL
+        501 => 12,      // UNIMPLEMENTED
+        503 => 14,      // UNAVAILABLE
+        504 => 4,       // DEADLINE_EXCEEDED
+        _ => 2,         // UNKNOWN
+    }
+}
+
+// grpc-message is percent-encoded outside printable ASCII
+fn percent_encode(message: &str) -> String {
+    message
+        .bytes()
+        .map(|b| match b {
+            b' '..=b'~' if b != b'%' => char::from(b).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,160 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::Uri;
+use serde::Deserialize;
+
+use crate::EndpointConfig;
+
+// The parts of an HTTP Archive (HAR 1.2), as browsers' dev tools save it,
+// that requests can be replayed and mocks created from
+#[derive(Deserialize)]
+pub struct Har {
+    pub log: HarLog,
+}
+
+#[derive(Deserialize)]
+pub struct HarLog {
+    pub entries: Vec<HarEntry>,
+}
+
+#[derive(Deserialize)]
+pub struct HarEntry {
+    pub request: HarRequest,
+    pub response: Option<HarResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct HarRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<HarHeader>,
+    #[serde(default, rename = "postData")]
+    pub post_data: Option<HarPostData>,
+}
+
+#[derive(Deserialize)]
+pub struct HarHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Deserialize)]
+pub struct HarPostData {
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+pub struct HarResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub content: Option<HarContent>,
+}
+
+#[derive(Deserialize)]
+pub struct HarContent {
+    #[serde(default, rename = "mimeType")]
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub text: Option<String>,
+    // "base64" for binary bodies
+    #[serde(default)]
+    pub encoding: Option<String>,
+}
+
+impl HarEntry {
+    // An endpoint answering the entry's request with its response. None
+    // for requests that got no response.
+    pub fn endpoint(&self) -> Result<Option<EndpointConfig>, String> {
+        // HAR uses 0 for requests that got no response
+        let Some(response) = self.response.as_ref().filter(|r| r.status != 0) else {
+            return Ok(None);
+        };
+        let uri: Uri = self
+            .request
+            .url
+            .parse()
+            .map_err(|e| format!("{}: {}", self.request.url, e))?;
+        let content = response.content.as_ref();
+        let content_type = content
+            .and_then(|c| c.mime_type.as_deref())
+            .filter(|mime| !mime.is_empty())
+            .unwrap_or("application/json");
+        let text = content.and_then(|c| c.text.as_deref()).unwrap_or_default();
+        let bytes = match content.and_then(|c| c.encoding.as_deref()) {
+            Some("base64") => STANDARD
+                .decode(text)
+                .map_err(|e| format!("{}: body isn't base64: {}", self.request.url, e))?,
+            _ => text.as_bytes().to_vec(),
+        };
+        let payload = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+            Ok(json) => json,
+            Err(_) if bytes.is_empty() => serde_json::Value::Null,
+            Err(_) => serde_json::Value::from(String::from_utf8_lossy(&bytes).into_owned()),
+        };
+        let endpoint = serde_json::json!({
+            "method": self.request.method.to_ascii_uppercase(),
+            "path": uri.path(),
+            "status": response.status,
+            "content_type": content_type,
+            "payload": payload,
+        });
+        serde_json::from_value(endpoint)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+}
+
+// The endpoints as a HAR file, one entry per endpoint with its default
+// response, for tools that read HAR. Templates are left unfilled.
+pub fn from_endpoints(endpoints: &[EndpointConfig], base_url: &str) -> serde_json::Value {
+    let entries: Vec<serde_json::Value> = endpoints
+        .iter()
+        .map(|endpoint| {
+            let text = match &endpoint.payload {
+                serde_json::Value::String(text) => text.clone(),
+                serde_json::Value::Null => String::new(),
+                payload => payload.to_string(),
+            };
+            let status = hyper::StatusCode::from_u16(endpoint.status).ok();
+            serde_json::json!({
+                "startedDateTime": chrono::Utc::now().to_rfc3339(),
+                "time": 0,
+                "request": {
+                    "method": endpoint.method.to_ascii_uppercase(),
+                    "url": format!("{}{}", base_url, endpoint.path),
+                    "httpVersion": "HTTP/1.1",
+                    "cookies": [],
+                    "headers": [],
+                    "queryString": [],
+                    "headersSize": -1,
+                    "bodySize": -1,
+                },
+                "response": {
+                    "status": endpoint.status,
+                    "statusText": status.and_then(|s| s.canonical_reason()).unwrap_or(""),
+                    "httpVersion": "HTTP/1.1",
+                    "cookies": [],
+                    "headers": [{ "name": "Content-Type", "value": endpoint.content_type }],
+                    "content": {
+                        "size": text.len(),
+                        "mimeType": endpoint.content_type,
+                        "text": text,
+                    },
+                    "redirectURL": "",
+                    "headersSize": -1,
+                    "bodySize": text.len(),
+                },
+                "cache": {},
+                "timings": { "send": 0, "wait": 0, "receive": 0 },
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "log": {
+            "version": "1.2",
+            "creator": { "name": "mock-api", "version": env!("CARGO_PKG_VERSION") },
+            "entries": entries,
+        }
+    })
+}
@@ -0,0 +1,221 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Response, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use utoipa::ToSchema;
+
+use crate::AppState;
+
+// Answers retries carrying the same idempotency key with the first response,
+// as payment-style APIs do, so client retry logic can be tested
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct Idempotency {
+    #[serde(default = "default_header")]
+    pub header: String,
+    // What a retry gets: the first response again, or `conflict_status`
+    #[serde(default, skip_serializing_if = "OnReplay::is_default")]
+    pub on_replay: OnReplay,
+    #[serde(default = "default_conflict_status")]
+    pub conflict_status: u16,
+    // How long keys are remembered, 24 hours by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+    // Without the header requests are served as usual unless this is set
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub required: bool,
+}
+
+fn default_header() -> String {
+    "Idempotency-Key".to_string()
+}
+
+fn default_conflict_status() -> u16 {
+    409
+}
+
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OnReplay {
+    #[default]
+    Replay,
+    Conflict,
+}
+
+impl OnReplay {
+    fn is_default(&self) -> bool {
+        *self == OnReplay::default()
+    }
+}
+
+impl Idempotency {
+    pub fn key(&self, headers: &HeaderMap) -> Option<String> {
+        let key = headers.get(self.header.as_str())?.to_str().ok()?.trim();
+        (!key.is_empty()).then(|| key.to_string())
+    }
+
+    pub fn missing_key(&self) -> Response<Body> {
+        error(
+            StatusCode::BAD_REQUEST,
+            &format!("Missing {} header", self.header),
+        )
+    }
+}
+
+// A key seen before, with the request body it came with and the response
+// kept for replays
+struct Cached {
+    request_body: Vec<u8>,
+    response: Option<Stored>, // None while the first request is being answered
+    expires: Option<Instant>, // None when the TTL goes past what Instant holds
+}
+
+struct Stored {
+    status: u16,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct IdempotencyCache {
+    entries: Mutex<HashMap<String, Cached>>,
+}
+
+// What to do with a request carrying an idempotency key
+pub enum Claim {
+    // The key is new and now held until the response is stored
+    New(Reservation),
+    // A retry, answered without serving it again
+    Answered(Response<Body>),
+}
+
+// A key held for the request answering it first. Retries meanwhile get a
+// 409. It's let go if the request ends without storing its response, e.g.
+// when a long poll times out.
+pub struct Reservation {
+    cache: Arc<IdempotencyCache>,
+    key: Option<String>,
+}
+
+impl IdempotencyCache {
+    // Holds `key` for this request if it's new; otherwise the answer to the
+    // retry. Both happen under one lock, so only one of two requests racing
+    // with the same key is served.
+    pub fn claim(
+        self: &Arc<Self>,
+        config: &Idempotency,
+        key: String,
+        request_body: &[u8],
+    ) -> Claim {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, cached| cached.expires.is_none_or(|expires| expires > now));
+        let Some(cached) = entries.get(&key) else {
+            let ttl = config.ttl_secs.map_or(DEFAULT_TTL, Duration::from_secs);
+            entries.insert(
+                key.clone(),
+                Cached {
+                    request_body: request_body.to_vec(),
+                    response: None,
+                    expires: now.checked_add(ttl),
+                },
+            );
+            return Claim::New(Reservation {
+                cache: self.clone(),
+                key: Some(key),
+            });
+        };
+        if cached.request_body != request_body {
+            return Claim::Answered(error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency key reused with a different request body",
+            ));
+        }
+        let Some(stored) = &cached.response else {
+            return Claim::Answered(error(
+                StatusCode::CONFLICT,
+                "A request with this idempotency key is still being processed",
+            ));
+        };
+        Claim::Answered(match config.on_replay {
+            OnReplay::Replay => Response::builder()
+                .status(StatusCode::from_u16(stored.status).unwrap())
+                .header(header::CONTENT_TYPE, &stored.content_type)
+                .header("Idempotent-Replayed", "true")
+                .body(Body::from(stored.body.clone()))
+                .unwrap(),
+            OnReplay::Conflict => error(
+                StatusCode::from_u16(config.conflict_status).unwrap_or(StatusCode::CONFLICT),
+                "Request with this idempotency key was already processed",
+            ),
+        })
+    }
+
+    fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl Reservation {
+    // Keeps the response for retries of the key
+    pub fn store(mut self, status: u16, content_type: &str, body: &[u8]) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        // Still there unless the keys were cleared meanwhile
+        if let Some(cached) = self.cache.entries.lock().unwrap().get_mut(&key) {
+            cached.response = Some(Stored {
+                status,
+                content_type: content_type.to_string(),
+                body: body.to_vec(),
+            });
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut entries = self.cache.entries.lock().unwrap();
+            if entries
+                .get(&key)
+                .is_some_and(|cached| cached.response.is_none())
+            {
+                entries.remove(&key);
+            }
+        }
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::json!({ "error": message }).to_string(),
+        ))
+        .unwrap()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/mockserver/admin/idempotency",
+    tag = "idempotency",
+    responses((status = 200, description = "Every remembered idempotency key was forgotten"))
+)]
+pub async fn clear_keys(State(state): State<AppState>) -> Response<Body> {
+    state.idempotency.clear();
+    tracing::info!("Forgot all idempotency keys");
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from("Idempotency keys cleared"))
+        .unwrap()
+}
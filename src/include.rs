@@ -0,0 +1,250 @@
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{interpolate, secrets, Settings};
+
+// How settings.json was put together, so it can be written back the same way
+#[derive(Default)]
+pub struct Source {
+    // What the includes alone provide
+    included: Option<Value>,
+    // Values expanded from `${VAR}` or `${secret:NAME}` and the templates
+    // they came from
+    templates: Mutex<HashMap<String, String>>,
+    // What each secret read at startup resolved to
+    secrets: HashMap<String, String>,
+}
+
+impl Source {
+    // Settings as written back by `write_settings_to_file`: `include` left
+    // as in the file, only what differs from the included files, and
+    // environment variables and secrets unexpanded
+    pub fn file_contents(&self, settings: &Settings) -> serde_json::Result<Value> {
+        let mut value = serde_json::to_value(settings)?;
+        if let Some(included) = &self.included {
+            strip(&mut value, included, "");
+        }
+        interpolate::restore(&mut value, &self.templates.lock().unwrap());
+        Ok(value)
+    }
+
+    // Settings as kept in Redis, pushed to cluster peers and exported:
+    // complete, but written like settings.json, with environment variables
+    // and secrets unexpanded and without the secrets' sources
+    pub fn shared_contents(&self, settings: &Settings) -> serde_json::Result<Value> {
+        let mut value = serde_json::to_value(settings)?;
+        interpolate::restore(&mut value, &self.templates.lock().unwrap());
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("secrets");
+        }
+        Ok(value)
+    }
+
+    // Reads shared settings, expanding them with this instance's
+    // environment and secrets
+    pub fn expand(&self, mut value: Value) -> Result<Settings, String> {
+        let mut templates = self.templates.lock().unwrap();
+        interpolate::expand(&mut value, &mut templates)?;
+        interpolate::expand_secrets(&mut value, &self.secrets, &mut templates)?;
+        serde_json::from_value(value).map_err(|e| e.to_string())
+    }
+}
+
+// Loads a settings file together with the files its `include` lists, which
+// are merged underneath it in order (see `merge`)
+pub fn load(path: &Path) -> Result<(Settings, Source), String> {
+    let mut templates = HashMap::new();
+    let (mut included, own) = read(path, &mut Vec::new(), &mut templates)?;
+    let mut merged = match &included {
+        Some(base) => merge(base.clone(), own, ""),
+        None => own,
+    };
+
+    // Secrets are only substituted once every file has been merged, as any
+    // of them may define or use one
+    let sources = match merged.get("secrets") {
+        Some(sources) => serde_json::from_value(sources.clone())
+            .map_err(|e| format!("{}: secrets: {}", path.display(), e))?,
+        None => BTreeMap::new(),
+    };
+    let secrets = secrets::resolve(&sources)?;
+    interpolate::expand_secrets(&mut merged, &secrets, &mut templates)?;
+    if let Some(base) = &mut included {
+        interpolate::expand_secrets(base, &secrets, &mut templates)?;
+    }
+
+    let settings: Settings =
+        serde_json::from_value(merged).map_err(|e| format!("{}: {}", path.display(), e))?;
+    // Compared in the same shape as saved settings when possible
+    let included = included.map(
+        |base| match serde_json::from_value::<Settings>(base.clone()) {
+            Ok(settings) => serde_json::to_value(settings).unwrap_or(base),
+            Err(_) => base,
+        },
+    );
+    let source = Source {
+        included,
+        templates: Mutex::new(templates),
+        secrets,
+    };
+    Ok((settings, source))
+}
+
+// A file's includes merged together, and the file itself without them
+fn read(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    templates: &mut HashMap<String, String>,
+) -> Result<(Option<Value>, Value), String> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    if stack.contains(&canonical) {
+        return Err(format!("{} includes itself", path.display()));
+    }
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut own: Value = serde_json::from_str(&text)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    interpolate::expand(&mut own, templates).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let includes = match own.get("include") {
+        None => return Ok((None, own)),
+        Some(includes) => serde_json::from_value::<Vec<String>>(includes.clone())
+            .map_err(|_| format!("{}: include must be a list of paths", path.display()))?,
+    };
+    // Only the top file's list is kept; nested ones are resolved here
+    if !stack.is_empty() {
+        own.as_object_mut().map(|own| own.remove("include"));
+    }
+
+    // Paths are relative to the including file
+    let dir = path.parent().unwrap_or(Path::new(""));
+    stack.push(canonical);
+    let mut included: Option<Value> = None;
+    for include in includes {
+        let (base, file) = read(&dir.join(&include), stack, templates)?;
+        let file = match base {
+            Some(base) => merge(base, file, ""),
+            None => file,
+        };
+        included = Some(match included {
+            Some(included) => merge(included, file, ""),
+            None => file,
+        });
+    }
+    stack.pop();
+    Ok((included, own))
+}
+
+// Lays `overlay` over `base`. Objects merge key by key; endpoint lists merge
+// by endpoint `id` (or method and path when there is none), workspaces by
+// `name` and virtual hosts by `hosts`, with the overlay's item replacing the
+// base's in place and new items appended. Anything else in the overlay
+// replaces the base's value.
+fn merge(base: Value, overlay: Value, field: &str) -> Value {
+    match (base, overlay) {
+        (Value::Object(mut base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let child = child_field(field, &key);
+                let merged = match base.remove(&key) {
+                    Some(existing) => merge(existing, value, child),
+                    None => value,
+                };
+                base.insert(key, merged);
+            }
+            Value::Object(base)
+        }
+        (Value::Array(mut base), Value::Array(overlay)) if item_key(field).is_some() => {
+            let key = item_key(field).unwrap();
+            for item in overlay {
+                match key(&item).and_then(|k| base.iter().position(|b| key(b).as_ref() == Some(&k)))
+                {
+                    Some(i) if field == "workspaces" => {
+                        let existing = std::mem::take(&mut base[i]);
+                        base[i] = merge(existing, item, "");
+                    }
+                    Some(i) => base[i] = item,
+                    None => base.push(item),
+                }
+            }
+            Value::Array(base)
+        }
+        (_, overlay) => overlay,
+    }
+}
+
+// Removes from `local` whatever `base` already provides, so merging the
+// result over `base` gives `local` back
+fn strip(local: &mut Value, base: &Value, field: &str) {
+    match (local, base) {
+        (Value::Object(local), Value::Object(base)) => {
+            let keys: Vec<String> = local.keys().cloned().collect();
+            for key in keys {
+                let Some(base) = base.get(&key) else {
+                    continue;
+                };
+                if local.get(&key) == Some(base) {
+                    local.remove(&key);
+                } else if let Some(value) = local.get_mut(&key) {
+                    strip(value, base, child_field(field, &key));
+                }
+            }
+        }
+        (Value::Array(local), Value::Array(base)) => {
+            let Some(key) = item_key(field) else {
+                return;
+            };
+            local.retain_mut(|item| {
+                let existing =
+                    key(item).and_then(|k| base.iter().find(|b| key(b) == Some(k.clone())));
+                let Some(existing) = existing else {
+                    return true;
+                };
+                if item == existing {
+                    return false;
+                }
+                if field == "workspaces" {
+                    strip(item, existing, "");
+                    if let Some(name) = existing.get("name") {
+                        item["name"] = name.clone();
+                    }
+                }
+                true
+            });
+        }
+        _ => {}
+    }
+}
+
+// Profiles hold endpoint lists under arbitrary names
+fn child_field<'a>(parent: &str, key: &'a str) -> &'a str {
+    if parent == "profiles" {
+        "endpoints"
+    } else {
+        key
+    }
+}
+
+type ItemKey = fn(&Value) -> Option<String>;
+
+fn item_key(field: &str) -> Option<ItemKey> {
+    match field {
+        "endpoints" => Some(endpoint_key),
+        "workspaces" => Some(|item| item.get("name")?.as_str().map(str::to_string)),
+        "virtual_hosts" => Some(|item| item.get("hosts").map(Value::to_string)),
+        _ => None,
+    }
+}
+
+fn endpoint_key(endpoint: &Value) -> Option<String> {
+    if let Some(id) = endpoint.get("id").and_then(Value::as_str) {
+        return Some(format!("id {}", id));
+    }
+    let method = endpoint.get("method")?.as_str()?.to_ascii_uppercase();
+    let path = endpoint.get("path")?.as_str()?;
+    Some(format!("{} {}", method, path))
+}
@@ -0,0 +1,138 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+// What a `${...}` reference expands to; None leaves it as written
+type Lookup<'a> = dyn Fn(&str, Option<&str>) -> Result<Option<String>, String> + 'a;
+
+// Expands `${VAR}` and `${VAR:-default}` in every string of `value` from the
+// environment. `${secret:NAME}` and `$${` are left for `expand_secrets`.
+// Expanded strings are added to `templates` so they can be written back as
+// they were.
+pub fn expand(value: &mut Value, templates: &mut HashMap<String, String>) -> Result<(), String> {
+    walk(value, templates, &|text| {
+        substitute(text, false, &|name, default| {
+            if name.starts_with("secret:") {
+                return Ok(None);
+            }
+            // Like the shell, an empty variable also takes the default
+            let value = std::env::var(name)
+                .ok()
+                .filter(|value| !value.is_empty() || default.is_none());
+            match (value, default) {
+                (Some(value), _) => Ok(Some(value)),
+                (None, Some(default)) => Ok(Some(default.to_string())),
+                (None, None) => Err(format!(
+                    "environment variable {} is not set (use ${{{}:-default}} for a fallback)",
+                    name, name
+                )),
+            }
+        })
+    })
+}
+
+// Replaces `${secret:NAME}` with the secret's value and `$${` with a literal
+// `${`, recording templates like `expand`
+pub fn expand_secrets(
+    value: &mut Value,
+    secrets: &HashMap<String, String>,
+    templates: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    walk(value, templates, &|text| {
+        substitute(text, true, &|name, _| match name.strip_prefix("secret:") {
+            Some(secret) => match secrets.get(secret) {
+                Some(value) => Ok(Some(value.clone())),
+                None => Err(format!("unknown secret {} (see secrets)", secret)),
+            },
+            None => Ok(None),
+        })
+    })
+}
+
+fn walk(
+    value: &mut Value,
+    templates: &mut HashMap<String, String>,
+    expand: &dyn Fn(&str) -> Result<String, String>,
+) -> Result<(), String> {
+    match value {
+        Value::String(text) if text.contains("${") => {
+            let expanded = expand(text)?;
+            if expanded != *text {
+                // A string expanded twice maps back to what was written
+                let original = templates.get(text.as_str()).unwrap_or(text).clone();
+                templates.insert(expanded.clone(), original);
+                *text = expanded;
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                walk(item, templates, expand)?;
+            }
+        }
+        Value::Object(fields) => {
+            for field in fields.values_mut() {
+                walk(field, templates, expand)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn substitute(text: &str, unescape: bool, lookup: &Lookup) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            out.push_str(if unescape { "${" } else { "$${" });
+            rest = after;
+            continue;
+        }
+        let Some(after) = rest.strip_prefix("${") else {
+            out.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let end = after
+            .find('}')
+            .ok_or_else(|| format!("unclosed ${{ in \"{}\"", text))?;
+        let (name, default) = match after[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&after[..end], None),
+        };
+        match lookup(name, default)? {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[..end + 3]),
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+// Puts the `${...}` templates back in place of the values they expanded to.
+// Any other `${`, e.g. in a payload set through the admin API, is escaped so
+// it isn't expanded when read back.
+pub fn restore(value: &mut Value, templates: &HashMap<String, String>) {
+    match value {
+        Value::String(text) => {
+            if let Some(template) = templates.get(text.as_str()) {
+                *text = template.clone();
+            } else if text.contains("${") {
+                *text = text.replace("${", "$${");
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                restore(item, templates);
+            }
+        }
+        Value::Object(fields) => {
+            for field in fields.values_mut() {
+                restore(field, templates);
+            }
+        }
+        _ => {}
+    }
+}
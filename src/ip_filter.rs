@@ -0,0 +1,55 @@
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::net::IpAddr;
+use utoipa::ToSchema;
+
+// Allow/deny lists of CIDR ranges. A bare address is treated as a single-host
+// range. Deny entries always win, and an empty allow list lets through every
+// address that isn't denied.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, ToSchema)]
+pub struct IpFilter {
+    #[schema(value_type = Vec<String>)]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_cidrs",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub allow: Vec<IpNet>,
+    #[schema(value_type = Vec<String>)]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_cidrs",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub deny: Vec<IpNet>,
+}
+
+impl IpFilter {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
+
+fn deserialize_cidrs<'de, D>(deserializer: D) -> Result<Vec<IpNet>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries = Vec::<String>::deserialize(deserializer)?;
+    entries
+        .iter()
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| serde::de::Error::custom(format!("invalid CIDR range: {}", entry)))
+        })
+        .collect()
+}
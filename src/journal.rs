@@ -0,0 +1,202 @@
+use axum::http::{request::Parts, Response};
+use chrono::{DateTime, Utc};
+use hyper::Body;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    redact::Redaction, request_id::RequestId, upstream::UpstreamTiming, ClientKey, MatchedEndpoint,
+    ProxiedTo, UpstreamTimed,
+};
+
+// Sent by test jobs to tag their requests, so parallel runs against one
+// server can each pick out their own
+pub const TEST_RUN_HEADER: &str = "x-test-run-id";
+
+// A request seen by the mock server and how it was answered
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JournalEntry {
+    pub id: u64,
+    // The request's X-Request-Id, as sent or generated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    #[schema(value_type = String)]
+    pub client_ip: IpAddr,
+    // The client key, when client_key_header is set and was sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    // The X-Test-Run-Id header, if sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_run: Option<String>,
+    // The scenario being recorded when it arrived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<String>,
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxied_to: Option<String>,
+    // Where the time went, for proxied requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_timing: Option<UpstreamTiming>,
+    pub status: u16,
+    pub duration_ms: u64,
+}
+
+impl JournalEntry {
+    pub fn new(client_ip: IpAddr, parts: &Parts) -> Self {
+        let mut headers = BTreeMap::new();
+        for (name, value) in &parts.headers {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            headers
+                .entry(name.to_string())
+                .and_modify(|existing: &mut String| {
+                    existing.push_str(", ");
+                    existing.push_str(&value);
+                })
+                .or_insert(value);
+        }
+        JournalEntry {
+            id: 0,
+            request_id: parts.extensions.get::<RequestId>().map(|id| id.0.clone()),
+            timestamp: Utc::now(),
+            client_ip,
+            client: parts.extensions.get::<ClientKey>().map(|key| key.0.clone()),
+            test_run: parts
+                .headers
+                .get(TEST_RUN_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|run| !run.is_empty())
+                .map(str::to_string),
+            scenario: None,
+            method: parts.method.to_string(),
+            path: parts.uri.path().to_string(),
+            query: parts.uri.query().map(str::to_string),
+            headers,
+            body: String::new(),
+            matched: None,
+            proxied_to: None,
+            upstream_timing: None,
+            status: 0,
+            duration_ms: 0,
+        }
+    }
+
+    pub fn set_body(&mut self, body: &[u8]) {
+        self.body = String::from_utf8_lossy(body).into_owned();
+    }
+
+    // Fills in the outcome from the response and the markers left on it
+    pub fn finish(&mut self, response: &Response<Body>, elapsed: Duration) {
+        self.status = response.status().as_u16();
+        self.matched = response
+            .extensions()
+            .get::<MatchedEndpoint>()
+            .map(|m| m.0.clone());
+        self.proxied_to = response
+            .extensions()
+            .get::<ProxiedTo>()
+            .map(|p| p.0.clone());
+        self.upstream_timing = response
+            .extensions()
+            .get::<UpstreamTimed>()
+            .map(|t| t.0.clone());
+        self.duration_ms = elapsed.as_millis() as u64;
+    }
+
+    // Masks what shouldn't be kept, before the entry is stored
+    pub fn redact(&mut self, redaction: &Redaction) {
+        for (name, value) in self.headers.iter_mut() {
+            if redaction.hides_header(name) {
+                *value = redaction.mask_text();
+            }
+        }
+        self.query = self.query.as_deref().map(|query| redaction.query(query));
+        self.body = redaction.body(&self.body);
+    }
+}
+
+// Narrows the journal to one client's requests (see client_key_header), one
+// test run's, or both
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JournalFilter {
+    pub client: Option<String>,
+    pub test_run: Option<String>,
+}
+
+impl JournalFilter {
+    pub fn is_empty(&self) -> bool {
+        self.client.is_none() && self.test_run.is_none()
+    }
+
+    pub fn selects(&self, entry: &JournalEntry) -> bool {
+        let matches =
+            |wanted: &Option<String>, value: &Option<String>| wanted.is_none() || wanted == value;
+        matches(&self.client, &entry.client) && matches(&self.test_run, &entry.test_run)
+    }
+}
+
+// Bounded, in-memory log of recent requests; the oldest entries are dropped
+// once `capacity` is reached.
+pub struct Journal {
+    capacity: usize,
+    next_id: AtomicU64,
+    entries: Mutex<VecDeque<JournalEntry>>,
+}
+
+impl Journal {
+    pub fn new(capacity: usize) -> Self {
+        Journal {
+            capacity,
+            next_id: AtomicU64::new(1),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // Stores the entry and returns the id assigned to it
+    pub fn record(&self, mut entry: JournalEntry) -> u64 {
+        entry.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let id = entry.id;
+        if self.capacity == 0 {
+            return id;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<JournalEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().find(|entry| entry.id == id).cloned()
+    }
+
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    // Clears the entries `filter` selects, which is everything when empty
+    pub fn clear(&self, filter: &JournalFilter) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|entry| !filter.selects(entry));
+    }
+}
@@ -0,0 +1,59 @@
+use axum::http::{header, HeaderMap};
+
+// The best of `available` for the request's Accept-Language. Each range the
+// client accepts, most preferred first, takes an exact match, then a match
+// with its subtags dropped one at a time (fr-CA finds fr), then any tag of
+// the same language (fr finds fr-CA). `*` takes the first available.
+pub fn negotiate<'a>(headers: &HeaderMap, available: &[&'a str]) -> Option<&'a str> {
+    let accepted = headers.get(header::ACCEPT_LANGUAGE)?.to_str().ok()?;
+    for range in preferences(accepted) {
+        if range == "*" {
+            return available.first().copied();
+        }
+        let mut prefix = range;
+        loop {
+            let matching = available
+                .iter()
+                .find(|tag| tag.eq_ignore_ascii_case(prefix));
+            if let Some(tag) = matching {
+                return Some(tag);
+            }
+            match prefix.rsplit_once('-') {
+                Some((shorter, _)) => prefix = shorter,
+                None => break,
+            }
+        }
+        let language = |tag: &str| {
+            tag.split('-')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase()
+        };
+        if let Some(tag) = available
+            .iter()
+            .find(|tag| language(tag) == language(range))
+        {
+            return Some(tag);
+        }
+    }
+    None
+}
+
+// Language ranges by falling quality, those with q=0 left out
+fn preferences(header: &str) -> Vec<&str> {
+    let mut ranges: Vec<(&str, f32)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let range = parts.next()?.trim();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse().ok())
+                .unwrap_or(1.0);
+            (!range.is_empty() && quality > 0.0).then_some((range, quality))
+        })
+        .collect();
+    // Stable, so equal qualities keep the client's order
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges.into_iter().map(|(range, _)| range).collect()
+}
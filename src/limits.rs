@@ -0,0 +1,63 @@
+use axum::{
+    body::Body,
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+    BoxError,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// Per-endpoint in-flight caps. Semaphores are keyed by method and path rather
+// than endpoint index so that reloading the endpoints doesn't reset requests
+// that are already holding a permit.
+#[derive(Default)]
+pub struct EndpointLimits {
+    semaphores: Mutex<HashMap<(String, String), LimitSemaphore>>,
+}
+
+// The configured cap alongside the semaphore enforcing it
+type LimitSemaphore = (usize, Arc<Semaphore>);
+
+impl EndpointLimits {
+    // Returns None when the endpoint already has `max` requests in flight
+    pub fn try_acquire(
+        &self,
+        method: &str,
+        path: &str,
+        max: usize,
+    ) -> Option<OwnedSemaphorePermit> {
+        let semaphore = {
+            let mut semaphores = self.semaphores.lock().unwrap();
+            let entry = semaphores
+                .entry((method.to_ascii_uppercase(), path.to_string()))
+                .or_insert_with(|| (max, Arc::new(Semaphore::new(max))));
+            if entry.0 != max {
+                *entry = (max, Arc::new(Semaphore::new(max)));
+            }
+            entry.1.clone()
+        };
+        semaphore.try_acquire_owned().ok()
+    }
+}
+
+pub fn service_unavailable(reason: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(header::RETRY_AFTER, "1")
+        .body(Body::from(format!("Service Unavailable: {}", reason)))
+        .unwrap()
+}
+
+// Turns errors from the global load-shedding layers into a 503
+pub async fn handle_overload(err: BoxError) -> impl IntoResponse {
+    if err.is::<tower::load_shed::error::Overloaded>() {
+        tracing::warn!("Shedding request: max_in_flight reached");
+        service_unavailable("too many requests in flight").into_response()
+    } else {
+        tracing::error!("Unhandled middleware error: {}", err);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into_response()
+    }
+}
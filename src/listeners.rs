@@ -0,0 +1,123 @@
+use axum::{Extension, Router};
+use std::{collections::BTreeMap, io, net::SocketAddr, sync::Arc};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, oneshot, Mutex},
+};
+
+use crate::{connection, ip_filter::IpFilter, startup::Listener, ListenerPort};
+
+// A listener being served; dropping `stop` closes it and lets its
+// connections drain
+struct Running {
+    addr: SocketAddr,
+    _stop: oneshot::Sender<()>,
+}
+
+// The HTTP listeners, keyed by the configured port they serve. Ports can be
+// added and removed while serving, e.g. when imported settings move a
+// workspace to another port.
+pub struct Listeners {
+    // Behind a lock as a Router is Send but not Sync
+    app: std::sync::Mutex<Option<Router>>,
+    ip_filter: Arc<IpFilter>,
+    running: Mutex<BTreeMap<u16, Vec<Running>>>,
+    failed: mpsc::UnboundedSender<(SocketAddr, hyper::Error)>,
+    failures: Mutex<mpsc::UnboundedReceiver<(SocketAddr, hyper::Error)>>,
+}
+
+impl Listeners {
+    pub fn new(ip_filter: Arc<IpFilter>) -> Self {
+        let (failed, failures) = mpsc::unbounded_channel();
+        Listeners {
+            app: std::sync::Mutex::new(None),
+            ip_filter,
+            running: Mutex::new(BTreeMap::new()),
+            failed,
+            failures: Mutex::new(failures),
+        }
+    }
+
+    // Starts serving `app` on the listeners bound at startup
+    pub async fn start(&self, app: Router, listeners: Vec<Listener>) {
+        let mut running = self.running.lock().await;
+        for bound in listeners {
+            let port = bound.port;
+            if bound.inherited {
+                println!("Listening on {} (from systemd)", bound.addr);
+            } else {
+                println!("Listening on {}", bound.addr);
+            }
+            let listener = self.serve(&app, bound.addr, port, bound.listener);
+            running.entry(port).or_default().push(listener);
+        }
+        *self.app.lock().unwrap() = Some(app);
+    }
+
+    // Listens on exactly `ports`. New ports are all bound before anything
+    // changes, so a taken port leaves the current listeners as they are.
+    // Listeners on ports that went away stop accepting and finish their
+    // in-flight requests in the background.
+    pub async fn set_ports(&self, ports: &[u16]) -> io::Result<()> {
+        let Some(app) = self.app.lock().unwrap().clone() else {
+            return Ok(());
+        };
+        let mut running = self.running.lock().await;
+        let mut bound = Vec::new();
+        for &port in ports.iter().filter(|port| !running.contains_key(port)) {
+            let addr = SocketAddr::from(([0, 0, 0, 0], port));
+            let listener = TcpListener::bind(addr)
+                .await
+                .map_err(|e| io::Error::new(e.kind(), format!("Failed to bind {}: {}", addr, e)))?;
+            bound.push((addr, port, listener));
+        }
+        for (addr, port, listener) in bound {
+            tracing::info!("Listening on {}", addr);
+            let listener = self.serve(&app, addr, port, listener);
+            running.insert(port, vec![listener]);
+        }
+        let removed: Vec<u16> = running
+            .keys()
+            .filter(|port| !ports.contains(port))
+            .copied()
+            .collect();
+        for port in removed {
+            for listener in running.remove(&port).unwrap_or_default() {
+                tracing::info!(
+                    "Stopped listening on {}; draining its connections",
+                    listener.addr
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Waits until a listener fails, which should stop the server
+    pub async fn failure(&self) -> (SocketAddr, hyper::Error) {
+        let mut failures = self.failures.lock().await;
+        // The sender lives as long as `self`, so this never ends
+        match failures.recv().await {
+            Some(failure) => failure,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn serve(&self, app: &Router, addr: SocketAddr, port: u16, listener: TcpListener) -> Running {
+        let (stop, stopped) = oneshot::channel::<()>();
+        let app = app.clone().layer(Extension(ListenerPort(port)));
+        let failed = self.failed.clone();
+        let ip_filter = self.ip_filter.clone();
+        tokio::spawn(async move {
+            let shutdown = async {
+                let _ = stopped.await;
+            };
+            match connection::serve(listener, app, ip_filter, shutdown).await {
+                Ok(()) => tracing::info!("Listener on {} drained", addr),
+                Err(e) => {
+                    let _ = failed.send((addr, e));
+                }
+            }
+        });
+        Running { addr, _stop: stop }
+    }
+}
@@ -0,0 +1,141 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Response, StatusCode},
+};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::Duration,
+};
+use utoipa::ToSchema;
+
+use crate::AppState;
+
+pub const LOGIN_COOKIE: &str = "mock_session";
+
+pub type SessionValues = BTreeMap<String, serde_json::Value>;
+
+// Simulated logins for mocked APIs. A `login` endpoint starts a session and
+// sets its cookie, `require` endpoints answer 401 without one, and `logout`
+// ends it. Payloads can use the session's values as {{session.NAME}}.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum SessionAction {
+    Login {
+        // Templates over the login request, e.g. {"user": "{{body.username}}"}
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        #[schema(value_type = Object)]
+        values: SessionValues,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl_secs: Option<u64>,
+    },
+    Require,
+    Logout,
+}
+
+struct MockSession {
+    values: SessionValues,
+    expires: Option<DateTime<Utc>>, // By the mock clock
+}
+
+// Sessions of mocked logins, kept in memory
+#[derive(Default)]
+pub struct MockSessions {
+    sessions: Mutex<HashMap<String, MockSession>>,
+}
+
+impl MockSessions {
+    // The id and values of the request's live session at `now`
+    pub fn get(&self, headers: &HeaderMap, now: DateTime<Utc>) -> Option<(String, SessionValues)> {
+        let id = cookie(headers)?;
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(&id)?;
+        if session.expires.is_some_and(|expires| expires <= now) {
+            sessions.remove(&id);
+            return None;
+        }
+        Some((id, session.values.clone()))
+    }
+
+    // Starts a session and returns its id
+    pub fn login(
+        &self,
+        values: SessionValues,
+        ttl: Option<Duration>,
+        now: DateTime<Utc>,
+    ) -> String {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, s| s.expires.is_none_or(|expires| expires > now));
+        let id = random_id();
+        sessions.insert(
+            id.clone(),
+            MockSession {
+                values,
+                expires: ttl
+                    .and_then(|ttl| now.checked_add_signed(chrono::Duration::from_std(ttl).ok()?)),
+            },
+        );
+        id
+    }
+
+    pub fn logout(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+}
+
+pub fn login_cookie(id: &str, ttl: Option<Duration>) -> String {
+    let mut cookie = format!("{}={}; Path=/; HttpOnly", LOGIN_COOKIE, id);
+    if let Some(ttl) = ttl {
+        cookie.push_str(&format!("; Max-Age={}", ttl.as_secs()));
+    }
+    cookie
+}
+
+pub fn logout_cookie() -> String {
+    format!("{}=; Path=/; HttpOnly; Max-Age=0", LOGIN_COOKIE)
+}
+
+pub fn unauthorized() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"error":"Not logged in"}"#))
+        .unwrap()
+}
+
+fn cookie(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .find_map(|pair| {
+            let (name, value) = pair.trim().split_once('=')?;
+            (name == LOGIN_COOKIE).then(|| value.to_string())
+        })
+}
+
+fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/mockserver/admin/logins",
+    tag = "logins",
+    responses((status = 200, description = "Every mocked login session was ended"))
+)]
+pub async fn clear_logins(State(state): State<AppState>) -> Response<Body> {
+    state.logins.sessions.lock().unwrap().clear();
+    tracing::info!("Ended all mocked login sessions");
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from("Login sessions cleared"))
+        .unwrap()
+}
@@ -1,301 +1,3076 @@
+mod alerts;
+mod assets;
+mod audit;
+mod bench;
+mod chain;
+mod chaos;
+mod cli;
+mod clock;
+mod cluster;
+mod codec;
+mod conditions;
+mod config_map;
+mod config_source;
+mod connection;
+mod control;
+mod csv;
+mod curl;
+mod dates;
+mod debug_headers;
+mod events;
+mod expect;
+mod explain;
+mod faker;
+mod fault;
+mod git;
+mod grpc_web;
+mod har;
+mod hop_by_hop;
+mod idempotency;
+mod include;
+mod interpolate;
+mod ip_filter;
+mod journal;
+mod language;
+mod limits;
+mod listeners;
+mod login;
+mod matching;
+mod mdns;
+mod metrics;
+mod ndjson;
+mod openapi;
+mod partials;
+mod protobuf;
+mod random;
+mod rbac;
+mod recording;
+mod redact;
+mod redirect;
+mod replay;
+mod request_id;
+mod request_schema;
+mod scenario;
+mod schedule;
+mod secrets;
+mod session;
+mod snapshot;
+mod socks;
+mod startup;
+mod static_files;
+mod store;
+mod systemd;
+mod tcp_mock;
+mod tee;
+mod trailers;
+mod transform;
+mod tui;
+mod unmatched;
+mod upstream;
+mod versions;
+mod vhost;
+mod webhook;
+mod websocket;
+mod workspace;
+mod xml;
+
+use alerts::{AlertSettings, Alerts};
+use audit::AuditLog;
 use axum::{
     body::Body,
-    extract::{Json, State},
-    http::{Request, Response, StatusCode},
+    error_handling::HandleErrorLayer,
+    extract::{ConnectInfo, DefaultBodyLimit, Json, Path, Query, State},
+    http::{header, request::Parts, HeaderMap, Method, Request, Response, StatusCode, Uri},
+    middleware::{self, Next},
     response::{Html, IntoResponse},
     routing::get_service,
-    Router,
+    Extension, Router,
 };
-use hyper::Client;
-use hyper::{body::to_bytes, client::HttpConnector};
-use hyper_rustls::HttpsConnectorBuilder;
+use chain::MockChain;
+use chaos::{BackendChaos, ChaosPolicy};
+use cluster::{Cluster, ClusterSettings};
+use codec::Codec;
+use conditions::Condition;
+use config_source::ConfigSource;
+use connection::KeepAlive;
+use csv::CsvPayload;
+use debug_headers::MatchOutcome;
+use events::{Events, LongPoll};
+use expect::ExpectContinue;
+use fault::Fault;
+use git::{ConfigRepo, GitSettings};
+use grpc_web::GrpcWeb;
+use hyper::body::{Bytes, HttpBody};
+use idempotency::{Claim, Idempotency, IdempotencyCache};
+use include::Source;
+use ip_filter::IpFilter;
+use journal::{JournalEntry, JournalFilter};
+use limits::EndpointLimits;
+use listeners::Listeners;
+use login::{MockSessions, SessionAction};
+use matching::{HeadRequests, MethodMismatch, PathMatching};
 use matchit::Router as MatchItRouter;
+use mdns::MdnsSettings;
+use metrics::{Metrics, Stage};
+use ndjson::NdjsonStream;
+use partials::Partials;
+use protobuf::ProtobufPayload;
+use rbac::AdminToken;
+use recording::{RecordedRequest, Recorder, RecordingSettings};
+use redact::Redaction;
+use redirect::RedirectChain;
+use request_schema::RequestSchema;
+use scenario::ScenarioStep;
+use schedule::Schedule;
+use secrets::SecretSource;
 use serde::{Deserialize, Serialize};
-use std::{net::SocketAddr, sync::Arc};
+use session::Sessions;
+use socks::Socks5Settings;
+use startup::{StartupError, UpstreamCheck};
+use static_files::StaticMount;
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use store::{RedisSettings, RedisStore};
+use tcp_mock::TcpMock;
+use tee::SentBody;
 use tokio::sync::RwLock;
-use tower::ServiceBuilder;
+use tower::{limit::GlobalConcurrencyLimitLayer, ServiceBuilder};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
-use tracing::{info, Level};
+use tracing::Level;
+use transform::{RequestRewrite, ResponseRewrite, ResponseTransform};
+use unmatched::{UnmatchedAction, UnmatchedSettings};
+use upstream::{HttpsClient, TrailersClient, UpstreamError, UpstreamSettings, UpstreamTiming};
+use utoipa::{IntoParams, ToSchema};
+use webhook::Webhook;
+use websocket::WebSocketScript;
+use workspace::{Workspace, WorkspaceSettings, Workspaces, BASE_PROFILE, DEFAULT_WORKSPACE};
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 struct EndpointConfig {
+    // Lets a settings file replace this endpoint from one it includes;
+    // otherwise method and path identify it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
     method: String,
     path: String,
+    #[serde(default = "default_status")]
     status: u16,
+    #[serde(default = "default_content_type")]
     content_type: String,
+    #[serde(default)]
     payload: serde_json::Value,
+    // Payloads by language tag, e.g. {"de": ..., "fr-CA": ...}, picked by the
+    // request's Accept-Language; payload is sent when none fits
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schema(value_type = Object)]
+    languages: BTreeMap<String, serde_json::Value>,
+    // Locale of {{fake.*}} values; otherwise that of the language picked, or
+    // the request's Accept-Language
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    locale: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ip_filter: Option<IpFilter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_body_bytes: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_concurrency: Option<usize>,
+    // Reject request bodies that don't match this JSON Schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_schema: Option<RequestSchema>,
+    // Free-form labels for finding endpoints in the admin UI
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    // Proxy to the backend and modify its response instead of mocking one.
+    // status, content_type and payload are then unused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transform: Option<ResponseTransform>,
+    // Alternative responses, the first whose test holds is used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    conditions: Vec<Condition>,
+    // Only served during these schedules; unmatched the rest of the time
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    active: Vec<Schedule>,
+    // Misbehave when serving the mocked response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fault: Option<Fault>,
+    // Redirect this many times before responding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    redirect: Option<RedirectChain>,
+    // Start, require or end a mocked login session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session: Option<SessionAction>,
+    // Answer retries with the same idempotency key like the first request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    idempotency: Option<Idempotency>,
+    // Hold the request until an event is triggered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    long_poll: Option<LongPoll>,
+    // Wait this long before sending the headers (time to first byte)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delay_headers_ms: Option<u64>,
+    // Then this long between the headers and the body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delay_body_ms: Option<u64>,
+    // Sent after the body; the connection is closed after such a response
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    trailers: BTreeMap<String, String>,
+    // How to answer `Expect: 100-continue`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expect_continue: Option<ExpectContinue>,
+    // Close keep-alive connections after this endpoint's responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<KeepAlive>,
+    // Answer 500 instead of sending an XML payload that isn't well-formed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    check_xml: bool,
+    // Send these rows as CSV; content_type and payload are then unused
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    csv: Option<CsvPayload>,
+    // Encode the JSON payload as this protobuf message; content_type is then
+    // unused
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protobuf: Option<ProtobufPayload>,
+    // Stream these records as NDJSON; content_type and payload are then
+    // unused
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ndjson: Option<NdjsonStream>,
+    // Answer WebSocket upgrades with this conversation; status, content_type
+    // and payload are then unused
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    websocket: Option<WebSocketScript>,
+    // Take part in a scenario, whose state conditions can test
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scenario: Option<ScenarioStep>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+fn default_content_type() -> String {
+    "application/json".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct Settings {
+    // Settings files merged underneath this one, e.g. a base shared between
+    // projects; read at startup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    include: Vec<String>,
+    #[serde(flatten)]
+    workspace: WorkspaceSettings,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    workspaces: Vec<WorkspaceSettings>,
+    #[serde(default, skip_serializing_if = "IpFilter::is_empty")]
+    ip_filter: IpFilter,
+    #[serde(default = "default_max_body_bytes")]
+    max_body_bytes: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_in_flight: Option<usize>,
+    // ignore_trailing_slash and case_insensitive_paths, read at startup
+    #[serde(flatten)]
+    path_matching: PathMatching,
+    // Read at startup
+    #[serde(default, skip_serializing_if = "MethodMismatch::is_default")]
+    method_mismatch: MethodMismatch,
+    // Read at startup
+    #[serde(default, skip_serializing_if = "HeadRequests::is_default")]
+    head_requests: HeadRequests,
+    // Match POSTs on X-HTTP-Method-Override or a `_method` form field; read
+    // at startup
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    method_override: bool,
+    // Read at startup
+    #[serde(default, skip_serializing_if = "UnmatchedSettings::is_default")]
+    unmatched: UnmatchedSettings,
+    // Applied to every proxied request they match; read at startup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    request_rewrites: Vec<RequestRewrite>,
+    // Applied to every proxied response they match; read at startup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    response_rewrites: Vec<ResponseRewrite>,
+    // Seed for random mock behaviour; MOCK_SEED overrides it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    // Honour X-Mock-Status and X-Mock-Delay on mocked requests; read at startup
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    control_headers: bool,
+    // Add X-Mock-Matched or X-Mock-Proxied and match timing to every
+    // response; read at startup
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    debug_headers: bool,
+    // Header naming the client a request belongs to, e.g. X-Test-Session,
+    // so parallel test runs keep their state apart; read at startup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client_key_header: Option<String>,
+    // Listeners speaking raw TCP instead of HTTP; read at startup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tcp_mocks: Vec<TcpMock>,
+    // A SOCKS5 proxy in front of the mocks; read at startup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    socks5: Option<Socks5Settings>,
+    // Announce the server over mDNS; read at startup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mdns: Option<MdnsSettings>,
+    // Consul or etcd prefix whose endpoints are served alongside the ones
+    // here and followed as they change; read at startup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    config_source: Option<ConfigSource>,
+    // Where the mocks are mounted, e.g. "/api"; stripped before matching and
+    // proxying. Read at startup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base_path: Option<String>,
+    // Directories served as static files; read at startup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    static_mounts: Vec<StaticMount>,
+    // Hop headers for when default_endpoint is another mock server; read at
+    // startup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mock_chain: Option<MockChain>,
+    // Save proxied exchanges as stubs; read at startup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recording: Option<RecordingSettings>,
+    // Masked in logs and the journal; read at startup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    redaction: Option<Redaction>,
+    // Webhook for unmatched-request spikes and failing backends; read at
+    // startup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alerts: Option<AlertSettings>,
+    // Told about every change made through the admin API; read at startup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    change_webhook: Option<String>,
+    // Credentials referenced as `${secret:NAME}` in any string value, so
+    // they are never saved with the settings; read at startup
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    secrets: BTreeMap<String, SecretSource>,
+    // Where {{> name}} payload includes are read from; "templates" by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    templates_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "UpstreamSettings::is_default")]
+    upstream: UpstreamSettings,
+    // Backend URL => latency and errors injected into traffic proxied to it;
+    // read at startup
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    backend_chaos: BTreeMap<String, ChaosPolicy>,
+    #[serde(default = "default_journal_capacity")]
+    journal_capacity: usize,
+    #[serde(default = "default_max_versions")]
+    max_versions: usize,
+    #[serde(default = "default_audit_capacity")]
+    audit_capacity: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    admin_tokens: Vec<AdminToken>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git: Option<GitSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    redis: Option<RedisSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cluster: Option<ClusterSettings>,
+}
+
+impl Settings {
+    // Settings from elsewhere, a peer or a snapshot, with this instance's own
+    // admin tokens, secrets, cluster, Redis and git configuration
+    fn with_local(self, local: &Settings) -> Settings {
+        Settings {
+            admin_tokens: local.admin_tokens.clone(),
+            secrets: local.secrets.clone(),
+            git: local.git.clone(),
+            redis: local.redis.clone(),
+            cluster: local.cluster.clone(),
+            ..self
+        }
+    }
+
+    fn workspace(&self, name: &str) -> Option<&WorkspaceSettings> {
+        if name == self.workspace.name() {
+            return Some(&self.workspace);
+        }
+        self.workspaces.iter().find(|w| w.name() == name)
+    }
+
+    fn workspace_mut(&mut self, name: &str) -> Option<&mut WorkspaceSettings> {
+        if name == self.workspace.name() {
+            return Some(&mut self.workspace);
+        }
+        self.workspaces.iter_mut().find(|w| w.name() == name)
+    }
+}
+
+const DEFAULT_PORT: u16 = 8000;
+
+fn default_max_body_bytes() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_journal_capacity() -> usize {
+    1000
+}
+
+fn default_max_versions() -> usize {
+    50
+}
+
+fn default_audit_capacity() -> usize {
+    1000
+}
+
+// Errors that stop a request from being mocked or proxied
+#[derive(Debug)]
+enum RequestError {
+    Hyper(hyper::Error),
+    PayloadTooLarge(usize),
+    Upstream(UpstreamError), // The backend failed, not this server
+}
+
+impl From<hyper::Error> for RequestError {
+    fn from(e: hyper::Error) -> Self {
+        RequestError::Hyper(e)
+    }
+}
+
+// Response extension marking which mock endpoint produced the response
+#[derive(Clone)]
+struct MatchedEndpoint(String);
+
+// Response extension marking the backend a request was proxied to
+#[derive(Clone)]
+struct ProxiedTo(String);
+
+// Response extension with how long the backend took, phase by phase
+#[derive(Clone)]
+struct UpstreamTimed(UpstreamTiming);
+
+// Request extension with the client key (see client_key_header), if sent
+#[derive(Clone)]
+struct ClientKey(String);
+
+// Request extension identifying the listener that accepted the connection
+#[derive(Clone, Copy)]
+struct ListenerPort(u16);
+
+#[derive(Clone)]
+struct AppState {
+    workspaces: Arc<Workspaces>,
+    client: Option<HttpsClient>, // None when no workspace has a default_endpoint
+    trailers_client: Option<TrailersClient>,
+    ip_filter: Arc<IpFilter>,
+    max_body_bytes: usize,
+    endpoint_limits: Arc<EndpointLimits>,
+    config_repo: Option<Arc<ConfigRepo>>,
+    store: Option<Arc<RedisStore>>, // Shared settings and journals when running replicas
+    cluster: Option<Arc<Cluster>>,
+    read_only: bool, // --read-only: admin changes are rejected
+    path_matching: PathMatching,
+    method_mismatch: MethodMismatch,
+    method_override: bool,
+    head_requests: HeadRequests,
+    unmatched: Arc<UnmatchedSettings>,
+    request_rewrites: Arc<Vec<RequestRewrite>>,
+    response_rewrites: Arc<Vec<ResponseRewrite>>,
+    random: Arc<random::Random>,
+    clock: Arc<clock::Clock>, // The time mocks see, settable by tests
+    control_headers: bool,
+    debug_headers: bool,
+    client_key_header: Option<Arc<str>>,
+    base_path: Option<Arc<str>>, // Normalized to "/api"
+    partials: Arc<Partials>,
+    mock_chain: Option<Arc<MockChain>>,
+    recorder: Option<Arc<Recorder>>,
+    redaction: Arc<Redaction>,
+    metrics: Arc<Metrics>,
+    alerts: Option<Arc<Alerts>>,
+    dashboard: Option<Arc<tui::Dashboard>>, // With --tui
+    backend_chaos: Arc<BackendChaos>,
+    change_webhook: Option<Arc<Webhook>>,
+    audit: Arc<AuditLog>,
+    sessions: Arc<Sessions>,
+    logins: Arc<MockSessions>, // Sessions of mocked login flows, not the admin UI's
+    idempotency: Arc<IdempotencyCache>,
+    events: Arc<Events>,             // What long-polling requests wait for
+    listeners: Arc<Listeners>,       // Rebound when settings change workspace ports
+    source: Arc<Source>,             // How settings.json was put together
+    settings: Arc<RwLock<Settings>>, // Persisted to settings.json on admin updates
+}
+#[tokio::main]
+async fn main() {
+    // Without a command, options go to `serve`
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, rest) = match args.first() {
+        Some(command) if !command.starts_with('-') => (command.as_str(), &args[1..]),
+        _ => ("serve", &args[..]),
+    };
+
+    // Set up logging. The dashboard takes the terminal over, so with --tui
+    // the log goes to a pane of it.
+    let dashboard = match command {
+        "serve" | "record" => tui::Dashboard::from_args(rest).unwrap_or_else(|e| e.exit()),
+        _ => None,
+    };
+    match &dashboard {
+        Some(dashboard) => tracing_subscriber::fmt()
+            .with_max_level(Level::INFO)
+            .with_ansi(false)
+            .with_writer(tui::LogPane(dashboard.clone()))
+            .init(),
+        None => tracing_subscriber::fmt().with_max_level(Level::INFO).init(),
+    }
+
+    let code = match command {
+        "serve" => serve(rest.to_vec(), None, dashboard).await,
+        "record" => match cli::RecordSession::from_args(rest) {
+            Ok((session, args)) => serve(args, Some(session), dashboard).await,
+            Err(e) => e.exit(),
+        },
+        "validate" => cli::validate(rest),
+        "replay" => replay::run(rest).await,
+        "export" => cli::export(rest),
+        "import" => cli::import(rest),
+        "bench" => bench::run(rest).await,
+        "help" => {
+            println!("{}", cli::USAGE);
+            0
+        }
+        _ => {
+            eprintln!("Unknown command {}\n{}", command, cli::USAGE);
+            2
+        }
+    };
+    std::process::exit(code)
+}
+
+// Anything that stops startup is reported as one JSON line on stderr, once
+// the dashboard has given the terminal back
+async fn serve(
+    args: Vec<String>,
+    record: Option<cli::RecordSession>,
+    dashboard: Option<Arc<tui::Dashboard>>,
+) -> i32 {
+    let tui = dashboard.is_some();
+    match start(args, record, dashboard).await {
+        Ok(()) => 0,
+        Err(e) => {
+            if tui {
+                tui::restore();
+            }
+            e.exit()
+        }
+    }
+}
+
+async fn start(
+    args: Vec<String>,
+    record: Option<cli::RecordSession>,
+    dashboard: Option<Arc<tui::Dashboard>>,
+) -> Result<(), StartupError> {
+    // Settings from a mounted ConfigMap can only change through the
+    // ConfigMap, so admin changes are disabled there too. A read-only server
+    // keeps the configuration it started with, and so does a recording one.
+    let config_map = config_map::dir_from_args(&args)?;
+    let read_only =
+        args.iter().any(|arg| arg == "--read-only") || config_map.is_some() || record.is_some();
+    if let Some(dir) = &config_map {
+        tracing::info!(
+            "Following settings in {}; admin changes are disabled",
+            dir.display()
+        );
+    } else if read_only {
+        tracing::info!("Running in read-only mode; admin changes are disabled");
+    }
+    let upstream_check = UpstreamCheck::from_args(&args)?;
+
+    // Load settings
+    let settings_path = match &config_map {
+        Some(dir) => dir.join("settings.json"),
+        None => std::path::PathBuf::from("settings.json"),
+    };
+    let (mut settings, source) = include::load(&settings_path).map_err(StartupError::config)?;
+    if let Some(session) = &record {
+        session.apply(&mut settings)?;
+    }
+    startup::validate(&settings)?;
+
+    // With Redis, settings already shared by other replicas take precedence
+    // over the local file; the first replica seeds them.
+    let store = match settings.redis.clone() {
+        Some(redis) => {
+            let store = RedisStore::connect(&redis, settings.journal_capacity)
+                .await
+                .map_err(|e| {
+                    StartupError::dependency(format!("Failed to connect to Redis: {}", e))
+                })?;
+            let shared = store.load_settings().await.map_err(|e| {
+                StartupError::dependency(format!("Failed to read settings from Redis: {}", e))
+            })?;
+            match shared {
+                Some(json) => {
+                    let shared = serde_json::from_str(&json)
+                        .map_err(|e| e.to_string())
+                        .and_then(|shared| source.expand(shared))
+                        .map_err(|e| {
+                            StartupError::config(format!(
+                                "Failed to parse settings from Redis: {}",
+                                e
+                            ))
+                        })?;
+                    settings = Settings {
+                        redis: Some(redis),
+                        secrets: settings.secrets,
+                        ..shared
+                    };
+                    startup::validate(&settings)?;
+                    tracing::info!("Loaded settings from Redis");
+                }
+                None => {
+                    let json =
+                        serde_json::to_string_pretty(&source.shared_contents(&settings).unwrap())
+                            .unwrap();
+                    store.save_settings(&json).await.map_err(|e| {
+                        StartupError::dependency(format!("Failed to seed settings in Redis: {}", e))
+                    })?;
+                    tracing::info!("Seeded Redis with settings.json");
+                }
+            }
+            Some(Arc::new(store))
+        }
+        None => None,
+    };
+
+    // Only built when some workspace has a backend to proxy to
+    let has_backend = std::iter::once(&settings.workspace)
+        .chain(&settings.workspaces)
+        .any(|w| {
+            w.default_endpoint.is_some()
+                || w.virtual_hosts.iter().any(|v| v.default_endpoint.is_some())
+        });
+    let (client, trailers_client) = has_backend
+        .then(|| upstream::build_proxy_clients(&settings.upstream))
+        .transpose()
+        .map_err(|e| StartupError::config(format!("Failed to build upstream client: {}", e)))?
+        .unzip();
+
+    let config_repo = match &settings.git {
+        Some(git) if !read_only => Some(Arc::new(ConfigRepo::open(git).await.map_err(|e| {
+            StartupError::dependency(format!("Failed to open settings git repository: {}", e))
+        })?)),
+        _ => None,
+    };
+
+    let max_in_flight = settings.max_in_flight;
+    let max_body_bytes = settings.max_body_bytes;
+    let static_mounts = settings.static_mounts.clone();
+
+    // Shared application state
+    let workspaces = Workspaces::new(
+        &settings.workspace,
+        &settings.workspaces,
+        settings.journal_capacity,
+        settings.max_versions,
+        settings.path_matching,
+    );
+    let ports = workspaces.ports();
+
+    // Taken ports and missing backends fail startup before anything is served
+    let bound = startup::bind(&ports).await?;
+    let mut tcp_mocks = Vec::new();
+    for mock in &settings.tcp_mocks {
+        tcp_mocks.push((mock.clone(), startup::bind_port(mock.port).await?));
+    }
+    let socks5 = match &settings.socks5 {
+        Some(socks5) => Some((socks5.clone(), startup::bind_port(socks5.port).await?)),
+        None => None,
+    };
+    startup::check_upstreams(&settings, client.as_ref(), &upstream_check).await?;
+
+    let alerts = settings
+        .alerts
+        .clone()
+        .map(Alerts::new)
+        .transpose()
+        .map_err(|e| {
+            StartupError::dependency(format!("Failed to build the alert webhook client: {}", e))
+        })?;
+    let change_webhook = settings
+        .change_webhook
+        .as_deref()
+        .map(Webhook::new)
+        .transpose()
+        .map_err(|e| {
+            StartupError::dependency(format!("Failed to build the change webhook client: {}", e))
+        })?;
+
+    let mdns = settings.mdns.clone();
+    let config_source = settings.config_source.clone();
+
+    let ip_filter = Arc::new(settings.ip_filter.clone());
+    let app_state = AppState {
+        workspaces: Arc::new(workspaces),
+        client,
+        trailers_client,
+        ip_filter: ip_filter.clone(),
+        max_body_bytes: settings.max_body_bytes,
+        endpoint_limits: Arc::new(EndpointLimits::default()),
+        config_repo,
+        store: store.clone(),
+        cluster: settings.cluster.as_ref().map(|c| Arc::new(Cluster::new(c))),
+        read_only,
+        path_matching: settings.path_matching,
+        method_mismatch: settings.method_mismatch,
+        method_override: settings.method_override,
+        head_requests: settings.head_requests,
+        unmatched: Arc::new(settings.unmatched.clone()),
+        request_rewrites: Arc::new(settings.request_rewrites.clone()),
+        response_rewrites: Arc::new(settings.response_rewrites.clone()),
+        random: Arc::new(random::Random::new(settings.seed)),
+        clock: Arc::new(clock::Clock::default()),
+        control_headers: settings.control_headers,
+        debug_headers: settings.debug_headers,
+        client_key_header: settings.client_key_header.as_deref().map(Arc::from),
+        base_path: settings
+            .base_path
+            .as_deref()
+            .map(|path| path.trim_matches('/'))
+            .filter(|path| !path.is_empty())
+            .map(|path| Arc::from(format!("/{}", path))),
+        partials: Arc::new(Partials::new(
+            settings.templates_dir.as_deref().unwrap_or("templates"),
+        )),
+        mock_chain: settings.mock_chain.clone().map(Arc::new),
+        recorder: settings
+            .recording
+            .clone()
+            .map(|recording| Arc::new(Recorder::new(recording))),
+        redaction: Arc::new(settings.redaction.clone().unwrap_or_default()),
+        metrics: Arc::new(Metrics::default()),
+        alerts: alerts.map(Arc::new),
+        dashboard,
+        backend_chaos: Arc::new(BackendChaos::new(&settings.backend_chaos)),
+        change_webhook: change_webhook.map(Arc::new),
+        audit: Arc::new(AuditLog::new(settings.audit_capacity, &settings)),
+        sessions: Arc::new(Sessions::default()),
+        logins: Arc::new(MockSessions::default()),
+        idempotency: Arc::new(IdempotencyCache::default()),
+        events: Arc::new(Events::default()),
+        listeners: Arc::new(Listeners::new(ip_filter.clone())),
+        source: Arc::new(source),
+        settings: Arc::new(RwLock::new(settings)),
+    };
+
+    if let Some(source) = config_source {
+        tokio::spawn(config_source::watch(source, app_state.workspaces.clone()));
+    }
+
+    if let Some(dir) = config_map {
+        tokio::spawn(config_map::watch(app_state.clone(), dir));
+    }
+
+    // Follow settings changes made through other replicas
+    if let Some(store) = store.filter(|_| !read_only) {
+        let state = app_state.clone();
+        tokio::spawn(async move {
+            store
+                .watch_settings(|json| {
+                    let state = state.clone();
+                    async move {
+                        let shared = serde_json::from_str(&json)
+                            .map_err(|e| e.to_string())
+                            .and_then(|shared| state.source.expand(shared));
+                        match shared {
+                            Ok(settings)
+                                if !settings_route_conflicts(&settings, state.path_matching)
+                                    .is_empty() =>
+                            {
+                                tracing::error!(
+                                    "Ignoring settings in Redis with conflicting routes"
+                                )
+                            }
+                            Ok(settings) => {
+                                apply_settings(&state, &settings, "Synced from Redis").await;
+                                state.audit.rebase(&settings);
+                                let mut current = state.settings.write().await;
+                                *current = Settings {
+                                    redis: current.redis.take(),
+                                    secrets: std::mem::take(&mut current.secrets),
+                                    ..settings
+                                };
+                                tracing::info!("Applied settings changed by another replica");
+                            }
+                            Err(e) => tracing::error!("Ignoring invalid settings in Redis: {}", e),
+                        }
+                    }
+                })
+                .await
+        });
+    }
+
+    // Admin routes that change the configuration
+    let admin_changes = Router::new()
+        .route(
+            "/mockserver/admin/update",
+            axum::routing::post(update_endpoints),
+        )
+        .route(
+            "/mockserver/admin/endpoints",
+            axum::routing::post(create_endpoint),
+        )
+        .route(
+            "/mockserver/admin/endpoints/:index",
+            axum::routing::put(update_endpoint).delete(delete_endpoint),
+        )
+        .route(
+            "/mockserver/admin/endpoints/from-curl",
+            axum::routing::post(curl::endpoint_from_curl),
+        )
+        .route(
+            "/mockserver/admin/profile/:name",
+            axum::routing::post(switch_profile),
+        )
+        .route(
+            "/mockserver/admin/rollback/:version",
+            axum::routing::post(rollback),
+        )
+        .route(
+            "/mockserver/admin/import",
+            axum::routing::post(snapshot::import_snapshot)
+                .layer(DefaultBodyLimit::max(max_body_bytes)),
+        )
+        .route(
+            "/mockserver/admin/cluster/sync",
+            axum::routing::post(cluster::receive_sync),
+        )
+        .route(
+            "/mockserver/admin/scenarios/recording",
+            axum::routing::post(scenario::start_recording).delete(scenario::stop_recording),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            session::csrf_middleware,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            read_only_middleware,
+        ));
+
+    // Build the Axum router with logging middleware
+    let mut router = Router::new()
+        .route("/mockserver/admin", axum::routing::get(admin_page))
+        .route(
+            "/mockserver/admin/endpoints",
+            axum::routing::get(list_endpoints),
+        )
+        .route(
+            "/mockserver/admin/endpoints/:index/curl",
+            axum::routing::get(curl::endpoint_curl),
+        )
+        .route(
+            "/mockserver/admin/journal/:id/curl",
+            axum::routing::get(curl::journal_curl),
+        )
+        .route(
+            "/mockserver/admin/profile",
+            axum::routing::get(get_profiles),
+        )
+        .route(
+            "/mockserver/admin/versions",
+            axum::routing::get(get_versions),
+        )
+        .route(
+            "/mockserver/admin/versions/:version",
+            axum::routing::get(get_version),
+        )
+        .route(
+            "/mockserver/admin/export",
+            axum::routing::get(snapshot::export_snapshot),
+        )
+        .route(
+            "/mockserver/admin/journal",
+            axum::routing::get(get_journal).delete(clear_journal),
+        )
+        .route("/mockserver/admin/audit", axum::routing::get(get_audit))
+        .route(
+            "/mockserver/admin/seed",
+            axum::routing::get(random::get_seed).post(random::reseed),
+        )
+        .route(
+            "/mockserver/admin/clock",
+            axum::routing::get(clock::get_clock)
+                .post(clock::set_clock)
+                .delete(clock::reset_clock),
+        )
+        .route(
+            "/mockserver/admin/logins",
+            axum::routing::delete(login::clear_logins),
+        )
+        .route(
+            "/mockserver/admin/scenarios",
+            axum::routing::get(scenario::get_scenarios).delete(scenario::reset_scenarios),
+        )
+        .route(
+            "/mockserver/admin/explain",
+            axum::routing::post(explain::explain),
+        )
+        .route(
+            "/mockserver/admin/idempotency",
+            axum::routing::delete(idempotency::clear_keys),
+        )
+        .route(
+            "/mockserver/admin/events/:name",
+            axum::routing::post(events::trigger),
+        )
+        .route(
+            "/mockserver/admin/metrics",
+            axum::routing::get(metrics::get_metrics),
+        )
+        .route(
+            "/mockserver/admin/stats",
+            axum::routing::get(metrics::get_stats),
+        )
+        .route(
+            "/mockserver/admin/metrics/prometheus",
+            axum::routing::get(metrics::prometheus_metrics),
+        )
+        .route(
+            "/mockserver/admin/openapi.json",
+            axum::routing::get(openapi::openapi_json),
+        )
+        .merge(admin_changes);
+
+    // Static directories. /static also serves the admin page's own files,
+    // from the binary if they aren't in the directory.
+    let mut static_dir = "static".to_string();
+    let mut root_files = None;
+    for mount in &static_mounts {
+        let path = mount.path();
+        tracing::info!("Serving {} at {}", mount.directory, path);
+        match path.as_str() {
+            "/static" => static_dir = mount.directory.clone(),
+            "/" => root_files = Some(mount.service()),
+            _ => router = router.nest_service(&path, mount.service()),
+        }
+    }
+    router = router.nest_service(
+        "/static",
+        get_service(ServeDir::new(static_dir).fallback(tower::service_fn(assets::serve_embedded)))
+            .handle_error(handle_error),
+    );
+    // A root mount gets whatever isn't under the base path
+    router = match (root_files, app_state.base_path.clone()) {
+        (Some(files), Some(base_path)) => {
+            let mocks = axum::handler::Handler::with_state(handler, app_state.clone());
+            router.fallback_service(static_files::split_root(files, mocks, base_path))
+        }
+        (Some(_), None) => panic!("a static mount at / needs base_path"),
+        (None, _) => router.fallback(handler),
+    };
+    let mut app = router
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            rbac::rbac_middleware,
+        ))
+        .with_state(app_state.clone());
+
+    // Shed load with a 503 once the global in-flight limit is reached
+    if let Some(limit) = max_in_flight {
+        app = app.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(limits::handle_overload))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(limit)),
+        );
+    }
+    let app = app
+        .layer(ServiceBuilder::new().layer(TraceLayer::new_for_http()))
+        .layer(middleware::from_fn(request_id::middleware));
+
+    // Run the server, one listener per port
+    let main_port = ports[0];
+    let listeners = app_state.listeners.clone();
+    listeners.start(app.clone(), bound).await;
+    for (mock, bound) in tcp_mocks {
+        tokio::spawn(tcp_mock::serve(mock, bound, app_state.ip_filter.clone()));
+    }
+    if let Some((socks5, bound)) = socks5 {
+        // Intercepted connections are handled as if sent to the main port
+        let app = app.clone().layer(Extension(ListenerPort(main_port)));
+        tokio::spawn(socks::serve(
+            socks5,
+            bound,
+            app,
+            app_state.ip_filter.clone(),
+        ));
+    }
+    if let Some(mdns) = mdns {
+        tokio::spawn(mdns::advertise(mdns, main_port));
+    }
+    systemd::notify("READY=1\nSTATUS=Serving mocks");
+    if let Some(dashboard) = app_state.dashboard.clone() {
+        tui::spawn(dashboard, ports.clone());
+    }
+    let (addr, e) = listeners.failure().await;
+    Err(startup::server_failed(addr, e))
+}
+
+// Rejects configuration changes when started with --read-only
+async fn read_only_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response<axum::body::BoxBody> {
+    if state.read_only {
+        return Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::from("Server is running in read-only mode"))
+            .unwrap()
+            .into_response();
+    }
+    next.run(req).await
+}
+
+fn forbidden() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .body(Body::from("Forbidden"))
+        .unwrap()
+}
+
+// Adjusted handler function
+async fn handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    req: Request<Body>,
+) -> Response<Body> {
+    let started = Instant::now();
+    let (mut parts, body) = req.into_parts();
+
+    // Everything outside the base path is unknown
+    if let Some(base_path) = &state.base_path {
+        match workspace::strip_path_prefix(base_path, parts.uri.path()) {
+            Some(path) => parts.uri = with_path(&parts.uri, &path),
+            None => {
+                tracing::info!("{} is outside base_path {}", parts.uri.path(), base_path);
+                return unmatched::not_found(&parts.method, parts.uri.path());
+            }
+        }
+    }
+
+    // Pick the workspace, dropping its path prefix (if any) before matching
+    let host = vhost::request_host(&parts).unwrap_or_default();
+    let (workspace, stripped_path) = state.workspaces.select(port, &host, parts.uri.path());
+    if let Some(path) = stripped_path {
+        parts.uri = with_path(&parts.uri, &path);
+    }
+    if workspace.name != DEFAULT_WORKSPACE {
+        tracing::info!("Using workspace: {}", workspace.name);
+    }
+
+    if let Some(key) = client_key(&state, &parts.headers) {
+        parts.extensions.insert(ClientKey(key));
+    }
+    let outcome = state.debug_headers.then(|| {
+        let outcome = MatchOutcome::default();
+        parts.extensions.insert(outcome.clone());
+        outcome
+    });
+
+    let mut entry = JournalEntry::new(addr.ip(), &parts);
+    entry.scenario = workspace.scenarios.recording();
+
+    // Settle `Expect: 100-continue` before the body is read
+    let refused = match expect_continue(&state, &workspace, &parts).await {
+        Some(expect) => expect.before_body().await,
+        None => None,
+    };
+
+    // Read the entire body, up to the configured limit, unless it only has
+    // to be passed on to the backend
+    let result = match refused {
+        Some(response) => {
+            tracing::info!("Refused the body of {}", parts.uri.path());
+            Ok(response)
+        }
+        None if streams_to_backend(&state, &workspace, &parts) => {
+            let limit = state.max_body_bytes;
+            if declared_length(&parts.headers).is_some_and(|len| len > limit) {
+                Err(RequestError::PayloadTooLarge(limit))
+            } else {
+                let (body, sent) = tee::tee(body, limit);
+                let result =
+                    process_request(&state, &workspace, addr.ip(), parts, body, sent.clone()).await;
+                entry.set_body(&sent.bytes());
+                result
+            }
+        }
+        None => match read_body_limited(&parts.headers, body, state.max_body_bytes).await {
+            Ok(bytes) => {
+                entry.set_body(&bytes);
+                let body = Body::from(bytes.clone());
+                let sent = SentBody::Whole(bytes);
+                process_request(&state, &workspace, addr.ip(), parts, body, sent).await
+            }
+            Err(e) => Err(e),
+        },
+    };
+
+    let mut response = match result {
+        Ok(response) => response,
+        Err(RequestError::PayloadTooLarge(limit)) => payload_too_large(limit),
+        Err(RequestError::Upstream(e)) => {
+            tracing::error!("Backend failed: {}", e);
+            e.response()
+        }
+        Err(RequestError::Hyper(e)) => {
+            tracing::error!("Request failed: {}", e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("Internal Server Error"))
+                .unwrap()
+        }
+    };
+
+    if let Some(chain) = &state.mock_chain {
+        chain.mark(&mut response);
+    }
+    if let Some(outcome) = &outcome {
+        debug_headers::apply(&mut response, outcome);
+    }
+
+    entry.finish(&response, started.elapsed());
+    if let Some(endpoint) = &entry.matched {
+        state
+            .metrics
+            .endpoint(&workspace.name, endpoint, Stage::Total, started.elapsed());
+    }
+    if let Some(backend) = &entry.proxied_to {
+        state
+            .metrics
+            .backend(backend, Stage::Total, started.elapsed());
+    }
+    state.metrics.request(&workspace.name, &entry);
+    if let Some(alerts) = &state.alerts {
+        alerts.request(&workspace.name, &entry);
+    }
+    if let Some(dashboard) = &state.dashboard {
+        dashboard.request(&workspace.name, &entry);
+    }
+    entry.redact(&state.redaction);
+    match &state.store {
+        Some(store) => {
+            let mut shared = entry.clone();
+            shared.id = workspace.journal.record(entry);
+            let store = store.clone();
+            let workspace = workspace.name.clone();
+            tokio::spawn(async move {
+                if let Err(e) = store.push_journal(&workspace, &shared).await {
+                    tracing::error!("Failed to journal request in Redis: {}", e);
+                }
+            });
+        }
+        None => {
+            workspace.journal.record(entry);
+        }
+    }
+    response
+}
+
+// The expect_continue option of the endpoint a request matches, if the
+// request is waiting for 100 Continue
+async fn expect_continue(
+    state: &AppState,
+    workspace: &Workspace,
+    parts: &axum::http::request::Parts,
+) -> Option<ExpectContinue> {
+    if !expect::expects_continue(&parts.headers) {
+        return None;
+    }
+    let host = vhost::request_host(parts).unwrap_or_default();
+    let routes = workspace.routes_for(&host);
+    let lookup = state.path_matching.request_path(parts.uri.path());
+    let matched = routes.router.at(&lookup).ok()?;
+    let now = state.clock.now();
+    matched
+        .value
+        .iter()
+        .map(|&idx| &routes.endpoints[idx])
+        .filter(|ep| schedule::any_active(&ep.active, now))
+        .find(|ep| ep.method.eq_ignore_ascii_case(parts.method.as_str()))?
+        .expect_continue
+        .clone()
+}
+
+fn client_key(state: &AppState, headers: &HeaderMap) -> Option<String> {
+    let name = state.client_key_header.as_deref()?;
+    let key = headers.get(name)?.to_str().ok()?.trim();
+    (!key.is_empty()).then(|| key.to_string())
+}
+
+// A body that only starts arriving after `delay`
+fn delayed_body(body: Bytes, delay: Duration) -> Body {
+    Body::wrap_stream(futures_util::stream::once(async move {
+        tokio::time::sleep(delay).await;
+        Ok::<_, std::convert::Infallible>(body)
+    }))
+}
+
+// 500 for a mocked payload that can't be produced as configured
+fn payload_error(error: &str, detail: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::json!({ "error": error, "detail": detail }).to_string(),
+        ))
+        .unwrap()
+}
+
+// Resolves the template values an endpoint's payload refers to
+type Lookup<'a> = dyn Fn(&str) -> Option<serde_json::Value> + 'a;
+
+// The mocked body for `payload`: templates filled in, path variables
+// injected and the result encoded as the endpoint asks. Errors are the
+// response to send instead.
+fn render_payload<'a>(
+    state: &AppState,
+    endpoint: &EndpointConfig,
+    content_type: &'a str,
+    payload: &serde_json::Value,
+    params: &[(&str, &str)],
+    lookup: &Lookup,
+    path: &str,
+) -> Result<(&'a str, Bytes), Box<Response<Body>>> {
+    // Shared fragments go in first so they can use the same values
+    let payload = state.partials.expand(payload);
+    let payload = &transform::fill(&payload, lookup);
+
+    let body = if let Some(csv) = &endpoint.csv {
+        match csv.render(params) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Failed to build the CSV for {}: {}", path, e);
+                return Err(Box::new(payload_error(
+                    "Failed to build the mocked CSV",
+                    &e,
+                )));
+            }
+        }
+    } else if content_type == "application/json"
+        || endpoint.protobuf.is_some()
+        || Codec::for_content_type(content_type).is_some()
+    {
+        // Inject parameters into the JSON payload
+        let mut payload = payload.clone();
+        if let serde_json::Value::Object(ref mut map) = payload {
+            for (key, value) in params {
+                map.insert(
+                    key.to_string(),
+                    serde_json::Value::String(value.to_string()),
+                );
+            }
+        }
+        serde_json::to_string(&payload).unwrap()
+    } else {
+        // For other content types, perform placeholder replacement
+        let mut body = match payload {
+            serde_json::Value::String(s) => s.clone(),
+            _ => payload.to_string(),
+        };
+        let is_xml = xml::is_xml(content_type);
+        for (key, value) in params {
+            let placeholder = format!("{{{{{}}}}}", key);
+            let value = if is_xml {
+                xml::escape(value)
+            } else {
+                Cow::Borrowed(*value)
+            };
+            body = body.replace(&placeholder, &value);
+        }
+        if is_xml && endpoint.check_xml {
+            if let Err(e) = xml::check(&body) {
+                tracing::error!("XML payload for {} isn't well-formed: {}", path, e);
+                return Err(Box::new(payload_error(
+                    "Mocked XML payload isn't well-formed",
+                    &e,
+                )));
+            }
+        }
+        body
+    };
+
+    // Log the response payload
+    tracing::info!("Response payload: {}", state.redaction.body(&body));
+
+    let (content_type, body) = if endpoint.csv.is_some() {
+        ("text/csv; charset=utf-8", Bytes::from(body))
+    } else if let Some(protobuf) = &endpoint.protobuf {
+        match protobuf.encode(&body) {
+            Ok(bytes) => (protobuf::CONTENT_TYPE, Bytes::from(bytes)),
+            Err(e) => {
+                tracing::error!("Failed to encode protobuf for {}: {}", path, e);
+                return Err(Box::new(payload_error(
+                    "Failed to encode the mocked protobuf",
+                    &e,
+                )));
+            }
+        }
+    } else if let Some(codec) = Codec::for_content_type(content_type) {
+        match codec.encode(&body) {
+            Ok(bytes) => (content_type, Bytes::from(bytes)),
+            Err(e) => {
+                tracing::error!("Failed to encode {:?} for {}: {}", codec, path, e);
+                return Err(Box::new(payload_error(
+                    "Failed to encode the mocked payload",
+                    &e,
+                )));
+            }
+        }
+    } else {
+        (content_type, Bytes::from(body))
+    };
+    Ok((content_type, body))
+}
+
+// Replaces the path of `uri`, keeping its query string
+fn with_path(uri: &Uri, path: &str) -> Uri {
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+    path_and_query.parse().unwrap_or_else(|_| uri.clone())
+}
+
+fn payload_too_large(limit: usize) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::PAYLOAD_TOO_LARGE)
+        .body(Body::from(format!(
+            "Payload Too Large: request body exceeds {} bytes",
+            limit
+        )))
+        .unwrap()
+}
+
+// Buffers the request body, bailing out as soon as it grows past `limit`
+async fn read_body_limited(
+    headers: &hyper::HeaderMap,
+    mut body: Body,
+    limit: usize,
+) -> Result<Bytes, RequestError> {
+    let declared_len = declared_length(headers);
+    if declared_len.is_some_and(|len| len > limit) {
+        return Err(RequestError::PayloadTooLarge(limit));
+    }
+
+    let mut buf = Vec::with_capacity(declared_len.unwrap_or(0));
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if buf.len() + chunk.len() > limit {
+            return Err(RequestError::PayloadTooLarge(limit));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(buf))
+}
+
+fn declared_length(headers: &hyper::HeaderMap) -> Option<usize> {
+    headers
+        .get("Content-Length")
+        .and_then(|len| len.to_str().ok())
+        .and_then(|len| len.parse::<usize>().ok())
+}
+
+async fn process_request(
+    state: &AppState,
+    workspace: &Workspace,
+    client_ip: IpAddr,
+    mut parts: Parts,
+    body: Body,
+    sent: SentBody,
+) -> Result<Response<Body>, RequestError> {
+    // A streamed body is only passed on to the backend, so nothing here
+    // looks at it
+    let bytes = match &sent {
+        SentBody::Whole(bytes) => bytes.clone(),
+        SentBody::Streamed(_) => Bytes::new(),
+    };
+
+    // Log the payload based on Content-Type
+    if let SentBody::Streamed(_) = sent {
+        tracing::info!("Streaming the body of {} to the backend", parts.uri.path());
+    } else if let Some(content_type) = parts
+        .headers
+        .get("Content-Type")
+        .and_then(|ct| ct.to_str().ok())
+    {
+        match content_type {
+            ct if ct.contains("application/json") => {
+                if let Ok(mut json) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+                    state.redaction.json(&mut json);
+                    tracing::info!("Request JSON payload: {}", json);
+                } else {
+                    let shown = state.redaction.body(&String::from_utf8_lossy(&bytes));
+                    tracing::info!("Request payload (invalid JSON): {:?}", shown);
+                }
+            }
+            _ => {
+                // For other content types, log as bytes or convert to string if possible
+                if let Ok(body_str) = String::from_utf8(bytes.to_vec()) {
+                    tracing::info!("Request payload: {}", state.redaction.body(&body_str));
+                } else {
+                    let shown = state.redaction.body(&String::from_utf8_lossy(&bytes));
+                    tracing::info!("Request payload (binary): {:?}", shown);
+                }
+            }
+        }
+    } else {
+        // If no Content-Type header is present
+        let shown = state.redaction.body(&String::from_utf8_lossy(&bytes));
+        tracing::info!("Request payload: {:?}", shown);
+    }
+
+    // MessagePack and CBOR bodies are matched as if they were JSON; the
+    // original bytes are still what gets proxied
+    let json_body = match parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .and_then(Codec::for_content_type)
+    {
+        Some(codec) => match codec.decode(&bytes) {
+            Ok(json) => {
+                let shown = state.redaction.body(&String::from_utf8_lossy(&json));
+                tracing::info!("Request {:?} payload: {}", codec, shown);
+                Bytes::from(json)
+            }
+            Err(e) => {
+                tracing::info!("Request payload (invalid {:?}): {}", codec, e);
+                bytes.clone()
+            }
+        },
+        None => bytes.clone(),
+    };
+
+    // Continue with existing processing logic
+    let path = parts.uri.path().to_string();
+    let overridden = state
+        .method_override
+        .then(|| matching::method_override(&parts.method, &parts.headers, &json_body))
+        .flatten();
+    let method = match overridden {
+        Some(method) => {
+            tracing::info!("Processing request: {} {} (sent as POST)", method, path);
+            method
+        }
+        None => {
+            tracing::info!("Processing request: {} {}", parts.method, path);
+            parts.method.clone()
+        }
+    };
+
+    // Take the current endpoints and router, preferring a virtual host's own
+    // set. Admin changes made meanwhile apply from the next request.
+    let matching = Instant::now();
+    let host = vhost::request_host(&parts).unwrap_or_default();
+    let routes = workspace.routes_for(&host);
+
+    // Match the request path
+    let lookup = state.path_matching.request_path(&path);
+    if let Ok(matched) = routes.router.at(&lookup) {
+        // Endpoints on the same path differ by method. Those outside their
+        // schedule are left out.
+        let now = state.clock.now();
+        let candidates: Vec<(usize, &EndpointConfig)> = matched
+            .value
+            .iter()
+            .map(|&idx| (idx, &routes.endpoints[idx]))
+            .filter(|(_, ep)| schedule::any_active(&ep.active, now))
+            .collect();
+        let with_method = |method: &str| {
+            candidates
+                .iter()
+                .copied()
+                .find(|(_, ep)| ep.method.eq_ignore_ascii_case(method))
+        };
+        let mut endpoint = with_method(method.as_str());
+        // HEAD falls back to the GET endpoint, answered without a body
+        let head_mocks_get = state.head_requests == HeadRequests::Mock;
+        if endpoint.is_none() && method == Method::HEAD && head_mocks_get {
+            endpoint = with_method("GET");
+        }
+        // Browsers ask before calling a protobuf endpoint over gRPC-Web
+        if endpoint.is_none()
+            && method == Method::OPTIONS
+            && candidates.iter().any(|(_, ep)| ep.protobuf.is_some())
+        {
+            if let Some(response) = grpc_web::preflight(&parts.headers) {
+                tracing::info!("Answered gRPC-Web preflight for {}", path);
+                return Ok(response);
+            }
+        }
+        if endpoint.is_none()
+            && !candidates.is_empty()
+            && state.method_mismatch == MethodMismatch::Reject
+        {
+            tracing::info!("No {} endpoint for path: {}", method, path);
+            if let Some(outcome) = parts.extensions.get::<MatchOutcome>() {
+                outcome.unmatched(matching.elapsed());
+            }
+            let head = (head_mocks_get && with_method("GET").is_some()).then_some("HEAD");
+            return Ok(matching::method_not_allowed(
+                candidates
+                    .iter()
+                    .map(|(_, ep)| ep.method.as_str())
+                    .chain(head),
+            ));
+        }
+
+        if let Some((index, endpoint)) = endpoint {
+            tracing::info!("Matched mock endpoint for path: {}", path);
+            let name = format!("{} {}", endpoint.method.to_ascii_uppercase(), endpoint.path);
+            state
+                .metrics
+                .endpoint(&workspace.name, &name, Stage::Matching, matching.elapsed());
+            if let Some(outcome) = parts.extensions.get::<MatchOutcome>() {
+                let id = endpoint.id.clone().unwrap_or_else(|| name.clone());
+                outcome.matched(id, matching.elapsed());
+            }
+
+            let params: Vec<(&str, &str)> = matched
+                .params
+                .iter()
+                .map(|(key, value)| (key, matching::original_param(&path, &lookup, value)))
+                .collect();
+
+            if let Some(filter) = &endpoint.ip_filter {
+                if !filter.permits(client_ip) {
+                    tracing::warn!(
+                        "Rejected {} for {}: blocked by endpoint ip_filter",
+                        path,
+                        client_ip
+                    );
+                    return Ok(forbidden());
+                }
+            }
+
+            if let Some(limit) = endpoint.max_body_bytes {
+                if bytes.len() > limit {
+                    tracing::warn!("Rejected {}: body exceeds endpoint max_body_bytes", path);
+                    return Ok(payload_too_large(limit));
+                }
+            }
+
+            // Held until the mocked response is built
+            let _permit = match endpoint.max_concurrency {
+                Some(max) => {
+                    match state
+                        .endpoint_limits
+                        .try_acquire(&endpoint.method, &endpoint.path, max)
+                    {
+                        Some(permit) => Some(permit),
+                        None => {
+                            tracing::warn!("Rejected {}: endpoint max_concurrency reached", path);
+                            return Ok(limits::service_unavailable(
+                                "endpoint concurrency limit reached",
+                            ));
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            let mut login = state.logins.get(&parts.headers, now);
+            if matches!(endpoint.session, Some(SessionAction::Require)) && login.is_none() {
+                tracing::info!("Rejected {}: not logged in", path);
+                return Ok(login::unauthorized());
+            }
+
+            // gRPC-Web calls to protobuf endpoints are unframed, and with a
+            // request_message read as JSON like other encoded bodies
+            let grpc_web = endpoint
+                .protobuf
+                .as_ref()
+                .and_then(|protobuf| Some((GrpcWeb::from_headers(&parts.headers)?, protobuf)));
+            let json_body = match grpc_web {
+                Some((grpc_web, protobuf)) => {
+                    let decoded = grpc_web.request_message(&bytes).and_then(|message| {
+                        protobuf
+                            .decode_request(&message)
+                            .transpose()
+                            .map(|json| json.map(Bytes::from))
+                    });
+                    match decoded {
+                        Ok(Some(json)) => {
+                            let shown = state.redaction.body(&String::from_utf8_lossy(&json));
+                            tracing::info!("Request gRPC-Web payload: {}", shown);
+                            json
+                        }
+                        Ok(None) => json_body,
+                        Err(e) => {
+                            tracing::info!("Rejected {}: invalid gRPC-Web request: {}", path, e);
+                            let message = format!("Invalid gRPC-Web request: {}", e);
+                            return Ok(grpc_web.error(400, &message.into(), &parts.headers));
+                        }
+                    }
+                }
+                None => json_body,
+            };
+            let grpc_web = grpc_web.map(|(grpc_web, _)| grpc_web);
+
+            if let Some(schema) = &endpoint.request_schema {
+                if let Some(response) = schema.check(&json_body) {
+                    tracing::info!("Rejected {}: body doesn't match request_schema", path);
+                    return Ok(response);
+                }
+            }
+
+            // Keys are remembered per endpoint and client
+            let idempotency_key = match &endpoint.idempotency {
+                Some(config) => match config.key(&parts.headers) {
+                    Some(key) => {
+                        let client = parts.extensions.get::<ClientKey>();
+                        let key = format!(
+                            "{}\n{} {}\n{}\n{}",
+                            workspace.name,
+                            endpoint.method.to_ascii_uppercase(),
+                            endpoint.path,
+                            client.map_or("", |client| client.0.as_str()),
+                            key
+                        );
+                        match state.idempotency.claim(config, key, &bytes) {
+                            Claim::New(reservation) => Some(reservation),
+                            Claim::Answered(mut response) => {
+                                tracing::info!(
+                                    "Answered retry of {} ({})",
+                                    path,
+                                    response.status()
+                                );
+                                response.extensions_mut().insert(MatchedEndpoint(format!(
+                                    "{} {}",
+                                    endpoint.method.to_ascii_uppercase(),
+                                    endpoint.path
+                                )));
+                                return Ok(response);
+                            }
+                        }
+                    }
+                    None if config.required => return Ok(config.missing_key()),
+                    None => None,
+                },
+                None => None,
+            };
+
+            let event = match &endpoint.long_poll {
+                Some(long_poll) => {
+                    let name = long_poll.event_name(&params);
+                    tracing::info!("Holding {} until event {}", path, name);
+                    match long_poll.wait(&state.events, &name).await {
+                        Some(data) => Some(data),
+                        None => {
+                            tracing::info!("Event {} wasn't triggered in time for {}", name, path);
+                            return Ok(long_poll.timeout_response());
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            if let Some(chain) = &endpoint.redirect {
+                // Point back at the path the client used, prefix included
+                let client_path = format!(
+                    "{}{}{}",
+                    state.base_path.as_deref().unwrap_or_default(),
+                    workspace.path_prefix().unwrap_or_default(),
+                    path
+                );
+                if let Some(mut response) = chain.next(&client_path, parts.uri.query()) {
+                    tracing::info!("Redirecting {} ({})", path, response.status());
+                    response.extensions_mut().insert(MatchedEndpoint(format!(
+                        "{} {}",
+                        endpoint.method.to_ascii_uppercase(),
+                        endpoint.path
+                    )));
+                    return Ok(response);
+                }
+            }
+
+            if let Some(transform) = &endpoint.transform {
+                let (Some(backend), Some(client)) = (workspace.backend_for(&host), &state.client)
+                else {
+                    return Ok(transform::bad_gateway(format!(
+                        "Workspace {} has no default_endpoint to transform",
+                        workspace.name
+                    )));
+                };
+                let mut req = Request::from_parts(parts, body);
+                if transform.edits_body() {
+                    // Patches need a body they can parse
+                    req.headers_mut().remove(header::ACCEPT_ENCODING);
+                }
+                tracing::info!("Proxying {} to {} for transformation", path, backend);
+                let response = match proxy_request(req, client, &backend, state).await {
+                    Ok(response) => response,
+                    Err(_) if sent.too_large() => {
+                        return Err(RequestError::PayloadTooLarge(state.max_body_bytes))
+                    }
+                    Err(e) => return Err(e),
+                };
+                let mut response = match transform.apply(response).await {
+                    Ok(response) => response,
+                    Err(message) => {
+                        tracing::warn!("Failed to transform response for {}: {}", path, message);
+                        return Ok(transform::bad_gateway(message));
+                    }
+                };
+                response.extensions_mut().insert(MatchedEndpoint(format!(
+                    "{} {}",
+                    endpoint.method.to_ascii_uppercase(),
+                    endpoint.path
+                )));
+                response.extensions_mut().insert(ProxiedTo(backend.clone()));
+                return Ok(response);
+            }
+
+            if let Some(script) = &endpoint.websocket {
+                let mut response = script.accept(&mut parts);
+                response.extensions_mut().insert(MatchedEndpoint(format!(
+                    "{} {}",
+                    endpoint.method.to_ascii_uppercase(),
+                    endpoint.path
+                )));
+                return Ok(response);
+            }
+
+            // Pick the response, letting the first matching condition override it
+            let client = parts
+                .extensions
+                .get::<ClientKey>()
+                .map(|key| key.0.as_str());
+            let scenario_state = endpoint
+                .scenario
+                .as_ref()
+                .map(|step| workspace.scenarios.state(client, &step.name));
+            let values = conditions::RequestValues {
+                parts: &parts,
+                params: &params,
+                body: &json_body,
+                now,
+                state: scenario_state.as_deref(),
+            };
+            let condition = endpoint.conditions.iter().find(|c| c.matches(&values));
+            if let (Some(step), Some(from)) = (&endpoint.scenario, &scenario_state) {
+                workspace.scenarios.advance(client, step, from);
+            }
+            let status = condition.and_then(|c| c.status).unwrap_or(endpoint.status);
+            let content_type = condition
+                .and_then(|c| c.content_type.as_deref())
+                .unwrap_or(&endpoint.content_type);
+            // Otherwise the payload in the client's language, if there is one
+            let condition_payload = condition.and_then(|c| c.payload.as_ref());
+            let language = match condition_payload {
+                Some(_) => None,
+                None => {
+                    let tags: Vec<&str> = endpoint.languages.keys().map(String::as_str).collect();
+                    language::negotiate(&parts.headers, &tags)
+                }
+            };
+            let payload = condition_payload
+                .or_else(|| endpoint.languages.get(language?))
+                .unwrap_or(&endpoint.payload);
+            let locale = endpoint
+                .locale
+                .as_deref()
+                .or_else(|| {
+                    let primary = language?.split('-').next()?;
+                    faker::LOCALES
+                        .into_iter()
+                        .find(|locale| locale.eq_ignore_ascii_case(primary))
+                })
+                .or_else(|| language::negotiate(&parts.headers, &faker::LOCALES))
+                .unwrap_or(faker::LOCALES[0]);
+
+            // Then let the request itself override the status and timing
+            let control = if state.control_headers {
+                control::ControlHeaders::read(&parts.headers)
+            } else {
+                control::ControlHeaders::default()
+            };
+            // Settings are validated on load and on every change, so this
+            // only falls back to 500 for a status that slipped through
+            let status = control.status.unwrap_or_else(|| {
+                StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+            });
+
+            // Form, MessagePack and CBOR bodies read as their JSON form
+            let json = serde_json::from_slice(&json_body).ok();
+            let context = transform::RequestContext {
+                parts: &parts,
+                body: json.as_ref(),
+            };
+            let mut set_cookie = None;
+            match &endpoint.session {
+                Some(SessionAction::Login { values, ttl_secs }) => {
+                    let values: login::SessionValues = values
+                        .iter()
+                        .map(|(key, value)| (key.clone(), context.fill(value)))
+                        .collect();
+                    let ttl = ttl_secs.map(Duration::from_secs);
+                    let id = state.logins.login(values.clone(), ttl, now);
+                    tracing::info!("Started mocked login session for {}", path);
+                    set_cookie = Some(login::login_cookie(&id, ttl));
+                    login = Some((id, values));
+                }
+                Some(SessionAction::Logout) => {
+                    if let Some((id, _)) = &login {
+                        state.logins.logout(id);
+                    }
+                    set_cookie = Some(login::logout_cookie());
+                }
+                Some(SessionAction::Require) | None => {}
+            }
+            // Fill in {{body.FIELD}}, {{query.NAME}} and {{header.NAME}} from
+            // the request, {{session.NAME}} for logged-in clients,
+            // {{event.FIELD}} from the event a long poll waited for,
+            // {{now}} and the date helpers from the mock clock and
+            // {{fake.KIND}} in the locale picked above
+            let values = |name: &str| match name.split_once('.')? {
+                ("body" | "query" | "header", _) => context.lookup(name),
+                ("session", key) => login.as_ref()?.1.get(key).cloned(),
+                ("event", field) => {
+                    let pointer = format!("/{}", field.replace('.', "/"));
+                    event.as_ref()?.pointer(&pointer).cloned()
+                }
+                _ => None,
+            };
+            let lookup = |name: &str| {
+                values(name)
+                    .or_else(|| dates::template(name, now, &values))
+                    .or_else(|| faker::template(name, locale, &state.random))
+            };
+
+            // gRPC-Web carries errors as a status with no message
+            if let Some(grpc_web) = grpc_web.filter(|_| !status.is_success()) {
+                let payload = transform::fill(payload, &lookup);
+                let mut response = grpc_web.error(status.as_u16(), &payload, &parts.headers);
+                response.extensions_mut().insert(MatchedEndpoint(format!(
+                    "{} {}",
+                    endpoint.method.to_ascii_uppercase(),
+                    endpoint.path
+                )));
+                if let Some(delay) = control.delay {
+                    tokio::time::sleep(delay).await;
+                }
+                tracing::info!("Mocked gRPC-Web error for {}: {}", path, status.as_u16());
+                return Ok(response);
+            }
+
+            if let Some(ndjson) = &endpoint.ndjson {
+                let body = ndjson.body(&|name| {
+                    params
+                        .iter()
+                        .find(|(key, _)| *key == name)
+                        .map(|(_, value)| serde_json::Value::String(value.to_string()))
+                        .or_else(|| lookup(name))
+                });
+                let mut builder = Response::builder()
+                    .status(status)
+                    .header(header::CONTENT_TYPE, ndjson::CONTENT_TYPE);
+                if let Some(cookie) = set_cookie {
+                    builder = builder.header(header::SET_COOKIE, cookie);
+                }
+                let mut response = builder.body(body).unwrap();
+                response.extensions_mut().insert(MatchedEndpoint(format!(
+                    "{} {}",
+                    endpoint.method.to_ascii_uppercase(),
+                    endpoint.path
+                )));
+                if let Some(delay) = control.delay {
+                    tokio::time::sleep(delay).await;
+                }
+                tracing::info!("Streaming NDJSON for {}", path);
+                return Ok(response);
+            }
+
+            // Static payloads were serialized when the endpoints were loaded
+            let templating = Instant::now();
+            let compiled = routes
+                .compiled(index)
+                .filter(|_| condition.is_none() && language.is_none());
+            let (content_type, body) = match compiled {
+                Some(body) => {
+                    tracing::info!(
+                        "Response payload: {}",
+                        state.redaction.body(&String::from_utf8_lossy(body))
+                    );
+                    (content_type, body.clone())
+                }
+                None => {
+                    let rendered = render_payload(
+                        state,
+                        endpoint,
+                        content_type,
+                        payload,
+                        &params,
+                        &lookup,
+                        &path,
+                    );
+                    match rendered {
+                        Ok(rendered) => rendered,
+                        Err(response) => return Ok(*response),
+                    }
+                }
+            };
+            state.metrics.endpoint(
+                &workspace.name,
+                &name,
+                Stage::Templating,
+                templating.elapsed(),
+            );
+
+            // gRPC-Web answers 200 with the message framed and the status,
+            // along with the endpoint's trailers, in a trailer frame
+            let (status, content_type, body) = match grpc_web {
+                Some(grpc_web) => {
+                    let body = grpc_web.body(&body, &endpoint.trailers);
+                    (StatusCode::OK, grpc_web.content_type(), Bytes::from(body))
+                }
+                None => (status, content_type, body),
+            };
+
+            let body = match &endpoint.fault {
+                Some(fault) => fault.body(body),
+                None => body,
+            };
+
+            if let Some(reservation) = idempotency_key {
+                reservation.store(status.as_u16(), content_type, &body);
+            }
+
+            // Return the mocked response
+            let mut builder = Response::builder()
+                .status(status)
+                .header("Content-Type", content_type);
+            if let Some(cookie) = set_cookie {
+                builder = builder.header(header::SET_COOKIE, cookie);
+            }
+            if !endpoint.languages.is_empty() {
+                builder = builder.header(header::VARY, "Accept-Language");
+            }
+            if let Some(language) = language {
+                builder = builder.header(header::CONTENT_LANGUAGE, language);
+            }
+            if grpc_web.is_some() {
+                builder = grpc_web::cors(builder, &parts.headers);
+            }
+            if let Some(disposition) = endpoint
+                .csv
+                .as_ref()
+                .and_then(|c| c.content_disposition(&params))
+            {
+                builder = builder.header(header::CONTENT_DISPOSITION, disposition);
+            }
+
+            // Responses hyper can't produce are written to the connection
+            // directly. hyper leaves out the body of HEAD responses itself,
+            // keeping the Content-Length, but not of those.
+            let raw_bytes = match &endpoint.fault {
+                Some(_) if method == Method::HEAD => None,
+                Some(fault) => fault.raw_response(status.as_u16(), content_type, &body),
+                None => None,
+            }
+            .or_else(|| {
+                let chunked = !endpoint.trailers.is_empty() && grpc_web.is_none();
+                (chunked && method != Method::HEAD).then(|| {
+                    let headers = builder.headers_ref();
+                    trailers::chunked_response(status.as_u16(), headers, &body, &endpoint.trailers)
+                })
+            });
+            if let Some(bytes) = raw_bytes {
+                match parts.extensions.get::<connection::RawResponse>() {
+                    Some(raw) => raw.send(bytes),
+                    None => tracing::warn!("Can't write the response for {} directly", path),
+                }
+            }
+            let body = match endpoint.delay_body_ms {
+                Some(ms) => {
+                    // Announce the full length so clients wait for it
+                    builder = builder.header(header::CONTENT_LENGTH, body.len());
+                    delayed_body(body, Duration::from_millis(ms))
+                }
+                None => Body::from(body),
+            };
+            let mut response = builder.body(body).unwrap();
+            if let Some(keep_alive) = &endpoint.keep_alive {
+                let stats = parts.extensions.get::<connection::ConnectionStats>();
+                keep_alive.apply(stats, &mut response);
+            }
+            response.extensions_mut().insert(MatchedEndpoint(format!(
+                "{} {}",
+                endpoint.method.to_ascii_uppercase(),
+                endpoint.path
+            )));
+
+            if let Some(delay) = control.delay {
+                tracing::info!("Delaying {} by {:?} as requested", path, delay);
+                tokio::time::sleep(delay).await;
+            }
+            if let Some(fault) = &endpoint.fault {
+                tracing::info!("Applying {:?} to {}", fault, path);
+                fault.delay().await;
+            }
+            if let Some(ms) = endpoint.delay_headers_ms {
+                tokio::time::sleep(Duration::from_millis(ms)).await;
+            }
+
+            tracing::info!("Mocked response for {}: {}", path, status);
+            return Ok(response);
+        }
+    }
+
+    if let Some(outcome) = parts.extensions.get::<MatchOutcome>() {
+        outcome.unmatched(matching.elapsed());
+    }
+    if let Some(response) = state.unmatched.action(&path).respond(&method, &path) {
+        tracing::info!("No mock endpoint for {} {}", method, path);
+        return Ok(response);
+    }
+
+    proxy_unmatched(state, workspace, parts, body, &sent).await
+}
+
+// Forwards a request no endpoint answered to the workspace's default backend
+async fn proxy_unmatched(
+    state: &AppState,
+    workspace: &Workspace,
+    mut parts: Parts,
+    body: Body,
+    sent: &SentBody,
+) -> Result<Response<Body>, RequestError> {
+    let method = parts.method.clone();
+    let path = parts.uri.path().to_string();
+
+    // Mock-only workspaces have nothing to proxy to
+    let host = vhost::request_host(&parts).unwrap_or_default();
+    let (Some(backend), Some(client)) = (workspace.backend_for(&host), &state.client) else {
+        tracing::info!("No mock endpoint or backend for {} {}", method, path);
+        return Ok(unmatched::not_found(&method, &path));
+    };
+
+    // gRPC-Web passes through as is, for backends that speak it, but the
+    // browser's CORS preflight is answered here and the status headers
+    // are exposed to the page
+    if method == Method::OPTIONS {
+        if let Some(response) = grpc_web::preflight(&parts.headers) {
+            tracing::info!("Answered gRPC-Web preflight for {}", path);
+            return Ok(response);
+        }
+    }
+    let grpc_web_headers = GrpcWeb::from_headers(&parts.headers).map(|_| parts.headers.clone());
+
+    if websocket::is_upgrade(&parts) {
+        let recording = state
+            .recorder
+            .clone()
+            .filter(|recorder| recorder.wants(&path))
+            .map(|recorder| (recorder, RecordedRequest::new(&parts, &path)));
+        tracing::info!("Proxying WebSocket {} to {}", path, backend);
+        let mut response = websocket::proxy(client, &backend, parts, via_name(state), recording)
+            .await
+            .map_err(RequestError::Upstream)?;
+        response.extensions_mut().insert(ProxiedTo(backend.clone()));
+        return Ok(response);
+    }
+
+    let recording = state
+        .recorder
+        .as_ref()
+        .filter(|recorder| recorder.wants(&path))
+        .map(|recorder| (recorder, RecordedRequest::new(&parts, &path)));
+
+    // Scenarios need bodies they can read
+    let capturing = workspace.scenarios.recording().is_some();
+    if capturing {
+        parts.headers.remove(header::ACCEPT_ENCODING);
+    }
+
+    let req = Request::from_parts(parts, body);
+
+    // Proxy the request to the default endpoint
+    tracing::info!("Proxying request to default backend: {}", backend);
+    match proxy_request(req, client, &backend, state).await {
+        Ok(mut response) => {
+            // Optionally, log the proxied response here
+            tracing::info!("Proxied response: {}", response.status());
+            if let Some((recorder, request)) = recording {
+                response = recorder.record(request, sent.clone(), response);
+            }
+            if capturing {
+                response = workspace.scenarios.capture(&method, &path, response);
+            }
+            if let Some(headers) = &grpc_web_headers {
+                grpc_web::expose(&mut response, headers);
+            }
+            response.extensions_mut().insert(ProxiedTo(backend.clone()));
+            Ok(response)
+        }
+        Err(_) if sent.too_large() => Err(RequestError::PayloadTooLarge(state.max_body_bytes)),
+        Err(e) => Err(e),
+    }
+}
+
+// Requests that are only passed on to the backend go there without their
+// bodies being read first: those no endpoint answers, and those for an
+// endpoint's transform, which only changes the response, unless something
+// else on the endpoint looks at the body. The endpoint is picked the way
+// process_request does.
+fn streams_to_backend(state: &AppState, workspace: &Workspace, parts: &Parts) -> bool {
+    let host = vhost::request_host(parts).unwrap_or_default();
+    if workspace.backend_for(&host).is_none() || state.client.is_none() {
+        return false;
+    }
+
+    let mut method = parts.method.clone();
+    if state.method_override {
+        // A form body can name another method
+        let is_form = parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .and_then(Codec::for_content_type)
+            .is_some_and(|codec| matches!(codec, Codec::Form));
+        if is_form && method == Method::POST {
+            return false;
+        }
+        method = matching::method_override(&method, &parts.headers, &[]).unwrap_or(method);
+    }
+
+    let routes = workspace.routes_for(&host);
+    let lookup = state.path_matching.request_path(parts.uri.path());
+    let now = state.clock.now();
+    let candidates: Vec<&EndpointConfig> = match routes.router.at(&lookup) {
+        Ok(matched) => matched
+            .value
+            .iter()
+            .map(|&idx| &routes.endpoints[idx])
+            .filter(|ep| schedule::any_active(&ep.active, now))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    let with_method = |method: &str| {
+        candidates
+            .iter()
+            .copied()
+            .find(|ep| ep.method.eq_ignore_ascii_case(method))
+    };
+    let mut endpoint = with_method(method.as_str());
+    if endpoint.is_none() && method == Method::HEAD && state.head_requests == HeadRequests::Mock {
+        endpoint = with_method("GET");
+    }
+    match endpoint {
+        Some(endpoint) => {
+            endpoint.transform.is_some()
+                && endpoint.max_body_bytes.is_none()
+                && endpoint.protobuf.is_none()
+                && endpoint.request_schema.is_none()
+                && endpoint.idempotency.is_none()
+        }
+        None => {
+            let rejected =
+                !candidates.is_empty() && state.method_mismatch == MethodMismatch::Reject;
+            !rejected
+                && matches!(
+                    state.unmatched.action(parts.uri.path()),
+                    UnmatchedAction::Proxy
+                )
+        }
+    }
+}
+
+async fn proxy_request(
+    req: Request<Body>,
+    client: &HttpsClient,
+    default_endpoint: &str,
+    state: &AppState,
+) -> Result<Response<Body>, RequestError> {
+    let mut req = transform::rewrite_request(&state.request_rewrites, req).await?;
+    if let Some(chain) = &state.mock_chain {
+        if let Some(response) = chain.forward(req.headers_mut()) {
+            return Ok(response);
+        }
+    }
+    let chaos = state
+        .backend_chaos
+        .apply(default_endpoint, req.uri().path(), &state.random)
+        .await;
+    if let Some(response) = chaos {
+        return Ok(response);
+    }
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let rewrites: Vec<&ResponseRewrite> = state
+        .response_rewrites
+        .iter()
+        .filter(|rule| rule.applies_to(&method, &path))
+        .collect();
+    if rewrites.iter().any(|rule| rule.edits_body()) {
+        // The patches need a body they can parse
+        req.headers_mut().remove(header::ACCEPT_ENCODING);
+    }
+
+    // Construct the new URI for the default endpoint
+    let uri = req.uri().clone();
+    let query = uri.query().map(|q| format!("?{}", q)).unwrap_or_default();
+    let new_uri_str = format!("{}{}{}", default_endpoint, uri.path(), query);
+    let new_uri = new_uri_str
+        .parse::<hyper::Uri>()
+        .map_err(|e| RequestError::Upstream(UpstreamError::invalid_url(default_endpoint, e)))?;
+    *req.uri_mut() = new_uri;
+
+    let shown_uri = match uri.query() {
+        Some(query) => format!(
+            "{}{}?{}",
+            default_endpoint,
+            uri.path(),
+            state.redaction.query(query)
+        ),
+        None => new_uri_str,
+    };
+    tracing::info!("Forwarding request to: {}", shown_uri);
+
+    // Remove the `Host` header to prevent potential issues
+    req.headers_mut().remove("host");
+    let via = via_name(state);
+    let version = req.version();
+    // Trailers are passed on to clients that say they take them, written to
+    // the connection like a mock's
+    let raw = req.extensions().get::<connection::RawResponse>().cloned();
+    let trailers_client = state
+        .trailers_client
+        .as_ref()
+        .zip(raw)
+        .filter(|_| method != Method::HEAD && hop_by_hop::takes_trailers(req.headers()));
+    hop_by_hop::strip(req.headers_mut());
+    hop_by_hop::via(req.headers_mut(), version, via);
+
+    // Forward the request
+    let sent = Instant::now();
+    let (result, timing, received) = match trailers_client {
+        Some((trailers_client, raw)) => {
+            req.headers_mut()
+                .insert(header::TE, header::HeaderValue::from_static("trailers"));
+            let ((result, timing), received) =
+                trailers::receive(upstream::send(trailers_client, req)).await;
+            (result, timing, Some((received, raw)))
+        }
+        None => {
+            let (result, timing) = upstream::send(client, req).await;
+            (result, timing, None)
+        }
+    };
+    if let Some(alerts) = &state.alerts {
+        alerts.upstream(default_endpoint, result.as_ref().map(|_| ()));
+    }
+    tracing::info!("Upstream timing for {}: {}", shown_uri, timing);
+    match result {
+        Ok(mut response) => {
+            state
+                .metrics
+                .backend(default_endpoint, Stage::Upstream, sent.elapsed());
+            tracing::info!(
+                "Received proxied response with status: {}",
+                response.status()
+            );
+            response.extensions_mut().insert(UpstreamTimed(timing));
+            let version = response.version();
+            hop_by_hop::strip(response.headers_mut());
+            hop_by_hop::via(response.headers_mut(), version, via);
+            if !rewrites.is_empty() {
+                response = transform::rewrite_response(&rewrites, response, &path).await;
+            }
+            let Some((received, raw)) = received else {
+                return Ok(response);
+            };
+            trailers::pass_on(response, received, &raw)
+                .await
+                .map_err(|e| RequestError::Upstream(UpstreamError::new(default_endpoint, &e)))
+        }
+        Err(e) => {
+            tracing::error!("Error during proxy request: {}", e);
+            Err(RequestError::Upstream(UpstreamError::new(
+                default_endpoint,
+                &e,
+            )))
+        }
+    }
+}
+
+// How this server names itself in Via headers
+fn via_name(state: &AppState) -> &str {
+    state
+        .mock_chain
+        .as_ref()
+        .map_or("mock-api", |chain| chain.name.as_str())
+}
+
+fn build_router(endpoints: &[EndpointConfig], matching: PathMatching) -> MatchItRouter<Vec<usize>> {
+    let (router, conflicts) = route_table(endpoints, matching);
+    for conflict in conflicts {
+        tracing::error!(
+            "Failed to insert route {}: {}",
+            conflict.path,
+            conflict.error
+        );
+    }
+    router
+}
+
+// An endpoint whose path can't be added to the router, e.g. a duplicate or a
+// pattern that overlaps another endpoint's
+#[derive(Debug, Serialize, ToSchema)]
+struct RouteConflict {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workspace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    index: usize, // Position in the endpoint list
+    method: String,
+    path: String,
+    error: String,
+}
+
+impl RouteConflict {
+    fn new(index: usize, endpoint: &EndpointConfig, error: String) -> Self {
+        RouteConflict {
+            workspace: None,
+            profile: None,
+            host: None,
+            index,
+            method: endpoint.method.clone(),
+            path: endpoint.path.clone(),
+            error,
+        }
+    }
+}
+
+// Endpoints sharing a path are routed together and told apart by method
+fn route_table(
+    endpoints: &[EndpointConfig],
+    matching: PathMatching,
+) -> (MatchItRouter<Vec<usize>>, Vec<RouteConflict>) {
+    let mut conflicts = Vec::new();
+    let mut routes: Vec<(String, Vec<usize>)> = Vec::new();
+    for (idx, ep) in endpoints.iter().enumerate() {
+        let pattern = matching.pattern(&ep.path);
+        let Some((_, indices)) = routes.iter_mut().find(|(p, _)| *p == pattern) else {
+            routes.push((pattern, vec![idx]));
+            continue;
+        };
+        match indices
+            .iter()
+            .find(|&&other| endpoints[other].method.eq_ignore_ascii_case(&ep.method))
+        {
+            Some(other) => conflicts.push(RouteConflict::new(
+                idx,
+                ep,
+                format!("Same method and path as endpoint {}", other),
+            )),
+            None => indices.push(idx),
+        }
+    }
+
+    let mut router = MatchItRouter::new();
+    for (pattern, indices) in routes {
+        match router.insert(&pattern, indices.clone()) {
+            Ok(_) => tracing::debug!("Inserted route: {}", pattern),
+            Err(e) => conflicts.extend(
+                indices
+                    .into_iter()
+                    .map(|idx| RouteConflict::new(idx, &endpoints[idx], e.to_string())),
+            ),
+        }
+    }
+    conflicts.sort_by_key(|conflict| conflict.index);
+    (router, conflicts)
+}
+
+fn route_conflicts(endpoints: &[EndpointConfig], matching: PathMatching) -> Vec<RouteConflict> {
+    route_table(endpoints, matching).1
+}
+
+// Conflicts in every endpoint set of `settings`: each workspace's base
+// endpoints, profiles and virtual hosts
+fn settings_route_conflicts(settings: &Settings, matching: PathMatching) -> Vec<RouteConflict> {
+    let mut conflicts = Vec::new();
+    for workspace in std::iter::once(&settings.workspace).chain(&settings.workspaces) {
+        let sets = std::iter::once((Some(BASE_PROFILE), None, &workspace.endpoints))
+            .chain(
+                workspace
+                    .profiles
+                    .iter()
+                    .map(|(profile, endpoints)| (Some(profile.as_str()), None, endpoints)),
+            )
+            .chain(workspace.virtual_hosts.iter().map(|vhost| {
+                (
+                    None,
+                    vhost.hosts.first().map(String::as_str),
+                    &vhost.endpoints,
+                )
+            }));
+        for (profile, host, endpoints) in sets {
+            conflicts.extend(
+                route_conflicts(endpoints, matching)
+                    .into_iter()
+                    .map(|conflict| RouteConflict {
+                        workspace: Some(workspace.name().to_string()),
+                        profile: profile.map(str::to_string),
+                        host: host.map(str::to_string),
+                        ..conflict
+                    }),
+            );
+        }
+    }
+    conflicts
+}
+
+// Body of the 422 response for conflicting routes
+#[derive(Serialize, ToSchema)]
+struct RouteConflicts {
+    error: &'static str,
+    conflicts: Vec<RouteConflict>,
+}
+
+// Settings an admin change would replace the current ones with, but that
+// wouldn't pass startup
+fn invalid_settings_response(e: StartupError) -> Response<Body> {
+    tracing::warn!("Rejected settings: {}", e.message);
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&e).unwrap()))
+        .unwrap()
+}
+
+// Secrets run commands and read files on this host, so settings from
+// elsewhere can't name any; only settings.json can
+fn secrets_refused(settings: &serde_json::Value) -> Option<Response<Body>> {
+    let secrets = settings.get("secrets")?;
+    if secrets.is_null()
+        || secrets
+            .as_object()
+            .is_some_and(|secrets| secrets.is_empty())
+    {
+        return None;
+    }
+    Some(invalid_settings_response(StartupError::config(
+        "secrets can only be set in settings.json",
+    )))
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Settings {
-    default_endpoint: String,
-    endpoints: Vec<EndpointConfig>,
+fn route_conflicts_response(conflicts: Vec<RouteConflict>) -> Response<Body> {
+    for conflict in &conflicts {
+        tracing::warn!(
+            "Rejected route {} {}: {}",
+            conflict.method,
+            conflict.path,
+            conflict.error
+        );
+    }
+    let body = RouteConflicts {
+        error: "Conflicting routes",
+        conflicts,
+    };
+    Response::builder()
+        .status(StatusCode::UNPROCESSABLE_ENTITY)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
+        .unwrap()
 }
 
-#[derive(Clone)]
-struct AppState {
-    endpoints: Arc<RwLock<Vec<EndpointConfig>>>,
-    router: Arc<RwLock<MatchItRouter<usize>>>, // For path matching
-    default_endpoint: String,
-    client: Client<hyper_rustls::HttpsConnector<HttpConnector>, Body>,
+// Selects which endpoint set the admin routes operate on
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AdminQuery {
+    workspace: Option<String>,
+    host: Option<String>,
+    token: Option<String>, // Admin token, remembered by the page's session
 }
-#[tokio::main]
-async fn main() {
-    // Set up logging
-    // tracing_subscriber::fmt().with_max_level(Level::INFO).init();
-    tracing_subscriber::fmt().with_max_level(Level::INFO).init();
 
-    // Load settings
-    let settings: Settings = {
-        let file = std::fs::File::open("settings.json").expect("Failed to open settings.json");
-        serde_json::from_reader(file).expect("Failed to parse settings.json")
-    };
+fn unknown_virtual_host(host: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from(format!("Unknown virtual host: {}", host)))
+        .unwrap()
+}
 
-    // HTTPS client setup using HttpsConnectorBuilder
-    let https = HttpsConnectorBuilder::new()
-        .with_native_roots()
-        .https_only()
-        .enable_http1()
-        .build();
-    let client = Client::builder().build(https);
+fn unknown_workspace(name: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from(format!("Unknown workspace: {}", name)))
+        .unwrap()
+}
 
-    // Shared application state
-    let endpoints = Arc::new(RwLock::new(settings.endpoints.clone()));
-    let router = build_router(&settings.endpoints);
+// The workspace an admin request is scoped to: named explicitly with
+// ?workspace=, otherwise chosen by listener port and Host like mock traffic.
+// Returns the unknown name as the error.
+fn admin_workspace(
+    state: &AppState,
+    query: &AdminQuery,
+    port: u16,
+    headers: &HeaderMap,
+) -> Result<Arc<Workspace>, String> {
+    match &query.workspace {
+        Some(name) => state
+            .workspaces
+            .get(name)
+            .cloned()
+            .ok_or_else(|| name.clone()),
+        None => {
+            let host = headers
+                .get("Host")
+                .and_then(|h| h.to_str().ok())
+                .map(vhost::normalize_host)
+                .unwrap_or_default();
+            Ok(state.workspaces.select(port, &host, "").0)
+        }
+    }
+}
 
-    let app_state = AppState {
-        endpoints,
-        router,
-        default_endpoint: settings.default_endpoint,
-        client,
+// Admin endpoint to update the endpoints dynamically
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/update",
+    tag = "endpoints",
+    params(AdminQuery),
+    request_body = Vec<EndpointConfig>,
+    responses(
+        (status = 200, description = "Endpoints replaced"),
+        (status = 404, description = "Unknown workspace or virtual host"),
+        (status = 422, description = "Conflicting routes", body = RouteConflicts)
+    )
+)]
+async fn update_endpoints(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+    Json(new_endpoints): Json<Vec<EndpointConfig>>,
+) -> Response<Body> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name),
     };
+    replace_endpoints(
+        state,
+        &workspace,
+        query.host.as_deref(),
+        new_endpoints,
+        &headers,
+        addr.ip(),
+    )
+    .await
+}
 
-    // Build the Axum router with logging middleware
-    let app = Router::new()
-        .route("/mockserver/admin", axum::routing::get(admin_page))
-        .route(
-            "/mockserver/admin/update",
-            axum::routing::post(update_endpoints),
-        )
-        .nest_service(
-            "/static",
-            get_service(ServeDir::new("static")).handle_error(handle_error),
+// The endpoints the admin routes edit: the workspace's own, or a virtual
+// host's. The error is the unknown host.
+async fn current_endpoints(
+    workspace: &Workspace,
+    host: Option<&str>,
+) -> Result<Vec<EndpointConfig>, String> {
+    match host {
+        Some(host) => {
+            let host = vhost::normalize_host(host);
+            let virtual_hosts = workspace.virtual_hosts.load();
+            match vhost::find(&virtual_hosts, &host) {
+                Some(vhost) => Ok(vhost.routes.endpoints.clone()),
+                None => Err(host),
+            }
+        }
+        None => Ok(workspace.routes.load().local().to_vec()),
+    }
+}
+
+// Serves and saves a new set of endpoints for the workspace, or for one of
+// its virtual hosts
+async fn replace_endpoints(
+    state: AppState,
+    workspace: &Workspace,
+    host: Option<&str>,
+    new_endpoints: Vec<EndpointConfig>,
+    headers: &HeaderMap,
+    client_ip: IpAddr,
+) -> Response<Body> {
+    let conflicts = route_conflicts(&new_endpoints, state.path_matching);
+    if !conflicts.is_empty() {
+        return route_conflicts_response(conflicts);
+    }
+
+    if let Some(host) = host {
+        return update_virtual_host_endpoints(
+            state,
+            workspace,
+            host,
+            new_endpoints,
+            headers,
+            client_ip,
         )
-        .fallback(handler)
-        .with_state(app_state)
-        .layer(ServiceBuilder::new().layer(TraceLayer::new_for_http()));
+        .await;
+    }
 
-    // Run the server
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8000));
-    println!("Listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
-}
+    // Hold the settings while serving the new endpoints, so concurrent
+    // changes are served and saved in the same order
+    let mut settings = state.settings.write().await;
+    workspace.set_endpoints(new_endpoints.clone());
 
-// Adjusted handler function
-async fn handler(State(state): State<AppState>, req: Request<Body>) -> impl IntoResponse {
-    match process_request(state, req).await {
-        Ok(response) => response,
-        Err(_) => Response::builder()
+    // Update the persisted settings with the new endpoints
+    let mut description = String::from("Updated endpoints");
+    if let Some(workspace_settings) = settings.workspace_mut(&workspace.name) {
+        *workspace_settings.active_endpoints_mut() = new_endpoints;
+        description = format!("Updated {} profile", workspace_settings.active_profile());
+        workspace.versions.record(workspace_settings, &description);
+    }
+
+    // Write settings to settings.json
+    if let Err(e) = persist_settings(
+        &state,
+        &settings,
+        &workspace.name,
+        &description,
+        headers,
+        client_ip,
+    )
+    .await
+    {
+        tracing::error!("Failed to write settings to file: {}", e);
+        return Response::builder()
             .status(StatusCode::INTERNAL_SERVER_ERROR)
-            .body(Body::from("Internal Server Error"))
-            .unwrap(),
+            .body(Body::from("Failed to write settings to file"))
+            .unwrap();
     }
+
+    tracing::info!("Endpoints updated dynamically.");
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from("Endpoints updated"))
+        .unwrap()
 }
 
-async fn process_request(
+async fn update_virtual_host_endpoints(
     state: AppState,
-    req: Request<Body>,
-) -> Result<Response<Body>, hyper::Error> {
-    // Split the request into its parts and the body
-    let (parts, body) = req.into_parts();
+    workspace: &Workspace,
+    host: &str,
+    new_endpoints: Vec<EndpointConfig>,
+    headers: &HeaderMap,
+    client_ip: IpAddr,
+) -> Response<Body> {
+    let host = vhost::normalize_host(host);
+    let mut settings = state.settings.write().await;
+    let virtual_hosts = workspace.virtual_hosts.load();
+    let Some(idx) = vhost::position(&virtual_hosts, &host) else {
+        return unknown_virtual_host(&host);
+    };
+    workspace.set_virtual_host_endpoints(&host, new_endpoints.clone());
 
-    // Read the entire body
-    let bytes = to_bytes(body).await?;
+    let description = format!("Updated virtual host {}", host);
+    if let Some(workspace_settings) = settings.workspace_mut(&workspace.name) {
+        workspace_settings.virtual_hosts[idx].endpoints = new_endpoints;
+        workspace.versions.record(workspace_settings, &description);
+    }
 
-    // Log the payload based on Content-Type
-    if let Some(content_type) = parts
-        .headers
-        .get("Content-Type")
-        .and_then(|ct| ct.to_str().ok())
+    if let Err(e) = persist_settings(
+        &state,
+        &settings,
+        &workspace.name,
+        &description,
+        headers,
+        client_ip,
+    )
+    .await
     {
-        match content_type {
-            ct if ct.contains("application/json") => {
-                if let Ok(json) = serde_json::from_slice::<serde_json::Value>(&bytes) {
-                    tracing::info!("Request JSON payload: {}", json);
-                } else {
-                    tracing::info!("Request payload (invalid JSON): {:?}", bytes);
-                }
-            }
-            _ => {
-                // For other content types, log as bytes or convert to string if possible
-                if let Ok(body_str) = String::from_utf8(bytes.to_vec()) {
-                    tracing::info!("Request payload: {}", body_str);
-                } else {
-                    tracing::info!("Request payload (binary): {:?}", bytes);
-                }
-            }
-        }
-    } else {
-        // If no Content-Type header is present
-        tracing::info!("Request payload: {:?}", bytes);
+        tracing::error!("Failed to write settings to file: {}", e);
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from("Failed to write settings to file"))
+            .unwrap();
     }
 
-    // Reconstruct the request with the same body for further processing
-    let req = Request::from_parts(parts, Body::from(bytes.clone()));
+    tracing::info!("Endpoints for virtual host {} updated dynamically.", host);
 
-    // Continue with existing processing logic
-    let method = req.method().clone();
-    let path = req.uri().path().to_string();
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from("Endpoints updated"))
+        .unwrap()
+}
 
-    tracing::info!("Processing request: {} {}", method, path);
+// Per-endpoint admin API used by the admin page. Endpoints are addressed by
+// their position in the list returned by `list_endpoints`.
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/endpoints",
+    tag = "endpoints",
+    params(AdminQuery),
+    responses(
+        (status = 200, description = "Current endpoints", body = Vec<EndpointConfig>),
+        (status = 404, description = "Unknown workspace or virtual host")
+    )
+)]
+async fn list_endpoints(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+) -> Response<axum::body::BoxBody> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name).into_response(),
+    };
+    match current_endpoints(&workspace, query.host.as_deref()).await {
+        Ok(endpoints) => Json(endpoints).into_response(),
+        Err(host) => unknown_virtual_host(&host).into_response(),
+    }
+}
 
-    // Read the endpoints and router
-    let endpoints = state.endpoints.read().await;
-    let router = state.router.read().await;
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/endpoints",
+    tag = "endpoints",
+    params(AdminQuery),
+    request_body = EndpointConfig,
+    responses(
+        (status = 200, description = "Endpoint added"),
+        (status = 404, description = "Unknown workspace or virtual host"),
+        (status = 422, description = "Conflicting routes", body = RouteConflicts)
+    )
+)]
+async fn create_endpoint(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+    Json(endpoint): Json<EndpointConfig>,
+) -> Response<Body> {
+    edit_endpoints(state, addr, port, query, headers, |endpoints| {
+        endpoints.push(endpoint);
+        true
+    })
+    .await
+}
 
-    // Match the request path
-    if let Ok(matched) = router.at(&path) {
-        let idx = *matched.value;
-        let endpoint = &endpoints[idx];
+#[utoipa::path(
+    put,
+    path = "/mockserver/admin/endpoints/{index}",
+    tag = "endpoints",
+    params(("index" = usize, Path, description = "Position in the endpoint list"), AdminQuery),
+    request_body = EndpointConfig,
+    responses(
+        (status = 200, description = "Endpoint replaced"),
+        (status = 404, description = "No such endpoint, workspace or virtual host"),
+        (status = 422, description = "Conflicting routes", body = RouteConflicts)
+    )
+)]
+async fn update_endpoint(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Path(index): Path<usize>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+    Json(endpoint): Json<EndpointConfig>,
+) -> Response<Body> {
+    edit_endpoints(
+        state,
+        addr,
+        port,
+        query,
+        headers,
+        |endpoints| match endpoints.get_mut(index) {
+            Some(existing) => {
+                *existing = endpoint;
+                true
+            }
+            None => false,
+        },
+    )
+    .await
+}
 
-        if endpoint.method.eq_ignore_ascii_case(method.as_str()) {
-            tracing::info!("Matched mock endpoint for path: {}", path);
+#[utoipa::path(
+    delete,
+    path = "/mockserver/admin/endpoints/{index}",
+    tag = "endpoints",
+    params(("index" = usize, Path, description = "Position in the endpoint list"), AdminQuery),
+    responses(
+        (status = 200, description = "Endpoint removed"),
+        (status = 404, description = "No such endpoint, workspace or virtual host")
+    )
+)]
+async fn delete_endpoint(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Path(index): Path<usize>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    edit_endpoints(state, addr, port, query, headers, |endpoints| {
+        if index < endpoints.len() {
+            endpoints.remove(index);
+            true
+        } else {
+            false
+        }
+    })
+    .await
+}
 
-            // Collect the path parameters
-            let params = matched.params.clone();
-
-            let body = if endpoint.content_type == "application/json" {
-                // Inject parameters into the JSON payload
-                let mut payload = endpoint.payload.clone();
-                if let serde_json::Value::Object(ref mut map) = payload {
-                    for (key, value) in params.iter() {
-                        map.insert(
-                            key.to_string(),
-                            serde_json::Value::String(value.to_string()),
-                        );
-                    }
-                }
-                serde_json::to_string(&payload).unwrap()
-            } else {
-                // For other content types, perform placeholder replacement
-                let mut body = match &endpoint.payload {
-                    serde_json::Value::String(s) => s.clone(),
-                    _ => endpoint.payload.to_string(),
-                };
-                for (key, value) in params.iter() {
-                    let placeholder = format!("{{{{{}}}}}", key);
-                    body = body.replace(&placeholder, value);
-                }
-                body
-            };
+// Applies `edit` to the current endpoints and saves the result. `edit`
+// returns false when the endpoint it was asked for doesn't exist.
+async fn edit_endpoints(
+    state: AppState,
+    addr: SocketAddr,
+    port: u16,
+    query: AdminQuery,
+    headers: HeaderMap,
+    edit: impl FnOnce(&mut Vec<EndpointConfig>) -> bool,
+) -> Response<Body> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name),
+    };
+    let mut endpoints = match current_endpoints(&workspace, query.host.as_deref()).await {
+        Ok(endpoints) => endpoints,
+        Err(host) => return unknown_virtual_host(&host),
+    };
+    if !edit(&mut endpoints) {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("No such endpoint"))
+            .unwrap();
+    }
+    replace_endpoints(
+        state,
+        &workspace,
+        query.host.as_deref(),
+        endpoints,
+        &headers,
+        addr.ip(),
+    )
+    .await
+}
 
-            // Log the response payload
-            tracing::info!("Response payload: {}", body);
+#[derive(Serialize, ToSchema)]
+struct ProfilesResponse {
+    active: String,
+    available: Vec<String>,
+}
 
-            // Return the mocked response
-            let response = Response::builder()
-                .status(StatusCode::from_u16(endpoint.status).unwrap())
-                .header("Content-Type", &endpoint.content_type)
-                .body(Body::from(body))
-                .unwrap();
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/profile",
+    tag = "profiles",
+    params(AdminQuery),
+    responses(
+        (status = 200, description = "Active and available profiles", body = ProfilesResponse),
+        (status = 404, description = "Unknown workspace")
+    )
+)]
+async fn get_profiles(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+) -> Response<axum::body::BoxBody> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name).into_response(),
+    };
+    let settings = state.settings.read().await;
+    let workspace_settings = settings.workspace(&workspace.name).unwrap();
+    Json(ProfilesResponse {
+        active: workspace_settings.active_profile().to_string(),
+        available: workspace_settings.profile_names(),
+    })
+    .into_response()
+}
 
-            tracing::info!("Mocked response for {}: {}", path, endpoint.status);
-            return Ok(response);
-        }
+// Swaps the workspace's served endpoints for another profile's set
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/profile/{name}",
+    tag = "profiles",
+    params(("name" = String, Path, description = "Profile to activate"), AdminQuery),
+    responses(
+        (status = 200, description = "Profile activated"),
+        (status = 404, description = "Unknown profile or workspace")
+    )
+)]
+async fn switch_profile(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Path(profile): Path<String>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name),
+    };
+
+    let mut settings = state.settings.write().await;
+    let workspace_settings = settings.workspace_mut(&workspace.name).unwrap();
+    if profile != BASE_PROFILE && !workspace_settings.profiles.contains_key(&profile) {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from(format!("Unknown profile: {}", profile)))
+            .unwrap();
     }
+    workspace_settings.active_profile = (profile != BASE_PROFILE).then(|| profile.clone());
+    workspace.set_endpoints(workspace_settings.active_endpoints().clone());
+    let description = format!("Switched to profile {}", profile);
+    workspace.versions.record(workspace_settings, &description);
 
-    // Proxy the request to the default endpoint
-    tracing::info!(
-        "Proxying request to default backend: {}",
-        state.default_endpoint
-    );
-    match proxy_request(req, state.clone()).await {
-        Ok(response) => {
-            // Optionally, log the proxied response here
-            tracing::info!("Proxied response: {}", response.status());
-            Ok(response)
-        }
-        Err(e) => {
-            tracing::error!("Failed to proxy request: {}", e);
-            Err(e)
-        }
+    if let Err(e) = persist_settings(
+        &state,
+        &settings,
+        &workspace.name,
+        &description,
+        &headers,
+        addr.ip(),
+    )
+    .await
+    {
+        tracing::error!("Failed to write settings to file: {}", e);
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from("Failed to write settings to file"))
+            .unwrap();
     }
-}
-async fn proxy_request(
-    mut req: Request<Body>,
-    state: AppState,
-) -> Result<Response<Body>, hyper::Error> {
-    // Construct the new URI for the default endpoint
-    let uri = req.uri().clone();
-    let query = uri.query().map(|q| format!("?{}", q)).unwrap_or_default();
-    let new_uri_str = format!("{}{}{}", state.default_endpoint, uri.path(), query);
-    let new_uri = new_uri_str
-        .parse::<hyper::Uri>()
-        .expect("Failed to parse new URI");
-    *req.uri_mut() = new_uri.clone();
 
-    tracing::info!("Forwarding request to: {}", new_uri);
+    tracing::info!(
+        "Switched workspace {} to profile {}",
+        workspace.name,
+        profile
+    );
 
-    // Remove the `Host` header to prevent potential issues
-    req.headers_mut().remove("host");
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(format!("Switched to profile {}", profile)))
+        .unwrap()
+}
 
-    // Forward the request
-    match state.client.request(req).await {
-        Ok(response) => {
-            tracing::info!(
-                "Received proxied response with status: {}",
-                response.status()
-            );
-            Ok(response)
-        }
-        Err(e) => {
-            tracing::error!("Error during proxy request: {}", e);
-            Err(e)
-        }
+// Configuration history of a workspace, newest first
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/versions",
+    tag = "versions",
+    params(AdminQuery),
+    responses(
+        (status = 200, description = "Saved versions, newest first", body = Vec<VersionSummary>),
+        (status = 404, description = "Unknown workspace")
+    )
+)]
+async fn get_versions(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+) -> Response<axum::body::BoxBody> {
+    match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => Json(workspace.versions.list()).into_response(),
+        Err(name) => unknown_workspace(&name).into_response(),
     }
 }
 
-fn build_router(endpoints: &[EndpointConfig]) -> Arc<RwLock<MatchItRouter<usize>>> {
-    let mut router = MatchItRouter::new();
-    for (idx, ep) in endpoints.iter().enumerate() {
-        match router.insert(&ep.path, idx) {
-            Ok(_) => tracing::debug!("Inserted route: {}", &ep.path),
-            Err(e) => tracing::error!("Failed to insert route {}: {}", &ep.path, e),
-        }
-    }
-    Arc::new(RwLock::new(router))
+fn unknown_version(version: u64) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from(format!("Unknown version: {}", version)))
+        .unwrap()
 }
 
-// Admin endpoint to update the endpoints dynamically
-async fn update_endpoints(
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/versions/{version}",
+    tag = "versions",
+    params(("version" = u64, Path, description = "Version number"), AdminQuery),
+    responses(
+        (status = 200, description = "The workspace configuration saved as this version", body = WorkspaceSettings),
+        (status = 404, description = "Unknown version or workspace")
+    )
+)]
+async fn get_version(
     State(state): State<AppState>,
-    Json(new_endpoints): Json<Vec<EndpointConfig>>,
-) -> impl IntoResponse {
-    // Update the endpoints and router
-    {
-        let mut endpoints = state.endpoints.write().await;
-        *endpoints = new_endpoints.clone();
-    }
-    {
-        let mut router = state.router.write().await;
-        *router = MatchItRouter::new();
-        for (idx, ep) in new_endpoints.iter().enumerate() {
-            router.insert(&ep.path, idx).unwrap();
-        }
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Path(version): Path<u64>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+) -> Response<axum::body::BoxBody> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name).into_response(),
+    };
+    match workspace.versions.get(version) {
+        Some(saved) => Json(saved.settings).into_response(),
+        None => unknown_version(version).into_response(),
     }
+}
 
-    // Assemble new Settings struct
-    let settings = Settings {
-        default_endpoint: state.default_endpoint.clone(),
-        endpoints: new_endpoints.clone(),
+// Restores a saved configuration. The rollback is itself recorded as a new
+// version so it can be undone the same way.
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/rollback/{version}",
+    tag = "versions",
+    params(("version" = u64, Path, description = "Version to restore"), AdminQuery),
+    responses(
+        (status = 200, description = "Version restored"),
+        (status = 404, description = "Unknown version or workspace")
+    )
+)]
+async fn rollback(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Path(version): Path<u64>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name),
+    };
+    let Some(saved) = workspace.versions.get(version) else {
+        return unknown_version(version);
     };
 
-    // Write settings to settings.json
-    if let Err(e) = write_settings_to_file(&settings) {
+    let mut settings = state.settings.write().await;
+    let description = format!("Rolled back to version {}", version);
+    if let Some(workspace_settings) = settings.workspace_mut(&workspace.name) {
+        *workspace_settings = saved.settings.clone();
+        workspace.apply(workspace_settings);
+        workspace.versions.record(workspace_settings, &description);
+    }
+    set_listener_ports(&state).await;
+
+    if let Err(e) = persist_settings(
+        &state,
+        &settings,
+        &workspace.name,
+        &description,
+        &headers,
+        addr.ip(),
+    )
+    .await
+    {
         tracing::error!("Failed to write settings to file: {}", e);
         return Response::builder()
             .status(StatusCode::INTERNAL_SERVER_ERROR)
@@ -303,118 +3078,323 @@ async fn update_endpoints(
             .unwrap();
     }
 
-    tracing::info!("Endpoints updated dynamically.");
+    tracing::info!(
+        "Workspace {} rolled back to version {}",
+        workspace.name,
+        version
+    );
 
     Response::builder()
         .status(StatusCode::OK)
-        .body(Body::from("Endpoints updated"))
+        .body(Body::from(format!("Rolled back to version {}", version)))
         .unwrap()
 }
 
+// Journal of requests received by a workspace, oldest first
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/journal",
+    tag = "journal",
+    params(AdminQuery, JournalFilter, JournalQuery),
+    responses(
+        (status = 200, description = "Recorded requests, oldest first", body = Vec<JournalEntry>),
+        (status = 404, description = "Unknown workspace")
+    )
+)]
+async fn get_journal(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    Query(filter): Query<JournalFilter>,
+    Query(journal_query): Query<JournalQuery>,
+    headers: HeaderMap,
+) -> Response<axum::body::BoxBody> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name).into_response(),
+    };
+    // The shared journal includes requests served by every replica
+    let mut entries = None;
+    if let Some(store) = &state.store {
+        match store.journal(&workspace.name).await {
+            Ok(shared) => entries = Some(shared),
+            Err(e) => tracing::error!("Failed to read journal from Redis: {}", e),
+        }
+    }
+    let mut entries = entries.unwrap_or_else(|| workspace.journal.entries());
+    entries.retain(|entry| filter.selects(entry));
+    if let Some(id) = &journal_query.request_id {
+        entries.retain(|entry| entry.request_id.as_ref() == Some(id));
+    }
+    Json(entries).into_response()
+}
+
+// Finds one request in the journal
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct JournalQuery {
+    // The request's X-Request-Id
+    request_id: Option<String>,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/mockserver/admin/journal",
+    tag = "journal",
+    params(AdminQuery, JournalFilter),
+    responses(
+        (status = 200, description = "Journal cleared"),
+        (status = 404, description = "Unknown workspace")
+    )
+)]
+async fn clear_journal(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    Query(filter): Query<JournalFilter>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => {
+            workspace.journal.clear(&filter);
+            if let Some(store) = &state.store {
+                if let Err(e) = store.clear_journal(&workspace.name, &filter).await {
+                    tracing::error!("Failed to clear journal in Redis: {}", e);
+                }
+            }
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from("Journal cleared"))
+                .unwrap()
+        }
+        Err(name) => unknown_workspace(&name),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/audit",
+    tag = "audit",
+    responses((status = 200, description = "Admin changes, oldest first", body = Vec<AuditEntry>))
+)]
+async fn get_audit(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.audit.entries())
+}
+
+// Saves an admin change and pushes it to any cluster peers
+async fn persist_settings(
+    state: &AppState,
+    settings: &Settings,
+    workspace: &str,
+    description: &str,
+    headers: &HeaderMap,
+    client_ip: IpAddr,
+) -> std::io::Result<()> {
+    let message = format!("{} (workspace {})", description, workspace);
+    let contents = save_settings(state, settings, &message, admin_user(headers)).await?;
+    let entry = state
+        .audit
+        .record(settings, description, client_ip, admin_user(headers));
+    if let Some(webhook) = &state.change_webhook {
+        webhook.post(&serde_json::json!({ "text": entry.summary(), "change": entry }));
+    }
+    if let Some(cluster) = &state.cluster {
+        cluster.broadcast(&contents);
+    }
+    Ok(())
+}
+
+// Writes settings.json (or the shared copy in Redis) and, when git
+// persistence is on, commits the change. A failed commit is logged but
+// doesn't fail the save. Returns the shared JSON, for cluster peers.
+async fn save_settings(
+    state: &AppState,
+    settings: &Settings,
+    message: &str,
+    author: Option<&str>,
+) -> std::io::Result<String> {
+    // Secrets stay out of anything saved or sent, and included settings out
+    // of settings.json
+    let contents = serde_json::to_string_pretty(&state.source.shared_contents(settings)?)?;
+    let file_contents = serde_json::to_string_pretty(&state.source.file_contents(settings)?)?;
+    match &state.store {
+        Some(store) => store
+            .save_settings(&contents)
+            .await
+            .map_err(std::io::Error::other)?,
+        None => write_settings_to_file(&file_contents)?,
+    }
+    if let Some(repo) = &state.config_repo {
+        if let Err(e) = repo.commit(&file_contents, message, author).await {
+            tracing::error!("Failed to commit settings: {}", e);
+        }
+    }
+    Ok(contents)
+}
+
+// Serves `settings` from every running workspace it configures and records
+// the change in their history. Returns the workspaces that were updated and
+// those that need a restart: new ones, and ones with a new default_endpoint,
+// which is only read at startup.
+async fn apply_settings(
+    state: &AppState,
+    settings: &Settings,
+    description: &str,
+) -> (Vec<String>, Vec<String>) {
+    let mut applied = Vec::new();
+    let mut pending_restart = Vec::new();
+    for workspace_settings in std::iter::once(&settings.workspace).chain(&settings.workspaces) {
+        match state.workspaces.get(workspace_settings.name()) {
+            Some(workspace) => {
+                workspace.apply(workspace_settings);
+                workspace.versions.record(workspace_settings, description);
+                if workspace.default_endpoint == workspace_settings.default_endpoint {
+                    applied.push(workspace.name.clone());
+                } else {
+                    pending_restart.push(workspace.name.clone());
+                }
+            }
+            None => pending_restart.push(workspace_settings.name().to_string()),
+        }
+    }
+    set_listener_ports(state).await;
+    (applied, pending_restart)
+}
+
+// Follows workspace port changes without a restart. A port that can't be
+// bound leaves the listeners as they were.
+async fn set_listener_ports(state: &AppState) {
+    if let Err(e) = state.listeners.set_ports(&state.workspaces.ports()).await {
+        tracing::error!("Keeping the current listeners: {}", e);
+    }
+}
+
+// Who is making an admin change, as reported by the X-Admin-User header
+fn admin_user(headers: &HeaderMap) -> Option<&str> {
+    headers.get("X-Admin-User").and_then(|h| h.to_str().ok())
+}
+
 // Function to write settings to the JSON file
-fn write_settings_to_file(settings: &Settings) -> std::io::Result<()> {
-    let file = std::fs::File::create("settings.json")?;
-    serde_json::to_writer_pretty(file, settings)?;
+fn write_settings_to_file(contents: &str) -> std::io::Result<()> {
+    std::fs::write("settings.json", contents)?;
     Ok(())
 }
 
-// Admin page handler
-async fn admin_page(State(state): State<AppState>) -> impl IntoResponse {
+// Admin page handler: a searchable endpoint table with per-endpoint editing
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin",
+    tag = "ui",
+    params(AdminQuery),
+    responses((status = 200, description = "The admin page", content_type = "text/html"))
+)]
+async fn admin_page(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+) -> Response<axum::body::BoxBody> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name).into_response(),
+    };
+
     // Read the current endpoint configurations
-    let endpoints = state.endpoints.read().await;
-    let endpoints_json = serde_json::to_string_pretty(&*endpoints).unwrap();
+    let endpoints = match current_endpoints(&workspace, query.host.as_deref()).await {
+        Ok(endpoints) => endpoints,
+        Err(host) => return unknown_virtual_host(&host).into_response(),
+    };
+    // Keep payloads containing "</script>" from ending the script early
+    let endpoints_json = serde_json::to_string(&endpoints)
+        .unwrap()
+        .replace("</", "<\\/");
 
-    // Build the HTML content
+    let (session_id, session, new_session) = state.sessions.start(&headers, query.token.clone());
+
+    // Build the HTML content. Scripts and styles are bundled with the
+    // binary (see assets.rs) so the page works offline.
     let html_content = format!(
         r#"
         <!DOCTYPE html>
         <html>
         <head>
             <title>Mock Server Admin</title>
-             <link rel="icon" href="/static/favicon.svg" type="image/x-icon">
-            <!-- Include JSONEditor via CDN -->
-            <link href="https://cdn.jsdelivr.net/npm/jsoneditor@9.5.6/dist/jsoneditor.min.css" rel="stylesheet" type="text/css">
-            <script src="https://cdn.jsdelivr.net/npm/jsoneditor@9.5.6/dist/jsoneditor.min.js"></script>
-            <!-- Include Toastify CSS and JS -->
-            <link rel="stylesheet" type="text/css" href="https://cdn.jsdelivr.net/npm/toastify-js/src/toastify.min.css">
-            <script type="text/javascript" src="https://cdn.jsdelivr.net/npm/toastify-js"></script>
-            <style>
-                /* Your custom styles here */
-            </style>
+            <meta name="csrf-token" content="{csrf_token}">
+            <link rel="icon" href="/static/favicon.svg" type="image/x-icon">
+            <link rel="stylesheet" type="text/css" href="/static/admin.css">
         </head>
         <body>
-            <h1>Mock Server Admin</h1>
-            <div id="jsoneditor" style="height: 80vh; width: 100%;"></div>
-            <button id="submit-button">Submit</button>
-            <script>
-                var container = document.getElementById('jsoneditor');
-                var options = {{
-                    mode: 'code',
-                    modes: ['code', 'form', 'text', 'tree', 'view'],
-                    onError: function (err) {{
-                        Toastify({{
-                            text: err.toString(),
-                            duration: 3000,
-                            close: true,
-                            gravity: 'top',
-                            position: 'right',
-                            backgroundColor: '#F44336'
-                        }}).showToast();
-                    }}
-                }};
-                var editor = new JSONEditor(container, options);
-                editor.set({json_data});
-        
-                function showToast(message, type) {{
-                    Toastify({{
-                        text: message,
-                        duration: 3000,
-                        close: true,
-                        gravity: 'top',
-                        position: 'right',
-                        backgroundColor: type === 'success' ? '#4CAF50' : '#F44336'
-                    }}).showToast();
-                }}
-        
-                function submitForm() {{
-                    try {{
-                        var data = editor.get();
-                        fetch('/mockserver/admin/update', {{  // Updated fetch URL
-                            method: 'POST',
-                            headers: {{
-                                'Content-Type': 'application/json'
-                            }},
-                            body: JSON.stringify(data)
-                        }})
-                        .then(function(response) {{
-                            if(response.ok) {{
-                                showToast('Endpoints updated successfully', 'success');
-                            }} else {{
-                                showToast('Failed to update endpoints', 'error');
-                            }}
-                        }});
-                    }} catch (err) {{
-                        showToast('Invalid JSON data', 'error');
-                    }}
-                }}
-        
-                document.getElementById('submit-button').addEventListener('click', submitForm);
-        
-                document.addEventListener('keydown', function(event) {{
-                    var key = event.key || event.keyCode;
-                    if ((event.ctrlKey || event.metaKey) && (key === 's' || key === 'S' || key === 83)) {{
-                        event.preventDefault();
-                        submitForm();
-                    }}
-                }});
-            </script>
+            <h1>Mock Server Admin <small>{workspace_name}</small></h1>
+            <section id="dashboard">
+                <div class="stats">
+                    <div><span class="stat-value" id="stat-rate">0</span> requests last minute</div>
+                    <div><span class="stat-value mocked" id="stat-mocked">0</span> mocked</div>
+                    <div><span class="stat-value proxied" id="stat-proxied">0</span> proxied</div>
+                    <div><span class="stat-value unmatched" id="stat-unmatched">0</span> unmatched</div>
+                    <div class="stat-note">in the last hour</div>
+                </div>
+                <div id="traffic-chart"></div>
+                <div class="dashboard-tables">
+                    <div>
+                        <h3>Top endpoints</h3>
+                        <table><tbody id="top-endpoints"></tbody></table>
+                    </div>
+                    <div>
+                        <h3>Recent errors</h3>
+                        <table><tbody id="recent-errors"></tbody></table>
+                    </div>
+                </div>
+            </section>
+            <div class="toolbar">
+                <input id="search" type="search" placeholder="Search by path, method or tag">
+                <select id="method-filter">
+                    <option value="">All methods</option>
+                    <option>GET</option>
+                    <option>POST</option>
+                    <option>PUT</option>
+                    <option>PATCH</option>
+                    <option>DELETE</option>
+                </select>
+                <button id="new-button">New endpoint</button>
+                <button id="curl-import-button">From curl</button>
+                <button id="scenario-button">Record scenario</button>
+            </div>
+            <div id="curl-panel">
+                <textarea id="curl-command" placeholder="Paste a curl command"></textarea>
+                <label><input id="curl-capture" type="checkbox"> Capture the response from the backend</label>
+                <button id="curl-create-button">Create endpoint</button>
+            </div>
+            <table>
+                <thead>
+                    <tr><th>Method</th><th>Path</th><th>Status</th><th>Content type</th><th>Tags</th><th></th></tr>
+                </thead>
+                <tbody id="endpoints"></tbody>
+            </table>
+            <div id="editor-panel">
+                <h2 id="editor-title"></h2>
+                <div id="jsoneditor"></div>
+                <button id="save-button">Save</button>
+                <button id="format-button">Format</button>
+                <button id="cancel-button">Cancel</button>
+            </div>
+            <script type="application/json" id="endpoints-data">{json_data}</script>
+            <script src="/static/admin.js"></script>
         </body>
         </html>
         "#,
-        json_data = endpoints_json
+        json_data = endpoints_json,
+        workspace_name = workspace.name,
+        csrf_token = session.csrf_token
     );
 
-    Html(html_content)
+    let mut response = Html(html_content).into_response();
+    if new_session {
+        let cookie = session::session_cookie(&session_id);
+        response
+            .headers_mut()
+            .insert(header::SET_COOKIE, cookie.parse().unwrap());
+    }
+    response
 }
 
 async fn handle_error(_err: std::io::Error) -> impl IntoResponse {
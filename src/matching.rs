@@ -0,0 +1,149 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Method, Response, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::codec::Codec;
+
+// How request paths are compared with endpoint paths. Both sides are
+// normalized the same way before routing, so `/Users/` can match `/users`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
+pub struct PathMatching {
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_trailing_slash: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub case_insensitive_paths: bool,
+}
+
+impl PathMatching {
+    // The form of an endpoint path inserted into the router. Parameter names
+    // inside `{}` keep their case.
+    pub fn pattern(&self, path: &str) -> String {
+        let path = self.trim(path);
+        if !self.case_insensitive_paths {
+            return path.to_string();
+        }
+        let mut depth = 0usize;
+        path.chars()
+            .map(|c| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                if depth == 0 {
+                    c.to_ascii_lowercase()
+                } else {
+                    c
+                }
+            })
+            .collect()
+    }
+
+    // The form of a request path looked up in the router. Only ASCII letters
+    // are folded, so byte offsets still line up with the original path.
+    pub fn request_path(&self, path: &str) -> String {
+        let path = self.trim(path);
+        if self.case_insensitive_paths {
+            path.to_ascii_lowercase()
+        } else {
+            path.to_string()
+        }
+    }
+
+    fn trim<'a>(&self, path: &'a str) -> &'a str {
+        if !self.ignore_trailing_slash {
+            return path;
+        }
+        match path.trim_end_matches('/') {
+            "" => "/",
+            trimmed => trimmed,
+        }
+    }
+}
+
+// A path parameter as the client sent it. `value` borrows from `lookup`, the
+// normalized form of `path` returned by `request_path`.
+pub fn original_param<'a>(path: &'a str, lookup: &str, value: &str) -> &'a str {
+    let start = value.as_ptr() as usize - lookup.as_ptr() as usize;
+    &path[start..start + value.len()]
+}
+
+// What to do when a request's path matches endpoints but its method doesn't
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MethodMismatch {
+    // 405 Method Not Allowed, listing the path's methods in `Allow`
+    #[default]
+    Reject,
+    // Handle it like any unmatched request, by default proxying it
+    Proxy,
+}
+
+impl MethodMismatch {
+    pub fn is_default(&self) -> bool {
+        *self == MethodMismatch::default()
+    }
+}
+
+// How HEAD requests are answered on paths with a GET endpoint but no HEAD
+// endpoint
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HeadRequests {
+    // The GET endpoint's response, without the body
+    #[default]
+    Mock,
+    // Like any other method without an endpoint; see MethodMismatch
+    Unmatched,
+}
+
+impl HeadRequests {
+    pub fn is_default(&self) -> bool {
+        *self == HeadRequests::default()
+    }
+}
+
+// Headers clients that can only send GET and POST name the real method in
+const OVERRIDE_HEADERS: [&str; 3] = [
+    "x-http-method-override",
+    "x-http-method",
+    "x-method-override",
+];
+
+// The method a POST stands for, from an override header or else a `_method`
+// field of a form body; `json_body` is the body as decoded for matching
+pub fn method_override(method: &Method, headers: &HeaderMap, json_body: &[u8]) -> Option<Method> {
+    if method != Method::POST {
+        return None;
+    }
+    let from_header = OVERRIDE_HEADERS
+        .iter()
+        .find_map(|name| headers.get(*name)?.to_str().ok());
+    let is_form = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .and_then(Codec::for_content_type)
+        .is_some_and(|codec| matches!(codec, Codec::Form));
+    let form: Option<serde_json::Value> = is_form
+        .then(|| serde_json::from_slice(json_body).ok())
+        .flatten();
+    let name = from_header.or_else(|| form.as_ref()?.get("_method")?.as_str())?;
+    let overridden = Method::from_bytes(name.trim().to_ascii_uppercase().as_bytes()).ok()?;
+    (overridden != Method::CONNECT).then_some(overridden)
+}
+
+pub fn method_not_allowed<'a>(methods: impl Iterator<Item = &'a str>) -> Response<Body> {
+    let mut allowed: Vec<String> = Vec::new();
+    for method in methods.map(str::to_ascii_uppercase) {
+        if !allowed.contains(&method) {
+            allowed.push(method);
+        }
+    }
+    Response::builder()
+        .status(StatusCode::METHOD_NOT_ALLOWED)
+        .header(header::ALLOW, allowed.join(", "))
+        .body(Body::from("Method Not Allowed"))
+        .unwrap()
+}
@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket as StdUdpSocket},
+    time::Duration,
+};
+use tokio::net::UdpSocket;
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+const SERVICE: &str = "_http._tcp.local";
+const SERVICES: &str = "_services._dns-sd._udp.local";
+
+// Record types and classes used here
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+// Marks records only this host answers for (RFC 6762 section 10.2)
+const CACHE_FLUSH: u16 = 0x8000;
+
+// Advertise the server on the local network as an `_http._tcp` service, so
+// phones and other devices can find it without the workstation's IP
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct MdnsSettings {
+    // Service name shown when browsing, "Mock API on <hostname>" by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Address to announce; found from the default route when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<Ipv4Addr>,
+}
+
+// What is announced
+struct Service {
+    instance: String, // "<name>._http._tcp.local"
+    host: String,     // "<hostname>-mock-api.local"
+    address: Ipv4Addr,
+    port: u16,
+    txt: Vec<String>,
+}
+
+// Answers mDNS queries for the service until the server stops. Failing to
+// join the multicast group is logged and otherwise ignored.
+pub async fn advertise(settings: MdnsSettings, port: u16) {
+    let service = match Service::new(&settings, port) {
+        Ok(service) => service,
+        Err(e) => {
+            tracing::error!("Not advertising over mDNS: {}", e);
+            return;
+        }
+    };
+    let socket = match bind() {
+        Ok(socket) => socket,
+        Err(e) => {
+            tracing::error!("Not advertising over mDNS: {}", e);
+            return;
+        }
+    };
+    tracing::info!(
+        "Advertising {} at {}:{} over mDNS",
+        service.instance,
+        service.address,
+        service.port
+    );
+
+    // Announce twice, a second apart, as RFC 6762 section 8.3 asks
+    let group = SocketAddr::from((MDNS_GROUP, MDNS_PORT));
+    let announcement = service.response(0, &[]);
+    for _ in 0..2 {
+        if let Err(e) = socket.send_to(&announcement, group).await {
+            tracing::warn!("Failed to send mDNS announcement: {}", e);
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    let mut buf = vec![0; 9000];
+    loop {
+        let (len, from) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                tracing::warn!("Failed to receive mDNS packet: {}", e);
+                continue;
+            }
+        };
+        let Some(query) = Query::parse(&buf[..len]) else {
+            continue;
+        };
+        if !query.questions.iter().any(|q| service.answers(q)) {
+            continue;
+        }
+        // Queries from another port are one-shot lookups (e.g. dig) that
+        // expect a plain unicast DNS reply
+        let (reply, to) = if from.port() != MDNS_PORT {
+            (service.response(query.id, &query.questions), from)
+        } else {
+            (service.response(0, &[]), group)
+        };
+        if let Err(e) = socket.send_to(&reply, to).await {
+            tracing::warn!("Failed to answer mDNS query from {}: {}", from, e);
+        }
+    }
+}
+
+// Port 5353 is usually shared with the system's own responder
+fn bind() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT).into())?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+impl Service {
+    fn new(settings: &MdnsSettings, port: u16) -> io::Result<Self> {
+        let hostname = hostname();
+        let address = match settings.address {
+            Some(address) => address,
+            None => lan_address()?,
+        };
+        let name = settings
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Mock API on {}", hostname));
+        Ok(Service {
+            instance: format!("{}.{}", name.replace('.', "-"), SERVICE),
+            host: format!("{}-mock-api.local", hostname),
+            address,
+            port,
+            txt: vec![
+                "path=/".to_string(),
+                "admin=/mockserver/admin".to_string(),
+                format!("admin_url=http://{}:{}/mockserver/admin", address, port),
+            ],
+        })
+    }
+
+    fn answers(&self, question: &Question) -> bool {
+        let is = |name: &str| question.name.eq_ignore_ascii_case(name);
+        match question.qtype {
+            TYPE_PTR => is(SERVICE) || is(SERVICES),
+            TYPE_SRV | TYPE_TXT => is(&self.instance),
+            TYPE_A => is(&self.host),
+            TYPE_ANY => is(SERVICE) || is(&self.instance) || is(&self.host),
+            _ => false,
+        }
+    }
+
+    // Every record of the service in one response, echoing `questions` for
+    // unicast replies
+    fn response(&self, id: u16, questions: &[Question]) -> Vec<u8> {
+        let mut srv = Vec::new();
+        srv.extend_from_slice(&0u16.to_be_bytes()); // Priority
+        srv.extend_from_slice(&0u16.to_be_bytes()); // Weight
+        srv.extend_from_slice(&self.port.to_be_bytes());
+        write_name(&mut srv, &self.host);
+        let mut txt = Vec::new();
+        for entry in &self.txt {
+            txt.push(entry.len() as u8);
+            txt.extend_from_slice(entry.as_bytes());
+        }
+        let mut instance = Vec::new();
+        write_name(&mut instance, &self.instance);
+        let mut service = Vec::new();
+        write_name(&mut service, SERVICE);
+
+        let records: [(&str, u16, bool, u32, Vec<u8>); 5] = [
+            (SERVICE, TYPE_PTR, false, 4500, instance),
+            (SERVICES, TYPE_PTR, false, 4500, service),
+            (&self.instance, TYPE_SRV, true, 120, srv),
+            (&self.instance, TYPE_TXT, true, 4500, txt),
+            (
+                &self.host,
+                TYPE_A,
+                true,
+                120,
+                self.address.octets().to_vec(),
+            ),
+        ];
+
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&id.to_be_bytes());
+        packet.extend_from_slice(&0x8400u16.to_be_bytes()); // Authoritative response
+        packet.extend_from_slice(&(questions.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0]); // No authority or additional records
+        for question in questions {
+            write_name(&mut packet, &question.name);
+            packet.extend_from_slice(&question.qtype.to_be_bytes());
+            packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        for (name, rtype, unique, ttl, data) in records {
+            // Unicast replies must not set the cache-flush bit
+            let class = if unique && questions.is_empty() {
+                CLASS_IN | CACHE_FLUSH
+            } else {
+                CLASS_IN
+            };
+            write_name(&mut packet, name);
+            packet.extend_from_slice(&rtype.to_be_bytes());
+            packet.extend_from_slice(&class.to_be_bytes());
+            packet.extend_from_slice(&ttl.to_be_bytes());
+            packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            packet.extend_from_slice(&data);
+        }
+        packet
+    }
+}
+
+struct Question {
+    name: String,
+    qtype: u16,
+}
+
+struct Query {
+    id: u16,
+    questions: Vec<Question>,
+}
+
+impl Query {
+    // None for responses and anything malformed
+    fn parse(packet: &[u8]) -> Option<Query> {
+        let u16_at = |at: usize| Some(u16::from_be_bytes([*packet.get(at)?, *packet.get(at + 1)?]));
+        let id = u16_at(0)?;
+        if u16_at(2)? & 0x8000 != 0 {
+            return None;
+        }
+        let count = u16_at(4)?;
+        let mut at = 12;
+        let mut questions = Vec::new();
+        for _ in 0..count {
+            let (name, next) = read_name(packet, at)?;
+            questions.push(Question {
+                name,
+                qtype: u16_at(next)?,
+            });
+            at = next + 4;
+        }
+        Some(Query { id, questions })
+    }
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.') {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        out.push(label.len() as u8);
+        out.extend_from_slice(label);
+    }
+    out.push(0);
+}
+
+// A possibly compressed name at `at`, and where the data after it starts
+fn read_name(packet: &[u8], mut at: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds pointer loops in malicious packets
+    for _ in 0..128 {
+        let len = *packet.get(at)? as usize;
+        match len {
+            0 => {
+                let name = labels.join(".");
+                return Some((name, end.unwrap_or(at + 1)));
+            }
+            len if len & 0xC0 == 0xC0 => {
+                let pointer = ((len & 0x3F) << 8) | *packet.get(at + 1)? as usize;
+                end.get_or_insert(at + 2);
+                at = pointer;
+            }
+            len => {
+                let label = packet.get(at + 1..at + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                at += 1 + len;
+            }
+        }
+    }
+    None
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|name| {
+            name.trim()
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+// The address other machines reach this one at: the source address of the
+// default route. Connecting a UDP socket sends nothing.
+fn lan_address() -> io::Result<Ipv4Addr> {
+    let socket = StdUdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9))?;
+    match socket.local_addr()? {
+        SocketAddr::V4(addr) if !addr.ip().is_unspecified() => Ok(*addr.ip()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "no IPv4 address to announce; set mdns.address",
+        )),
+    }
+}
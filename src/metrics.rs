@@ -0,0 +1,495 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, Response, StatusCode},
+    Json,
+};
+use chrono::{DateTime, Duration as MinuteSpan, DurationRound, Utc};
+use hyper::Body;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    sync::Mutex,
+    time::Duration,
+};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{journal::JournalEntry, AppState};
+
+// How far back the per-minute counts go
+const MINUTES: i64 = 60;
+// Most busy endpoints and latest failures shown in the overview
+const TOP_ENDPOINTS: usize = 10;
+const RECENT_ERRORS: usize = 20;
+
+// Upper bounds of the histogram buckets, in seconds; slower requests only
+// count towards +Inf
+const BUCKETS: [f64; 16] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+    5.0, 10.0,
+];
+
+// Where the time of a request went
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    // Finding the endpoint for the request
+    Matching,
+    // Building the mocked body from the payload
+    Templating,
+    // Waiting for a backend's response headers
+    Upstream,
+    // The whole request, as the client saw it
+    Total,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Matching => "matching",
+            Stage::Templating => "templating",
+            Stage::Upstream => "upstream",
+            Stage::Total => "total",
+        }
+    }
+}
+
+// Mocked endpoints are told apart by workspace, backends by URL
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Series {
+    Endpoint {
+        workspace: String,
+        endpoint: String,
+        stage: Stage,
+    },
+    Backend {
+        backend: String,
+        stage: Stage,
+    },
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = BUCKETS.iter().position(|&le| seconds <= le) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    // Interpolated within the bucket it falls in, as Prometheus'
+    // histogram_quantile does
+    fn quantile(&self, q: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let rank = q * self.count as f64;
+        let mut below = 0;
+        let mut lower = 0.0;
+        for (count, &upper) in self.buckets.iter().zip(BUCKETS.iter()) {
+            if (below + count) as f64 >= rank && *count > 0 {
+                return lower + (upper - lower) * (rank - below as f64) / *count as f64;
+            }
+            below += count;
+            lower = upper;
+        }
+        // In +Inf; the best that can be said is the largest bound
+        BUCKETS[BUCKETS.len() - 1]
+    }
+
+    fn summary(&self) -> LatencySummary {
+        let ms = |seconds: f64| seconds * 1000.0;
+        LatencySummary {
+            count: self.count,
+            mean_ms: ms(self.sum / self.count.max(1) as f64),
+            p50_ms: ms(self.quantile(0.5)),
+            p95_ms: ms(self.quantile(0.95)),
+            p99_ms: ms(self.quantile(0.99)),
+        }
+    }
+}
+
+// Request counts for the traffic overview
+#[derive(Default)]
+struct Traffic {
+    minutes: VecDeque<MinuteCounts>,
+    totals: Split,
+    endpoints: BTreeMap<(String, String), u64>,
+    errors: VecDeque<RecentError>,
+}
+
+impl Traffic {
+    fn count(&mut self, workspace: &str, entry: &JournalEntry) {
+        let minute = entry
+            .timestamp
+            .duration_trunc(MinuteSpan::minutes(1))
+            .unwrap_or(entry.timestamp);
+        if self.minutes.back().is_none_or(|last| last.minute < minute) {
+            self.minutes.push_back(MinuteCounts {
+                minute,
+                requests: Split::default(),
+            });
+        }
+        while self
+            .minutes
+            .front()
+            .is_some_and(|first| first.minute <= minute - MinuteSpan::minutes(MINUTES))
+        {
+            self.minutes.pop_front();
+        }
+
+        let counts = &mut self.minutes.back_mut().unwrap().requests;
+        for split in [counts, &mut self.totals] {
+            match (&entry.matched, &entry.proxied_to) {
+                (_, Some(_)) => split.proxied += 1,
+                (Some(_), None) => split.mocked += 1,
+                (None, None) => split.unmatched += 1,
+            }
+        }
+        if let Some(endpoint) = &entry.matched {
+            let key = (workspace.to_string(), endpoint.clone());
+            *self.endpoints.entry(key).or_insert(0) += 1;
+        }
+        if entry.status >= 500 {
+            if self.errors.len() == RECENT_ERRORS {
+                self.errors.pop_back();
+            }
+            self.errors.push_front(RecentError {
+                timestamp: entry.timestamp,
+                workspace: workspace.to_string(),
+                method: entry.method.clone(),
+                path: entry.path.clone(),
+                status: entry.status,
+                matched: entry.matched.clone(),
+                proxied_to: entry.proxied_to.clone(),
+            });
+        }
+    }
+
+    fn overview(&self) -> TrafficOverview {
+        let now = Utc::now();
+        let now = now.duration_trunc(MinuteSpan::minutes(1)).unwrap_or(now);
+        // Quiet minutes have no entry of their own
+        let minutes = (0..MINUTES)
+            .rev()
+            .map(|ago| now - MinuteSpan::minutes(ago))
+            .map(|minute| {
+                let requests = self
+                    .minutes
+                    .iter()
+                    .find(|counts| counts.minute == minute)
+                    .map_or_else(Split::default, |counts| counts.requests);
+                MinuteCounts { minute, requests }
+            })
+            .collect();
+        let mut top_endpoints: Vec<EndpointCount> = self
+            .endpoints
+            .iter()
+            .map(|((workspace, endpoint), requests)| EndpointCount {
+                workspace: workspace.clone(),
+                endpoint: endpoint.clone(),
+                requests: *requests,
+            })
+            .collect();
+        top_endpoints.sort_by_key(|count| std::cmp::Reverse(count.requests));
+        top_endpoints.truncate(TOP_ENDPOINTS);
+        TrafficOverview {
+            minutes,
+            totals: self.totals,
+            top_endpoints,
+            recent_errors: self.errors.iter().cloned().collect(),
+        }
+    }
+}
+
+// Requests answered by a mock, by a backend, or by neither (a 404 or an
+// `unmatched` response)
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct Split {
+    mocked: u64,
+    proxied: u64,
+    unmatched: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MinuteCounts {
+    minute: DateTime<Utc>,
+    #[serde(flatten)]
+    requests: Split,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EndpointCount {
+    workspace: String,
+    endpoint: String,
+    requests: u64,
+}
+
+// A request answered with a 5xx
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RecentError {
+    timestamp: DateTime<Utc>,
+    workspace: String,
+    method: String,
+    path: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxied_to: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrafficOverview {
+    // The last hour, oldest first, including the current minute
+    minutes: Vec<MinuteCounts>,
+    // Since the server started
+    totals: Split,
+    top_endpoints: Vec<EndpointCount>,
+    // Newest first
+    recent_errors: Vec<RecentError>,
+}
+
+// Latency distributions and request counts since the server started
+#[derive(Default)]
+pub struct Metrics {
+    histograms: Mutex<BTreeMap<Series, Histogram>>,
+    traffic: Mutex<Traffic>,
+}
+
+impl Metrics {
+    // Counts a finished request for the traffic overview
+    pub fn request(&self, workspace: &str, entry: &JournalEntry) {
+        self.traffic.lock().unwrap().count(workspace, entry);
+    }
+
+    fn overview(&self) -> TrafficOverview {
+        self.traffic.lock().unwrap().overview()
+    }
+
+    pub fn endpoint(&self, workspace: &str, endpoint: &str, stage: Stage, elapsed: Duration) {
+        let series = Series::Endpoint {
+            workspace: workspace.to_string(),
+            endpoint: endpoint.to_string(),
+            stage,
+        };
+        self.observe(series, elapsed);
+    }
+
+    pub fn backend(&self, backend: &str, stage: Stage, elapsed: Duration) {
+        let series = Series::Backend {
+            backend: backend.to_string(),
+            stage,
+        };
+        self.observe(series, elapsed);
+    }
+
+    fn observe(&self, series: Series, elapsed: Duration) {
+        let mut histograms = self.histograms.lock().unwrap();
+        histograms
+            .entry(series)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    fn report(&self) -> LatencyReport {
+        let histograms = self.histograms.lock().unwrap();
+        let mut report = LatencyReport::default();
+        for (series, histogram) in histograms.iter() {
+            match series {
+                Series::Endpoint {
+                    workspace,
+                    endpoint,
+                    stage,
+                } => report.endpoints.push(EndpointLatency {
+                    workspace: workspace.clone(),
+                    endpoint: endpoint.clone(),
+                    stage: *stage,
+                    latency: histogram.summary(),
+                }),
+                Series::Backend { backend, stage } => report.backends.push(BackendLatency {
+                    backend: backend.clone(),
+                    stage: *stage,
+                    latency: histogram.summary(),
+                }),
+            }
+        }
+        report
+    }
+
+    // The Prometheus text exposition format
+    fn prometheus(&self) -> String {
+        let histograms = self.histograms.lock().unwrap();
+        let mut out = String::new();
+        for (metric, help, backends) in [
+            (
+                "mock_api_endpoint_latency_seconds",
+                "Time spent on requests matched to an endpoint, by stage",
+                false,
+            ),
+            (
+                "mock_api_backend_latency_seconds",
+                "Time spent on requests proxied to a backend, by stage",
+                true,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {} {}", metric, help);
+            let _ = writeln!(out, "# TYPE {} histogram", metric);
+            for (series, histogram) in histograms.iter() {
+                let labels = match series {
+                    Series::Endpoint {
+                        workspace,
+                        endpoint,
+                        stage,
+                    } if !backends => format!(
+                        "workspace=\"{}\",endpoint=\"{}\",stage=\"{}\"",
+                        escape(workspace),
+                        escape(endpoint),
+                        stage.name()
+                    ),
+                    Series::Backend { backend, stage } if backends => {
+                        format!("backend=\"{}\",stage=\"{}\"", escape(backend), stage.name())
+                    }
+                    _ => continue,
+                };
+                let mut cumulative = 0;
+                for (count, le) in histogram.buckets.iter().zip(BUCKETS) {
+                    cumulative += count;
+                    let _ = writeln!(
+                        out,
+                        "{}_bucket{{{},le=\"{}\"}} {}",
+                        metric, labels, le, cumulative
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{{},le=\"+Inf\"}} {}",
+                    metric, labels, histogram.count
+                );
+                let _ = writeln!(out, "{}_sum{{{}}} {}", metric, labels, histogram.sum);
+                let _ = writeln!(out, "{}_count{{{}}} {}", metric, labels, histogram.count);
+            }
+        }
+        out
+    }
+}
+
+// Label values escape backslashes, quotes and newlines
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Percentiles are estimated from the histogram buckets
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LatencySummary {
+    count: u64,
+    mean_ms: f64,
+    p50_ms: f64,
+    p95_ms: f64,
+    p99_ms: f64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EndpointLatency {
+    workspace: String,
+    endpoint: String, // Method and path, e.g. "GET /users/{id}"
+    stage: Stage,
+    #[serde(flatten)]
+    latency: LatencySummary,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BackendLatency {
+    backend: String,
+    stage: Stage,
+    #[serde(flatten)]
+    latency: LatencySummary,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct LatencyReport {
+    endpoints: Vec<EndpointLatency>,
+    backends: Vec<BackendLatency>,
+}
+
+// Latency percentiles per endpoint and backend, split by stage
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/metrics",
+    tag = "metrics",
+    responses((status = 200, description = "Latency since the server started", body = LatencyReport))
+)]
+pub async fn get_metrics(State(state): State<AppState>) -> Json<LatencyReport> {
+    Json(state.metrics.report())
+}
+
+// Requests per minute, the mock/proxy split, the busiest endpoints and the
+// latest 5xx responses, for the admin page's dashboard
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/stats",
+    tag = "metrics",
+    params(StatsQuery),
+    responses((status = 200, description = "Traffic since the server started", body = TrafficOverview))
+)]
+pub async fn get_stats(
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> Json<TrafficOverview> {
+    let Some(test_run) = query.test_run else {
+        return Json(state.metrics.overview());
+    };
+    // Rebuilt from the journal, as the running counts aren't kept per run
+    let mut entries: Vec<(String, JournalEntry)> = state
+        .workspaces
+        .iter()
+        .flat_map(|workspace| {
+            let name = workspace.name.clone();
+            workspace
+                .journal
+                .entries()
+                .into_iter()
+                .map(move |entry| (name.clone(), entry))
+        })
+        .filter(|(_, entry)| entry.test_run.as_ref() == Some(&test_run))
+        .collect();
+    entries.sort_by_key(|(_, entry)| entry.timestamp);
+    let mut traffic = Traffic::default();
+    for (workspace, entry) in &entries {
+        traffic.count(workspace, entry);
+    }
+    Json(traffic.overview())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    // Only the requests tagged with this X-Test-Run-Id that are still in the
+    // journal
+    test_run: Option<String>,
+}
+
+// The same histograms for Prometheus to scrape
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/metrics/prometheus",
+    tag = "metrics",
+    responses((status = 200, description = "Prometheus text exposition format", body = String))
+)]
+pub async fn prometheus_metrics(State(state): State<AppState>) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(state.metrics.prometheus()))
+        .unwrap()
+}
@@ -0,0 +1,65 @@
+use axum::body::{Body, Bytes};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use utoipa::ToSchema;
+
+use crate::transform;
+
+pub const CONTENT_TYPE: &str = "application/x-ndjson";
+
+// Streams records as newline-delimited JSON, like log tails and firehoses
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct NdjsonStream {
+    // Sent in turn, starting over when `count` is larger
+    #[schema(value_type = Vec<Object>)]
+    pub records: Vec<serde_json::Value>,
+    // Pause between records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_ms: Option<u64>,
+    // How many records to send; each one once if left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    // Keep sending until the client disconnects
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub forever: bool,
+}
+
+impl NdjsonStream {
+    // Records are filled in from `lookup` up front and with {{seq}}, the
+    // record's position from 0, as they're sent
+    pub fn body(&self, lookup: &dyn Fn(&str) -> Option<serde_json::Value>) -> Body {
+        let records: Arc<Vec<serde_json::Value>> = Arc::new(
+            self.records
+                .iter()
+                .map(|record| transform::fill(record, lookup))
+                .collect(),
+        );
+        let total = match self.forever {
+            true => None,
+            false => Some(self.count.unwrap_or(records.len() as u64)),
+        };
+        let interval = Duration::from_millis(self.interval_ms.unwrap_or(0));
+        let stream = futures_util::stream::unfold(0u64, move |seq| {
+            let records = records.clone();
+            async move {
+                if records.is_empty() || total.is_some_and(|total| seq >= total) {
+                    return None;
+                }
+                if seq > 0 {
+                    tokio::time::sleep(interval).await;
+                }
+                let record = &records[(seq % records.len() as u64) as usize];
+                let record = transform::fill(record, &|name| {
+                    (name == "seq").then(|| serde_json::Value::from(seq))
+                });
+                let mut line = serde_json::to_vec(&record).unwrap();
+                line.push(b'\n');
+                Some((
+                    Ok::<_, std::convert::Infallible>(Bytes::from(line)),
+                    seq + 1,
+                ))
+            }
+        });
+        Body::wrap_stream(stream)
+    }
+}
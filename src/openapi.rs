@@ -0,0 +1,170 @@
+use axum::Json;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{
+    audit::{AuditEntry, ProfileSwitch, WorkspaceChanges},
+    clock::{ClockStatus, ClockUpdate},
+    conditions::{Check, Condition},
+    connection::KeepAlive,
+    csv::CsvPayload,
+    curl::FromCurlRequest,
+    events::{LongPoll, TriggerResult},
+    expect::ExpectContinue,
+    explain::{Candidate, ConditionReport, Decision, ExplainRequest, Explanation},
+    fault::Fault,
+    idempotency::{Idempotency, OnReplay},
+    ip_filter::IpFilter,
+    journal::JournalEntry,
+    login::SessionAction,
+    metrics::{
+        BackendLatency, EndpointCount, EndpointLatency, LatencyReport, LatencySummary,
+        MinuteCounts, RecentError, Split, Stage, TrafficOverview,
+    },
+    ndjson::NdjsonStream,
+    protobuf::ProtobufPayload,
+    random::{ReseedRequest, SeedBody},
+    redirect::RedirectChain,
+    request_schema::RequestSchema,
+    scenario::{RecordingStatus, ScenarioStatus, ScenarioStep, StartRecording},
+    schedule::Schedule,
+    snapshot::{ImportSummary, Snapshot},
+    transform::ResponseTransform,
+    upstream::UpstreamTiming,
+    versions::VersionSummary,
+    vhost::VirtualHost,
+    websocket::{Frame, Side, WebSocketScript},
+    workspace::WorkspaceSettings,
+    EndpointConfig, ProfilesResponse, RouteConflict, RouteConflicts,
+};
+
+// Describes every /mockserver/admin route so clients can be generated
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Mock server admin API",
+        description = "Manage the mock server's endpoints, profiles, versions and journals."
+    ),
+    paths(
+        crate::admin_page,
+        crate::update_endpoints,
+        crate::list_endpoints,
+        crate::create_endpoint,
+        crate::update_endpoint,
+        crate::delete_endpoint,
+        crate::curl::endpoint_curl,
+        crate::curl::endpoint_from_curl,
+        crate::explain::explain,
+        crate::get_profiles,
+        crate::switch_profile,
+        crate::get_versions,
+        crate::get_version,
+        crate::rollback,
+        crate::snapshot::export_snapshot,
+        crate::snapshot::import_snapshot,
+        crate::get_journal,
+        crate::clear_journal,
+        crate::curl::journal_curl,
+        crate::get_audit,
+        crate::random::get_seed,
+        crate::random::reseed,
+        crate::clock::get_clock,
+        crate::clock::set_clock,
+        crate::clock::reset_clock,
+        crate::login::clear_logins,
+        crate::idempotency::clear_keys,
+        crate::scenario::get_scenarios,
+        crate::scenario::reset_scenarios,
+        crate::scenario::start_recording,
+        crate::scenario::stop_recording,
+        crate::events::trigger,
+        crate::metrics::get_metrics,
+        crate::metrics::get_stats,
+        crate::metrics::prometheus_metrics,
+        crate::cluster::receive_sync,
+    ),
+    components(schemas(
+        EndpointConfig,
+        IpFilter,
+        WorkspaceSettings,
+        VirtualHost,
+        ProfilesResponse,
+        VersionSummary,
+        JournalEntry,
+        UpstreamTiming,
+        AuditEntry,
+        WorkspaceChanges,
+        ProfileSwitch,
+        Snapshot,
+        ImportSummary,
+        FromCurlRequest,
+        RouteConflict,
+        RouteConflicts,
+        ResponseTransform,
+        Condition,
+        Schedule,
+        ExplainRequest,
+        Explanation,
+        Candidate,
+        ConditionReport,
+        Check,
+        Decision,
+        SeedBody,
+        ReseedRequest,
+        ClockStatus,
+        ClockUpdate,
+        Fault,
+        RedirectChain,
+        SessionAction,
+        Idempotency,
+        OnReplay,
+        LongPoll,
+        TriggerResult,
+        ExpectContinue,
+        KeepAlive,
+        CsvPayload,
+        ProtobufPayload,
+        RequestSchema,
+        NdjsonStream,
+        WebSocketScript,
+        Frame,
+        Side,
+        ScenarioStep,
+        ScenarioStatus,
+        RecordingStatus,
+        StartRecording,
+        LatencyReport,
+        EndpointLatency,
+        BackendLatency,
+        LatencySummary,
+        Stage,
+        TrafficOverview,
+        MinuteCounts,
+        Split,
+        EndpointCount,
+        RecentError,
+    )),
+    modifiers(&AdminTokenAuth),
+    security(("admin_token" = [])),
+)]
+pub struct AdminApi;
+
+// Admin tokens (see admin_tokens in settings.json) are sent as bearer tokens
+struct AdminTokenAuth;
+
+impl Modify for AdminTokenAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "admin_token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(AdminApi::openapi())
+}
@@ -0,0 +1,110 @@
+use std::path::{Component, Path, PathBuf};
+
+// Nested partials deeper than this are left as they are, which stops a
+// partial that includes itself
+const MAX_DEPTH: usize = 8;
+
+// Shared payload fragments kept as files, included with {{> name}}. A
+// payload string that is exactly one include takes the fragment's JSON;
+// includes inside longer strings are replaced with its text. Files are read
+// on each use, so edits show up straight away.
+pub struct Partials {
+    dir: PathBuf,
+}
+
+impl Partials {
+    pub fn new(dir: &str) -> Self {
+        Partials {
+            dir: PathBuf::from(dir),
+        }
+    }
+
+    pub fn expand(&self, payload: &serde_json::Value) -> serde_json::Value {
+        self.expand_at(payload, 0)
+    }
+
+    fn expand_at(&self, value: &serde_json::Value, depth: usize) -> serde_json::Value {
+        match value {
+            serde_json::Value::String(s) if depth < MAX_DEPTH && s.contains("{{>") => {
+                let whole = s
+                    .trim()
+                    .strip_prefix("{{>")
+                    .and_then(|rest| rest.strip_suffix("}}"))
+                    .filter(|name| !name.contains("}}"));
+                match whole {
+                    Some(name) => match self.load(name.trim()) {
+                        Some(fragment) => self.expand_at(&fragment, depth + 1),
+                        None => value.clone(),
+                    },
+                    None => serde_json::Value::String(self.expand_str(s, depth)),
+                }
+            }
+            serde_json::Value::Array(items) => {
+                serde_json::Value::Array(items.iter().map(|v| self.expand_at(v, depth)).collect())
+            }
+            serde_json::Value::Object(map) => serde_json::Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), self.expand_at(v, depth)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    fn expand_str(&self, template: &str, depth: usize) -> String {
+        let mut out = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("{{>") {
+            let Some(end) = rest[start..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 3..start + end].trim();
+            out.push_str(&rest[..start]);
+            match self
+                .load(name)
+                .map(|fragment| self.expand_at(&fragment, depth + 1))
+            {
+                Some(serde_json::Value::String(s)) => out.push_str(&s),
+                Some(fragment) => out.push_str(&fragment.to_string()),
+                None => out.push_str(&rest[start..start + end + 2]),
+            }
+            rest = &rest[start + end + 2..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    // `name`, or `name.json`, under the templates directory. JSON files are
+    // parsed; anything else is text.
+    fn load(&self, name: &str) -> Option<serde_json::Value> {
+        let relative = Path::new(name);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            tracing::warn!("Ignoring partial outside the templates directory: {}", name);
+            return None;
+        }
+        let path = self.dir.join(relative);
+        let (path, text) = match std::fs::read_to_string(&path) {
+            Ok(text) => (path, text),
+            Err(_) => {
+                let path = path.with_extension("json");
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => (path, text),
+                    Err(e) => {
+                        tracing::warn!("Failed to read partial {}: {}", name, e);
+                        return None;
+                    }
+                }
+            }
+        };
+        if path.extension().is_some_and(|ext| ext == "json") {
+            match serde_json::from_str(&text) {
+                Ok(json) => return Some(json),
+                Err(e) => tracing::warn!("Partial {} isn't valid JSON: {}", name, e),
+            }
+        }
+        Some(serde_json::Value::String(text))
+    }
+}
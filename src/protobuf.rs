@@ -0,0 +1,56 @@
+use prost_reflect::{
+    prost::Message, DescriptorPool, DeserializeOptions, DynamicMessage, MessageDescriptor,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+pub const CONTENT_TYPE: &str = "application/x-protobuf";
+
+// Encodes the JSON payload as a protobuf message, for REST APIs that speak
+// protobuf without gRPC
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct ProtobufPayload {
+    // A FileDescriptorSet, e.g. from
+    // `protoc --include_imports --descriptor_set_out=api.desc api.proto`.
+    // Read on every request.
+    pub descriptor_set: String,
+    // Fully qualified, e.g. "shop.v1.Order"
+    pub message: String,
+    // The message gRPC-Web requests send, e.g. "shop.v1.GetOrderRequest",
+    // so conditions and templates can read it as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_message: Option<String>,
+}
+
+impl ProtobufPayload {
+    // `json` uses the proto3 JSON mapping; fields the message doesn't have,
+    // such as injected path variables it doesn't declare, are left out
+    pub fn encode(&self, json: &str) -> Result<Vec<u8>, String> {
+        let descriptor = self.descriptor(&self.message)?;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let options = DeserializeOptions::new().deny_unknown_fields(false);
+        let message =
+            DynamicMessage::deserialize_with_options(descriptor, &mut deserializer, &options)
+                .map_err(|e| format!("payload doesn't fit {}: {}", self.message, e))?;
+        Ok(message.encode_to_vec())
+    }
+
+    // A `request_message` as JSON; None without one
+    pub fn decode_request(&self, bytes: &[u8]) -> Option<Result<Vec<u8>, String>> {
+        let name = self.request_message.as_ref()?;
+        Some(self.descriptor(name).and_then(|descriptor| {
+            let message = DynamicMessage::decode(descriptor, bytes)
+                .map_err(|e| format!("body isn't a {}: {}", name, e))?;
+            serde_json::to_vec(&message).map_err(|e| e.to_string())
+        }))
+    }
+
+    fn descriptor(&self, name: &str) -> Result<MessageDescriptor, String> {
+        let bytes = std::fs::read(&self.descriptor_set)
+            .map_err(|e| format!("{}: {}", self.descriptor_set, e))?;
+        let pool = DescriptorPool::decode(bytes.as_slice())
+            .map_err(|e| format!("{}: {}", self.descriptor_set, e))?;
+        pool.get_message_by_name(name)
+            .ok_or_else(|| format!("{} has no message {}", self.descriptor_set, name))
+    }
+}
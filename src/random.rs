@@ -0,0 +1,95 @@
+use axum::{extract::State, Json};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::AppState;
+
+// Overrides the `seed` setting, e.g. to replay a failing CI run
+const SEED_ENV: &str = "MOCK_SEED";
+
+// Randomness for mock behaviour. Everything random the server does to
+// responses draws from here, so the same seed and the same sequence of
+// requests give the same results. Secrets such as session ids must not.
+pub struct Random {
+    state: Mutex<(u64, StdRng)>, // (seed, generator)
+}
+
+impl Random {
+    // Uses MOCK_SEED, then the `seed` setting, then a fresh seed. The seed
+    // is logged either way so any run can be reproduced.
+    pub fn new(configured: Option<u64>) -> Self {
+        let from_env = std::env::var(SEED_ENV)
+            .ok()
+            .and_then(|seed| match seed.parse() {
+                Ok(seed) => Some(seed),
+                Err(_) => {
+                    tracing::warn!("Ignoring {}={}: not a number", SEED_ENV, seed);
+                    None
+                }
+            });
+        let seed = from_env
+            .or(configured)
+            .unwrap_or_else(|| rand::thread_rng().next_u64());
+        tracing::info!("Random seed: {} (set {} to reproduce)", seed, SEED_ENV);
+        Random {
+            state: Mutex::new((seed, StdRng::seed_from_u64(seed))),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.state.lock().unwrap().0
+    }
+
+    // Runs `f` with the generator, e.g. to draw several values in a row
+    pub fn with<T>(&self, f: impl FnOnce(&mut StdRng) -> T) -> T {
+        f(&mut self.state.lock().unwrap().1)
+    }
+
+    // Restarts the sequence, e.g. at the start of each test case
+    pub fn reseed(&self, seed: u64) {
+        *self.state.lock().unwrap() = (seed, StdRng::seed_from_u64(seed));
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SeedBody {
+    seed: u64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReseedRequest {
+    // Leave out to restart from the current seed
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/seed",
+    tag = "random",
+    responses((status = 200, description = "The current random seed", body = SeedBody))
+)]
+pub async fn get_seed(State(state): State<AppState>) -> Json<SeedBody> {
+    Json(SeedBody {
+        seed: state.random.seed(),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/seed",
+    tag = "random",
+    request_body = ReseedRequest,
+    responses((status = 200, description = "The random sequence was restarted", body = SeedBody))
+)]
+pub async fn reseed(
+    State(state): State<AppState>,
+    Json(request): Json<ReseedRequest>,
+) -> Json<SeedBody> {
+    let seed = request.seed.unwrap_or_else(|| state.random.seed());
+    state.random.reseed(seed);
+    tracing::info!("Reseeded random sequence with {}", seed);
+    Json(SeedBody { seed })
+}
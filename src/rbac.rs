@@ -0,0 +1,141 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderValue, Method, Request, Response, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{cluster, AppState};
+
+// What a token may do with the admin API. Each role includes the ones below it.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // Read the admin page, journal, versions and audit log
+    Viewer,
+    // Change endpoints, switch profiles, roll back and clear journals
+    Editor,
+    // Export and import the whole configuration, including backends and
+    // tokens, and push settings as a cluster peer
+    Admin,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AdminToken {
+    pub token: String,
+    pub role: Role,
+    // Recorded as the author of changes made with this token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+// The role an admin request needs, or None for mocked traffic, which isn't
+// governed by tokens. Peer syncs replace the whole configuration too.
+fn required_role(method: &Method, path: &str) -> Option<Role> {
+    if !path.starts_with("/mockserver/admin") {
+        return None;
+    }
+    if path == "/mockserver/admin/export"
+        || path == "/mockserver/admin/import"
+        || path == cluster::SYNC_PATH
+    {
+        Some(Role::Admin)
+    } else if method == Method::GET || method == Method::HEAD {
+        Some(Role::Viewer)
+    } else {
+        Some(Role::Editor)
+    }
+}
+
+// The token from an `Authorization: Bearer` header, or a `token` query
+// parameter so the admin page can be opened in a browser. Requests without
+// either fall back to the token their admin UI session was opened with.
+fn request_token<B>(req: &Request<B>) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    if let Some(token) = bearer {
+        return Some(token.trim().to_string());
+    }
+    req.uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .map(str::to_string)
+}
+
+// Checks admin requests against `admin_tokens`. With no tokens configured the
+// admin API stays open. Peers pushing settings may present the cluster
+// secret instead of a token.
+pub async fn rbac_middleware(
+    State(state): State<AppState>,
+    mut req: Request<Body>,
+    next: Next<Body>,
+) -> Response<axum::body::BoxBody> {
+    let Some(required) = required_role(req.method(), req.uri().path()) else {
+        return next.run(req).await;
+    };
+    // Don't hold the settings lock while the request runs; admin changes
+    // need to write them
+    let (open, token) = {
+        let settings = state.settings.read().await;
+        if req.uri().path() == cluster::SYNC_PATH && cluster::verified(&settings, req.headers()) {
+            drop(settings);
+            return next.run(req).await;
+        }
+        let presented = request_token(&req).or_else(|| {
+            let session = state.sessions.get(req.headers())?;
+            session.admin_token
+        });
+        let token = presented.and_then(|presented| {
+            settings
+                .admin_tokens
+                .iter()
+                .find(|t| t.token == presented)
+                .cloned()
+        });
+        (settings.admin_tokens.is_empty(), token)
+    };
+    if open {
+        return next.run(req).await;
+    }
+
+    let Some(token) = token else {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(header::WWW_AUTHENTICATE, "Bearer")
+            .body(Body::from("Missing or unknown admin token"))
+            .unwrap()
+            .into_response();
+    };
+    if token.role < required {
+        tracing::warn!(
+            "Rejected {} {}: {:?} token needs {:?}",
+            req.method(),
+            req.uri().path(),
+            token.role,
+            required
+        );
+        return Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::from(format!(
+                "This token's role can't do that (needs {:?})",
+                required
+            )))
+            .unwrap()
+            .into_response();
+    }
+
+    // The token, not a client-supplied header, decides who made a change
+    req.headers_mut().remove("X-Admin-User");
+    if let Some(name) = token.name.as_deref() {
+        if let Ok(value) = HeaderValue::from_str(name) {
+            req.headers_mut().insert("X-Admin-User", value);
+        }
+    }
+    next.run(req).await
+}
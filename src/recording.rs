@@ -0,0 +1,235 @@
+use axum::{
+    body::Body,
+    http::{header, request::Parts, HeaderMap, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::Mutex;
+
+use crate::{
+    redact,
+    tee::{self, SentBody},
+    websocket::WebSocketScript,
+};
+
+// Saves proxied exchanges as stubs in the settings' endpoint format, with
+// secrets dropped or masked so the file is safe to commit
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RecordingSettings {
+    // A JSON array of endpoints; a stub replaces any earlier one for the
+    // same method and path
+    pub file: String,
+    // Path prefixes to record; everything if left out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    // Path prefixes never recorded, even when included
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    // Left out of the recorded request and response headers
+    #[serde(default = "default_drop_headers")]
+    pub drop_headers: Vec<String>,
+    // JSONPaths masked in recorded JSON bodies, e.g. "$.user.email" or
+    // "$..token"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mask: Vec<String>,
+    #[serde(default = "default_mask_with")]
+    pub mask_with: serde_json::Value,
+}
+
+fn default_drop_headers() -> Vec<String> {
+    [
+        "authorization",
+        "proxy-authorization",
+        "cookie",
+        "set-cookie",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+fn default_mask_with() -> serde_json::Value {
+    serde_json::Value::from("***")
+}
+
+// What's kept of the request until its response arrives
+pub struct RecordedRequest {
+    method: String,
+    path: String,
+    query: Option<String>,
+    headers: HeaderMap,
+}
+
+impl RecordedRequest {
+    pub fn new(parts: &Parts, path: &str) -> Self {
+        RecordedRequest {
+            method: parts.method.to_string(),
+            path: path.to_string(),
+            query: parts.uri.query().map(str::to_string),
+            headers: parts.headers.clone(),
+        }
+    }
+}
+
+pub struct Recorder {
+    settings: RecordingSettings,
+    file: Mutex<()>, // Held while the file is rewritten
+}
+
+impl Recorder {
+    pub fn new(settings: RecordingSettings) -> Self {
+        Recorder {
+            settings,
+            file: Mutex::new(()),
+        }
+    }
+
+    pub fn wants(&self, path: &str) -> bool {
+        let included = self.settings.include.is_empty()
+            || self
+                .settings
+                .include
+                .iter()
+                .any(|p| path.starts_with(p.as_str()));
+        included
+            && !self
+                .settings
+                .exclude
+                .iter()
+                .any(|p| path.starts_with(p.as_str()))
+    }
+
+    // Saves the exchange once the response body has streamed through to the
+    // client, unless it's larger than tee::WHOLE_BYTES. A streamed request
+    // body is recorded only as far as it was kept.
+    pub fn record(
+        self: &Arc<Self>,
+        request: RecordedRequest,
+        request_body: SentBody,
+        response: Response<Body>,
+    ) -> Response<Body> {
+        if response.headers().contains_key(header::CONTENT_ENCODING) {
+            tracing::info!("Not recording the compressed response for {}", request.path);
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let content_type = parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+        let status = parts.status.as_u16();
+        let response_headers = self.headers(&parts.headers);
+        let recorder = self.clone();
+        let body = tee::keep_copy(body, tee::WHOLE_BYTES, move |bytes| {
+            let Some(bytes) = bytes else {
+                tracing::info!(
+                    "Not recording the response for {}: larger than {} bytes",
+                    request.path,
+                    tee::WHOLE_BYTES
+                );
+                return;
+            };
+            let stub = serde_json::json!({
+                "method": request.method,
+                "path": request.path,
+                "status": status,
+                "content_type": content_type,
+                "payload": recorder.body(&bytes),
+                // Ignored when the stub is loaded; kept for reference
+                "recorded": {
+                    "query": request.query,
+                    "request_headers": recorder.headers(&request.headers),
+                    "request_body": recorder.body(&request_body.bytes()),
+                    "response_headers": response_headers,
+                },
+            });
+            tokio::spawn(async move {
+                if let Err(e) = recorder.save(stub).await {
+                    tracing::error!("Failed to record to {}: {}", recorder.settings.file, e);
+                } else {
+                    tracing::info!("Recorded {} {}", request.method, request.path);
+                }
+            });
+        });
+        Response::from_parts(parts, body)
+    }
+
+    // Saves a proxied WebSocket conversation as a scripted WebSocket mock
+    pub async fn record_websocket(&self, request: RecordedRequest, mut script: WebSocketScript) {
+        // JSON messages are masked like bodies
+        let texts = script
+            .frames
+            .iter_mut()
+            .filter_map(|frame| frame.text.as_mut());
+        for text in texts.filter(|_| !self.settings.mask.is_empty()) {
+            let json = self.body(text.as_bytes());
+            if json.is_object() || json.is_array() {
+                *text = json.to_string();
+            }
+        }
+        let stub = serde_json::json!({
+            "method": request.method,
+            "path": request.path,
+            "websocket": script,
+            // Ignored when the stub is loaded; kept for reference
+            "recorded": {
+                "query": request.query,
+                "request_headers": self.headers(&request.headers),
+            },
+        });
+        if let Err(e) = self.save(stub).await {
+            tracing::error!("Failed to record to {}: {}", self.settings.file, e);
+        } else {
+            tracing::info!("Recorded WebSocket {}", request.path);
+        }
+    }
+
+    fn headers(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        headers
+            .iter()
+            .filter(|(name, _)| {
+                !self
+                    .settings
+                    .drop_headers
+                    .iter()
+                    .any(|dropped| name.as_str().eq_ignore_ascii_case(dropped))
+            })
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (name.to_string(), value)
+            })
+            .collect()
+    }
+
+    // JSON bodies are masked; anything else is kept as text
+    fn body(&self, bytes: &[u8]) -> serde_json::Value {
+        if bytes.is_empty() {
+            return serde_json::Value::Null;
+        }
+        match serde_json::from_slice::<serde_json::Value>(bytes) {
+            Ok(mut json) => {
+                for path in &self.settings.mask {
+                    redact::mask(&mut json, path, &self.settings.mask_with);
+                }
+                json
+            }
+            Err(_) => serde_json::Value::from(String::from_utf8_lossy(bytes).into_owned()),
+        }
+    }
+
+    async fn save(&self, stub: serde_json::Value) -> std::io::Result<()> {
+        let _guard = self.file.lock().await;
+        let mut stubs: Vec<serde_json::Value> = match tokio::fs::read(&self.settings.file).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        stubs.retain(|existing| {
+            existing.get("method") != stub.get("method") || existing.get("path") != stub.get("path")
+        });
+        stubs.push(stub);
+        let json = serde_json::to_vec_pretty(&stubs)?;
+        tokio::fs::write(&self.settings.file, json).await
+    }
+}
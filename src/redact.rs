@@ -0,0 +1,210 @@
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+// Hides sensitive values before requests and responses are logged or
+// journaled
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Redaction {
+    // Header names whose values are masked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<String>,
+    // JSONPaths, or bare field names matched at any depth, e.g. "password"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    #[serde(default = "default_mask_with")]
+    pub mask_with: serde_json::Value,
+    // Finds `name=value` and `name: value` in text, built on first use
+    #[serde(skip)]
+    in_text: OnceLock<Option<Regex>>,
+}
+
+fn default_mask_with() -> serde_json::Value {
+    serde_json::Value::from("***")
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Redaction {
+            headers: Vec::new(),
+            fields: Vec::new(),
+            mask_with: default_mask_with(),
+            in_text: OnceLock::new(),
+        }
+    }
+}
+
+impl Redaction {
+    pub fn hides_header(&self, name: &str) -> bool {
+        self.headers.iter().any(|h| h.eq_ignore_ascii_case(name))
+    }
+
+    pub fn mask_text(&self) -> String {
+        match &self.mask_with {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    pub fn json(&self, value: &mut serde_json::Value) {
+        for field in &self.fields {
+            if field.starts_with('$') {
+                mask(value, field, &self.mask_with);
+            } else {
+                mask(value, &format!("$..{}", field), &self.mask_with);
+            }
+        }
+    }
+
+    // A body as it may be shown: JSON with the fields masked, a form like a
+    // query string, and other text wherever a field is followed by `=` or `:`
+    pub fn body(&self, body: &str) -> String {
+        if self.fields.is_empty() {
+            return body.to_string();
+        }
+        match serde_json::from_str::<serde_json::Value>(body) {
+            Ok(mut json) => {
+                self.json(&mut json);
+                json.to_string()
+            }
+            Err(_) if body.contains('=') && !body.contains(char::is_whitespace) => self.query(body),
+            Err(_) => self.text(body),
+        }
+    }
+
+    // A query string or form body with the values of fields masked. Field
+    // paths match by their last key.
+    pub fn query(&self, query: &str) -> String {
+        let names = self.names();
+        let pairs: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
+        if !pairs.iter().any(|(name, _)| names.contains(&name.as_ref())) {
+            return query.to_string();
+        }
+        let mask = self.mask_text();
+        let mut masked = form_urlencoded::Serializer::new(String::new());
+        for (name, value) in &pairs {
+            match names.contains(&name.as_ref()) {
+                true => masked.append_pair(name, &mask),
+                false => masked.append_pair(name, value),
+            };
+        }
+        masked.finish()
+    }
+
+    fn text(&self, text: &str) -> String {
+        let pattern = self.in_text.get_or_init(|| {
+            let names: Vec<String> = self.names().into_iter().map(regex::escape).collect();
+            let pattern = format!(
+                r#"(?i)(\b(?:{})\b["']?\s*[:=]\s*["']?)([^"'&,;\s]+)"#,
+                names.join("|")
+            );
+            Regex::new(&pattern).ok()
+        });
+        let Some(pattern) = pattern else {
+            return text.to_string();
+        };
+        let mask = self.mask_text();
+        pattern
+            .replace_all(text, |found: &Captures| format!("{}{}", &found[1], mask))
+            .into_owned()
+    }
+
+    // Field names, the last key of each path
+    fn names(&self) -> Vec<&str> {
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let last = field.rsplit(['.', '[']).next()?;
+                let name = last.trim_end_matches(']').trim_matches(['\'', '"']);
+                (!name.is_empty() && name != "*").then_some(name)
+            })
+            .collect()
+    }
+}
+
+// Masks values at JSONPaths such as `$.user.email`, `$.items[*].card` or
+// `$..password` (any depth). Paths that match nothing are ignored.
+pub fn mask(value: &mut serde_json::Value, path: &str, replacement: &serde_json::Value) {
+    match parse(path) {
+        Some(segments) => mask_at(value, &segments, replacement),
+        None => tracing::warn!("Invalid redaction path {}", path),
+    }
+}
+
+enum Segment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+    Descendant(String),
+}
+
+fn parse(path: &str) -> Option<Vec<Segment>> {
+    let mut rest = path.trim().strip_prefix('$')?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            segments.push(Segment::Descendant(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            segments.push(match &after[..end] {
+                "*" => Segment::Wildcard,
+                key => Segment::Key(key.to_string()),
+            });
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']')?;
+            let key = &after[..end];
+            segments.push(match key.trim_matches(|c| c == '\'' || c == '"') {
+                "*" => Segment::Wildcard,
+                quoted if quoted.len() != key.len() => Segment::Key(quoted.to_string()),
+                index => Segment::Index(index.parse().ok()?),
+            });
+            rest = &after[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(segments)
+}
+
+fn mask_at(value: &mut serde_json::Value, segments: &[Segment], replacement: &serde_json::Value) {
+    let Some((segment, rest)) = segments.split_first() else {
+        *value = replacement.clone();
+        return;
+    };
+    match segment {
+        Segment::Key(key) => {
+            if let Some(child) = value.get_mut(key.as_str()) {
+                mask_at(child, rest, replacement);
+            }
+        }
+        Segment::Index(index) => {
+            if let Some(child) = value.get_mut(*index) {
+                mask_at(child, rest, replacement);
+            }
+        }
+        Segment::Wildcard => {
+            for child in children(value) {
+                mask_at(child, rest, replacement);
+            }
+        }
+        Segment::Descendant(key) => {
+            if let Some(child) = value.get_mut(key.as_str()) {
+                mask_at(child, rest, replacement);
+            }
+            for child in children(value) {
+                mask_at(child, segments, replacement);
+            }
+        }
+    }
+}
+
+fn children(value: &mut serde_json::Value) -> Vec<&mut serde_json::Value> {
+    match value {
+        serde_json::Value::Object(map) => map.values_mut().collect(),
+        serde_json::Value::Array(items) => items.iter_mut().collect(),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,66 @@
+use axum::{
+    body::Body,
+    http::{header, Response, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// Query parameter counting the hops a client has followed
+const HOP_PARAM: &str = "mock_redirect_hop";
+
+// Redirects a request `hops` times before the endpoint's response is served,
+// to test clients' redirect limits and whether they keep the method
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct RedirectChain {
+    pub hops: u32,
+    // Statuses used for successive hops, repeating; 302 by default
+    #[serde(default = "default_statuses")]
+    pub statuses: Vec<u16>,
+    // Start over after the last hop instead of serving the response
+    #[serde(default, rename = "loop", skip_serializing_if = "std::ops::Not::not")]
+    pub loops: bool,
+}
+
+fn default_statuses() -> Vec<u16> {
+    vec![302]
+}
+
+impl RedirectChain {
+    // The next redirect for a request to `path` (as the client sees it), or
+    // None once the chain is done
+    pub fn next(&self, path: &str, query: Option<&str>) -> Option<Response<Body>> {
+        let mut hop = 0;
+        let mut rest = Vec::new();
+        for pair in query
+            .unwrap_or_default()
+            .split('&')
+            .filter(|p| !p.is_empty())
+        {
+            match pair.split_once('=') {
+                Some((HOP_PARAM, value)) => hop = value.parse().unwrap_or(0),
+                _ => rest.push(pair),
+            }
+        }
+        if hop >= self.hops {
+            if !self.loops {
+                return None;
+            }
+            hop = 0;
+        }
+
+        let status = match self.statuses.as_slice() {
+            [] => 302,
+            statuses => statuses[hop as usize % statuses.len()],
+        };
+        let next_hop = format!("{}={}", HOP_PARAM, hop + 1);
+        rest.push(&next_hop);
+        let location = format!("{}?{}", path, rest.join("&"));
+        Some(
+            Response::builder()
+                .status(StatusCode::from_u16(status).unwrap_or(StatusCode::FOUND))
+                .header(header::LOCATION, location)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    }
+}
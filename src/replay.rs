@@ -0,0 +1,229 @@
+use hyper::{body::Bytes, Body, Method, Request, Uri};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    har::{Har, HarEntry},
+    journal::JournalEntry,
+    upstream::{self, HttpsClient, UpstreamSettings},
+};
+
+const USAGE: &str = "usage: mock-api replay <capture.json> --target <base url> \
+                     [--concurrency <n>] [--rate <requests per second>] [--insecure]";
+
+// Not copied from the capture; the client sets them for the new connection
+const SKIPPED_HEADERS: [&str; 5] = [
+    "host",
+    "content-length",
+    "connection",
+    "transfer-encoding",
+    "keep-alive",
+];
+
+// One request to re-issue and the status it got when it was captured
+struct Captured {
+    method: String,
+    path_and_query: String,
+    headers: Vec<(String, String)>,
+    body: String,
+    status: Option<u16>,
+}
+
+// Re-issues the requests in a journal export or HAR file against `--target`
+// and reports those whose status differs. Returns the process exit code.
+pub async fn run(args: &[String]) -> i32 {
+    let mut capture = None;
+    let mut target = None;
+    let mut concurrency = 1;
+    let mut rate = None;
+    let mut insecure = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target" => match args.next() {
+                Some(url) => target = Some(url.trim_end_matches('/').to_string()),
+                None => return usage(),
+            },
+            "--concurrency" => match args.next().and_then(|v| v.parse().ok()) {
+                Some(n) => concurrency = n,
+                None => return usage(),
+            },
+            "--rate" => match args.next().and_then(|v| v.parse::<f64>().ok()) {
+                Some(r) if r > 0.0 => rate = Some(r),
+                _ => return usage(),
+            },
+            "--insecure" => insecure = true,
+            path if capture.is_none() && !path.starts_with("--") => {
+                capture = Some(path.to_string())
+            }
+            _ => return usage(),
+        }
+    }
+    let (Some(capture), Some(target)) = (capture, target) else {
+        return usage();
+    };
+
+    let requests = match load(&capture) {
+        Ok(requests) => requests,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", capture, e);
+            return 2;
+        }
+    };
+    let settings = UpstreamSettings {
+        allow_http: true,
+        insecure_skip_verify: insecure,
+        ..UpstreamSettings::default()
+    };
+    let client = match upstream::build_client(&settings) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to set up the HTTP client: {}", e);
+            return 2;
+        }
+    };
+
+    println!(
+        "Replaying {} requests from {} against {}",
+        requests.len(),
+        capture,
+        target
+    );
+    let started = Instant::now();
+    let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut interval = rate.map(|rate| tokio::time::interval(Duration::from_secs_f64(1.0 / rate)));
+    let mut tasks = JoinSet::new();
+    for (index, request) in requests.into_iter().enumerate() {
+        if let Some(interval) = &mut interval {
+            interval.tick().await;
+        }
+        let permit = permits.clone().acquire_owned().await.unwrap();
+        let client = client.clone();
+        let target = target.clone();
+        tasks.spawn(async move {
+            let result = send(&client, &target, &request).await;
+            drop(permit);
+            (index, request, result)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        results.push(joined.unwrap());
+    }
+    results.sort_by_key(|(index, _, _)| *index);
+
+    let (mut matched, mut mismatched, mut failed) = (0, 0, 0);
+    for (_, request, result) in &results {
+        let name = format!("{} {}", request.method, request.path_and_query);
+        match (result, request.status) {
+            (Err(e), _) => {
+                failed += 1;
+                println!("FAILED   {}: {}", name, e);
+            }
+            (Ok(status), Some(expected)) if *status != expected => {
+                mismatched += 1;
+                println!("MISMATCH {}: expected {}, got {}", name, expected, status);
+            }
+            (Ok(_), _) => matched += 1,
+        }
+    }
+    let elapsed = started.elapsed();
+    println!(
+        "{} requests in {:.2?} ({:.1}/s): {} matched, {} mismatched, {} failed",
+        results.len(),
+        elapsed,
+        results.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        matched,
+        mismatched,
+        failed
+    );
+    if mismatched + failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+fn usage() -> i32 {
+    eprintln!("{}", USAGE);
+    2
+}
+
+async fn send(client: &HttpsClient, target: &str, request: &Captured) -> Result<u16, String> {
+    let uri: Uri = format!("{}{}", target, request.path_and_query)
+        .parse()
+        .map_err(|e| format!("invalid URL: {}", e))?;
+    let method = Method::from_bytes(request.method.as_bytes()).map_err(|e| e.to_string())?;
+    let mut builder = Request::builder().method(method).uri(uri);
+    for (name, value) in &request.headers {
+        if !name.starts_with(':') && !SKIPPED_HEADERS.contains(&name.to_ascii_lowercase().as_str())
+        {
+            builder = builder.header(name, value);
+        }
+    }
+    let req = builder
+        .body(Body::from(Bytes::from(request.body.clone())))
+        .map_err(|e| e.to_string())?;
+    let response = client.request(req).await.map_err(|e| e.to_string())?;
+    let status = response.status().as_u16();
+    // Read the body so the connection can be reused
+    hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(status)
+}
+
+// A journal export (GET /mockserver/admin/journal) or a HAR file
+fn load(path: &str) -> Result<Vec<Captured>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    if let Ok(entries) = serde_json::from_str::<Vec<JournalEntry>>(&text) {
+        return Ok(entries.into_iter().map(from_journal).collect());
+    }
+    match serde_json::from_str::<Har>(&text) {
+        Ok(har) => har.log.entries.into_iter().map(from_har).collect(),
+        Err(e) => Err(format!("neither a journal export nor a HAR file: {}", e)),
+    }
+}
+
+fn from_journal(entry: JournalEntry) -> Captured {
+    let path_and_query = match &entry.query {
+        Some(query) => format!("{}?{}", entry.path, query),
+        None => entry.path.clone(),
+    };
+    Captured {
+        method: entry.method,
+        path_and_query,
+        headers: entry.headers.into_iter().collect(),
+        body: entry.body,
+        status: Some(entry.status),
+    }
+}
+
+fn from_har(entry: HarEntry) -> Result<Captured, String> {
+    let uri: Uri = entry
+        .request
+        .url
+        .parse()
+        .map_err(|e| format!("{}: {}", entry.request.url, e))?;
+    let path_and_query = uri
+        .path_and_query()
+        .map(|p| p.to_string())
+        .unwrap_or_else(|| "/".to_string());
+    Ok(Captured {
+        method: entry.request.method,
+        path_and_query,
+        headers: entry
+            .request
+            .headers
+            .into_iter()
+            .map(|h| (h.name, h.value))
+            .collect(),
+        body: entry.request.post_data.map(|p| p.text).unwrap_or_default(),
+        // HAR uses 0 for requests that got no response
+        status: entry.response.map(|r| r.status).filter(|s| *s != 0),
+    })
+}
@@ -0,0 +1,59 @@
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use hyper::Body;
+use tracing::Instrument;
+
+pub const HEADER: &str = "x-request-id";
+
+// Request extension with the ID the request is known by
+#[derive(Clone)]
+pub struct RequestId(pub String);
+
+// Tags every request with an ID: the client's X-Request-Id when it sent a
+// usable one, otherwise a new UUID. The ID is set on the request, so proxied
+// requests pass it on to the backend, shown on every log line written while
+// handling it, and returned in the response unless the response already has
+// its own.
+pub async fn middleware(mut req: Request<Body>, next: Next<Body>) -> Response {
+    let id = req
+        .headers()
+        .get(HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| usable(id))
+        .map(str::to_string)
+        .unwrap_or_else(generate);
+    let Ok(value) = HeaderValue::from_str(&id) else {
+        return next.run(req).await;
+    };
+    req.headers_mut().insert(HEADER, value.clone());
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let span = tracing::info_span!("request", id = %id);
+    let mut response = next.run(req).instrument(span).await;
+    response.headers_mut().entry(HEADER).or_insert(value);
+    response
+}
+
+// Short printable IDs are kept; anything else could garble the logs
+fn usable(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+// A random (version 4) UUID
+fn generate() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
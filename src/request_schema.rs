@@ -0,0 +1,85 @@
+use axum::http::{header, Response, StatusCode};
+use hyper::Body;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+use crate::transform;
+
+// Checks request bodies against a JSON Schema before the endpoint answers,
+// so a client that serializes something wrong fails at the mock instead of
+// getting a happy response
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct RequestSchema {
+    #[schema(value_type = Object)]
+    pub schema: Value,
+    // Status for bodies that don't match; 400 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    // Response body for them, where "{{violations}}" is the list of problems
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub payload: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct Violation {
+    path: String, // JSON pointer into the body; "" for the body itself
+    message: String,
+}
+
+impl RequestSchema {
+    // None when `body` matches, otherwise the response to send. The schema
+    // is compiled on every request, so edits apply right away.
+    pub fn check(&self, body: &[u8]) -> Option<Response<Body>> {
+        let validator = match jsonschema::validator_for(&self.schema) {
+            Ok(validator) => validator,
+            Err(e) => {
+                tracing::error!("Invalid request_schema: {}", e);
+                return Some(response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    json!({ "error": "The endpoint's request_schema is invalid", "detail": e.to_string() }),
+                ));
+            }
+        };
+        let violations: Vec<Violation> = match serde_json::from_slice::<Value>(body) {
+            Ok(instance) => validator
+                .iter_errors(&instance)
+                .map(|e| Violation {
+                    path: e.instance_path.to_string(),
+                    message: e.to_string(),
+                })
+                .collect(),
+            Err(e) => vec![Violation {
+                path: String::new(),
+                message: format!("the body isn't JSON: {}", e),
+            }],
+        };
+        if violations.is_empty() {
+            return None;
+        }
+        let violations = serde_json::to_value(violations).unwrap();
+        let payload = match &self.payload {
+            Some(payload) => transform::fill(payload, &|name| {
+                (name == "violations").then(|| violations.clone())
+            }),
+            None => json!({
+                "error": "The request body doesn't match the schema",
+                "violations": violations,
+            }),
+        };
+        let status = self
+            .status
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .unwrap_or(StatusCode::BAD_REQUEST);
+        Some(response(status, payload))
+    }
+}
+
+fn response(status: StatusCode, payload: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap()
+}
@@ -0,0 +1,475 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Query, State},
+    http::{header, Method, Response, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use hyper::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    admin_workspace, conditions::Condition, edit_endpoints, tee, unknown_workspace, AdminQuery,
+    AppState, EndpointConfig, ListenerPort,
+};
+
+// Every scenario starts in this state, and goes back to it when reset
+pub const STARTED: &str = "started";
+
+// Puts an endpoint in a scenario: a named state its endpoints share and
+// their conditions can test with `state`. Answering a request moves the
+// scenario from its current state to the one `next` maps it to, if any.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct ScenarioStep {
+    pub name: String,
+    // Current state => state after this endpoint answered
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub next: BTreeMap<String, String>,
+}
+
+// A proxied request and its response, captured while recording
+struct Exchange {
+    method: String,
+    path: String,
+    status: u16,
+    content_type: String,
+    payload: serde_json::Value,
+}
+
+struct Capture {
+    name: String,
+    exchanges: Vec<Exchange>,
+}
+
+// The exchanges of one method and path, each the last in its state, and
+// the steps its requests took
+struct Route {
+    answers: Vec<(String, Exchange)>,
+    next: BTreeMap<String, String>,
+}
+
+// The scenarios of one workspace, and the one being recorded from its
+// proxied traffic, if any
+#[derive(Default)]
+pub struct Scenarios {
+    // (client key, scenario) => state, so parallel test workers each have
+    // their own (see client_key_header). Unkeyed requests share one.
+    states: Mutex<HashMap<(Option<String>, String), String>>,
+    recording: Arc<Mutex<Option<Capture>>>, // Shared with responses still streaming
+}
+
+impl Scenarios {
+    pub fn state(&self, client: Option<&str>, name: &str) -> String {
+        self.states
+            .lock()
+            .unwrap()
+            .get(&key(client, name))
+            .cloned()
+            .unwrap_or_else(|| STARTED.to_string())
+    }
+
+    // Moves the step's scenario on from `from`, the state the request was
+    // answered in, unless a request answered since has already moved it
+    pub fn advance(&self, client: Option<&str>, step: &ScenarioStep, from: &str) {
+        let Some(to) = step.next.get(from) else {
+            return;
+        };
+        let key = key(client, &step.name);
+        let mut states = self.states.lock().unwrap();
+        if states.get(&key).map_or(STARTED, String::as_str) == from {
+            match client {
+                Some(client) => tracing::info!(
+                    "Scenario {} moved from {} to {} for client {}",
+                    step.name,
+                    from,
+                    to,
+                    client
+                ),
+                None => tracing::info!("Scenario {} moved from {} to {}", step.name, from, to),
+            }
+            states.insert(key, to.clone());
+        }
+    }
+
+    // The name of the scenario being recorded
+    pub fn recording(&self) -> Option<String> {
+        let recording = self.recording.lock().unwrap();
+        recording.as_ref().map(|capture| capture.name.clone())
+    }
+
+    // Keeps a proxied response for the scenario being recorded. Its place in
+    // the scenario is taken right away; the body is filled in once it has
+    // streamed through to the client, unless it's larger than
+    // tee::WHOLE_BYTES. Compressed bodies aren't captured.
+    pub fn capture(&self, method: &Method, path: &str, response: Response<Body>) -> Response<Body> {
+        if response.headers().contains_key(header::CONTENT_ENCODING) {
+            tracing::info!("Not capturing the compressed response for {}", path);
+            return response;
+        }
+        let (name, at) = {
+            let mut recording = self.recording.lock().unwrap();
+            let Some(capture) = recording.as_mut() else {
+                return response;
+            };
+            capture.exchanges.push(Exchange {
+                method: method.to_string(),
+                path: path.to_string(),
+                status: response.status().as_u16(),
+                content_type: response
+                    .headers()
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|ct| ct.to_str().ok())
+                    .unwrap_or("application/octet-stream")
+                    .to_string(),
+                payload: serde_json::Value::Null,
+            });
+            tracing::info!("Captured {} {} for scenario {}", method, path, capture.name);
+            (capture.name.clone(), capture.exchanges.len() - 1)
+        };
+        let recording = self.recording.clone();
+        let path = path.to_string();
+        let (parts, body) = response.into_parts();
+        let body = tee::keep_copy(body, tee::WHOLE_BYTES, move |bytes| {
+            let Some(bytes) = bytes else {
+                tracing::info!(
+                    "Captured {} without its body: larger than {} bytes",
+                    path,
+                    tee::WHOLE_BYTES
+                );
+                return;
+            };
+            let mut recording = recording.lock().unwrap();
+            let exchange = recording
+                .as_mut()
+                .filter(|capture| capture.name == name)
+                .and_then(|capture| capture.exchanges.get_mut(at));
+            if let Some(exchange) = exchange {
+                exchange.payload = payload(&bytes);
+            }
+        });
+        Response::from_parts(parts, body)
+    }
+}
+
+fn key(client: Option<&str>, name: &str) -> (Option<String>, String) {
+    (client.map(str::to_string), name.to_string())
+}
+
+fn payload(bytes: &[u8]) -> serde_json::Value {
+    if bytes.is_empty() {
+        return serde_json::Value::Null;
+    }
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| serde_json::Value::from(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+impl Capture {
+    // Every request that may change something (all but GET, HEAD and
+    // OPTIONS) moves the scenario to the next step: "started", "step 1",
+    // "step 2"... Each method and path becomes one endpoint answering with
+    // its last response, and with a condition for each earlier step it
+    // answered differently in.
+    fn endpoints(self) -> Vec<EndpointConfig> {
+        let mut routes: Vec<Route> = Vec::new();
+        let mut state = STARTED.to_string();
+        let mut steps = 0;
+        for exchange in self.exchanges {
+            let route = routes.iter().position(|route| {
+                let (_, first) = &route.answers[0];
+                first.method == exchange.method && first.path == exchange.path
+            });
+            let route = match route {
+                Some(at) => &mut routes[at],
+                None => {
+                    routes.push(Route {
+                        answers: Vec::new(),
+                        next: BTreeMap::new(),
+                    });
+                    routes.last_mut().unwrap()
+                }
+            };
+            let changes = !matches!(exchange.method.as_str(), "GET" | "HEAD" | "OPTIONS");
+            route
+                .answers
+                .retain(|(answered_in, _)| *answered_in != state);
+            route.answers.push((state.clone(), exchange));
+            if changes {
+                steps += 1;
+                let to = format!("step {}", steps);
+                route.next.insert(state, to.clone());
+                state = to;
+            }
+        }
+
+        routes
+            .into_iter()
+            .map(|Route { mut answers, next }| {
+                let (_, last) = answers.pop().unwrap();
+                let conditions: Vec<Condition> = answers
+                    .into_iter()
+                    .filter(|(_, answer)| {
+                        answer.status != last.status
+                            || answer.content_type != last.content_type
+                            || answer.payload != last.payload
+                    })
+                    .map(|(state, answer)| Condition {
+                        state: Some(state),
+                        status: Some(answer.status),
+                        content_type: Some(answer.content_type),
+                        payload: Some(answer.payload),
+                        ..Condition::default()
+                    })
+                    .collect();
+                let endpoint = serde_json::json!({
+                    "method": last.method,
+                    "path": last.path,
+                    "status": last.status,
+                    "content_type": last.content_type,
+                    "payload": last.payload,
+                    "conditions": conditions,
+                    "scenario": ScenarioStep {
+                        name: self.name.clone(),
+                        next,
+                    },
+                });
+                serde_json::from_value(endpoint).unwrap()
+            })
+            .collect()
+    }
+}
+
+// The client whose scenario states to list or reset (see client_key_header)
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ScenarioQuery {
+    client: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ScenarioStatus {
+    // Scenario => current state, for those that have left "started", for
+    // the client asked about
+    states: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recording: Option<RecordingStatus>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RecordingStatus {
+    name: String,
+    // Responses captured so far
+    captured: usize,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct StartRecording {
+    name: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/scenarios",
+    tag = "scenarios",
+    params(AdminQuery, ScenarioQuery),
+    responses(
+        (status = 200, description = "Scenario states, and the recording in progress", body = ScenarioStatus),
+        (status = 404, description = "Unknown workspace")
+    )
+)]
+pub async fn get_scenarios(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    Query(scenario_query): Query<ScenarioQuery>,
+    headers: HeaderMap,
+) -> Response<axum::body::BoxBody> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name).into_response(),
+    };
+    let scenarios = &workspace.scenarios;
+    let states = scenarios
+        .states
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|((client, _), _)| *client == scenario_query.client)
+        .map(|((_, name), state)| (name.clone(), state.clone()))
+        .collect();
+    let recording = scenarios
+        .recording
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|capture| RecordingStatus {
+            name: capture.name.clone(),
+            captured: capture.exchanges.len(),
+        });
+    Json(ScenarioStatus { states, recording }).into_response()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/mockserver/admin/scenarios",
+    tag = "scenarios",
+    params(AdminQuery, ScenarioQuery),
+    responses(
+        (status = 200, description = "Every scenario is back in its \"started\" state, for the client if one is given"),
+        (status = 404, description = "Unknown workspace")
+    )
+)]
+pub async fn reset_scenarios(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    Query(scenario_query): Query<ScenarioQuery>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name),
+    };
+    let mut states = workspace.scenarios.states.lock().unwrap();
+    match &scenario_query.client {
+        Some(client) => {
+            states.retain(|(key, _), _| key.as_ref() != Some(client));
+            tracing::info!(
+                "Reset the scenarios of workspace {} for client {}",
+                workspace.name,
+                client
+            );
+        }
+        None => {
+            states.clear();
+            tracing::info!("Reset the scenarios of workspace {}", workspace.name);
+        }
+    }
+    drop(states);
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from("Scenarios reset"))
+        .unwrap()
+}
+
+// Starts capturing the workspace's proxied traffic, in order, as a scenario
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/scenarios/recording",
+    tag = "scenarios",
+    params(AdminQuery),
+    request_body = StartRecording,
+    responses(
+        (status = 200, description = "Recording started"),
+        (status = 400, description = "No scenario name"),
+        (status = 404, description = "Unknown workspace"),
+        (status = 409, description = "Another scenario is being recorded")
+    )
+)]
+pub async fn start_recording(
+    State(state): State<AppState>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+    Json(request): Json<StartRecording>,
+) -> Response<Body> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name),
+    };
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("The scenario needs a name"))
+            .unwrap();
+    }
+    let mut recording = workspace.scenarios.recording.lock().unwrap();
+    if let Some(capture) = recording.as_ref() {
+        return Response::builder()
+            .status(StatusCode::CONFLICT)
+            .body(Body::from(format!(
+                "Scenario {} is already being recorded",
+                capture.name
+            )))
+            .unwrap();
+    }
+    *recording = Some(Capture {
+        name: name.to_string(),
+        exchanges: Vec::new(),
+    });
+    tracing::info!(
+        "Recording scenario {} in workspace {}",
+        name,
+        workspace.name
+    );
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(format!("Recording scenario {}", name)))
+        .unwrap()
+}
+
+// Stops recording and adds the scenario's endpoints, replacing any with the
+// same method and path, then starts the scenario over
+#[utoipa::path(
+    delete,
+    path = "/mockserver/admin/scenarios/recording",
+    tag = "scenarios",
+    params(AdminQuery),
+    responses(
+        (status = 200, description = "The endpoints added", body = [EndpointConfig]),
+        (status = 404, description = "Unknown workspace, or no scenario is being recorded"),
+        (status = 422, description = "Conflicting routes", body = RouteConflicts)
+    )
+)]
+pub async fn stop_recording(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(ListenerPort(port)): Extension<ListenerPort>,
+    Query(query): Query<AdminQuery>,
+    headers: HeaderMap,
+) -> Response<axum::body::BoxBody> {
+    let workspace = match admin_workspace(&state, &query, port, &headers) {
+        Ok(workspace) => workspace,
+        Err(name) => return unknown_workspace(&name).into_response(),
+    };
+    let Some(capture) = workspace.scenarios.recording.lock().unwrap().take() else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("No scenario is being recorded"))
+            .unwrap()
+            .into_response();
+    };
+    let name = capture.name.clone();
+    let endpoints = capture.endpoints();
+    tracing::info!(
+        "Stopped recording scenario {}: {} endpoints",
+        name,
+        endpoints.len()
+    );
+    if endpoints.is_empty() {
+        return Json(endpoints).into_response();
+    }
+    let added = endpoints.clone();
+    let response = edit_endpoints(state, addr, port, query, headers, |existing| {
+        existing.retain(|old| {
+            !added
+                .iter()
+                .any(|new| old.method.eq_ignore_ascii_case(&new.method) && old.path == new.path)
+        });
+        existing.extend(added);
+        true
+    })
+    .await;
+    if !response.status().is_success() {
+        return response.into_response();
+    }
+    let mut states = workspace.scenarios.states.lock().unwrap();
+    states.retain(|(_, scenario), _| *scenario != name);
+    Json(endpoints).into_response()
+}
@@ -0,0 +1,126 @@
+use chrono::{DateTime, Datelike, FixedOffset, NaiveTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// A recurring period in which something applies: a daily window from `from`
+// to `to` (which may wrap past midnight), or every minute a cron expression
+// matches. Times are UTC unless `utc_offset` says otherwise, e.g. "+02:00".
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct Schedule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>, // "HH:MM" or "HH:MM:SS"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    // minute hour day-of-month month day-of-week, e.g. "0-9 2 * * 1-5"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utc_offset: Option<String>,
+}
+
+impl Schedule {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        match self.check(now) {
+            Ok(active) => active,
+            Err(e) => {
+                tracing::warn!("Ignoring invalid schedule {:?}: {}", self, e);
+                false
+            }
+        }
+    }
+
+    fn check(&self, now: DateTime<Utc>) -> Result<bool, String> {
+        let offset = match &self.utc_offset {
+            Some(offset) => offset
+                .parse::<FixedOffset>()
+                .map_err(|_| format!("bad utc_offset {}", offset))?,
+            None => FixedOffset::east_opt(0).unwrap(),
+        };
+        let now = now.with_timezone(&offset);
+
+        let in_window = match (&self.from, &self.to) {
+            (Some(from), Some(to)) => {
+                let (from, to) = (parse_time(from)?, parse_time(to)?);
+                let time = now.time();
+                if from <= to {
+                    from <= time && time < to
+                } else {
+                    time >= from || time < to
+                }
+            }
+            (None, None) => true,
+            _ => return Err("from and to go together".to_string()),
+        };
+        let in_cron = match &self.cron {
+            Some(cron) => cron_matches(cron, &now)?,
+            None => true,
+        };
+        Ok(in_window && in_cron)
+    }
+}
+
+// True when any of `schedules` is active; no schedules means always
+pub fn any_active(schedules: &[Schedule], now: DateTime<Utc>) -> bool {
+    schedules.is_empty() || schedules.iter().any(|s| s.is_active(now))
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .map_err(|_| format!("bad time {}", time))
+}
+
+fn cron_matches(cron: &str, now: &DateTime<FixedOffset>) -> Result<bool, String> {
+    let fields: Vec<&str> = cron.split_whitespace().collect();
+    let [minute, hour, day, month, weekday] = fields[..] else {
+        return Err(format!("cron needs 5 fields: {}", cron));
+    };
+    let weekday_now = now.weekday().num_days_from_sunday();
+    let day_matches = field_matches(day, now.day(), 1, 31)?;
+    let weekday_matches = field_matches(weekday, weekday_now, 0, 7)?
+        || (weekday_now == 0 && field_matches(weekday, 7, 0, 7)?);
+    // As in cron, a restricted day-of-month or day-of-week may match
+    let date_matches = match (day == "*", weekday == "*") {
+        (false, false) => day_matches || weekday_matches,
+        _ => day_matches && weekday_matches,
+    };
+    Ok(field_matches(minute, now.minute(), 0, 59)?
+        && field_matches(hour, now.hour(), 0, 23)?
+        && field_matches(month, now.month(), 1, 12)?
+        && date_matches)
+}
+
+// One cron field: `*`, `N`, `A-B`, any of those with `/STEP`, or a comma list
+fn field_matches(field: &str, value: u32, min: u32, max: u32) -> Result<bool, String> {
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .map_err(|_| format!("bad step in {}", field))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (parse_number(a, field)?, parse_number(b, field)?),
+                None => {
+                    let n = parse_number(range, field)?;
+                    (n, if step > 1 { max } else { n })
+                }
+            },
+        };
+        if step == 0 || start < min || end > max {
+            return Err(format!("out of range: {}", field));
+        }
+        if (start..=end).contains(&value) && (value - start).is_multiple_of(step) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn parse_number(n: &str, field: &str) -> Result<u32, String> {
+    n.parse().map_err(|_| format!("bad number in {}", field))
+}
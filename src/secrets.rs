@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    process::Command,
+};
+
+// Where a named secret is read from at startup, e.g.
+// `{ "env": "PAYMENTS_API_KEY" }`, `{ "file": "/run/secrets/admin" }` or
+// `{ "command": "vault kv get -field=token secret/mock" }`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretSource {
+    Env(String),
+    File(String),
+    // Run with `sh -c`; its output is the secret
+    Command(String),
+}
+
+impl SecretSource {
+    fn resolve(&self) -> Result<String, String> {
+        let value = match self {
+            SecretSource::Env(name) => std::env::var(name)
+                .map_err(|_| format!("environment variable {} is not set", name))?,
+            SecretSource::File(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {}", path, e))?,
+            SecretSource::Command(command) => {
+                let output = Command::new("sh")
+                    .args(["-c", command])
+                    .output()
+                    .map_err(|e| format!("failed to run `{}`: {}", command, e))?;
+                if !output.status.success() {
+                    return Err(format!(
+                        "`{}` failed with {}: {}",
+                        command,
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                String::from_utf8(output.stdout)
+                    .map_err(|_| format!("`{}` printed something that isn't UTF-8", command))?
+            }
+        };
+        // Files and commands usually end with a newline that isn't part of it
+        Ok(value.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+// Reads every secret, failing on the first that can't be read
+pub fn resolve(
+    sources: &BTreeMap<String, SecretSource>,
+) -> Result<HashMap<String, String>, String> {
+    sources
+        .iter()
+        .map(|(name, source)| {
+            let value = source
+                .resolve()
+                .map_err(|e| format!("secret {}: {}", name, e))?;
+            Ok((name.clone(), value))
+        })
+        .collect()
+}
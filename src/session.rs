@@ -0,0 +1,137 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Request, Response, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+};
+use rand::RngCore;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::AppState;
+
+pub const SESSION_COOKIE: &str = "mockserver_session";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+// Sessions that haven't been used for this long are forgotten
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(12 * 60 * 60);
+
+#[derive(Clone)]
+pub struct Session {
+    pub csrf_token: String,
+    // Admin token the session was opened with, so the page's own requests
+    // don't need to carry it
+    pub admin_token: Option<String>,
+    last_seen: Instant,
+}
+
+// Admin UI sessions, kept in memory. A restart logs everyone out.
+#[derive(Default)]
+pub struct Sessions {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Sessions {
+    // Returns the request's live session, creating one if there isn't any.
+    // The bool is true for a new session, whose cookie still has to be set.
+    pub fn start(
+        &self,
+        headers: &HeaderMap,
+        admin_token: Option<String>,
+    ) -> (String, Session, bool) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, s| s.last_seen.elapsed() < SESSION_IDLE_TIMEOUT);
+
+        if let Some(id) = session_id(headers) {
+            if let Some(session) = sessions.get_mut(&id) {
+                session.last_seen = Instant::now();
+                if admin_token.is_some() {
+                    session.admin_token = admin_token;
+                }
+                return (id, session.clone(), false);
+            }
+        }
+
+        let id = random_token();
+        let session = Session {
+            csrf_token: random_token(),
+            admin_token,
+            last_seen: Instant::now(),
+        };
+        sessions.insert(id.clone(), session.clone());
+        (id, session, true)
+    }
+
+    pub fn get(&self, headers: &HeaderMap) -> Option<Session> {
+        let id = session_id(headers)?;
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(&id)?;
+        if session.last_seen.elapsed() >= SESSION_IDLE_TIMEOUT {
+            sessions.remove(&id);
+            return None;
+        }
+        session.last_seen = Instant::now();
+        Some(session.clone())
+    }
+}
+
+pub fn session_cookie(id: &str) -> String {
+    format!(
+        "{}={}; Path=/mockserver/admin; HttpOnly; SameSite=Strict",
+        SESSION_COOKIE, id
+    )
+}
+
+fn session_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .find_map(|pair| {
+            let (name, value) = pair.trim().split_once('=')?;
+            (name == SESSION_COOKIE).then(|| value.to_string())
+        })
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Admin changes made from a browser must carry the CSRF token of their
+// session. Browsers always send an Origin header with these requests, so
+// API clients without one (curl, scripts) aren't affected.
+pub async fn csrf_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response<axum::body::BoxBody> {
+    let headers = req.headers();
+    let from_browser = headers.contains_key(header::ORIGIN) || session_id(headers).is_some();
+    if from_browser {
+        let presented = headers.get(CSRF_HEADER).and_then(|h| h.to_str().ok());
+        let valid = match (state.sessions.get(headers), presented) {
+            (Some(session), Some(presented)) => session.csrf_token == presented,
+            _ => false,
+        };
+        if !valid {
+            tracing::warn!(
+                "Rejected {} {}: missing or invalid CSRF token",
+                req.method(),
+                req.uri().path()
+            );
+            return Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::from("Missing or invalid CSRF token"))
+                .unwrap()
+                .into_response();
+        }
+    }
+    next.run(req).await
+}
@@ -0,0 +1,184 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Json, State},
+    http::{header, HeaderMap, Response, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, net::SocketAddr};
+use utoipa::ToSchema;
+
+use crate::{
+    apply_settings, invalid_settings_response,
+    journal::JournalEntry,
+    persist_settings, route_conflicts_response, secrets_refused, settings_route_conflicts,
+    startup::{self, StartupError},
+    workspace::DEFAULT_WORKSPACE,
+    AppState,
+};
+
+const SNAPSHOT_FORMAT: u32 = 1;
+
+// Everything needed to reproduce a mock environment elsewhere: the full
+// settings (every workspace, profile and virtual host), written like
+// settings.json with secrets unexpanded, plus each workspace's journal.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Snapshot {
+    pub format: u32,
+    pub exported_at: DateTime<Utc>,
+    #[schema(value_type = Object)]
+    pub settings: serde_json::Value,
+    #[serde(default)]
+    pub journals: BTreeMap<String, Vec<JournalEntry>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImportSummary {
+    applied: Vec<String>,
+    // Workspaces that need a restart, e.g. because they open a new listener
+    // or proxy to a new default_endpoint
+    pending_restart: Vec<String>,
+    journal_entries: usize,
+}
+
+#[utoipa::path(
+    get,
+    path = "/mockserver/admin/export",
+    tag = "snapshots",
+    responses((status = 200, description = "Settings and journals of every workspace", body = Snapshot))
+)]
+pub async fn export_snapshot(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, &'static str)> {
+    let settings = match state.source.shared_contents(&*state.settings.read().await) {
+        Ok(settings) => settings,
+        Err(e) => {
+            tracing::error!("Failed to export settings: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to export settings",
+            ));
+        }
+    };
+    let journals = state
+        .workspaces
+        .iter()
+        .map(|w| (w.name.clone(), w.journal.entries()))
+        .collect();
+    let snapshot = Snapshot {
+        format: SNAPSHOT_FORMAT,
+        exported_at: Utc::now(),
+        settings,
+        journals,
+    };
+    let filename = format!(
+        "mockserver-snapshot-{}.json",
+        snapshot.exported_at.format("%Y%m%d-%H%M%S")
+    );
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )],
+        Json(snapshot),
+    ))
+}
+
+// Replaces the configuration with the snapshot's and appends its journal
+// entries. Workspace ports are rebound; settings that are only read at
+// startup (upstream client, limits) are saved to settings.json and apply
+// after a restart. This instance keeps its own admin tokens, secrets,
+// cluster, Redis and git configuration, and snapshots naming secrets are
+// refused.
+#[utoipa::path(
+    post,
+    path = "/mockserver/admin/import",
+    tag = "snapshots",
+    request_body = Snapshot,
+    responses(
+        (status = 200, description = "Snapshot applied", body = ImportSummary),
+        (status = 400, description = "Settings that wouldn't start"),
+        (status = 422, description = "Unsupported snapshot format or conflicting routes")
+    )
+)]
+pub async fn import_snapshot(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(snapshot): Json<Snapshot>,
+) -> Response<Body> {
+    if snapshot.format != SNAPSHOT_FORMAT {
+        return Response::builder()
+            .status(StatusCode::UNPROCESSABLE_ENTITY)
+            .body(Body::from(format!(
+                "Unsupported snapshot format: {}",
+                snapshot.format
+            )))
+            .unwrap();
+    }
+
+    if let Some(response) = secrets_refused(&snapshot.settings) {
+        return response;
+    }
+    let imported = match state.source.expand(snapshot.settings) {
+        Ok(imported) => imported,
+        Err(e) => return invalid_settings_response(StartupError::config(e)),
+    };
+    let conflicts = settings_route_conflicts(&imported, state.path_matching);
+    if !conflicts.is_empty() {
+        return route_conflicts_response(conflicts);
+    }
+
+    let mut settings = state.settings.write().await;
+    let imported = imported.with_local(&settings);
+    if let Err(e) = startup::validate(&imported) {
+        return invalid_settings_response(e);
+    }
+    *settings = imported;
+
+    let (applied, pending_restart) = apply_settings(&state, &settings, "Imported snapshot").await;
+    let mut summary = ImportSummary {
+        applied,
+        pending_restart,
+        journal_entries: 0,
+    };
+
+    for (name, entries) in snapshot.journals {
+        if let Some(workspace) = state.workspaces.get(&name) {
+            summary.journal_entries += entries.len();
+            for entry in entries {
+                workspace.journal.record(entry);
+            }
+        }
+    }
+
+    if let Err(e) = persist_settings(
+        &state,
+        &settings,
+        DEFAULT_WORKSPACE,
+        "Imported snapshot",
+        &headers,
+        addr.ip(),
+    )
+    .await
+    {
+        tracing::error!("Failed to write settings to file: {}", e);
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from("Failed to write settings to file"))
+            .unwrap();
+    }
+
+    tracing::info!(
+        "Imported snapshot: {} workspace(s) applied, {} journal entries",
+        summary.applied.len(),
+        summary.journal_entries
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&summary).unwrap()))
+        .unwrap()
+}
@@ -0,0 +1,158 @@
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{connection, ip_filter::IpFilter, startup::Listener};
+
+// A SOCKS5 listener for clients that can only be pointed at a SOCKS proxy,
+// such as mobile emulators. Connections to `intercept_ports` are answered by
+// the mock server as if they had been sent to it directly, so the Host header
+// picks the workspace or virtual host; everything else is tunneled to its
+// real destination untouched.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Socks5Settings {
+    pub port: u16,
+    #[serde(default = "default_intercept_ports")]
+    pub intercept_ports: Vec<u16>,
+}
+
+fn default_intercept_ports() -> Vec<u16> {
+    vec![80]
+}
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const CONNECT: u8 = 1;
+
+// Reply codes
+const SUCCEEDED: u8 = 0;
+const HOST_UNREACHABLE: u8 = 4;
+const COMMAND_NOT_SUPPORTED: u8 = 7;
+const ADDRESS_NOT_SUPPORTED: u8 = 8;
+
+// Serves the proxy on the listener bound for it at startup
+pub async fn serve(
+    settings: Socks5Settings,
+    bound: Listener,
+    app: Router,
+    ip_filter: Arc<IpFilter>,
+) {
+    let listener = bound.listener;
+    tracing::info!("Listening on {} (SOCKS5)", bound.addr);
+    let settings = Arc::new(settings);
+    loop {
+        match listener.accept().await {
+            // Blocked clients can't tunnel anywhere either
+            Ok((_, remote)) if !ip_filter.permits(remote.ip()) => {
+                tracing::warn!("Closed connection from {}: blocked by ip_filter", remote);
+            }
+            Ok((stream, remote)) => {
+                let settings = settings.clone();
+                let app = app.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(&settings, stream, remote, app).await {
+                        tracing::warn!("SOCKS5 connection from {} failed: {}", remote, e);
+                    }
+                });
+            }
+            Err(e) => {
+                tracing::error!("Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+async fn handle(
+    settings: &Socks5Settings,
+    mut stream: TcpStream,
+    remote: SocketAddr,
+    app: Router,
+) -> io::Result<()> {
+    // Greeting: version, then the authentication methods offered
+    let [version, count] = read_array(&mut stream).await?;
+    if version != VERSION {
+        return Err(invalid("not a SOCKS5 client"));
+    }
+    let mut methods = vec![0; count as usize];
+    stream.read_exact(&mut methods).await?;
+    if !methods.contains(&NO_AUTH) {
+        stream.write_all(&[VERSION, NO_ACCEPTABLE_METHOD]).await?;
+        return Err(invalid("client requires authentication"));
+    }
+    stream.write_all(&[VERSION, NO_AUTH]).await?;
+
+    // Request: version, command, reserved, then the destination
+    let [_, command, _, address_type] = read_array(&mut stream).await?;
+    let host = match address_type {
+        1 => Ipv4Addr::from(read_array::<4>(&mut stream).await?).to_string(),
+        3 => {
+            let [len] = read_array(&mut stream).await?;
+            let mut name = vec![0; len as usize];
+            stream.read_exact(&mut name).await?;
+            String::from_utf8_lossy(&name).into_owned()
+        }
+        4 => Ipv6Addr::from(read_array::<16>(&mut stream).await?).to_string(),
+        _ => {
+            reply(&mut stream, ADDRESS_NOT_SUPPORTED).await?;
+            return Err(invalid("unknown address type"));
+        }
+    };
+    let port = u16::from_be_bytes(read_array(&mut stream).await?);
+    if command != CONNECT {
+        reply(&mut stream, COMMAND_NOT_SUPPORTED).await?;
+        return Err(invalid("only CONNECT is supported"));
+    }
+
+    if settings.intercept_ports.contains(&port) {
+        tracing::info!(
+            "SOCKS5: serving {}:{} for {} from mocks",
+            host,
+            port,
+            remote
+        );
+        reply(&mut stream, SUCCEEDED).await?;
+        return connection::serve_stream(stream, remote, app)
+            .await
+            .map_err(io::Error::other);
+    }
+
+    tracing::info!("SOCKS5: tunneling {} to {}:{}", remote, host, port);
+    let mut upstream = match TcpStream::connect((host.as_str(), port)).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            reply(&mut stream, HOST_UNREACHABLE).await?;
+            return Err(e);
+        }
+    };
+    reply(&mut stream, SUCCEEDED).await?;
+    tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+    Ok(())
+}
+
+async fn read_array<const N: usize>(stream: &mut TcpStream) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    stream.read_exact(&mut bytes).await?;
+    Ok(bytes)
+}
+
+// The bound address is left as 0.0.0.0:0; clients don't use it for CONNECT
+async fn reply(stream: &mut TcpStream, code: u8) -> io::Result<()> {
+    stream
+        .write_all(&[VERSION, code, 0, 1, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
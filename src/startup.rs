@@ -0,0 +1,387 @@
+use hyper::{Body, Method, Request, Uri};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tokio::net::TcpListener;
+
+use crate::{systemd, upstream::HttpsClient, vhost, Settings};
+
+// How long one probe of a backend may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long `--wait-for-upstream` waits when no number of seconds is given
+const DEFAULT_UPSTREAM_WAIT: Duration = Duration::from_secs(60);
+
+// Why the server could not start. Printed to stderr as one line of JSON so
+// orchestrators and scripts can tell the cases apart without parsing logs.
+#[derive(Debug, Serialize)]
+pub struct StartupError {
+    pub error: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl StartupError {
+    // settings.json (or the copy in Redis) is unreadable or inconsistent
+    pub fn config(message: impl Into<String>) -> Self {
+        StartupError::new("config_invalid", message)
+    }
+
+    // Redis, the settings git repository or a webhook client could not be set up
+    pub fn dependency(message: impl Into<String>) -> Self {
+        StartupError::new("dependency_unavailable", message)
+    }
+
+    // A command-line argument that can't be understood
+    pub fn usage(message: impl Into<String>) -> Self {
+        StartupError::new("usage", message)
+    }
+
+    fn new(error: &'static str, message: impl Into<String>) -> Self {
+        StartupError {
+            error,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    fn with_details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+
+    // Exit codes follow sysexits.h
+    fn exit_code(&self) -> i32 {
+        match self.error {
+            "config_invalid" => 78, // EX_CONFIG
+            "bind_failed" => 71,    // EX_OSERR
+            "server_failed" => 70,  // EX_SOFTWARE
+            "usage" => 64,          // EX_USAGE
+            _ => 69,                // EX_UNAVAILABLE
+        }
+    }
+
+    pub fn exit(self) -> ! {
+        tracing::error!("{}", self.message);
+        eprintln!("{}", serde_json::to_string(&self).unwrap());
+        std::process::exit(self.exit_code())
+    }
+}
+
+// A listener that stopped serving after startup
+pub fn server_failed(addr: SocketAddr, e: impl std::fmt::Display) -> StartupError {
+    StartupError::new(
+        "server_failed",
+        format!("Listener on {} failed: {}", addr, e),
+    )
+    .with_details(serde_json::json!({ "addr": addr }))
+}
+
+// What `--check-upstream` and `--wait-for-upstream` ask for
+#[derive(Debug, Default)]
+pub struct UpstreamCheck {
+    // Waited for this long when set, otherwise probed once
+    pub wait: Option<Duration>,
+    pub enabled: bool,
+}
+
+impl UpstreamCheck {
+    // `--check-upstream` probes once; `--wait-for-upstream[=<seconds>]` retries
+    pub fn from_args(args: &[String]) -> Result<Self, StartupError> {
+        let mut check = UpstreamCheck::default();
+        for arg in args {
+            if arg == "--check-upstream" {
+                check.enabled = true;
+            } else if arg == "--wait-for-upstream" {
+                check.enabled = true;
+                check.wait = Some(DEFAULT_UPSTREAM_WAIT);
+            } else if let Some(secs) = arg.strip_prefix("--wait-for-upstream=") {
+                let secs: f64 = secs
+                    .parse()
+                    .ok()
+                    .filter(|s: &f64| *s >= 0.0)
+                    .ok_or_else(|| {
+                        StartupError::usage(format!(
+                            "--wait-for-upstream takes a number of seconds, not {}",
+                            secs
+                        ))
+                    })?;
+                check.enabled = true;
+                check.wait = Some(Duration::from_secs_f64(secs));
+            }
+        }
+        Ok(check)
+    }
+}
+
+// Problems the server would otherwise only hit (or panic on) later
+pub fn validate(settings: &Settings) -> Result<(), StartupError> {
+    let conflicts = crate::settings_route_conflicts(settings, settings.path_matching);
+    if !conflicts.is_empty() {
+        return Err(StartupError::config(format!(
+            "settings contain {} conflicting route(s)",
+            conflicts.len()
+        ))
+        .with_details(conflicts));
+    }
+
+    let mut problems = Vec::new();
+    let mut names = HashSet::new();
+    for workspace in std::iter::once(&settings.workspace).chain(&settings.workspaces) {
+        if !names.insert(workspace.name()) {
+            problems.push(format!(
+                "workspace {} is defined more than once",
+                workspace.name()
+            ));
+        }
+        let endpoints = std::iter::once(&workspace.endpoints)
+            .chain(workspace.profiles.values())
+            .chain(workspace.virtual_hosts.iter().map(|vhost| &vhost.endpoints))
+            .flatten();
+        for endpoint in endpoints {
+            let statuses = std::iter::once(endpoint.status)
+                .chain(endpoint.conditions.iter().filter_map(|c| c.status));
+            for status in statuses.filter(|status| !(100..=999).contains(status)) {
+                problems.push(format!(
+                    "status {} of {} {} in workspace {}: must be between 100 and 999",
+                    status,
+                    endpoint.method.to_ascii_uppercase(),
+                    endpoint.path,
+                    workspace.name()
+                ));
+            }
+        }
+        if let Some(backend) = &workspace.default_endpoint {
+            if let Err(e) = absolute_url(backend) {
+                problems.push(format!(
+                    "default_endpoint {} of workspace {}: {}",
+                    backend,
+                    workspace.name(),
+                    e
+                ));
+            }
+        }
+        for vhost in &workspace.virtual_hosts {
+            let Some(backend) = &vhost.default_endpoint else {
+                continue;
+            };
+            if let Err(e) = absolute_url(backend) {
+                problems.push(format!(
+                    "default_endpoint {} of virtual host {}: {}",
+                    backend,
+                    vhost.hosts.join(", "),
+                    e
+                ));
+            }
+        }
+        let hosts = workspace
+            .virtual_hosts
+            .iter()
+            .flat_map(|vhost| &vhost.hosts);
+        for host in hosts.filter(|host| !vhost::valid_host(host)) {
+            problems.push(format!(
+                "virtual host {} of workspace {}: a wildcard must be the whole first label, e.g. *.sandbox.local",
+                host,
+                workspace.name()
+            ));
+        }
+    }
+    let urls = settings
+        .alerts
+        .iter()
+        .map(|alerts| ("alerts.webhook", alerts.webhook.as_str()))
+        .chain(
+            settings
+                .change_webhook
+                .iter()
+                .map(|url| ("change_webhook", url.as_str())),
+        )
+        .chain(
+            settings
+                .config_source
+                .iter()
+                .map(|source| ("config_source", source.url())),
+        );
+    for (field, url) in urls {
+        if let Err(e) = absolute_url(url) {
+            problems.push(format!("{} {}: {}", field, url, e));
+        }
+    }
+    for mount in &settings.static_mounts {
+        let path = format!("/{}", mount.mount_path.trim_matches('/'));
+        if path == "/mockserver" || path.starts_with("/mockserver/") {
+            problems.push(format!(
+                "static mount_path {} is not allowed",
+                mount.mount_path
+            ));
+        } else if path == "/" && settings.base_path.is_none() {
+            problems.push("a static mount at / needs base_path".to_string());
+        }
+    }
+    match problems.len() {
+        0 => Ok(()),
+        1 => Err(StartupError::config(problems.remove(0))),
+        n => Err(
+            StartupError::config(format!("settings contain {} problems", n)).with_details(problems),
+        ),
+    }
+}
+
+fn absolute_url(url: &str) -> Result<(), String> {
+    let uri: Uri = url.parse().map_err(|e| format!("{}", e))?;
+    if uri.scheme().is_none() || uri.authority().is_none() {
+        return Err("not an absolute http(s) URL".to_string());
+    }
+    Ok(())
+}
+
+// A bound socket and the configured port whose workspaces it serves
+pub struct Listener {
+    pub addr: SocketAddr,
+    pub port: u16,
+    pub listener: TcpListener,
+    pub inherited: bool, // Passed in by systemd socket activation
+}
+
+// Binds every port before anything is served, so a port that is taken fails
+// startup as a whole instead of leaving a partly listening server. Sockets
+// from systemd are used for the ports they listen on; any on other ports
+// stand in for the main port.
+pub async fn bind(ports: &[u16]) -> Result<Vec<Listener>, StartupError> {
+    let mut inherited = Vec::new();
+    let fds = systemd::listen_fds().map_err(|e| bind_failed("systemd sockets", e))?;
+    for listener in fds {
+        let addr = listener
+            .local_addr()
+            .map_err(|e| bind_failed("systemd sockets", e))?;
+        let listener = TcpListener::from_std(listener).map_err(|e| bind_failed(addr, e))?;
+        inherited.push((addr, listener));
+    }
+
+    let mut listeners = Vec::new();
+    let mut unbound = Vec::new();
+    for &port in ports {
+        match inherited.iter().position(|(addr, _)| addr.port() == port) {
+            Some(i) => {
+                let (addr, listener) = inherited.remove(i);
+                listeners.push(Listener {
+                    addr,
+                    port,
+                    listener,
+                    inherited: true,
+                });
+            }
+            None => unbound.push(port),
+        }
+    }
+    let main_port = ports[0];
+    if !inherited.is_empty() {
+        unbound.retain(|port| *port != main_port);
+    }
+    for (addr, listener) in inherited {
+        listeners.push(Listener {
+            addr,
+            port: main_port,
+            listener,
+            inherited: true,
+        });
+    }
+    for port in unbound {
+        listeners.push(bind_port(port).await?);
+    }
+    Ok(listeners)
+}
+
+// Binds a port on all interfaces, e.g. for a raw TCP mock or the SOCKS proxy
+pub async fn bind_port(port: u16) -> Result<Listener, StartupError> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| bind_failed(addr, e))?;
+    Ok(Listener {
+        addr,
+        port,
+        listener,
+        inherited: false,
+    })
+}
+
+fn bind_failed(addr: impl Serialize + std::fmt::Display, e: std::io::Error) -> StartupError {
+    StartupError::new("bind_failed", format!("Failed to bind {}: {}", addr, e))
+        .with_details(serde_json::json!({ "addr": addr, "reason": e.to_string() }))
+}
+
+// Probes every default_endpoint until each answers or the wait runs out. Any
+// HTTP response counts, even an error status; only failing to connect doesn't.
+pub async fn check_upstreams(
+    settings: &Settings,
+    client: Option<&HttpsClient>,
+    check: &UpstreamCheck,
+) -> Result<(), StartupError> {
+    let Some(client) = client.filter(|_| check.enabled) else {
+        return Ok(());
+    };
+    let mut backends: Vec<&str> = std::iter::once(&settings.workspace)
+        .chain(&settings.workspaces)
+        .flat_map(|w| {
+            let vhosts = w
+                .virtual_hosts
+                .iter()
+                .map(|v| v.default_endpoint.as_deref());
+            std::iter::once(w.default_endpoint.as_deref()).chain(vhosts)
+        })
+        .flatten()
+        .collect();
+    backends.sort_unstable();
+    backends.dedup();
+    let deadline = Instant::now() + check.wait.unwrap_or_default();
+    let mut failures = Vec::new();
+    for backend in backends {
+        let mut attempt = 0u32;
+        loop {
+            attempt += 1;
+            match probe(client, backend).await {
+                Ok(status) => {
+                    tracing::info!("Backend {} answered {}", backend, status);
+                    break;
+                }
+                Err(e) if Instant::now() < deadline => {
+                    if attempt == 1 {
+                        tracing::info!("Waiting for backend {}: {}", backend, e);
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Err(e) => {
+                    failures.push(serde_json::json!({ "backend": backend, "reason": e }));
+                    break;
+                }
+            }
+        }
+    }
+    if failures.is_empty() {
+        return Ok(());
+    }
+    Err(StartupError::new(
+        "upstream_unavailable",
+        format!("{} backend(s) did not answer", failures.len()),
+    )
+    .with_details(failures))
+}
+
+async fn probe(client: &HttpsClient, backend: &str) -> Result<u16, String> {
+    let req = Request::builder()
+        .method(Method::GET)
+        .uri(backend)
+        .body(Body::empty())
+        .map_err(|e| e.to_string())?;
+    match tokio::time::timeout(PROBE_TIMEOUT, client.request(req)).await {
+        Ok(Ok(response)) => Ok(response.status().as_u16()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("no answer within {:?}", PROBE_TIMEOUT)),
+    }
+}
@@ -0,0 +1,86 @@
+use axum::{
+    body::{Body, BoxBody},
+    http::{Request, Response, StatusCode},
+    routing::{get_service, MethodRouter},
+};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, path::Path, sync::Arc};
+use tower::ServiceExt;
+use tower_http::services::{ServeDir, ServeFile};
+
+use crate::workspace;
+
+// A directory served as static files under `mount_path`, e.g. a frontend
+// build next to the API it talks to
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StaticMount {
+    pub mount_path: String,
+    pub directory: String,
+    // Serve index.html for paths with no file, for client-side routing
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub spa_fallback: bool,
+}
+
+impl StaticMount {
+    // The mount path normalized to "/app", or "/" at the root; panics on one
+    // that can't be mounted, as settings are checked at startup
+    pub fn path(&self) -> String {
+        let path = format!("/{}", self.mount_path.trim_matches('/'));
+        if path == "/mockserver" || path.starts_with("/mockserver/") {
+            panic!("static mount_path {} is not allowed", self.mount_path);
+        }
+        path
+    }
+
+    pub fn service<S: Clone + Send + Sync + 'static>(&self) -> MethodRouter<S> {
+        let dir = ServeDir::new(&self.directory);
+        if self.spa_fallback {
+            let index = ServeFile::new(Path::new(&self.directory).join("index.html"));
+            get_service(dir.fallback(index)).handle_error(io_error)
+        } else {
+            get_service(dir).handle_error(io_error)
+        }
+    }
+}
+
+async fn io_error(e: std::io::Error) -> (StatusCode, String) {
+    tracing::error!("Failed to serve static file: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Something went wrong..".to_string(),
+    )
+}
+
+// Sends requests outside `base_path` to a static mount at the root and the
+// rest to `mocks`, so a frontend and its mocked API can share one origin
+pub fn split_root<M>(
+    files: MethodRouter,
+    mocks: M,
+    base_path: Arc<str>,
+) -> impl tower::Service<
+    Request<Body>,
+    Response = Response<BoxBody>,
+    Error = Infallible,
+    Future = impl Send + 'static,
+> + Clone
+       + Send
+       + 'static
+where
+    M: tower::Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    M::Future: Send + 'static,
+{
+    tower::service_fn(move |req: Request<Body>| {
+        let outside = workspace::strip_path_prefix(&base_path, req.uri().path()).is_none();
+        let (files, mocks) = (files.clone(), mocks.clone());
+        async move {
+            if outside {
+                files.oneshot(req).await
+            } else {
+                mocks.oneshot(req).await
+            }
+        }
+    })
+}
@@ -0,0 +1,133 @@
+use futures_util::StreamExt;
+use redis::{aio::ConnectionManager, AsyncCommands, Client, RedisResult};
+use serde::{Deserialize, Serialize};
+use std::{future::Future, time::Duration};
+
+use crate::journal::{JournalEntry, JournalFilter};
+
+// Keeps settings and journals in Redis so replicas behind a load balancer
+// share one mock configuration
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RedisSettings {
+    pub url: String,
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String,
+}
+
+fn default_key_prefix() -> String {
+    "mockserver".to_string()
+}
+
+pub struct RedisStore {
+    client: Client,
+    conn: ConnectionManager,
+    prefix: String,
+    journal_capacity: usize,
+    // Published with each settings change so a replica can skip its own
+    instance_id: String,
+}
+
+impl RedisStore {
+    pub async fn connect(settings: &RedisSettings, journal_capacity: usize) -> RedisResult<Self> {
+        let client = Client::open(settings.url.as_str())?;
+        let conn = ConnectionManager::new(client.clone()).await?;
+        Ok(RedisStore {
+            client,
+            conn,
+            prefix: settings.key_prefix.clone(),
+            journal_capacity,
+            instance_id: format!(
+                "{}-{}",
+                std::process::id(),
+                chrono::Utc::now().timestamp_micros()
+            ),
+        })
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}:{}", self.prefix, name)
+    }
+
+    pub async fn load_settings(&self) -> RedisResult<Option<String>> {
+        self.conn.clone().get(self.key("settings")).await
+    }
+
+    // Stores the settings and tells the other replicas to reload them
+    pub async fn save_settings(&self, json: &str) -> RedisResult<()> {
+        let mut conn = self.conn.clone();
+        conn.set::<_, _, ()>(self.key("settings"), json).await?;
+        conn.publish::<_, _, ()>(self.key("settings-updates"), &self.instance_id)
+            .await
+    }
+
+    pub async fn push_journal(&self, workspace: &str, entry: &JournalEntry) -> RedisResult<()> {
+        let key = self.key(&format!("journal:{}", workspace));
+        let json = serde_json::to_string(entry).unwrap();
+        let mut conn = self.conn.clone();
+        conn.rpush::<_, _, ()>(&key, json).await?;
+        conn.ltrim(&key, -(self.journal_capacity as isize), -1)
+            .await
+    }
+
+    // Journal entries from every replica, oldest first
+    pub async fn journal(&self, workspace: &str) -> RedisResult<Vec<JournalEntry>> {
+        let key = self.key(&format!("journal:{}", workspace));
+        let entries: Vec<String> = self.conn.clone().lrange(key, 0, -1).await?;
+        Ok(entries
+            .iter()
+            .filter_map(|entry| serde_json::from_str(entry).ok())
+            .collect())
+    }
+
+    // Clears everything, or only the entries of one client
+    pub async fn clear_journal(&self, workspace: &str, filter: &JournalFilter) -> RedisResult<()> {
+        let key = self.key(&format!("journal:{}", workspace));
+        let mut conn = self.conn.clone();
+        if filter.is_empty() {
+            return conn.del(key).await;
+        }
+        let entries: Vec<String> = conn.lrange(&key, 0, -1).await?;
+        for json in entries {
+            let entry: Option<JournalEntry> = serde_json::from_str(&json).ok();
+            if entry.is_some_and(|entry| filter.selects(&entry)) {
+                conn.lrem::<_, _, ()>(&key, 1, json).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Runs `on_change` with the new settings whenever another replica saves
+    // them. Reconnects after errors and never returns.
+    pub async fn watch_settings<F, Fut>(&self, on_change: F)
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        loop {
+            if let Err(e) = self.watch_settings_once(&on_change).await {
+                tracing::error!("Lost Redis settings subscription: {}", e);
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn watch_settings_once<F, Fut>(&self, on_change: &F) -> RedisResult<()>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub.subscribe(self.key("settings-updates")).await?;
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let sender: String = message.get_payload()?;
+            if sender == self.instance_id {
+                continue;
+            }
+            if let Some(json) = self.load_settings().await? {
+                on_change(json).await;
+            }
+        }
+        Ok(())
+    }
+}
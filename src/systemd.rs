@@ -0,0 +1,71 @@
+use std::{
+    io,
+    net::TcpListener,
+    os::{fd::FromRawFd, unix::net::UnixDatagram},
+};
+
+// The first descriptor systemd passes (SD_LISTEN_FDS_START)
+const LISTEN_FDS_START: i32 = 3;
+
+// Listening sockets handed over by systemd socket activation, in the order
+// of the socket unit. Empty when the server wasn't socket-activated.
+pub fn listen_fds() -> io::Result<Vec<TcpListener>> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let count = std::env::var("LISTEN_FDS").ok();
+    // The variables describe the process systemd started; children must not
+    // pick them up
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+    let (Some(pid), Some(count)) = (pid, count) else {
+        return Ok(Vec::new());
+    };
+    if pid.parse() != Ok(std::process::id()) {
+        return Ok(Vec::new());
+    }
+    let count: i32 = count.parse().map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("LISTEN_FDS={}", count))
+    })?;
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // Safety: systemd passes these descriptors to this process only
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+            // Fails for anything but a TCP socket, e.g. a unix socket unit
+            listener.local_addr().map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("inherited fd {} is not a TCP socket: {}", fd, e),
+                )
+            })?;
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        })
+        .collect()
+}
+
+// Tells the service manager about the server's state, e.g. "READY=1", when
+// it runs as a `Type=notify` service; does nothing otherwise
+pub fn notify(state: &str) {
+    let Ok(path) = std::env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(&path, state) {
+        tracing::warn!("Failed to notify systemd at {}: {}", path, e);
+    }
+}
+
+fn send(path: &str, state: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    match path.strip_prefix('@') {
+        // A socket in the abstract namespace
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        None => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
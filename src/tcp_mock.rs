@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+use crate::{ip_filter::IpFilter, startup::Listener};
+
+// A listener for a simple line-based TCP protocol, such as an SMTP server
+// or a custom service, that answers with configured bytes instead of HTTP
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TcpMock {
+    pub port: u16,
+    // Sent as soon as a client connects, e.g. "220 mock ESMTP\r\n"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<String>,
+    // Checked in order against each line received
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<TcpRule>,
+    // Reply to lines no rule matches; without it they're ignored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_reply: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TcpRule {
+    // Matches lines starting with this; an empty prefix matches any line
+    #[serde(default)]
+    pub prefix: String,
+    pub reply: String,
+    // Close the connection after replying
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub close: bool,
+}
+
+impl TcpMock {
+    fn reply(&self, line: &str) -> Option<(&str, bool)> {
+        match self
+            .rules
+            .iter()
+            .find(|rule| line.starts_with(&rule.prefix))
+        {
+            Some(rule) => Some((&rule.reply, rule.close)),
+            None => self.default_reply.as_deref().map(|reply| (reply, false)),
+        }
+    }
+}
+
+// Serves `mock` on the listener bound for it at startup
+pub async fn serve(mock: TcpMock, bound: Listener, ip_filter: Arc<IpFilter>) {
+    let listener = bound.listener;
+    tracing::info!("Listening on {} (raw TCP)", bound.addr);
+    let mock = Arc::new(mock);
+    loop {
+        match listener.accept().await {
+            Ok((_, remote)) if !ip_filter.permits(remote.ip()) => {
+                tracing::warn!("Closed connection from {}: blocked by ip_filter", remote);
+            }
+            Ok((stream, remote)) => {
+                let mock = mock.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(&mock, stream).await {
+                        tracing::warn!("Raw TCP connection from {} failed: {}", remote, e);
+                    }
+                });
+            }
+            Err(e) => {
+                tracing::error!("Failed to accept connection: {}", e);
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+async fn handle(mock: &TcpMock, stream: TcpStream) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    if let Some(banner) = &mock.banner {
+        writer.write_all(banner.as_bytes()).await?;
+    }
+    let mut lines = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if lines.read_until(b'\n', &mut line).await? == 0 {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+        tracing::info!("Raw TCP request on port {}: {:?}", mock.port, text);
+        if let Some((reply, close)) = mock.reply(text) {
+            writer.write_all(reply.as_bytes()).await?;
+            if close {
+                return writer.shutdown().await;
+            }
+        }
+    }
+}
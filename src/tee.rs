@@ -0,0 +1,138 @@
+use futures_util::StreamExt;
+use hyper::{
+    body::{Bytes, HttpBody},
+    Body,
+};
+use std::sync::{Arc, Mutex};
+
+// How much of a streamed request body is kept for the journal and recordings
+pub const KEEP_BYTES: usize = 64 * 1024;
+
+// What was sent of a request body: all of it when it was read before
+// proxying, or the start of it when it was streamed to the backend
+#[derive(Clone)]
+pub enum SentBody {
+    Whole(Bytes),
+    Streamed(Arc<Mutex<Streamed>>),
+}
+
+#[derive(Default)]
+pub struct Streamed {
+    head: Vec<u8>,
+    len: usize,
+    too_large: bool,
+}
+
+impl SentBody {
+    // Everything kept so far; a streamed body may still be arriving
+    pub fn bytes(&self) -> Bytes {
+        match self {
+            SentBody::Whole(bytes) => bytes.clone(),
+            SentBody::Streamed(streamed) => Bytes::from(streamed.lock().unwrap().head.clone()),
+        }
+    }
+
+    // Whether streaming stopped because the body went over the limit
+    pub fn too_large(&self) -> bool {
+        match self {
+            SentBody::Whole(_) => false,
+            SentBody::Streamed(streamed) => streamed.lock().unwrap().too_large,
+        }
+    }
+}
+
+// Passes `body` on chunk by chunk, keeping only its first KEEP_BYTES, so
+// uploads of any size go through in constant memory. The stream fails once
+// more than `limit` bytes have arrived.
+pub fn tee(body: Body, limit: usize) -> (Body, SentBody) {
+    let streamed = Arc::new(Mutex::new(Streamed::default()));
+    let shared = streamed.clone();
+    let body = body.map(
+        move |chunk| -> Result<Bytes, Box<dyn std::error::Error + Send + Sync>> {
+            let chunk = chunk?;
+            let mut streamed = shared.lock().unwrap();
+            streamed.len += chunk.len();
+            if streamed.len > limit {
+                streamed.too_large = true;
+                return Err(format!("request body is larger than {} bytes", limit).into());
+            }
+            let room = KEEP_BYTES.saturating_sub(streamed.head.len());
+            streamed
+                .head
+                .extend_from_slice(&chunk[..room.min(chunk.len())]);
+            Ok(chunk)
+        },
+    );
+    (Body::wrap_stream(body), SentBody::Streamed(streamed))
+}
+
+// The largest body read whole to be patched, recorded or captured for a
+// scenario. Larger ones stream on untouched.
+pub const WHOLE_BYTES: usize = 16 * 1024 * 1024;
+
+// A body that was read if it was small enough, or streams on
+pub enum Peeked {
+    Whole(Bytes),
+    Streaming(Body),
+}
+
+// Reads `body` if it's no larger than `limit`. Reading stops as soon as it
+// goes over, and what was read is put back in front of the rest.
+pub async fn read_whole(mut body: Body, limit: usize) -> Result<Peeked, hyper::Error> {
+    if body.size_hint().lower() > limit as u64 {
+        return Ok(Peeked::Streaming(body));
+    }
+    let mut chunks = Vec::new();
+    let mut len = 0;
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        len += chunk.len();
+        chunks.push(chunk);
+        if len > limit {
+            let read = futures_util::stream::iter(chunks.into_iter().map(Ok));
+            return Ok(Peeked::Streaming(Body::wrap_stream(read.chain(body))));
+        }
+    }
+    Ok(Peeked::Whole(Bytes::from(chunks.concat())))
+}
+
+// Passes `body` on chunk by chunk while keeping a copy, and calls `done`
+// with it once the whole body went through, or with None once it's more than
+// `limit` bytes. `done` isn't called when the body fails or the client goes
+// away first.
+pub fn keep_copy(
+    body: Body,
+    limit: usize,
+    done: impl FnOnce(Option<Bytes>) + Send + 'static,
+) -> Body {
+    let copy = Some((Vec::new(), done));
+    let stream = futures_util::stream::unfold((body, copy), move |(mut body, mut copy)| {
+        async move {
+            let Some(chunk) = body.data().await else {
+                if let Some((kept, done)) = copy {
+                    done(Some(Bytes::from(kept)));
+                }
+                return None;
+            };
+            let Ok(chunk) = chunk else {
+                return Some((chunk, (body, None)));
+            };
+            if let Some((kept, _)) = &mut copy {
+                if kept.len() + chunk.len() > limit {
+                    let (_, done) = copy.take().unwrap();
+                    done(None);
+                } else {
+                    kept.extend_from_slice(&chunk);
+                }
+            }
+            // hyper stops asking once the Content-Length has been sent
+            if body.is_end_stream() {
+                if let Some((kept, done)) = copy.take() {
+                    done(Some(Bytes::from(kept)));
+                }
+            }
+            Some((Ok(chunk), (body, copy)))
+        }
+    });
+    Body::wrap_stream(stream)
+}
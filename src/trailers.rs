@@ -0,0 +1,343 @@
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use hyper::{
+    client::connect::{Connected, Connection},
+    Body, Response, Uri,
+};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tower::Service;
+
+use crate::{
+    connection::RawResponse,
+    tee::{self, Peeked},
+};
+
+// hyper only writes trailers over HTTP/2, so a mocked response with them is
+// written to the connection as a chunked HTTP/1.1 response of its own,
+// through `connection::RawResponse`. The connection is closed after it.
+pub fn chunked_response(
+    status: u16,
+    headers: Option<&HeaderMap>,
+    body: &[u8],
+    trailers: &BTreeMap<String, String>,
+) -> Vec<u8> {
+    let reason = StatusCode::from_u16(status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("");
+    let mut head = format!("HTTP/1.1 {} {}\r\n", status, reason);
+    for (name, value) in headers.into_iter().flatten() {
+        if [
+            header::CONTENT_LENGTH,
+            header::TRANSFER_ENCODING,
+            header::CONNECTION,
+            header::TRAILER,
+        ]
+        .contains(name)
+        {
+            continue;
+        }
+        head.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    let trailers: Vec<(&String, &String)> = trailers
+        .iter()
+        .filter(|(name, value)| {
+            let valid = header::HeaderName::from_bytes(name.as_bytes()).is_ok()
+                && header::HeaderValue::from_str(value).is_ok();
+            if !valid {
+                tracing::warn!("Skipping invalid trailer {}", name);
+            }
+            valid
+        })
+        .collect();
+    let names: Vec<&str> = trailers.iter().map(|(name, _)| name.as_str()).collect();
+    head.push_str(&format!(
+        "Transfer-Encoding: chunked\r\nTrailer: {}\r\nConnection: close\r\n\r\n",
+        names.join(", ")
+    ));
+
+    let mut bytes = head.into_bytes();
+    if !body.is_empty() {
+        bytes.extend_from_slice(format!("{:x}\r\n", body.len()).as_bytes());
+        bytes.extend_from_slice(body);
+        bytes.extend_from_slice(b"\r\n");
+    }
+    bytes.extend_from_slice(b"0\r\n");
+    for (name, value) in trailers {
+        bytes.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+    }
+    bytes.extend_from_slice(b"\r\n");
+    bytes
+}
+
+// hyper's HTTP/1 client reads past the trailers of a chunked response without
+// keeping them, so a request that wants them is sent on a connection of its
+// own that picks them out of the bytes as they arrive. `receive` gives the
+// request a place to put them, and they're there once its body has been read.
+#[derive(Clone, Default)]
+pub struct Received(Arc<Mutex<Option<HeaderMap>>>);
+
+impl Received {
+    // Each field once, with repeated ones joined
+    pub fn take(&self) -> BTreeMap<String, String> {
+        let mut fields = BTreeMap::new();
+        let Some(received) = self.0.lock().unwrap().take() else {
+            return fields;
+        };
+        for (name, value) in &received {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            fields
+                .entry(name.to_string())
+                .and_modify(|joined: &mut String| {
+                    joined.push_str(", ");
+                    joined.push_str(&value);
+                })
+                .or_insert_with(|| value.to_string());
+        }
+        fields
+    }
+}
+
+tokio::task_local! {
+    static RECEIVING: Received;
+}
+
+// Runs `sending`, putting the trailers of the response it gets, if it's read
+// over a `Capturing` connection, in the returned `Received`
+pub async fn receive<F: Future>(sending: F) -> (F::Output, Received) {
+    let received = Received::default();
+    let output = RECEIVING.scope(received.clone(), sending).await;
+    (output, received)
+}
+
+// A connector whose connections look for trailers in what they read. Its
+// client mustn't keep idle connections, so each carries one request.
+#[derive(Clone)]
+pub struct Capturing<S>(pub S);
+
+impl<S> Service<Uri> for Capturing<S>
+where
+    S: Service<Uri>,
+    S::Future: Send + 'static,
+{
+    type Response = Captured<S::Response>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connecting = self.0.call(uri);
+        Box::pin(async move {
+            let stream = connecting.await?;
+            // Connections are opened for the request waiting on them
+            let received = RECEIVING.try_with(Received::clone).ok();
+            Ok(Captured {
+                stream,
+                reader: received.map(Reader::new),
+            })
+        })
+    }
+}
+
+pub struct Captured<S> {
+    stream: S,
+    reader: Option<Reader>,
+}
+
+// Longest line looked at; the response is left alone past it
+const MAX_LINE: usize = 64 * 1024;
+
+// Follows the framing of a response: its head, then the chunks of a chunked
+// body, then the trailer fields after the last one
+struct Reader {
+    received: Received,
+    line: Vec<u8>,
+    state: Reading,
+    status: Option<u16>,
+    chunked: bool,
+    fields: HeaderMap,
+}
+
+enum Reading {
+    Head,
+    Size,
+    Data(u64), // Bytes left in the chunk, its CRLF included
+    Fields,
+    Done,
+}
+
+impl Reader {
+    fn new(received: Received) -> Self {
+        Reader {
+            received,
+            line: Vec::new(),
+            state: Reading::Head,
+            status: None,
+            chunked: false,
+            fields: HeaderMap::new(),
+        }
+    }
+
+    fn read(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            match &mut self.state {
+                Reading::Done => return,
+                Reading::Data(left) => {
+                    let skipped = (*left).min(bytes.len() as u64);
+                    *left -= skipped;
+                    bytes = &bytes[skipped as usize..];
+                    if *left == 0 {
+                        self.state = Reading::Size;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            let end = bytes.iter().position(|&b| b == b'\n');
+            let (part, rest) = bytes.split_at(end.map_or(bytes.len(), |i| i + 1));
+            self.line.extend_from_slice(part);
+            bytes = rest;
+            if self.line.len() > MAX_LINE {
+                self.state = Reading::Done;
+            } else if end.is_some() {
+                let line = std::mem::take(&mut self.line);
+                self.line(String::from_utf8_lossy(&line).trim_end());
+            }
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        match self.state {
+            Reading::Head if self.status.is_none() => {
+                self.status = line
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|code| code.parse().ok());
+            }
+            Reading::Head if line.is_empty() => {
+                self.state = match self.status.take() {
+                    // An interim response; the real one follows
+                    Some(100..=199) if !self.chunked => Reading::Head,
+                    _ if self.chunked => Reading::Size,
+                    _ => Reading::Done,
+                };
+            }
+            Reading::Head => {
+                if let Some((name, value)) = line.split_once(':') {
+                    self.chunked |= name.trim().eq_ignore_ascii_case("transfer-encoding")
+                        && value.to_ascii_lowercase().contains("chunked");
+                }
+            }
+            Reading::Size => {
+                let size = line.split(';').next().unwrap_or_default().trim();
+                self.state = match u64::from_str_radix(size, 16) {
+                    Ok(0) => Reading::Fields,
+                    Ok(size) => Reading::Data(size.saturating_add(2)),
+                    Err(_) => Reading::Done,
+                };
+            }
+            Reading::Fields if line.is_empty() => {
+                *self.received.0.lock().unwrap() = Some(std::mem::take(&mut self.fields));
+                self.state = Reading::Done;
+            }
+            Reading::Fields => {
+                let field = line.split_once(':').and_then(|(name, value)| {
+                    Some((
+                        HeaderName::from_bytes(name.trim().as_bytes()).ok()?,
+                        HeaderValue::from_str(value.trim()).ok()?,
+                    ))
+                });
+                if let Some((name, value)) = field {
+                    self.fields.append(name, value);
+                }
+            }
+            Reading::Data(_) | Reading::Done => {}
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Captured<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.stream).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(reader)) = (&result, &mut this.reader) {
+            reader.read(&buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Captured<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+impl<S: Connection> Connection for Captured<S> {
+    fn connected(&self) -> Connected {
+        self.stream.connected()
+    }
+}
+
+// Writes a proxied response along with the trailers its backend sent, in
+// the same way as a mocked one with trailers. The body has to be read first,
+// as the trailers come after it; one too large to read is passed on without
+// them.
+pub async fn pass_on(
+    response: Response<Body>,
+    received: Received,
+    raw: &RawResponse,
+) -> hyper::Result<Response<Body>> {
+    let (parts, body) = response.into_parts();
+    let body = match tee::read_whole(body, tee::WHOLE_BYTES).await? {
+        Peeked::Whole(body) => body,
+        Peeked::Streaming(body) => {
+            tracing::warn!(
+                "Backend response is larger than {} bytes, passing it on without trailers",
+                tee::WHOLE_BYTES
+            );
+            return Ok(Response::from_parts(parts, body));
+        }
+    };
+    let trailers = received.take();
+    if !trailers.is_empty() {
+        raw.send(chunked_response(
+            parts.status.as_u16(),
+            Some(&parts.headers),
+            &body,
+            &trailers,
+        ));
+    }
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
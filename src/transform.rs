@@ -0,0 +1,413 @@
+use axum::{
+    body::Body,
+    http::{header, request, HeaderName, HeaderValue, Method, Request, Response, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+use crate::tee::{self, Peeked};
+
+// Changes made to the real backend's response for an endpoint that proxies
+// instead of mocking, e.g. to force one field of otherwise real data
+#[derive(Debug, Deserialize, Serialize, Clone, Default, ToSchema)]
+pub struct ResponseTransform {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    // Set or replace these headers
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove_headers: Vec<String>,
+    // RFC 7386 JSON merge patch applied to the body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub merge_patch: Option<serde_json::Value>,
+    // RFC 6902 JSON patch applied to the body, after `merge_patch`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Vec<Object>>)]
+    pub json_patch: Option<json_patch::Patch>,
+}
+
+impl ResponseTransform {
+    // Whether the body has to be read and parsed as JSON
+    pub fn edits_body(&self) -> bool {
+        self.merge_patch.is_some() || self.json_patch.is_some()
+    }
+
+    // The error is returned to the client as a 502
+    pub async fn apply(&self, response: Response<Body>) -> Result<Response<Body>, String> {
+        let (mut parts, body) = response.into_parts();
+
+        if let Some(status) = self.status {
+            parts.status = StatusCode::from_u16(status)
+                .map_err(|_| format!("Invalid transform status: {}", status))?;
+        }
+        for name in &self.remove_headers {
+            parts.headers.remove(name.as_str());
+        }
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("Invalid header name: {}", name))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| format!("Invalid value for header {}", name))?;
+            parts.headers.insert(name, value);
+        }
+
+        if !self.edits_body() {
+            return Ok(Response::from_parts(parts, body));
+        }
+
+        let peeked = tee::read_whole(body, tee::WHOLE_BYTES)
+            .await
+            .map_err(|e| format!("Failed to read backend response: {}", e))?;
+        let Peeked::Whole(bytes) = peeked else {
+            return Err(format!(
+                "Backend response is larger than {} bytes, too large to patch",
+                tee::WHOLE_BYTES
+            ));
+        };
+        let mut json: serde_json::Value = serde_json::from_slice(&bytes)
+            .map_err(|e| format!("Backend response isn't JSON: {}", e))?;
+        if let Some(patch) = &self.merge_patch {
+            json_patch::merge(&mut json, patch);
+        }
+        if let Some(patch) = &self.json_patch {
+            json_patch::patch(&mut json, patch)
+                .map_err(|e| format!("Failed to apply JSON patch: {}", e))?;
+        }
+
+        // The body changed size, so hyper recomputes the length
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.remove(header::TRANSFER_ENCODING);
+        Ok(Response::from_parts(parts, Body::from(json.to_string())))
+    }
+}
+
+// Changes made to every proxied response a rule matches, without mocking
+// the endpoint, e.g. a fallback body when search fails or dropping
+// Strict-Transport-Security in development
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ResponseRewrite {
+    // Only requests whose path starts with this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    // Only backend responses with one of these statuses; any when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub statuses: Vec<u16>,
+    // Replaces the body: a string as is, anything else as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    // For `payload`; application/json by default, text/plain for strings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    // status, headers, remove_headers and the patches, applied after
+    // `payload`
+    #[serde(flatten)]
+    pub changes: ResponseTransform,
+}
+
+impl ResponseRewrite {
+    pub fn applies_to(&self, method: &Method, path: &str) -> bool {
+        let path_matches = self
+            .path_prefix
+            .as_deref()
+            .is_none_or(|prefix| path.starts_with(prefix));
+        let method_matches = self
+            .method
+            .as_deref()
+            .is_none_or(|m| method.as_str().eq_ignore_ascii_case(m));
+        path_matches && method_matches
+    }
+
+    // Whether the backend's body has to be parsed, so it mustn't be
+    // compressed
+    pub fn edits_body(&self) -> bool {
+        self.payload.is_none() && self.changes.edits_body()
+    }
+
+    async fn apply(&self, response: Response<Body>) -> Result<Response<Body>, String> {
+        let response = match &self.payload {
+            Some(payload) => {
+                let (mut parts, _) = response.into_parts();
+                let (body, content_type) = match payload {
+                    serde_json::Value::String(text) => (text.clone(), "text/plain"),
+                    json => (json.to_string(), "application/json"),
+                };
+                let content_type = self.content_type.as_deref().unwrap_or(content_type);
+                let content_type = HeaderValue::from_str(content_type)
+                    .map_err(|_| format!("Invalid content_type: {}", content_type))?;
+                parts.headers.insert(header::CONTENT_TYPE, content_type);
+                parts.headers.remove(header::CONTENT_ENCODING);
+                parts.headers.remove(header::CONTENT_LENGTH);
+                parts.headers.remove(header::TRANSFER_ENCODING);
+                Response::from_parts(parts, Body::from(body))
+            }
+            None => response,
+        };
+        self.changes.apply(response).await
+    }
+}
+
+// Applies every rule that matches the request and the backend's status, in
+// order; a rule that can't be applied makes the response a 502
+pub async fn rewrite_response(
+    rules: &[&ResponseRewrite],
+    mut response: Response<Body>,
+    path: &str,
+) -> Response<Body> {
+    let status = response.status().as_u16();
+    let mut applied = 0;
+    for rule in rules {
+        if !rule.statuses.is_empty() && !rule.statuses.contains(&status) {
+            continue;
+        }
+        response = match rule.apply(response).await {
+            Ok(response) => response,
+            Err(message) => {
+                tracing::warn!("Failed to rewrite response for {}: {}", path, message);
+                return bad_gateway(message);
+            }
+        };
+        applied += 1;
+    }
+    if applied > 0 {
+        tracing::info!(
+            "Rewrote {} response for {} with {} rule(s)",
+            status,
+            path,
+            applied
+        );
+    }
+    response
+}
+
+pub fn bad_gateway(message: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::BAD_GATEWAY)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+// Changes made to a request before it's proxied to the backend, e.g. to swap
+// an account id or add a test flag. Strings in `headers`, `merge_patch` and
+// `json_patch` are templates; see `RequestContext`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RequestRewrite {
+    // Only requests whose path starts with this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove_headers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_patch: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_patch: Option<json_patch::Patch>,
+}
+
+impl RequestRewrite {
+    fn applies_to(&self, parts: &request::Parts) -> bool {
+        let path_matches = self
+            .path_prefix
+            .as_deref()
+            .is_none_or(|prefix| parts.uri.path().starts_with(prefix));
+        let method_matches = self
+            .method
+            .as_deref()
+            .is_none_or(|method| parts.method.as_str().eq_ignore_ascii_case(method));
+        path_matches && method_matches
+    }
+
+    fn edits_body(&self) -> bool {
+        self.merge_patch.is_some() || self.json_patch.is_some()
+    }
+}
+
+// What templates in a rewrite can refer to: {{method}}, {{path}},
+// {{query.NAME}}, {{header.NAME}} and {{body.FIELD}} where FIELD is a dotted
+// path into a JSON body, e.g. {{body.account.id}}
+pub struct RequestContext<'a> {
+    pub parts: &'a request::Parts,
+    pub body: Option<&'a serde_json::Value>,
+}
+
+impl RequestContext<'_> {
+    pub fn fill_str(&self, template: &str) -> String {
+        fill_str(template, &|name| self.lookup(name))
+    }
+
+    pub fn fill(&self, value: &serde_json::Value) -> serde_json::Value {
+        fill(value, &|name| self.lookup(name))
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<serde_json::Value> {
+        let text = |s: &str| Some(serde_json::Value::String(s.to_string()));
+        match name.split_once('.') {
+            None if name == "method" => text(self.parts.method.as_str()),
+            None if name == "path" => text(self.parts.uri.path()),
+            Some(("query", key)) => self
+                .parts
+                .uri
+                .query()?
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(k, _)| *k == key)
+                .and_then(|(_, v)| text(v)),
+            Some(("header", key)) => text(self.parts.headers.get(key)?.to_str().ok()?),
+            Some(("body", field)) => {
+                let pointer = format!("/{}", field.replace('.', "/"));
+                self.body?.pointer(&pointer).cloned()
+            }
+            _ => None,
+        }
+    }
+}
+
+// Fills in {{...}} placeholders with what `lookup` finds for their names;
+// unknown ones are left as they are
+pub fn fill_str(template: &str, lookup: &dyn Fn(&str) -> Option<serde_json::Value>) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + end].trim();
+        out.push_str(&rest[..start]);
+        match lookup(name) {
+            Some(serde_json::Value::String(s)) => out.push_str(&s),
+            Some(value) => out.push_str(&value.to_string()),
+            None => out.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// Fills in the strings in `value`. A string that is exactly one placeholder
+// takes the looked-up JSON value, so numbers stay numbers.
+pub fn fill(
+    value: &serde_json::Value,
+    lookup: &dyn Fn(&str) -> Option<serde_json::Value>,
+) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) => {
+            let trimmed = s.trim();
+            let whole = trimmed
+                .strip_prefix("{{")
+                .and_then(|s| s.strip_suffix("}}"))
+                .filter(|name| !name.contains("{{"));
+            match whole {
+                Some(name) => lookup(name.trim()).unwrap_or_else(|| value.clone()),
+                None => serde_json::Value::String(fill_str(s, lookup)),
+            }
+        }
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.iter().map(|v| fill(v, lookup)).collect())
+        }
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), fill(v, lookup)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+// Applies every matching rule, in order
+pub async fn rewrite_request(
+    rules: &[RequestRewrite],
+    req: Request<Body>,
+) -> Result<Request<Body>, hyper::Error> {
+    let (mut parts, body) = req.into_parts();
+    let rules: Vec<&RequestRewrite> = rules.iter().filter(|r| r.applies_to(&parts)).collect();
+    if rules.is_empty() {
+        return Ok(Request::from_parts(parts, body));
+    }
+
+    // Only body edits need the body read, and only one small enough to
+    // patch; otherwise it streams through
+    let (body, mut json) = if rules.iter().any(|r| r.edits_body()) {
+        match tee::read_whole(body, tee::WHOLE_BYTES).await? {
+            Peeked::Whole(bytes) => {
+                let json = serde_json::from_slice::<serde_json::Value>(&bytes).ok();
+                (Body::from(bytes), json)
+            }
+            Peeked::Streaming(body) => {
+                tracing::warn!(
+                    "Not rewriting the body of {}: larger than {} bytes",
+                    parts.uri.path(),
+                    tee::WHOLE_BYTES
+                );
+                (body, None)
+            }
+        }
+    } else {
+        (body, None)
+    };
+    let original_json = json.clone();
+
+    for rule in rules {
+        let context = RequestContext {
+            parts: &parts,
+            body: original_json.as_ref(),
+        };
+        let mut headers = Vec::new();
+        for (name, template) in &rule.headers {
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&context.fill_str(template)),
+            ) {
+                (Ok(name), Ok(value)) => headers.push((name, value)),
+                _ => tracing::warn!("Skipping invalid rewrite header {}", name),
+            }
+        }
+
+        match json.as_mut() {
+            Some(json) => {
+                if let Some(patch) = &rule.merge_patch {
+                    json_patch::merge(json, &context.fill(patch));
+                }
+                if let Some(patch) = &rule.json_patch {
+                    let filled = context.fill(&serde_json::to_value(patch).unwrap());
+                    let result = serde_json::from_value::<json_patch::Patch>(filled)
+                        .map_err(|e| e.to_string())
+                        .and_then(|patch| {
+                            json_patch::patch(json, &patch).map_err(|e| e.to_string())
+                        });
+                    if let Err(e) = result {
+                        tracing::warn!("Failed to rewrite request body: {}", e);
+                    }
+                }
+            }
+            None if rule.edits_body() => {
+                tracing::warn!("Not rewriting {}: body isn't JSON", parts.uri.path())
+            }
+            None => {}
+        }
+
+        for name in &rule.remove_headers {
+            parts.headers.remove(name.as_str());
+        }
+        for (name, value) in headers {
+            parts.headers.insert(name, value);
+        }
+    }
+
+    let body = match json {
+        Some(json) if Some(&json) != original_json.as_ref() => {
+            // The body changed size, so hyper recomputes the length
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(json.to_string())
+        }
+        _ => body,
+    };
+    Ok(Request::from_parts(parts, body))
+}
@@ -0,0 +1,381 @@
+use chrono::{DateTime, Local};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, IsTerminal},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::{journal::JournalEntry, startup::StartupError};
+
+// Kept for the live request table and the log pane
+const REQUESTS: usize = 500;
+const LOG_LINES: usize = 500;
+
+// How often the screen is redrawn while nothing is pressed
+const REDRAW: Duration = Duration::from_millis(250);
+
+// A terminal dashboard of live requests, how each was answered and counts
+// per endpoint, shown with --tui instead of the log. The log moves to a pane
+// at the bottom.
+#[derive(Default)]
+pub struct Dashboard {
+    traffic: Mutex<Traffic>,
+    log: Mutex<VecDeque<String>>,
+}
+
+#[derive(Default)]
+struct Traffic {
+    requests: VecDeque<Seen>, // Newest first
+    // (workspace, what answered) => counts
+    counters: BTreeMap<(String, String), Counter>,
+    mocked: u64,
+    proxied: u64,
+    unmatched: u64,
+}
+
+struct Seen {
+    time: DateTime<Local>,
+    workspace: String,
+    method: String,
+    path: String,
+    status: u16,
+    answer: Answer,
+    duration_ms: u64,
+}
+
+// How a request was answered: by an endpoint, a backend or neither
+enum Answer {
+    Mocked(String),
+    Proxied(String),
+    Unmatched,
+}
+
+impl Answer {
+    fn of(entry: &JournalEntry) -> Answer {
+        match (&entry.matched, &entry.proxied_to) {
+            (Some(endpoint), _) => Answer::Mocked(endpoint.clone()),
+            (None, Some(backend)) => Answer::Proxied(backend.clone()),
+            (None, None) => Answer::Unmatched,
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Answer::Mocked(endpoint) => endpoint.clone(),
+            Answer::Proxied(backend) => format!("-> {}", backend),
+            Answer::Unmatched => "unmatched".to_string(),
+        }
+    }
+
+    fn style(&self) -> Style {
+        match self {
+            Answer::Mocked(_) => Style::default().fg(Color::Cyan),
+            Answer::Proxied(_) => Style::default().fg(Color::Magenta),
+            Answer::Unmatched => Style::default().fg(Color::DarkGray),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counter {
+    requests: u64,
+    client_errors: u64,
+    server_errors: u64,
+    total_ms: u64,
+}
+
+impl Dashboard {
+    // With --tui; the dashboard needs a terminal to draw on
+    pub fn from_args(args: &[String]) -> Result<Option<Arc<Dashboard>>, StartupError> {
+        if !args.iter().any(|arg| arg == "--tui") {
+            return Ok(None);
+        }
+        if !io::stdout().is_terminal() {
+            return Err(StartupError::usage("--tui needs a terminal"));
+        }
+        Ok(Some(Arc::new(Dashboard::default())))
+    }
+
+    // Counts a finished request and adds it to the live table
+    pub fn request(&self, workspace: &str, entry: &JournalEntry) {
+        let answer = Answer::of(entry);
+        let mut traffic = self.traffic.lock().unwrap();
+        match answer {
+            Answer::Mocked(_) => traffic.mocked += 1,
+            Answer::Proxied(_) => traffic.proxied += 1,
+            Answer::Unmatched => traffic.unmatched += 1,
+        }
+        let counter = traffic
+            .counters
+            .entry((workspace.to_string(), answer.label()))
+            .or_default();
+        counter.requests += 1;
+        counter.total_ms += entry.duration_ms;
+        match entry.status {
+            400..=499 => counter.client_errors += 1,
+            500.. => counter.server_errors += 1,
+            _ => {}
+        }
+        if traffic.requests.len() >= REQUESTS {
+            traffic.requests.pop_back();
+        }
+        traffic.requests.push_front(Seen {
+            time: entry.timestamp.with_timezone(&Local),
+            workspace: workspace.to_string(),
+            method: entry.method.clone(),
+            path: entry.path.clone(),
+            status: entry.status,
+            answer,
+            duration_ms: entry.duration_ms,
+        });
+    }
+
+    fn log_line(&self, line: &str) {
+        let mut log = self.log.lock().unwrap();
+        if log.len() >= LOG_LINES {
+            log.pop_front();
+        }
+        log.push_back(line.to_string());
+    }
+
+    fn draw(&self, frame: &mut Frame, ports: &[u16]) {
+        let [header, middle, log] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(6),
+            Constraint::Length(8),
+        ])
+        .areas(frame.area());
+        let [requests, counters] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(middle);
+
+        let traffic = self.traffic.lock().unwrap();
+        let ports: Vec<String> = ports.iter().map(|port| format!(":{}", port)).collect();
+        let total = traffic.mocked + traffic.proxied + traffic.unmatched;
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        frame.render_widget(
+            Line::from(vec![
+                Span::styled(format!(" mock-api {} ", ports.join(" ")), bold),
+                Span::raw(format!(" {} requests: ", total)),
+                Span::styled(
+                    format!("{} mocked", traffic.mocked),
+                    Answer::Mocked(String::new()).style(),
+                ),
+                Span::raw(", "),
+                Span::styled(
+                    format!("{} proxied", traffic.proxied),
+                    Answer::Proxied(String::new()).style(),
+                ),
+                Span::raw(", "),
+                Span::styled(
+                    format!("{} unmatched", traffic.unmatched),
+                    Answer::Unmatched.style(),
+                ),
+                Span::raw("   q quit, r reset counters"),
+            ]),
+            header,
+        );
+        self.draw_requests(frame, requests, &traffic);
+        self.draw_counters(frame, counters, &traffic);
+        drop(traffic);
+        self.draw_log(frame, log);
+    }
+
+    fn draw_requests(&self, frame: &mut Frame, area: Rect, traffic: &Traffic) {
+        let rows = traffic
+            .requests
+            .iter()
+            .take(area.height.saturating_sub(3) as usize)
+            .map(|seen| {
+                Row::new(vec![
+                    Span::raw(seen.time.format("%H:%M:%S").to_string()),
+                    Span::raw(seen.workspace.clone()),
+                    Span::raw(format!("{} {}", seen.method, seen.path)),
+                    Span::styled(seen.status.to_string(), status_style(seen.status)),
+                    Span::styled(seen.answer.label(), seen.answer.style()),
+                    Span::raw(format!("{}ms", seen.duration_ms)),
+                ])
+            });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(10),
+                Constraint::Fill(2),
+                Constraint::Length(3),
+                Constraint::Fill(1),
+                Constraint::Length(7),
+            ],
+        )
+        .header(header_row(&[
+            "Time",
+            "Workspace",
+            "Request",
+            "",
+            "Answered by",
+            "Took",
+        ]))
+        .block(Block::bordered().title(" Live requests "));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_counters(&self, frame: &mut Frame, area: Rect, traffic: &Traffic) {
+        let mut counters: Vec<_> = traffic.counters.iter().collect();
+        counters.sort_by_key(|(_, counter)| std::cmp::Reverse(counter.requests));
+        let rows = counters
+            .into_iter()
+            .take(area.height.saturating_sub(3) as usize)
+            .map(|((workspace, label), counter)| {
+                let errors = |count: u64, color: Color| {
+                    let style = match count {
+                        0 => Style::default().fg(Color::DarkGray),
+                        _ => Style::default().fg(color),
+                    };
+                    Span::styled(count.to_string(), style)
+                };
+                Row::new(vec![
+                    Span::raw(format!("{} {}", workspace, label)),
+                    Span::raw(counter.requests.to_string()),
+                    errors(counter.client_errors, Color::Yellow),
+                    errors(counter.server_errors, Color::Red),
+                    Span::raw(format!("{}ms", counter.total_ms / counter.requests)),
+                ])
+            });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(6),
+                Constraint::Length(5),
+                Constraint::Length(5),
+                Constraint::Length(7),
+            ],
+        )
+        .header(header_row(&["Endpoint", "Count", "4xx", "5xx", "Avg"]))
+        .block(Block::bordered().title(" Per endpoint "));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_log(&self, frame: &mut Frame, area: Rect) {
+        let log = self.log.lock().unwrap();
+        let shown = area.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = log
+            .iter()
+            .skip(log.len().saturating_sub(shown))
+            .map(|line| Line::raw(line.clone()))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Log ")),
+            area,
+        );
+    }
+}
+
+fn header_row(titles: &[&'static str]) -> Row<'static> {
+    Row::new(titles.to_vec()).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+fn status_style(status: u16) -> Style {
+    match status {
+        200..=299 => Style::default().fg(Color::Green),
+        300..=399 => Style::default().fg(Color::Blue),
+        400..=499 => Style::default().fg(Color::Yellow),
+        _ => Style::default().fg(Color::Red),
+    }
+}
+
+// Takes over the terminal until q, Esc or Ctrl-C is pressed, which stops
+// the server
+pub fn spawn(dashboard: Arc<Dashboard>, ports: Vec<u16>) {
+    std::thread::spawn(move || {
+        let mut terminal = ratatui::init();
+        let result = run(&mut terminal, &dashboard, &ports);
+        ratatui::restore();
+        if let Err(e) = result {
+            eprintln!("Dashboard failed: {}", e);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    });
+}
+
+// Gives the terminal back, e.g. before a fatal error is printed
+pub fn restore() {
+    ratatui::restore();
+}
+
+fn run(terminal: &mut DefaultTerminal, dashboard: &Dashboard, ports: &[u16]) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| dashboard.draw(frame, ports))?;
+        if !event::poll(REDRAW)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Char('r') => {
+                let mut traffic = dashboard.traffic.lock().unwrap();
+                *traffic = Traffic {
+                    requests: std::mem::take(&mut traffic.requests),
+                    ..Traffic::default()
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+// Where log lines go while the dashboard is shown
+pub struct LogPane(pub Arc<Dashboard>);
+
+pub struct LogWriter<'a> {
+    dashboard: &'a Dashboard,
+    buffer: Vec<u8>,
+}
+
+impl<'a> MakeWriter<'a> for LogPane {
+    type Writer = LogWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogWriter {
+            dashboard: &self.0,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl io::Write for LogWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Each event is written in pieces, so lines are only added once it's done
+impl Drop for LogWriter<'_> {
+    fn drop(&mut self) {
+        for line in String::from_utf8_lossy(&self.buffer).lines() {
+            self.dashboard.log_line(line);
+        }
+    }
+}
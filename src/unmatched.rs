@@ -0,0 +1,121 @@
+use axum::{
+    body::Body,
+    http::{header, Method, Response, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// What happens to requests no endpoint matches, optionally per path prefix
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct UnmatchedSettings {
+    #[serde(flatten)]
+    pub default: UnmatchedAction,
+    // Path prefix => action, the longest matching prefix wins
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prefixes: BTreeMap<String, UnmatchedAction>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum UnmatchedAction {
+    // Forward to the workspace's default backend
+    #[default]
+    Proxy,
+    // 404 with a body in which {{method}} and {{path}} are filled in
+    NotFound {
+        #[serde(default = "default_not_found_body")]
+        body: String,
+    },
+    // A fixed response, templated like `not_found`
+    Mock {
+        status: u16,
+        content_type: String,
+        payload: serde_json::Value,
+    },
+    // 500 and an error in the log, for test runs that must not reach a backend
+    Fail,
+}
+
+fn default_not_found_body() -> String {
+    "No mock for {{method}} {{path}}".to_string()
+}
+
+impl UnmatchedSettings {
+    pub fn is_default(&self) -> bool {
+        matches!(self.default, UnmatchedAction::Proxy) && self.prefixes.is_empty()
+    }
+
+    pub fn action(&self, path: &str) -> &UnmatchedAction {
+        self.prefixes
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, action)| action)
+            .unwrap_or(&self.default)
+    }
+}
+
+impl UnmatchedAction {
+    // The response for an unmatched request, or None to proxy it
+    pub fn respond(&self, method: &Method, path: &str) -> Option<Response<Body>> {
+        let fill = |text: &str| {
+            text.replace("{{method}}", method.as_str())
+                .replace("{{path}}", path)
+        };
+        match self {
+            UnmatchedAction::Proxy => None,
+            UnmatchedAction::NotFound { body } => Some(not_found_with(fill(body))),
+            UnmatchedAction::Mock {
+                status,
+                content_type,
+                payload,
+            } => {
+                let body = match payload {
+                    serde_json::Value::String(s) => fill(s),
+                    _ => fill(&payload.to_string()),
+                };
+                Some(
+                    Response::builder()
+                        .status(StatusCode::from_u16(*status).unwrap_or(StatusCode::OK))
+                        .header(header::CONTENT_TYPE, content_type)
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+            }
+            UnmatchedAction::Fail => {
+                tracing::error!(
+                    "Unmatched request {} {} with unmatched policy \"fail\"",
+                    method,
+                    path
+                );
+                Some(
+                    Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .header(header::CONTENT_TYPE, "text/plain")
+                        .body(Body::from(format!(
+                            "No mock endpoint for {} {}",
+                            method, path
+                        )))
+                        .unwrap(),
+                )
+            }
+        }
+    }
+}
+
+// The `not_found` response with the default body, also used for unmatched
+// requests in workspaces without a backend
+pub fn not_found(method: &Method, path: &str) -> Response<Body> {
+    let body = default_not_found_body()
+        .replace("{{method}}", method.as_str())
+        .replace("{{path}}", path);
+    not_found_with(body)
+}
+
+fn not_found_with(body: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(body))
+        .unwrap()
+}
@@ -0,0 +1,515 @@
+use hyper::{
+    client::{
+        connect::{
+            dns::{GaiResolver, Name},
+            Connect,
+        },
+        HttpConnector,
+    },
+    header, Body, Client, Request, Response, StatusCode, Uri,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, RootCertStore, ServerName,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    error::Error,
+    fs::File,
+    future::Future,
+    io::{self, BufReader},
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+use tower::Service;
+use utoipa::ToSchema;
+
+use crate::trailers;
+
+pub type Connector = Timed<HttpsConnector<Timed<HttpConnector<StaticResolver>>>>;
+pub type HttpsClient = Client<Connector, Body>;
+// For proxied requests that take trailers, with a connection each
+pub type TrailersClient = Client<trailers::Capturing<Connector>, Body>;
+
+// Tuning for the client used to proxy requests to the default endpoint
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct UpstreamSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_max_idle_per_host: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_idle_timeout_secs: Option<u64>,
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp_keepalive_secs: Option<u64>,
+    // Give up on connecting after this long; left to the OS otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_ms: Option<u64>,
+    // Hostname -> IP pairs that bypass normal DNS resolution
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub dns_overrides: HashMap<String, IpAddr>,
+    // Extra PEM bundles trusted on top of the system roots
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ca_files: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_http: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure_skip_verify: bool,
+}
+
+impl Default for UpstreamSettings {
+    fn default() -> Self {
+        UpstreamSettings {
+            pool_max_idle_per_host: None,
+            pool_idle_timeout_secs: None,
+            keep_alive: default_keep_alive(),
+            tcp_keepalive_secs: None,
+            connect_timeout_ms: None,
+            dns_overrides: HashMap::new(),
+            ca_files: Vec::new(),
+            allow_http: false,
+            insecure_skip_verify: false,
+        }
+    }
+}
+
+impl UpstreamSettings {
+    pub fn is_default(&self) -> bool {
+        *self == UpstreamSettings::default()
+    }
+}
+
+fn default_keep_alive() -> bool {
+    true
+}
+
+// Resolves hostnames listed in `dns_overrides` to their fixed IP and defers
+// everything else to the system resolver. TLS still uses the original
+// hostname, so certificates for the overridden name keep validating.
+#[derive(Clone)]
+pub struct StaticResolver {
+    overrides: Arc<HashMap<String, IpAddr>>,
+    fallback: GaiResolver,
+}
+
+impl StaticResolver {
+    fn new(overrides: &HashMap<String, IpAddr>) -> Self {
+        let overrides = overrides
+            .iter()
+            .map(|(host, ip)| (host.to_ascii_lowercase(), *ip))
+            .collect();
+        StaticResolver {
+            overrides: Arc::new(overrides),
+            fallback: GaiResolver::new(),
+        }
+    }
+}
+
+type ResolveFuture =
+    Pin<Box<dyn Future<Output = Result<std::vec::IntoIter<SocketAddr>, std::io::Error>> + Send>>;
+
+impl Service<Name> for StaticResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = std::io::Error;
+    type Future = ResolveFuture;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.fallback.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        if let Some(ip) = self.overrides.get(&name.as_str().to_ascii_lowercase()) {
+            tracing::debug!("Resolved {} to {} via dns_overrides", name, ip);
+            record(Phase::Dns, Duration::ZERO);
+            // The connector fills in the port from the request URI
+            let addrs = vec![SocketAddr::new(*ip, 0)];
+            return Box::pin(async move { Ok(addrs.into_iter()) });
+        }
+        let started = Instant::now();
+        let lookup = self.fallback.call(name);
+        Box::pin(async move {
+            let addrs = lookup
+                .await
+                .map_err(|e| io::Error::new(e.kind(), LookupFailed(e)))?
+                .collect::<Vec<_>>();
+            record(Phase::Dns, started.elapsed());
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+// Marks resolver errors, so failures can be told apart from the ones that
+// come after the lookup
+#[derive(Debug)]
+struct LookupFailed(io::Error);
+
+impl std::fmt::Display for LookupFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for LookupFailed {}
+
+// Why the backend couldn't be reached or didn't answer properly
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    Dns,
+    ConnectionRefused,
+    Timeout,
+    Tls,
+    ConnectionClosed,
+    InvalidResponse,
+    // The backend and path don't make a URL, e.g. a default_endpoint with a
+    // space in it
+    InvalidUrl,
+    Other,
+}
+
+impl FailureReason {
+    fn classify(error: &hyper::Error) -> FailureReason {
+        if error.is_timeout() {
+            return FailureReason::Timeout;
+        }
+        if error.is_parse() || error.is_parse_status() {
+            return FailureReason::InvalidResponse;
+        }
+        let causes = std::iter::successors(error.source(), |&cause| cause.source());
+        for cause in causes {
+            // io::Error hides the error it wraps from source(), and the TLS
+            // connector wraps twice
+            let mut current = Some(cause);
+            while let Some(error) = current {
+                if error.is::<LookupFailed>() {
+                    return FailureReason::Dns;
+                }
+                if error.is::<rustls::Error>() {
+                    return FailureReason::Tls;
+                }
+                let Some(io) = error.downcast_ref::<io::Error>() else {
+                    break;
+                };
+                match io.kind() {
+                    io::ErrorKind::ConnectionRefused => return FailureReason::ConnectionRefused,
+                    io::ErrorKind::TimedOut => return FailureReason::Timeout,
+                    io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof => return FailureReason::ConnectionClosed,
+                    _ => {}
+                }
+                current = io.get_ref().map(|inner| inner as &(dyn Error + 'static));
+            }
+        }
+        if error.is_incomplete_message() || error.is_closed() || error.is_canceled() {
+            return FailureReason::ConnectionClosed;
+        }
+        FailureReason::Other
+    }
+
+    // 503 when nothing listens, as the backend is down rather than broken
+    pub fn status(self) -> StatusCode {
+        match self {
+            FailureReason::ConnectionRefused => StatusCode::SERVICE_UNAVAILABLE,
+            FailureReason::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+// A request that couldn't be proxied, answered with a 502, 503 or 504 that
+// says why instead of a 500
+#[derive(Debug)]
+pub struct UpstreamError {
+    pub backend: String,
+    pub reason: FailureReason,
+    pub message: String,
+}
+
+impl UpstreamError {
+    pub fn new(backend: &str, error: &hyper::Error) -> Self {
+        UpstreamError {
+            backend: backend.to_string(),
+            reason: FailureReason::classify(error),
+            message: error.to_string(),
+        }
+    }
+
+    pub fn invalid_url(backend: &str, error: impl std::fmt::Display) -> Self {
+        UpstreamError {
+            backend: backend.to_string(),
+            reason: FailureReason::InvalidUrl,
+            message: error.to_string(),
+        }
+    }
+
+    pub fn response(&self) -> Response<Body> {
+        let status = self.reason.status();
+        let body = serde_json::json!({
+            "error": status.canonical_reason().unwrap_or("Bad Gateway"),
+            "backend": self.backend,
+            "reason": self.reason,
+            "message": self.message,
+        });
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+}
+
+impl std::fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:?}): {}", self.backend, self.reason, self.message)
+    }
+}
+
+// How long each phase of one upstream request took. The connection phases
+// are left out when a pooled connection was reused; `total_ms` ends with the
+// response headers, as the body is streamed on to the client.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UpstreamTiming {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_ms: Option<f64>,
+    // From having a connection to the first byte of the response
+    pub ttfb_ms: f64,
+    pub total_ms: f64,
+}
+
+impl std::fmt::Display for UpstreamTiming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phases = [
+            ("dns", self.dns_ms),
+            ("connect", self.connect_ms),
+            ("tls", self.tls_ms),
+            ("ttfb", Some(self.ttfb_ms)),
+            ("total", Some(self.total_ms)),
+        ];
+        let phases: Vec<String> = phases
+            .iter()
+            .filter_map(|(name, ms)| Some(format!("{} {}ms", name, (*ms)?)))
+            .collect();
+        f.write_str(&phases.join(", "))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Phase {
+    Dns,
+    Connect, // Ends once TCP is connected, so includes the lookup
+    Tls,     // Ends once TLS is set up, so includes everything before it
+}
+
+// When each connection phase of the request being sent ended
+#[derive(Clone, Copy, Default)]
+struct Phases {
+    dns: Option<Duration>,
+    connect: Option<Duration>,
+    tls: Option<Duration>,
+}
+
+tokio::task_local! {
+    static PHASES: Cell<Phases>;
+}
+
+// Notes a phase of the connection being opened for the request `send` is
+// waiting on. A connection hyper finishes in the background, after a pooled
+// one won the race, belongs to no request and isn't recorded.
+fn record(phase: Phase, elapsed: Duration) {
+    let _ = PHASES.try_with(|phases| {
+        let mut current = phases.get();
+        match phase {
+            Phase::Dns => current.dns = Some(elapsed),
+            Phase::Connect => current.connect = Some(elapsed),
+            Phase::Tls => current.tls = Some(elapsed),
+        }
+        phases.set(current);
+    });
+}
+
+// Sends a request through `client`, timing each phase
+pub async fn send<C>(
+    client: &Client<C, Body>,
+    req: Request<Body>,
+) -> (hyper::Result<Response<Body>>, UpstreamTiming)
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    let https = req.uri().scheme() == Some(&hyper::http::uri::Scheme::HTTPS);
+    PHASES
+        .scope(Cell::new(Phases::default()), async move {
+            let started = Instant::now();
+            let result = client.request(req).await;
+            let total = started.elapsed();
+            let phases = PHASES.with(Cell::get);
+            let connected = match phases.tls.filter(|_| https) {
+                Some(tls) => Some(tls),
+                None => phases.connect,
+            };
+            let ms = |d: Duration| (d.as_secs_f64() * 100_000.0).round() / 100.0;
+            let timing = UpstreamTiming {
+                dns_ms: phases.dns.map(ms),
+                connect_ms: phases
+                    .connect
+                    .map(|connect| ms(connect.saturating_sub(phases.dns.unwrap_or_default()))),
+                tls_ms: phases
+                    .tls
+                    .filter(|_| https)
+                    .zip(phases.connect)
+                    .map(|(tls, connect)| ms(tls.saturating_sub(connect))),
+                ttfb_ms: ms(total.saturating_sub(connected.unwrap_or_default())),
+                total_ms: ms(total),
+            };
+            (result, timing)
+        })
+        .await
+}
+
+type ConnectFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+// A connector that notes how long `inner` took to connect
+#[derive(Clone)]
+pub struct Timed<S> {
+    inner: S,
+    phase: Phase,
+}
+
+impl<S> Service<Uri> for Timed<S>
+where
+    S: Service<Uri>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ConnectFuture<S::Response, S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let started = Instant::now();
+        let phase = self.phase;
+        let connecting = self.inner.call(uri);
+        Box::pin(async move {
+            let connection = connecting.await?;
+            record(phase, started.elapsed());
+            Ok(connection)
+        })
+    }
+}
+
+// Accepts any certificate. Only installed when `insecure_skip_verify` is set.
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn tls_config(settings: &UpstreamSettings) -> std::io::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    match rustls_native_certs::load_native_certs() {
+        Ok(certs) => {
+            for cert in certs {
+                if let Err(e) = roots.add(&Certificate(cert.0)) {
+                    tracing::debug!("Skipping invalid native root certificate: {}", e);
+                }
+            }
+        }
+        Err(e) => tracing::warn!("Failed to load native root certificates: {}", e),
+    }
+
+    for path in &settings.ca_files {
+        let mut reader = BufReader::new(File::open(path)?);
+        let certs = rustls_pemfile::certs(&mut reader)?;
+        let (added, _) = roots.add_parsable_certificates(&certs);
+        tracing::info!("Loaded {} CA certificate(s) from {}", added, path);
+    }
+
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    if settings.insecure_skip_verify {
+        tracing::warn!("TLS certificate verification is disabled for upstream requests");
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoVerification));
+    }
+    Ok(config)
+}
+
+pub fn build_client(settings: &UpstreamSettings) -> std::io::Result<HttpsClient> {
+    Ok(client_builder(settings).build(connector(settings)?))
+}
+
+// The client proxied requests go through, and the one for those that take
+// trailers, sharing their settings
+pub fn build_proxy_clients(
+    settings: &UpstreamSettings,
+) -> std::io::Result<(HttpsClient, TrailersClient)> {
+    let connector = connector(settings)?;
+    let trailers = client_builder(settings)
+        .pool_max_idle_per_host(0)
+        .build(trailers::Capturing(connector.clone()));
+    Ok((client_builder(settings).build(connector), trailers))
+}
+
+fn connector(settings: &UpstreamSettings) -> std::io::Result<Connector> {
+    let mut http = HttpConnector::new_with_resolver(StaticResolver::new(&settings.dns_overrides));
+    http.enforce_http(false);
+    http.set_keepalive(settings.tcp_keepalive_secs.map(Duration::from_secs));
+    http.set_connect_timeout(settings.connect_timeout_ms.map(Duration::from_millis));
+
+    // HTTPS client setup using HttpsConnectorBuilder
+    let builder = HttpsConnectorBuilder::new().with_tls_config(tls_config(settings)?);
+    let builder = if settings.allow_http {
+        builder.https_or_http()
+    } else {
+        builder.https_only()
+    };
+    let http = Timed {
+        inner: http,
+        phase: Phase::Connect,
+    };
+    Ok(Timed {
+        inner: builder.enable_http1().wrap_connector(http),
+        phase: Phase::Tls,
+    })
+}
+
+fn client_builder(settings: &UpstreamSettings) -> hyper::client::Builder {
+    let mut builder = Client::builder();
+    if let Some(secs) = settings.pool_idle_timeout_secs {
+        builder.pool_idle_timeout(Duration::from_secs(secs));
+    }
+    if !settings.keep_alive {
+        // Without idle connections in the pool every request opens a new one
+        builder.pool_max_idle_per_host(0);
+    } else if let Some(max) = settings.pool_max_idle_per_host {
+        builder.pool_max_idle_per_host(max);
+    }
+    builder
+}
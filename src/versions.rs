@@ -0,0 +1,79 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Mutex};
+use utoipa::ToSchema;
+
+use crate::workspace::WorkspaceSettings;
+
+// A saved copy of a workspace's configuration
+#[derive(Clone)]
+pub struct ConfigVersion {
+    pub version: u64,
+    pub timestamp: DateTime<Utc>,
+    pub description: String,
+    pub settings: WorkspaceSettings,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VersionSummary {
+    pub version: u64,
+    pub timestamp: DateTime<Utc>,
+    pub description: String,
+    pub endpoints: usize,
+}
+
+// The last `capacity` configurations of a workspace. Versions are numbered
+// from 1 and never reused, even after older ones are dropped.
+pub struct VersionHistory {
+    capacity: usize,
+    versions: Mutex<(u64, VecDeque<ConfigVersion>)>,
+}
+
+impl VersionHistory {
+    pub fn new(capacity: usize, initial: &WorkspaceSettings) -> Self {
+        let history = VersionHistory {
+            capacity: capacity.max(1),
+            versions: Mutex::new((1, VecDeque::new())),
+        };
+        history.record(initial, "Loaded from settings.json");
+        history
+    }
+
+    pub fn record(&self, settings: &WorkspaceSettings, description: &str) -> u64 {
+        let mut guard = self.versions.lock().unwrap();
+        let (next, versions) = &mut *guard;
+        let version = *next;
+        *next += 1;
+        while versions.len() >= self.capacity {
+            versions.pop_front();
+        }
+        versions.push_back(ConfigVersion {
+            version,
+            timestamp: Utc::now(),
+            description: description.to_string(),
+            settings: settings.clone(),
+        });
+        version
+    }
+
+    // Newest first
+    pub fn list(&self) -> Vec<VersionSummary> {
+        let guard = self.versions.lock().unwrap();
+        guard
+            .1
+            .iter()
+            .rev()
+            .map(|v| VersionSummary {
+                version: v.version,
+                timestamp: v.timestamp,
+                description: v.description.clone(),
+                endpoints: v.settings.active_endpoints().len(),
+            })
+            .collect()
+    }
+
+    pub fn get(&self, version: u64) -> Option<ConfigVersion> {
+        let guard = self.versions.lock().unwrap();
+        guard.1.iter().find(|v| v.version == version).cloned()
+    }
+}
@@ -0,0 +1,114 @@
+use axum::http::request::Parts;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, sync::Arc};
+use utoipa::ToSchema;
+
+use crate::{matching::PathMatching, workspace::Routes, EndpointConfig};
+
+// A separate endpoint set served for requests addressed to one of `hosts`.
+// A host may be a wildcard like `*.sandbox.local`, matching any name under
+// sandbox.local.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct VirtualHost {
+    pub hosts: Vec<String>,
+    pub endpoints: Vec<EndpointConfig>,
+    // Backend for this host's unmatched requests instead of the workspace's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_endpoint: Option<String>,
+}
+
+// Routing data for one virtual host, rebuilt whenever its endpoints change
+#[derive(Clone)]
+pub struct VirtualHostRoutes {
+    pub hosts: Vec<String>,
+    pub routes: Arc<Routes>,
+    pub default_endpoint: Option<String>,
+    path_matching: PathMatching,
+}
+
+impl VirtualHostRoutes {
+    pub fn new(vhost: &VirtualHost, path_matching: PathMatching) -> Self {
+        VirtualHostRoutes {
+            hosts: vhost.hosts.iter().map(|h| normalize_host(h)).collect(),
+            routes: Arc::new(Routes::new(vhost.endpoints.clone(), path_matching)),
+            default_endpoint: vhost.default_endpoint.clone(),
+            path_matching,
+        }
+    }
+
+    // How closely this virtual host matches `host`, if at all: exactly, or
+    // by the longest of its wildcards that covers it
+    fn specificity(&self, host: &str) -> Option<usize> {
+        self.hosts
+            .iter()
+            .filter_map(|pattern| {
+                if pattern == host {
+                    return Some(usize::MAX);
+                }
+                let suffix = wildcard_suffix(pattern)?;
+                (host.len() > suffix.len() && host.ends_with(suffix)).then_some(suffix.len())
+            })
+            .max()
+    }
+
+    pub fn set_endpoints(&mut self, endpoints: Vec<EndpointConfig>) {
+        self.routes = Arc::new(Routes::new(endpoints, self.path_matching));
+    }
+}
+
+// The virtual host serving `host`. An exact name wins over wildcards, a
+// longer wildcard over a shorter one, and the first listed over later ones.
+pub fn position(virtual_hosts: &[VirtualHostRoutes], host: &str) -> Option<usize> {
+    virtual_hosts
+        .iter()
+        .enumerate()
+        .filter_map(|(i, vhost)| Some((vhost.specificity(host)?, Reverse(i))))
+        .max()
+        .map(|(_, Reverse(i))| i)
+}
+
+pub fn find<'a>(
+    virtual_hosts: &'a [VirtualHostRoutes],
+    host: &str,
+) -> Option<&'a VirtualHostRoutes> {
+    position(virtual_hosts, host).map(|i| &virtual_hosts[i])
+}
+
+// `.sandbox.local` for `*.sandbox.local`
+fn wildcard_suffix(pattern: &str) -> Option<&str> {
+    pattern
+        .strip_prefix('*')
+        .filter(|suffix| suffix.len() > 1 && suffix.starts_with('.'))
+}
+
+// Wildcards are only allowed as a whole leading label
+pub fn valid_host(host: &str) -> bool {
+    !host.contains('*') || wildcard_suffix(host).is_some_and(|suffix| !suffix.contains('*'))
+}
+
+// The host a request was addressed to, taken from an absolute-form URI or the
+// Host header. TLS is terminated in front of the mock server, so a terminator
+// that forwards the SNI name as Host gets SNI-based routing for free.
+pub fn request_host(parts: &Parts) -> Option<String> {
+    parts
+        .uri
+        .host()
+        .or_else(|| parts.headers.get("Host").and_then(|h| h.to_str().ok()))
+        .map(normalize_host)
+}
+
+// Lowercases the host and drops any port
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim().to_ascii_lowercase();
+    if host.starts_with('[') {
+        // IPv6 literal, e.g. [::1]:8000
+        return match host.find(']') {
+            Some(end) => host[..=end].to_string(),
+            None => host,
+        };
+    }
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name.to_string(),
+        _ => host,
+    }
+}
@@ -0,0 +1,47 @@
+use hyper::{header, Body, Method, Request};
+
+use crate::upstream::{self, HttpsClient, UpstreamSettings};
+
+// A URL that JSON notifications are posted to in the background; failures
+// are only logged
+pub struct Webhook {
+    url: String,
+    client: HttpsClient,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> std::io::Result<Self> {
+        let client = upstream::build_client(&UpstreamSettings {
+            allow_http: true,
+            ..UpstreamSettings::default()
+        })?;
+        Ok(Webhook {
+            url: url.to_string(),
+            client,
+        })
+    }
+
+    pub fn post(&self, body: &serde_json::Value) {
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()));
+        let req = match req {
+            Ok(req) => req,
+            Err(e) => {
+                tracing::error!("Invalid webhook {}: {}", self.url, e);
+                return;
+            }
+        };
+        let client = self.client.clone();
+        let url = self.url.clone();
+        tokio::spawn(async move {
+            match client.request(req).await {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => tracing::error!("Webhook {} answered {}", url, response.status()),
+                Err(e) => tracing::error!("Failed to post to webhook {}: {}", url, e),
+            }
+        });
+    }
+}
@@ -0,0 +1,306 @@
+use axum::http::{header, request::Parts, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::{SinkExt, StreamExt};
+use hyper::{
+    upgrade::{OnUpgrade, Upgraded},
+    Body,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+    WebSocketStream,
+};
+use utoipa::ToSchema;
+
+use crate::{
+    hop_by_hop,
+    recording::{RecordedRequest, Recorder},
+    upstream::{self, HttpsClient, UpstreamError},
+};
+
+// A WebSocket conversation played to clients that open one on the endpoint,
+// e.g. one captured by `recording`
+#[derive(Debug, Deserialize, Serialize, Clone, Default, ToSchema)]
+pub struct WebSocketScript {
+    pub frames: Vec<Frame>,
+    // Sent as Sec-WebSocket-Protocol
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    // Wait for the client to close after the last frame instead of closing
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_open: bool,
+}
+
+// One message. The server's are sent `delay_ms` after the frame before;
+// for each of the client's the script waits for a message, whatever it says.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct Frame {
+    pub from: Side,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    // Base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Client,
+    Server,
+}
+
+impl Frame {
+    fn new(from: Side, message: &Message, delay: Option<Duration>) -> Option<Frame> {
+        let (text, binary) = match message {
+            Message::Text(text) => (Some(text.clone()), None),
+            Message::Binary(bytes) => (None, Some(STANDARD.encode(bytes))),
+            _ => return None,
+        };
+        Some(Frame {
+            from,
+            text,
+            binary,
+            delay_ms: delay.map(|delay| delay.as_millis() as u64),
+        })
+    }
+
+    fn message(&self) -> Option<Message> {
+        if let Some(text) = &self.text {
+            return Some(Message::Text(text.clone()));
+        }
+        match STANDARD.decode(self.binary.as_deref()?) {
+            Ok(bytes) => Some(Message::Binary(bytes)),
+            Err(e) => {
+                tracing::warn!("Skipping WebSocket frame that isn't base64: {}", e);
+                None
+            }
+        }
+    }
+}
+
+// A request to open a WebSocket that hyper can hand over the connection for
+pub fn is_upgrade(parts: &Parts) -> bool {
+    let has = |name: header::HeaderName, token: &str| {
+        parts
+            .headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+    has(header::CONNECTION, "upgrade")
+        && has(header::UPGRADE, "websocket")
+        && parts.headers.contains_key(header::SEC_WEBSOCKET_KEY)
+        && parts.extensions.get::<OnUpgrade>().is_some()
+}
+
+impl WebSocketScript {
+    // Accepts the upgrade and plays the script on the new connection, or
+    // answers 426 when the request isn't an upgrade
+    pub fn accept(&self, parts: &mut Parts) -> Response<Body> {
+        if !is_upgrade(parts) {
+            return Response::builder()
+                .status(StatusCode::UPGRADE_REQUIRED)
+                .header(header::UPGRADE, "websocket")
+                .header(header::CONNECTION, "upgrade")
+                .body(Body::from("This endpoint only speaks WebSocket"))
+                .unwrap();
+        }
+        let key = parts.headers[header::SEC_WEBSOCKET_KEY].as_bytes();
+        let mut builder = Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::UPGRADE, "websocket")
+            .header(header::CONNECTION, "upgrade")
+            .header(header::SEC_WEBSOCKET_ACCEPT, derive_accept_key(key));
+        if let Some(protocol) = &self.protocol {
+            builder = builder.header(header::SEC_WEBSOCKET_PROTOCOL, protocol);
+        }
+        let on_upgrade = parts.extensions.remove::<OnUpgrade>().unwrap();
+        let script = self.clone();
+        let path = parts.uri.path().to_string();
+        tracing::info!("Playing WebSocket script for {}", path);
+        tokio::spawn(async move {
+            match on_upgrade.await {
+                Ok(upgraded) => {
+                    let socket =
+                        WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                    script.play(socket).await;
+                    tracing::info!("WebSocket script for {} ended", path);
+                }
+                Err(e) => tracing::warn!("WebSocket upgrade for {} failed: {}", path, e),
+            }
+        });
+        builder.body(Body::empty()).unwrap()
+    }
+
+    async fn play(&self, mut socket: WebSocketStream<Upgraded>) {
+        for frame in &self.frames {
+            match frame.from {
+                Side::Server => {
+                    if let Some(ms) = frame.delay_ms {
+                        tokio::time::sleep(Duration::from_millis(ms)).await;
+                    }
+                    let Some(message) = frame.message() else {
+                        continue;
+                    };
+                    if socket.send(message).await.is_err() {
+                        return;
+                    }
+                }
+                // Pings are answered while waiting
+                Side::Client => loop {
+                    match socket.next().await {
+                        Some(Ok(Message::Text(_) | Message::Binary(_))) => break,
+                        Some(Ok(Message::Close(_))) => {
+                            let _ = socket.flush().await;
+                            return;
+                        }
+                        Some(Err(_)) | None => return,
+                        Some(Ok(_)) => continue,
+                    }
+                },
+            }
+        }
+        if !self.keep_open {
+            let _ = socket.close(None).await;
+        }
+        // Until the client closes, or acknowledges our close
+        while let Some(Ok(_)) = socket.next().await {}
+    }
+}
+
+// Opens the same WebSocket on `backend` and relays messages both ways until
+// either side closes. With `recording` the conversation is saved as a
+// script. Responses other than 101 are passed on as they are.
+pub async fn proxy(
+    client: &HttpsClient,
+    backend: &str,
+    mut parts: Parts,
+    via: &str,
+    recording: Option<(Arc<Recorder>, RecordedRequest)>,
+) -> Result<Response<Body>, UpstreamError> {
+    let on_upgrade = parts.extensions.remove::<OnUpgrade>().unwrap();
+    let query = parts
+        .uri
+        .query()
+        .map(|q| format!("?{}", q))
+        .unwrap_or_default();
+    let uri = format!("{}{}{}", backend, parts.uri.path(), query);
+    let mut headers = parts.headers.clone();
+    headers.remove(header::HOST);
+    hop_by_hop::strip(&mut headers);
+    // Compressed frames couldn't be read to relay them
+    headers.remove(header::SEC_WEBSOCKET_EXTENSIONS);
+    reconnect(&mut headers);
+    hop_by_hop::via(&mut headers, parts.version, via);
+    let mut req = Request::get(uri)
+        .body(Body::empty())
+        .map_err(|e| UpstreamError::invalid_url(backend, e))?;
+    *req.headers_mut() = headers;
+
+    tracing::info!("Opening WebSocket on {}{}", backend, parts.uri.path());
+    let (result, timing) = upstream::send(client, req).await;
+    let mut response = result.map_err(|e| UpstreamError::new(backend, &e))?;
+    tracing::info!(
+        "Upstream timing for WebSocket {}: {}",
+        parts.uri.path(),
+        timing
+    );
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        tracing::info!("Backend refused the WebSocket: {}", response.status());
+        return Ok(response);
+    }
+
+    let backend_upgrade = hyper::upgrade::on(&mut response);
+    let version = response.version();
+    let headers = response.headers_mut();
+    hop_by_hop::strip(headers);
+    reconnect(headers);
+    hop_by_hop::via(headers, version, via);
+    let protocol = headers
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let path = parts.uri.path().to_string();
+    tokio::spawn(async move {
+        let (client_side, backend_side) = match tokio::try_join!(on_upgrade, backend_upgrade) {
+            Ok(upgraded) => upgraded,
+            Err(e) => {
+                tracing::warn!("WebSocket upgrade for {} failed: {}", path, e);
+                return;
+            }
+        };
+        let client_side = WebSocketStream::from_raw_socket(client_side, Role::Server, None).await;
+        let backend_side = WebSocketStream::from_raw_socket(backend_side, Role::Client, None).await;
+        let frames = relay(client_side, backend_side).await;
+        tracing::info!("WebSocket {} closed after {} messages", path, frames.len());
+        if let Some((recorder, request)) = recording {
+            let script = WebSocketScript {
+                frames,
+                protocol,
+                keep_open: false,
+            };
+            recorder.record_websocket(request, script).await;
+        }
+    });
+    Ok(response)
+}
+
+// Connection and Upgrade are hop-by-hop, but each hop of a WebSocket needs
+// them to switch protocols
+fn reconnect(headers: &mut HeaderMap) {
+    headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+}
+
+// Passes text and binary messages and the close on; each side answers its
+// own pings. Returns the messages as frames of a script.
+async fn relay(
+    mut client: WebSocketStream<Upgraded>,
+    mut backend: WebSocketStream<Upgraded>,
+) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut last = Instant::now();
+    loop {
+        let (from, received) = tokio::select! {
+            received = client.next() => (Side::Client, received),
+            received = backend.next() => (Side::Server, received),
+        };
+        let (to, from_socket) = match from {
+            Side::Client => (&mut backend, &mut client),
+            Side::Server => (&mut client, &mut backend),
+        };
+        match received {
+            Some(Ok(message @ (Message::Text(_) | Message::Binary(_)))) => {
+                let delay = (from == Side::Server).then(|| last.elapsed());
+                frames.extend(Frame::new(from, &message, delay));
+                last = Instant::now();
+                if to.send(message).await.is_err() {
+                    let _ = from_socket.close(None).await;
+                    break;
+                }
+            }
+            Some(Ok(Message::Close(frame))) => {
+                let _ = to.close(frame).await;
+                // Sends the reply tungstenite queued
+                let _ = from_socket.flush().await;
+                break;
+            }
+            Some(Ok(_)) => {}
+            Some(Err(_)) | None => {
+                let _ = to.close(None).await;
+                break;
+            }
+        }
+    }
+    frames
+}
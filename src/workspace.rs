@@ -0,0 +1,369 @@
+use arc_swap::ArcSwap;
+use hyper::body::Bytes;
+use matchit::Router as MatchItRouter;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use utoipa::ToSchema;
+
+use crate::{
+    build_router,
+    journal::Journal,
+    matching::PathMatching,
+    scenario::Scenarios,
+    versions::VersionHistory,
+    vhost::{self, VirtualHost, VirtualHostRoutes},
+    EndpointConfig, DEFAULT_PORT,
+};
+
+pub const DEFAULT_WORKSPACE: &str = "default";
+
+// Profile name that refers to the workspace's own `endpoints`
+pub const BASE_PROFILE: &str = "default";
+
+// Everything a workspace owns. The top level of settings.json is itself the
+// default workspace; entries in `workspaces` are selected by listener port,
+// Host header or path prefix.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct WorkspaceSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+    // The real backend; without one the workspace only serves mocks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_endpoint: Option<String>,
+    // May be left out when an included settings file provides them
+    #[serde(default)]
+    pub endpoints: Vec<EndpointConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub virtual_hosts: Vec<VirtualHost>,
+    // Alternative full endpoint sets, e.g. "errors" or "slow"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Vec<EndpointConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_profile: Option<String>,
+}
+
+impl WorkspaceSettings {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(DEFAULT_WORKSPACE)
+    }
+
+    pub fn active_profile(&self) -> &str {
+        match &self.active_profile {
+            Some(profile) if self.profiles.contains_key(profile) => profile,
+            _ => BASE_PROFILE,
+        }
+    }
+
+    // The endpoint set currently being served
+    pub fn active_endpoints(&self) -> &Vec<EndpointConfig> {
+        match self.profiles.get(self.active_profile()) {
+            Some(endpoints) => endpoints,
+            None => &self.endpoints,
+        }
+    }
+
+    pub fn active_endpoints_mut(&mut self) -> &mut Vec<EndpointConfig> {
+        let profile = self.active_profile().to_string();
+        match self.profiles.get_mut(&profile) {
+            Some(endpoints) => endpoints,
+            None => &mut self.endpoints,
+        }
+    }
+
+    pub fn profile_names(&self) -> Vec<String> {
+        std::iter::once(BASE_PROFILE.to_string())
+            .chain(self.profiles.keys().filter(|p| *p != BASE_PROFILE).cloned())
+            .collect()
+    }
+}
+
+// Endpoints and the router built from them. Updates swap in a new set, so
+// requests keep using the one they started with and never wait on a lock.
+pub struct Routes {
+    pub endpoints: Vec<EndpointConfig>,
+    pub router: MatchItRouter<Vec<usize>>, // For path matching
+    compiled: Vec<Option<Bytes>>,          // Static bodies, by endpoint index
+    local: usize,                          // How many endpoints come from settings
+}
+
+impl Routes {
+    pub fn new(endpoints: Vec<EndpointConfig>, path_matching: PathMatching) -> Self {
+        Self::layered(endpoints, &[], path_matching)
+    }
+
+    // `local` endpoints with the `shared` ones from a config source after
+    // them. A local endpoint with the same id, or method and path, hides the
+    // shared one.
+    pub fn layered(
+        mut endpoints: Vec<EndpointConfig>,
+        shared: &[EndpointConfig],
+        path_matching: PathMatching,
+    ) -> Self {
+        let local = endpoints.len();
+        let hidden: Vec<String> = endpoints.iter().map(endpoint_key).collect();
+        endpoints.extend(
+            shared
+                .iter()
+                .filter(|endpoint| !hidden.contains(&endpoint_key(endpoint)))
+                .cloned(),
+        );
+        Routes {
+            router: build_router(&endpoints, path_matching),
+            compiled: endpoints.iter().map(compile).collect(),
+            endpoints,
+            local,
+        }
+    }
+
+    // The endpoints from settings, which the admin routes edit
+    pub fn local(&self) -> &[EndpointConfig] {
+        &self.endpoints[..self.local]
+    }
+
+    // The body serialized at load time, when nothing about the request can
+    // change it
+    pub fn compiled(&self, index: usize) -> Option<&Bytes> {
+        self.compiled.get(index)?.as_ref()
+    }
+}
+
+// Plain JSON payloads without templates or injected path variables are the
+// same on every request, so they are serialized once here
+fn compile(endpoint: &EndpointConfig) -> Option<Bytes> {
+    if endpoint.content_type != "application/json"
+        || endpoint.csv.is_some()
+        || endpoint.protobuf.is_some()
+        || endpoint.ndjson.is_some()
+    {
+        return None;
+    }
+    if endpoint.payload.is_object() && endpoint.path.contains('{') {
+        return None;
+    }
+    let body = serde_json::to_string(&endpoint.payload).ok()?;
+    (!body.contains("{{")).then(|| Bytes::from(body))
+}
+
+fn endpoint_key(endpoint: &EndpointConfig) -> String {
+    match &endpoint.id {
+        Some(id) => format!("id {}", id),
+        None => format!("{} {}", endpoint.method.to_ascii_uppercase(), endpoint.path),
+    }
+}
+
+pub struct Workspace {
+    pub name: String,
+    port: ArcSwap<Option<u16>>,
+    host: Option<String>,
+    path_prefix: Option<String>,
+    pub default_endpoint: Option<String>,
+    pub routes: ArcSwap<Routes>,
+    pub virtual_hosts: ArcSwap<Vec<VirtualHostRoutes>>,
+    shared: ArcSwap<Vec<EndpointConfig>>, // From `config_source`, served under `routes`
+    path_matching: PathMatching,
+    pub journal: Journal,
+    pub versions: VersionHistory,
+    pub scenarios: Scenarios,
+}
+
+impl Workspace {
+    pub fn new(
+        settings: &WorkspaceSettings,
+        journal_capacity: usize,
+        max_versions: usize,
+        path_matching: PathMatching,
+    ) -> Self {
+        Workspace {
+            name: settings.name().to_string(),
+            port: ArcSwap::from_pointee(settings.port),
+            host: settings.host.as_deref().map(vhost::normalize_host),
+            path_prefix: settings
+                .path_prefix
+                .as_deref()
+                .map(|prefix| format!("/{}", prefix.trim_matches('/'))),
+            default_endpoint: settings.default_endpoint.clone(),
+            routes: ArcSwap::from_pointee(Routes::new(
+                settings.active_endpoints().clone(),
+                path_matching,
+            )),
+            virtual_hosts: ArcSwap::from_pointee(
+                settings
+                    .virtual_hosts
+                    .iter()
+                    .map(|vhost| VirtualHostRoutes::new(vhost, path_matching))
+                    .collect(),
+            ),
+            shared: ArcSwap::from_pointee(Vec::new()),
+            path_matching,
+            journal: Journal::new(journal_capacity),
+            versions: VersionHistory::new(max_versions, settings),
+            scenarios: Scenarios::default(),
+        }
+    }
+
+    // Serves everything routable from `settings`, e.g. after a rollback
+    pub fn apply(&self, settings: &WorkspaceSettings) {
+        self.port.store(Arc::new(settings.port));
+        self.set_endpoints(settings.active_endpoints().clone());
+        self.virtual_hosts.store(Arc::new(
+            settings
+                .virtual_hosts
+                .iter()
+                .map(|vhost| VirtualHostRoutes::new(vhost, self.path_matching))
+                .collect(),
+        ));
+    }
+
+    pub fn set_endpoints(&self, endpoints: Vec<EndpointConfig>) {
+        self.routes.store(Arc::new(Routes::layered(
+            endpoints,
+            &self.shared.load(),
+            self.path_matching,
+        )));
+    }
+
+    // Replaces the endpoints shared through a config source, keeping the
+    // workspace's own
+    pub fn set_shared_endpoints(&self, shared: Vec<EndpointConfig>) {
+        self.shared.store(Arc::new(shared));
+        self.routes.rcu(|routes| {
+            Routes::layered(
+                routes.local().to_vec(),
+                &self.shared.load(),
+                self.path_matching,
+            )
+        });
+    }
+
+    // Replaces the endpoints of the virtual host serving `host`, if any
+    pub fn set_virtual_host_endpoints(&self, host: &str, endpoints: Vec<EndpointConfig>) {
+        self.virtual_hosts.rcu(|virtual_hosts| {
+            let mut virtual_hosts = (**virtual_hosts).clone();
+            if let Some(i) = vhost::position(&virtual_hosts, host) {
+                virtual_hosts[i].set_endpoints(endpoints.clone());
+            }
+            virtual_hosts
+        });
+    }
+
+    // The routes for requests addressed to `host`: a virtual host's own, or
+    // the workspace's
+    pub fn routes_for(&self, host: &str) -> Arc<Routes> {
+        match vhost::find(&self.virtual_hosts.load(), host) {
+            Some(vhost) => {
+                tracing::info!("Using endpoints for virtual host: {}", host);
+                vhost.routes.clone()
+            }
+            None => self.routes.load_full(),
+        }
+    }
+
+    // Where unmatched requests addressed to `host` are proxied: the virtual
+    // host's own default_endpoint, or the workspace's
+    pub fn backend_for(&self, host: &str) -> Option<String> {
+        vhost::find(&self.virtual_hosts.load(), host)
+            .and_then(|vhost| vhost.default_endpoint.clone())
+            .or_else(|| self.default_endpoint.clone())
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        **self.port.load()
+    }
+
+    pub fn path_prefix(&self) -> Option<&str> {
+        self.path_prefix.as_deref()
+    }
+
+    // The path with this workspace's prefix removed, if it lives under it
+    fn strip_prefix(&self, path: &str) -> Option<String> {
+        strip_path_prefix(self.path_prefix.as_deref()?, path)
+    }
+}
+
+// `path` without `prefix` (e.g. "/team-a"), if it lives under it
+pub fn strip_path_prefix(prefix: &str, path: &str) -> Option<String> {
+    let rest = path.strip_prefix(prefix)?;
+    if rest.is_empty() {
+        Some("/".to_string())
+    } else if rest.starts_with('/') {
+        Some(rest.to_string())
+    } else {
+        None
+    }
+}
+
+// All configured workspaces, the default one first
+pub struct Workspaces(Vec<Arc<Workspace>>);
+
+impl Workspaces {
+    pub fn new(
+        root: &WorkspaceSettings,
+        named: &[WorkspaceSettings],
+        journal_capacity: usize,
+        max_versions: usize,
+        path_matching: PathMatching,
+    ) -> Self {
+        let mut workspaces = vec![Arc::new(Workspace::new(
+            root,
+            journal_capacity,
+            max_versions,
+            path_matching,
+        ))];
+        for settings in named {
+            if workspaces.iter().any(|w| w.name == settings.name()) {
+                panic!("Duplicate workspace name: {}", settings.name());
+            }
+            workspaces.push(Arc::new(Workspace::new(
+                settings,
+                journal_capacity,
+                max_versions,
+                path_matching,
+            )));
+        }
+        Workspaces(workspaces)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Workspace>> {
+        self.0.iter().find(|w| w.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Workspace>> {
+        self.0.iter()
+    }
+
+    // The ports to listen on: the default workspace's first, then any other
+    // workspace that wants its own listener
+    pub fn ports(&self) -> Vec<u16> {
+        let mut ports = vec![self.0[0].port().unwrap_or(DEFAULT_PORT)];
+        for port in self.0[1..].iter().filter_map(|w| w.port()) {
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+        ports
+    }
+
+    // Picks the workspace for a request by listener port, then Host header,
+    // then path prefix. Also returns the path with any prefix stripped.
+    pub fn select(&self, port: u16, host: &str, path: &str) -> (Arc<Workspace>, Option<String>) {
+        let named = &self.0[1..];
+        if let Some(w) = named.iter().find(|w| w.port() == Some(port)) {
+            return (w.clone(), None);
+        }
+        if let Some(w) = named.iter().find(|w| w.host.as_deref() == Some(host)) {
+            return (w.clone(), None);
+        }
+        for w in named {
+            if let Some(stripped) = w.strip_prefix(path) {
+                return (w.clone(), Some(stripped));
+            }
+        }
+        (self.0[0].clone(), None)
+    }
+}
@@ -0,0 +1,53 @@
+use std::borrow::Cow;
+
+use quick_xml::{events::Event, Reader};
+
+// application/xml, text/xml and the +xml types such as application/soap+xml
+pub fn is_xml(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence == "application/xml" || essence == "text/xml" || essence.ends_with("+xml")
+}
+
+// Path variables can hold anything, so they're escaped before going into XML
+pub fn escape(value: &str) -> Cow<'_, str> {
+    quick_xml::escape::escape(value)
+}
+
+// Checks that `body` is one well-formed element, optionally preceded by a
+// declaration, comments and processing instructions
+pub fn check(body: &str) -> Result<(), String> {
+    let mut reader = Reader::from_str(body);
+    let mut depth = 0usize;
+    let mut roots = 0;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(_)) => {
+                if depth == 0 {
+                    roots += 1;
+                }
+                depth += 1;
+            }
+            Ok(Event::End(_)) => depth = depth.saturating_sub(1),
+            Ok(Event::Empty(_)) if depth == 0 => roots += 1,
+            Ok(Event::Text(text)) if depth == 0 => {
+                if !text.as_ref().trim().is_empty() {
+                    return Err("text outside the root element".to_string());
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => return Err(format!("at byte {}: {}", reader.error_position(), e)),
+        }
+    }
+    match (depth, roots) {
+        (0, 1) => Ok(()),
+        (0, 0) => Err("no root element".to_string()),
+        (0, _) => Err("more than one root element".to_string()),
+        _ => Err("unclosed element".to_string()),
+    }
+}
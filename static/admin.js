@@ -0,0 +1,376 @@
+// Admin page: a searchable endpoint table with per-endpoint editing.
+// Self-contained so the page works without internet access.
+
+// ?workspace=, ?host= and ?token= apply to every API call
+var api = '/mockserver/admin/endpoints';
+var query = window.location.search;
+var csrfToken = document.querySelector('meta[name="csrf-token"]').content;
+var endpoints = JSON.parse(document.getElementById('endpoints-data').textContent);
+// Index being edited, or null for a new endpoint
+var editing = null;
+
+// A textarea that holds JSON, flagging syntax errors as you type
+function JsonEditor(container) {
+    this.textarea = document.createElement('textarea');
+    this.textarea.className = 'json-editor';
+    this.textarea.spellcheck = false;
+    this.status = document.createElement('div');
+    this.status.className = 'json-editor-status';
+    container.appendChild(this.textarea);
+    container.appendChild(this.status);
+
+    var self = this;
+    this.textarea.addEventListener('input', function () { self.validate(); });
+    this.textarea.addEventListener('keydown', function (event) {
+        if (event.key === 'Tab') {
+            event.preventDefault();
+            var start = self.textarea.selectionStart;
+            var value = self.textarea.value;
+            self.textarea.value = value.slice(0, start) + '  ' + value.slice(self.textarea.selectionEnd);
+            self.textarea.selectionStart = self.textarea.selectionEnd = start + 2;
+        }
+    });
+}
+
+JsonEditor.prototype.set = function (value) {
+    this.textarea.value = JSON.stringify(value, null, 2);
+    this.validate();
+};
+
+// Throws if the text isn't valid JSON
+JsonEditor.prototype.get = function () {
+    return JSON.parse(this.textarea.value);
+};
+
+JsonEditor.prototype.validate = function () {
+    try {
+        JSON.parse(this.textarea.value);
+        this.textarea.classList.remove('invalid');
+        this.status.textContent = '';
+    } catch (err) {
+        this.textarea.classList.add('invalid');
+        this.status.textContent = err.message;
+    }
+};
+
+JsonEditor.prototype.format = function () {
+    try {
+        this.set(this.get());
+    } catch (err) {
+        showToast('Invalid JSON data', 'error');
+    }
+};
+
+function showToast(message, type) {
+    var container = document.querySelector('.toasts');
+    if (!container) {
+        container = document.createElement('div');
+        container.className = 'toasts';
+        document.body.appendChild(container);
+    }
+    var toast = document.createElement('div');
+    toast.className = 'toast ' + (type === 'success' ? 'success' : 'error');
+    toast.textContent = message;
+    toast.addEventListener('click', function () { toast.remove(); });
+    container.appendChild(toast);
+    setTimeout(function () { toast.remove(); }, 3000);
+}
+
+var editor = new JsonEditor(document.getElementById('jsoneditor'));
+
+function request(method, url, body) {
+    var options = {
+        method: method,
+        headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken }
+    };
+    if (body !== undefined) {
+        options.body = JSON.stringify(body);
+    }
+    return fetch(url + query, options).then(function (response) {
+        if (!response.ok) {
+            return response.text().then(function (text) { throw new Error(text || response.statusText); });
+        }
+        return response;
+    });
+}
+
+function reload() {
+    return request('GET', api)
+        .then(function (response) { return response.json(); })
+        .then(function (data) { endpoints = data; render(); });
+}
+
+function matches(endpoint) {
+    var method = document.getElementById('method-filter').value;
+    if (method && endpoint.method.toUpperCase() !== method) {
+        return false;
+    }
+    var search = document.getElementById('search').value.trim().toLowerCase();
+    if (!search) {
+        return true;
+    }
+    var fields = [endpoint.method, endpoint.path].concat(endpoint.tags || []);
+    return fields.some(function (field) { return field.toLowerCase().indexOf(search) !== -1; });
+}
+
+function cell(row, text, className) {
+    var td = row.insertCell();
+    td.textContent = text;
+    if (className) {
+        td.className = className;
+    }
+    return td;
+}
+
+function button(parent, label, onClick) {
+    var b = document.createElement('button');
+    b.textContent = label;
+    b.addEventListener('click', onClick);
+    parent.appendChild(b);
+}
+
+function render() {
+    var body = document.getElementById('endpoints');
+    body.innerHTML = '';
+    endpoints.forEach(function (endpoint, index) {
+        if (!matches(endpoint)) {
+            return;
+        }
+        var row = body.insertRow();
+        cell(row, endpoint.method, 'method');
+        cell(row, endpoint.path, 'path');
+        cell(row, endpoint.status);
+        cell(row, endpoint.content_type);
+        var tags = cell(row, '');
+        (endpoint.tags || []).forEach(function (tag) {
+            var span = document.createElement('span');
+            span.className = 'tag';
+            span.textContent = tag;
+            tags.appendChild(span);
+        });
+        var actions = row.insertCell();
+        button(actions, 'Edit', function () { edit(index, endpoint); });
+        button(actions, 'Clone', function () { edit(null, endpoint); });
+        button(actions, 'Delete', function () { remove(index, endpoint); });
+        button(actions, 'curl', function (event) { copyCurl(index, event.shiftKey ? 'backend' : 'mock'); });
+    });
+}
+
+// Copies a curl command for the endpoint; shift-click targets the real backend
+function copyCurl(index, target) {
+    var url = api + '/' + index + '/curl' + (query ? query + '&' : '?') + 'target=' + target;
+    fetch(url)
+        .then(function (response) {
+            return response.text().then(function (text) {
+                if (!response.ok) {
+                    throw new Error(text || response.statusText);
+                }
+                return text;
+            });
+        })
+        .then(function (command) {
+            if (navigator.clipboard && window.isSecureContext) {
+                return navigator.clipboard.writeText(command).then(function () {
+                    showToast('Copied curl command for the ' + target, 'success');
+                });
+            }
+            window.prompt('curl command for the ' + target, command);
+        })
+        .catch(function (err) { showToast('Failed to build curl command: ' + err.message, 'error'); });
+}
+
+function edit(index, endpoint) {
+    editing = index;
+    document.getElementById('editor-title').textContent =
+        index === null ? 'New endpoint' : 'Edit ' + endpoint.method + ' ' + endpoint.path;
+    editor.set(endpoint);
+    document.getElementById('editor-panel').style.display = 'block';
+}
+
+function closeEditor() {
+    document.getElementById('editor-panel').style.display = 'none';
+}
+
+function save() {
+    var endpoint;
+    try {
+        endpoint = editor.get();
+    } catch (err) {
+        showToast('Invalid JSON data', 'error');
+        return;
+    }
+    var saved = editing === null
+        ? request('POST', api, endpoint)
+        : request('PUT', api + '/' + editing, endpoint);
+    saved.then(function () {
+        showToast('Endpoint saved', 'success');
+        closeEditor();
+        return reload();
+    }).catch(function (err) { showToast('Failed to save endpoint: ' + err.message, 'error'); });
+}
+
+function remove(index, endpoint) {
+    if (!confirm('Delete ' + endpoint.method + ' ' + endpoint.path + '?')) {
+        return;
+    }
+    request('DELETE', api + '/' + index).then(function () {
+        showToast('Endpoint deleted', 'success');
+        return reload();
+    }).catch(function (err) { showToast('Failed to delete endpoint: ' + err.message, 'error'); });
+}
+
+// Traffic dashboard, from /mockserver/admin/stats
+var kinds = ['mocked', 'proxied', 'unmatched'];
+
+function renderDashboard(stats) {
+    var minutes = stats.minutes;
+    // The current minute is still filling up
+    var last = minutes[minutes.length - 2] || { mocked: 0, proxied: 0, unmatched: 0 };
+    document.getElementById('stat-rate').textContent = last.mocked + last.proxied + last.unmatched;
+    kinds.forEach(function (kind) {
+        var total = minutes.reduce(function (sum, minute) { return sum + minute[kind]; }, 0);
+        document.getElementById('stat-' + kind).textContent = total;
+    });
+
+    var chart = document.getElementById('traffic-chart');
+    chart.innerHTML = '';
+    var peak = Math.max.apply(null, minutes.map(function (m) { return m.mocked + m.proxied + m.unmatched; }).concat([1]));
+    minutes.forEach(function (minute) {
+        var bar = document.createElement('div');
+        bar.className = 'bar';
+        bar.title = new Date(minute.minute).toLocaleTimeString() + ': ' +
+            kinds.map(function (kind) { return minute[kind] + ' ' + kind; }).join(', ');
+        kinds.forEach(function (kind) {
+            var part = document.createElement('div');
+            part.className = kind;
+            part.style.height = (100 * minute[kind] / peak) + '%';
+            bar.appendChild(part);
+        });
+        chart.appendChild(bar);
+    });
+
+    var top = document.getElementById('top-endpoints');
+    top.innerHTML = '';
+    stats.top_endpoints.forEach(function (endpoint) {
+        var row = top.insertRow();
+        cell(row, endpoint.endpoint, 'path');
+        cell(row, endpoint.workspace);
+        cell(row, endpoint.requests);
+    });
+    if (!stats.top_endpoints.length) {
+        cell(top.insertRow(), 'No mocked requests yet');
+    }
+
+    var errors = document.getElementById('recent-errors');
+    errors.innerHTML = '';
+    stats.recent_errors.forEach(function (error) {
+        var row = errors.insertRow();
+        cell(row, new Date(error.timestamp).toLocaleTimeString());
+        cell(row, error.method + ' ' + error.path, 'path');
+        cell(row, error.status);
+        cell(row, error.matched || error.proxied_to || 'unmatched');
+    });
+    if (!stats.recent_errors.length) {
+        cell(errors.insertRow(), 'No errors');
+    }
+}
+
+function refreshDashboard() {
+    request('GET', '/mockserver/admin/stats')
+        .then(function (response) { return response.json(); })
+        .then(renderDashboard)
+        .catch(function () { /* Try again on the next tick */ });
+}
+
+// Name of the scenario being recorded from proxied traffic, or null
+var recordingScenario = null;
+
+function showRecording(status) {
+    var recording = status.recording;
+    recordingScenario = recording ? recording.name : null;
+    var b = document.getElementById('scenario-button');
+    b.textContent = recording
+        ? 'Stop recording ' + recording.name + ' (' + recording.captured + ')'
+        : 'Record scenario';
+    b.classList.toggle('recording', !!recording);
+}
+
+function refreshScenarios() {
+    request('GET', '/mockserver/admin/scenarios')
+        .then(function (response) { return response.json(); })
+        .then(showRecording)
+        .catch(function () { /* Try again on the next tick */ });
+}
+
+// Starts capturing proxied traffic, or stops and turns it into endpoints
+function toggleScenario() {
+    if (recordingScenario === null) {
+        var name = window.prompt('Name of the scenario to record');
+        if (!name) {
+            return;
+        }
+        request('POST', '/mockserver/admin/scenarios/recording', { name: name })
+            .then(function () {
+                showToast('Recording ' + name + ': proxied requests are captured until you stop', 'success');
+                refreshScenarios();
+            })
+            .catch(function (err) { showToast('Failed to start recording: ' + err.message, 'error'); });
+        return;
+    }
+    var recorded = recordingScenario;
+    request('DELETE', '/mockserver/admin/scenarios/recording')
+        .then(function (response) { return response.json(); })
+        .then(function (added) {
+            showToast('Added ' + added.length + ' endpoints for scenario ' + recorded, 'success');
+            refreshScenarios();
+            return reload();
+        })
+        .catch(function (err) { showToast('Failed to save scenario: ' + err.message, 'error'); });
+}
+
+document.getElementById('search').addEventListener('input', render);
+document.getElementById('method-filter').addEventListener('change', render);
+document.getElementById('new-button').addEventListener('click', function () {
+    edit(null, { method: 'GET', path: '/', status: 200, content_type: 'application/json', payload: {} });
+});
+// Scaffolds a new endpoint from a pasted curl command, for review before saving
+function createFromCurl() {
+    var body = {
+        curl: document.getElementById('curl-command').value,
+        capture: document.getElementById('curl-capture').checked
+    };
+    request('POST', api + '/from-curl', body)
+        .then(function (response) { return response.json(); })
+        .then(function (endpoint) {
+            document.getElementById('curl-panel').style.display = 'none';
+            edit(null, endpoint);
+        })
+        .catch(function (err) { showToast('Failed to read curl command: ' + err.message, 'error'); });
+}
+
+document.getElementById('curl-import-button').addEventListener('click', function () {
+    var panel = document.getElementById('curl-panel');
+    panel.style.display = panel.style.display === 'block' ? 'none' : 'block';
+});
+document.getElementById('curl-create-button').addEventListener('click', createFromCurl);
+document.getElementById('scenario-button').addEventListener('click', toggleScenario);
+document.getElementById('format-button').addEventListener('click', function () { editor.format(); });
+document.getElementById('save-button').addEventListener('click', save);
+document.getElementById('cancel-button').addEventListener('click', closeEditor);
+document.addEventListener('keydown', function (event) {
+    var key = event.key || event.keyCode;
+    if ((event.ctrlKey || event.metaKey) && (key === 's' || key === 'S' || key === 83)) {
+        event.preventDefault();
+        if (document.getElementById('editor-panel').style.display === 'block') {
+            save();
+        }
+    }
+});
+
+render();
+refreshDashboard();
+refreshScenarios();
+setInterval(function () {
+    refreshDashboard();
+    refreshScenarios();
+}, 10000);